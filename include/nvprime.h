@@ -0,0 +1,62 @@
+/* Generated with cbindgen, run `just header` to refresh. Do not edit. */
+
+#ifndef NVPRIME_H
+#define NVPRIME_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Apply the tuning described by `config_json` for the process `pid`, in the
+ * form the running daemon's API version takes. The JSON has the same shape
+ * the CLI sends: `{"cpu": ..., "gpu": ..., "sys": ...}`. End it with
+ * `nvprime_end_session` once the game exits.
+ *
+ * # Safety
+ * `config_json` must be a valid NUL terminated string.
+ */
+int32_t nvprime_apply_tuning(uint32_t pid, const char *config_json);
+
+/**
+ * End the session of `pid` once its game exited, the tuning of the
+ * caller's other games and profiles stays applied
+ */
+int32_t nvprime_end_session(uint32_t pid);
+
+/**
+ * End every session of the caller and release its profiles, like
+ * `nvprime reset`. Not meant for a game exiting, see `nvprime_end_session`.
+ */
+int32_t nvprime_reset_tuning(void);
+
+/**
+ * Daemon status as a JSON string, or NULL on failure.
+ * The result must be released with `nvprime_string_free`.
+ */
+char *nvprime_get_status(void);
+
+/**
+ * Release a string returned by this library. NULL is ignored.
+ *
+ * # Safety
+ * `s` must come from this library and must not be used afterward.
+ */
+void nvprime_string_free(char *s);
+
+/**
+ * Message of the last error on this thread, or NULL if there was none.
+ * The pointer stays valid until the next failing call on the same thread.
+ */
+const char *nvprime_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* NVPRIME_H */
@@ -1,22 +1,902 @@
 use anyhow::{Context, Result};
-use log::{error, info};
-use nvprime::common::{Config, NvPrimeClientProxy, logging};
-use nvprime::runner::Launcher;
+use clap::{Parser, Subcommand};
+use futures_util::StreamExt;
+use log::{LevelFilter, debug, error, info, warn};
+use nix::sys::signal::{Signal, kill};
+use nix::unistd::Pid;
+use nvprime::common::config::{GameConfig, GpuTune, HooksConfig, LaunchBackend};
+use nvprime::common::ipc::DaemonApi;
+use nvprime::common::nvgpu::driver_version_string;
+use nvprime::common::steam::SteamCompat;
+use nvprime::common::{
+    Config, LinkInfo, NvGpu, NvPrimeClientProxy, TelemetrySample, logging, paths, proc, telemetry,
+};
+use nvprime::runner::autotune::{self, AutotuneGoal, Segment};
+use nvprime::runner::catalog::{self, Catalog, Workaround};
+use nvprime::runner::command;
+use nvprime::runner::mangohud::{self, HudCommand};
+use nvprime::runner::prefix::{self, PrefixCheck};
+use nvprime::runner::updates;
+use nvprime::runner::{
+    ConfigDraft, DoNotDisturb, EnvGolden, EnvSnapshot, HookContext, HookRunner, LaunchReport,
+    LaunchTimer, Launcher, OomWatch, PipewireForce, SessionHistory, SessionRecord, SessionSummary,
+    archive_captures, exec_nested, export_telemetry, notify, outer_session, wait_for,
+};
+use nvprime::service::MANUAL_GPU_PROFILE;
+use std::io::{BufRead, IsTerminal, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 use zbus::Connection;
 
+#[derive(Parser)]
+#[command(name = "nvprime", version, about)]
+struct Cli {
+    /// Print how long each step of a launch took
+    #[arg(long)]
+    timing: bool,
+
+    /// Print how the game command was understood and exit without running it
+    #[arg(long)]
+    print_command: bool,
+
+    /// Tune the GPU even when something else already keeps it busy
+    #[arg(long)]
+    force: bool,
+
+    /// Set a variable for this launch only, over anything the config sets.
+    /// Can be given more than once.
+    #[arg(short = 'E', long = "env", value_name = "KEY=VALUE", value_parser = parse_env)]
+    env: Vec<(String, String)>,
+
+    /// Keep the config, logs, history and caches next to the nvprime
+    /// executable, like NVPRIME_PORTABLE=1
+    #[arg(long)]
+    portable: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print a config section reproducing the environment of a running game
+    Snapshot {
+        /// PID of the running game process
+        pid: u32,
+
+        /// Section name to use instead of the detected executable name
+        #[arg(long)]
+        name: Option<String>,
+    },
+
+    /// Check the environment resolved for a game against its stored snapshot
+    EnvSnapshot {
+        /// Game name as used in `[game.<name>]`
+        game: String,
+
+        /// Store the current environment as the snapshot instead of checking it
+        #[arg(long)]
+        write: bool,
+    },
+
+    /// Compare the latest session of a game with its last working one
+    DiffSessions {
+        /// Game name as used in `[game.<name>]`
+        game: String,
+    },
+
+    /// Play the game at several power limits and recommend one for its config
+    Autotune {
+        /// Power limits to try, e.g. `200W,250W,300W`
+        #[arg(long, required = true, value_delimiter = ',', value_parser = parse_limit)]
+        limits: Vec<u32>,
+
+        /// Seconds to play at each power limit
+        #[arg(long, default_value_t = 60)]
+        segment_secs: u64,
+
+        /// What the recommended limit should optimize for
+        #[arg(long, value_enum, default_value_t = AutotuneGoal::Efficiency)]
+        goal: AutotuneGoal,
+
+        /// Write the recommendation without asking for confirmation
+        #[arg(long, short)]
+        yes: bool,
+
+        /// Game command line (`nvprime autotune --limits ... %command%`)
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+
+    /// Check a game's Wine prefix for the components listed in its `requires`
+    VerifyPrefix {
+        /// Game name as used in `[game.<name>]`
+        game: String,
+
+        /// Prefix to check instead of the one detected for the game
+        #[arg(long)]
+        prefix: Option<PathBuf>,
+
+        /// Install missing components with protontricks or winetricks
+        #[arg(long)]
+        install: bool,
+
+        /// Steam app ID, installs through protontricks instead of winetricks
+        #[arg(long)]
+        app_id: Option<String>,
+    },
+
+    /// Show known workarounds for a game and optionally add them to the config
+    Suggest {
+        /// Game name as used in `[game.<name>]`
+        game: String,
+
+        /// Steam app ID, defaults to the one Steam passes in the environment
+        #[arg(long)]
+        app_id: Option<u32>,
+
+        /// Add the suggested variables to the game's environment group
+        #[arg(long)]
+        apply: bool,
+
+        /// Apply without asking for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+
+    /// Apply the GPU and CPU tuning of `[game.<name>]` for an app not started
+    /// through nvprime, e.g. Blender, until it is released again
+    ApplyProfile {
+        /// Profile name as used in `[game.<name>]`
+        name: String,
+    },
+
+    /// Apply the global tuning for a while without a game, e.g. for an
+    /// emulator started elsewhere. `release-profile boost` ends it early.
+    Boost {
+        /// How long the tuning stays applied
+        #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..))]
+        minutes: u32,
+    },
+
+    /// Release a profile taken with `apply-profile`
+    ReleaseProfile {
+        /// Profile name as used in `[game.<name>]`
+        name: String,
+    },
+
+    /// Reset the tuning of your sessions, e.g. after a crash left it applied
+    Reset {
+        /// Discard every session of every user, even those the daemon thinks
+        /// are still running, and restore all defaults. Needs root.
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Show the GPU's performance state and PCIe link
+    Status,
+
+    /// Look for common reasons tuning does not help
+    Doctor,
+
+    /// Manage the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// Restore GPU settings through the daemon, with or without a session
+    Gpu {
+        #[command(subcommand)]
+        command: GpuCommand,
+    },
+
+    /// Restore CPU settings through the daemon, with or without a session
+    Cpu {
+        #[command(subcommand)]
+        command: CpuCommand,
+    },
+
+    /// Control the MangoHud overlay of a running game
+    Hud {
+        #[command(subcommand)]
+        command: HudAction,
+
+        /// Only the game started by this PID, every game otherwise
+        #[arg(long)]
+        pid: Option<u32>,
+    },
+
+    /// Anything else is treated as the game command line (`nvprime %command%`)
+    #[command(external_subcommand)]
+    Run(Vec<String>),
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Edit the config in `$EDITOR`, only saving it once it is valid
+    Edit {
+        /// Open the editor at this game's `[game.<name>]` section
+        game: Option<String>,
+    },
+
+    /// Check that the config parses and does not contradict itself
+    Check,
+}
+
+#[derive(Subcommand)]
+enum GpuCommand {
+    /// Unlock clocks another tool left pinned
+    ResetClocks,
+
+    /// Restore the default power limit
+    ResetPower,
+}
+
+#[derive(Subcommand)]
+enum HudAction {
+    /// Show or hide the overlay
+    Toggle,
+
+    /// Switch to the preset with every metric
+    Detailed,
+}
+
+#[derive(Subcommand)]
+enum CpuCommand {
+    /// Set the EPP hint back to `amd_epp_base`
+    ResetEpp,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    logging::init(true)?;
+    logging::init(LevelFilter::Info);
+
+    let cli = Cli::parse();
+    if cli.portable {
+        paths::enable_portable()?;
+    }
+
+    match cli.command {
+        Command::Snapshot { pid, name } => snapshot(pid, name),
+        Command::EnvSnapshot { game, write } => env_snapshot(&game, write),
+        Command::DiffSessions { game } => diff_sessions(&game),
+        Command::Autotune {
+            limits,
+            segment_secs,
+            goal,
+            yes,
+            command,
+        } => autotune(command, limits, segment_secs, goal, yes).await,
+        Command::VerifyPrefix {
+            game,
+            prefix,
+            install,
+            app_id,
+        } => verify_prefix(&game, prefix, install, app_id),
+        Command::Suggest {
+            game,
+            app_id,
+            apply,
+            yes,
+        } => suggest(&game, app_id, apply, yes),
+        Command::ApplyProfile { name } => apply_profile(&name).await,
+        Command::Boost { minutes } => boost(minutes).await,
+        Command::ReleaseProfile { name } => release_profile(&name).await,
+        Command::Reset { force } => reset(force).await,
+        Command::Status => status(),
+        Command::Doctor => doctor().await,
+        Command::Config {
+            command: ConfigCommand::Edit { game },
+        } => config_edit(game.as_deref()),
+        Command::Config {
+            command: ConfigCommand::Check,
+        } => config_check(),
+        Command::Gpu { command } => gpu(command).await,
+        Command::Cpu { command } => cpu(command).await,
+        Command::Hud { command, pid } => hud(command, pid),
+        Command::Run(args) if cli.print_command => print_command(args, &cli.env),
+        Command::Run(args) => run(args, cli.timing, cli.force, &cli.env).await,
+    }
+}
+
+fn parse_limit(value: &str) -> Result<u32, String> {
+    nvprime::common::config::parse_power_limit(value)
+}
+
+fn parse_env(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, val)) if !key.is_empty() && !key.contains(char::is_whitespace) => {
+            Ok((key.to_string(), val.to_string()))
+        }
+        _ => Err(format!("expected KEY=VALUE, got '{}'", value)),
+    }
+}
+
+fn snapshot(pid: u32, name: Option<String>) -> Result<()> {
+    let snapshot = EnvSnapshot::capture(pid, name)?;
+    let output = snapshot.to_toml();
+
+    if output.is_empty() {
+        info!("Environment of PID {} matches nvprime defaults", pid);
+    } else {
+        print!("{}", output);
+    }
+
+    Ok(())
+}
+
+fn env_snapshot(game: &str, write: bool) -> Result<()> {
+    let config = Config::load()?;
+    let current = EnvGolden::resolve(&config, game);
+    let path = EnvGolden::path(&config.paths()?, game);
+
+    if write {
+        current.write(&path)?;
+        println!(
+            "Wrote {} variables to {}",
+            current.vars.len(),
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let changes = current.verify(&EnvGolden::read(&path)?);
+    if changes.is_empty() {
+        println!("Environment of '{}' matches {}", game, path.display());
+        return Ok(());
+    }
+
+    println!("Environment of '{}' differs from {}:", game, path.display());
+    for change in &changes {
+        println!("  {}", change);
+    }
+    anyhow::bail!("{} variables changed", changes.len())
+}
+
+fn suggest(game: &str, app_id: Option<u32>, apply: bool, yes: bool) -> Result<()> {
+    let config = Config::load()?;
+    let app_id = app_id.or(SteamCompat::from_env().app_id);
+
+    let mut catalog = Catalog::builtin();
+    let mut updated = Vec::new();
+    if let Some(manifest) = updates::check(&config.updates, &config.paths()?.cache_dir) {
+        let defaults = manifest.changed_defaults();
+        if !defaults.is_empty() {
+            updated.push("Updated defaults".to_string());
+            catalog.workarounds.push(Workaround {
+                name: "Updated defaults".to_string(),
+                description: format!("Default changes from update {}", manifest.version),
+                exe: vec![game.to_string()],
+                app_id: Vec::new(),
+                env: defaults
+                    .into_iter()
+                    .map(|(key, val)| (key.to_string(), val.to_string()))
+                    .collect(),
+            });
+        }
+        updated.extend(catalog.merge(manifest.workarounds));
+    }
+
+    let workarounds = catalog.find(game, app_id);
+    if workarounds.is_empty() {
+        println!("No known workarounds for '{}'", game);
+        return Ok(());
+    }
+
+    let mut missing = std::collections::BTreeMap::new();
+    for workaround in &workarounds {
+        let note = if updated.contains(&workaround.name) {
+            " (update)"
+        } else {
+            ""
+        };
+        println!("{}{}: {}", workaround.name, note, workaround.description);
+
+        let needed = workaround.missing(&config, game);
+        for (key, val) in &workaround.env {
+            let note = if needed.contains_key(key.as_str()) {
+                ""
+            } else {
+                " (already set)"
+            };
+            println!("  {}={}{}", key, val, note);
+        }
+        missing.extend(needed);
+    }
+
+    if !apply {
+        return Ok(());
+    }
+    if missing.is_empty() {
+        println!("Every suggested variable is already in the config");
+        return Ok(());
+    }
+
+    if !yes
+        && !confirm(&format!(
+            "Add {} variables to [{}] in the config?",
+            missing.len(),
+            game
+        ))?
+    {
+        info!("Config left unchanged");
+        return Ok(());
+    }
+
+    let path = Config::path()?;
+    catalog::save_env(&path, game, &missing)?;
+    info!("Saved workarounds to {}", path.display());
+    Ok(())
+}
+
+/// Run the condition hooks on the daemon's `GpuCondition` signals, until the
+/// returned task is aborted
+async fn watch_conditions(
+    proxy: &NvPrimeClientProxy<'_>,
+    hooks: &HooksConfig,
+) -> Option<tokio::task::JoinHandle<()>> {
+    let mut conditions = match proxy.receive_gpu_condition().await {
+        Ok(conditions) => conditions,
+        Err(e) => {
+            warn!(
+                "Failed to watch GPU conditions, condition hooks are off: {}",
+                e
+            );
+            return None;
+        }
+    };
+
+    let hooks = hooks.clone();
+    Some(tokio::spawn(async move {
+        while let Some(signal) = conditions.next().await {
+            let Ok(args) = signal.args() else {
+                continue;
+            };
+            let (kind, temperature_c, threshold_c) =
+                (args.kind.clone(), args.temperature_c, args.threshold_c);
+
+            if hooks.hud_detailed_on.contains(&kind) {
+                let game = std::process::id();
+                if let Err(e) = mangohud::send(HudCommand::Detailed, Some(game)) {
+                    warn!("{:#}", e);
+                }
+            }
+
+            // Hooks block until they exit, e.g. a fan script
+            let hooks = hooks.clone();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) =
+                    HookRunner::new(&hooks).run_condition(&kind, temperature_c, threshold_c)
+                {
+                    error!("{:#}", e);
+                }
+            });
+        }
+    }))
+}
+
+async fn daemon_proxy() -> Result<NvPrimeClientProxy<'static>> {
+    let conn = Connection::system()
+        .await
+        .context("Failed to connect to system bus")?;
+    NvPrimeClientProxy::new(&conn)
+        .await
+        .context("Failed to create D-Bus proxy")
+}
+
+async fn apply_profile(name: &str) -> Result<()> {
+    let config = Config::load()?;
+    let game = match config.game.get(name) {
+        Some(game) => game.clone(),
+        None => {
+            warn!("No [game.{}] section, applying the global tuning", name);
+            Default::default()
+        }
+    };
+
+    let profile = serde_json::json!({
+        "cpu": config.cpu_for(&game),
+        "gpu": config.gpu_for(&game),
+    });
+    let refs = daemon_proxy()
+        .await?
+        .apply_profile(name.to_string(), profile.to_string())
+        .await
+        .context("Failed to apply profile")?;
+
+    println!("Applied profile '{}' ({} active)", name, refs);
+    Ok(())
+}
+
+async fn boost(minutes: u32) -> Result<()> {
+    let config = Config::load()?;
+    let game = GameConfig::default();
+    let profile = serde_json::json!({
+        "cpu": config.cpu_for(&game),
+        "gpu": config.gpu_for(&game),
+    });
+
+    let refs = daemon_proxy()
+        .await?
+        .boost(minutes, profile.to_string())
+        .await
+        .context("Failed to start boost")?;
+
+    println!("Boosting for {} minutes ({} active)", minutes, refs);
+    Ok(())
+}
+
+async fn release_profile(name: &str) -> Result<()> {
+    let refs = daemon_proxy()
+        .await?
+        .release_profile(name.to_string())
+        .await
+        .context("Failed to release profile")?;
+
+    if refs == 0 {
+        println!("Released profile '{}'", name);
+    } else {
+        println!("Profile '{}' is still held {} more times", name, refs);
+    }
+    Ok(())
+}
+
+async fn reset(force: bool) -> Result<()> {
+    let proxy = daemon_proxy().await?;
+
+    if !force {
+        proxy
+            .reset_tuning()
+            .await
+            .context("Failed to reset tuning")?;
+        println!("Tuning reset");
+        return Ok(());
+    }
+
+    let discarded = proxy
+        .force_reset_tuning()
+        .await
+        .context("Failed to force a reset, it needs root")?;
+    println!("Discarded {} sessions and restored all defaults", discarded);
+    Ok(())
+}
+
+fn init_nvml() -> Result<NvGpu> {
+    NvGpu::init(Config::load()?.gpu.gpu_uuid).context("Failed to initialize NVML")
+}
+
+fn link_info() -> Result<LinkInfo> {
+    init_nvml()?.link_info().context("Failed to read GPU state")
+}
+
+fn status() -> Result<()> {
+    let gpu = init_nvml()?;
+    let info = gpu.link_info().context("Failed to read GPU state")?;
+    let headroom = gpu.headroom().context("Failed to read GPU headroom")?;
+    let pstate = info
+        .pstate
+        .map_or_else(|| "unknown".to_string(), |p| format!("P{}", p));
+
+    println!("GPU:           {}", info.name);
+    println!(
+        "Driver:        {}",
+        driver_version_string().as_deref().unwrap_or("unknown")
+    );
+    println!("P-state:       {}", pstate);
+    println!(
+        "PCIe link:     gen {} x{} (max gen {} x{})",
+        info.pcie_gen, info.pcie_width, info.pcie_max_gen, info.pcie_max_width
+    );
+    println!(
+        "Resizable BAR: {} (BAR1 {}MiB)",
+        if info.resizable_bar() { "on" } else { "off" },
+        info.bar1_mib
+    );
+    println!(
+        "Power:         {}W of {}W limit ({}%){}",
+        headroom.power_mw / 1000,
+        headroom.power_limit_mw / 1000,
+        headroom.power_percent(),
+        headroom
+            .max_power_limit_mw
+            .map_or_else(String::new, |max| format!(", max {}W", max / 1000))
+    );
+    println!(
+        "Temperature:   {}°C{}",
+        headroom.temperature_c,
+        headroom
+            .slowdown_c
+            .map_or_else(String::new, |slowdown| format!(
+                ", slowdown at {}°C ({}°C left)",
+                slowdown,
+                slowdown.saturating_sub(headroom.temperature_c)
+            ))
+    );
+    println!(
+        "Clock:         {}MHz{}",
+        headroom.clock_mhz,
+        headroom
+            .max_clock_mhz
+            .zip(headroom.clock_percent())
+            .map_or_else(String::new, |(max, percent)| format!(
+                " of {}MHz max boost ({}%)",
+                max, percent
+            ))
+    );
+    println!("Headroom:      {}", headroom.verdict());
+
+    // Games are children of the nvprime session that launched them
+    for (pid, vram_mib) in gpu.process_vram().unwrap_or_default() {
+        if let Some(session) = proc::ancestor_named(pid, "nvprime") {
+            println!(
+                "Game VRAM:     {}MiB by {} ({}, session {})",
+                vram_mib,
+                proc::name(pid).as_deref().unwrap_or("unknown"),
+                pid,
+                session
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn doctor() -> Result<()> {
+    let mut problems = Vec::new();
+
+    if driver_version_string().is_none() {
+        problems.push("No NVIDIA driver is loaded".to_string());
+    }
+
+    let reachable = match daemon_proxy().await {
+        Ok(proxy) if proxy.ping().await.is_ok() => Some(DaemonApi::query(&proxy).await),
+        _ => None,
+    };
+    match reachable {
+        Some(api) => problems.extend(api.version_warning()),
+        None => problems.push("The nvprime daemon is not reachable, tuning is skipped".to_string()),
+    }
+
+    match link_info() {
+        Ok(info) => problems.extend(info.problems()),
+        Err(e) => problems.push(format!("{:#}", e)),
+    }
+
+    if problems.is_empty() {
+        println!("No problems found");
+    }
+    for problem in &problems {
+        println!("- {}", problem);
+    }
+    Ok(())
+}
+
+async fn gpu(command: GpuCommand) -> Result<()> {
+    let proxy = daemon_proxy().await?;
+
+    match command {
+        GpuCommand::ResetClocks => {
+            proxy
+                .reset_gpu_clocks()
+                .await
+                .context("Failed to reset GPU clocks")?;
+            println!("GPU clocks unlocked");
+        }
+        GpuCommand::ResetPower => {
+            proxy
+                .reset_gpu_tuning()
+                .await
+                .context("Failed to reset GPU power limit")?;
+            println!("GPU power limit restored");
+        }
+    }
+    Ok(())
+}
+
+async fn cpu(command: CpuCommand) -> Result<()> {
+    let proxy = daemon_proxy().await?;
+
+    match command {
+        CpuCommand::ResetEpp => {
+            let epp = Config::load()?.cpu.amd_epp_base;
+            proxy
+                .reset_epp(epp.clone())
+                .await
+                .context("Failed to reset CPU EPP")?;
+            println!("CPU EPP set to {}", epp);
+        }
+    }
+    Ok(())
+}
+
+fn hud(action: HudAction, pid: Option<u32>) -> Result<()> {
+    let command = match action {
+        HudAction::Toggle => HudCommand::Toggle,
+        HudAction::Detailed => HudCommand::Detailed,
+    };
+    let pids = mangohud::send(command, pid)?;
+    println!("Sent to MangoHud of PID {:?}", pids);
+    Ok(())
+}
+
+fn config_check() -> Result<()> {
+    let path = Config::path()?;
+    Config::load_file(path.clone())?.validate()?;
+    println!("{} is valid", path.display());
+    Ok(())
+}
 
-    let args: Vec<String> = std::env::args().skip(1).collect();
+fn config_edit(game: Option<&str>) -> Result<()> {
+    let draft = ConfigDraft::open(&Config::path()?)?;
 
-    if args.is_empty() {
-        error!("Usage: nvprime <executable> [args...]");
-        std::process::exit(1);
+    let line = match game {
+        Some(game) => {
+            let content = std::fs::read_to_string(draft.path())?;
+            let line = nvprime::runner::game_line(&content, game);
+            if line.is_none() {
+                warn!("No [game.{}] section in the config yet", game);
+            }
+            line
+        }
+        None => None,
+    };
+
+    loop {
+        draft.edit(line)?;
+
+        if !draft.is_changed()? {
+            println!("No changes made");
+            return draft.discard();
+        }
+
+        match draft.validate() {
+            Ok(_) => return draft.commit(),
+            Err(e) => {
+                println!("{}", e);
+                if !confirm("The config is not valid, edit it again?")? {
+                    anyhow::bail!(
+                        "Kept the live config, the changes are saved in {}",
+                        draft.path().display()
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn diff_sessions(game: &str) -> Result<()> {
+    let config = Config::load()?;
+    let diff = SessionHistory::open(&config.paths()?).diff_latest(game)?;
+
+    println!(
+        "Last working session started at {}, latest at {} exited with {}",
+        format_time(diff.good_started_ms),
+        format_time(diff.latest_started_ms),
+        diff.latest_exit_code
+    );
+
+    if diff.is_empty() {
+        println!("No differences in environment, config or driver version");
+        return Ok(());
+    }
+
+    if let Some(driver) = &diff.driver {
+        println!("\nDriver:\n  {}", driver);
+    }
+
+    for (title, changes) in [("Environment", &diff.env), ("Config", &diff.config)] {
+        if !changes.is_empty() {
+            println!("\n{}:", title);
+            for change in changes {
+                println!("  {}", change);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn verify_prefix(
+    game: &str,
+    prefix: Option<PathBuf>,
+    install: bool,
+    app_id: Option<String>,
+) -> Result<()> {
+    let config = Config::load()?;
+    let game_config = config
+        .game
+        .get(game)
+        .with_context(|| format!("No [game.{}] section in the config", game))?;
+    anyhow::ensure!(
+        !game_config.requires.is_empty(),
+        "[game.{}] has no `requires` list",
+        game
+    );
+
+    let prefix = prefix
+        .or_else(|| prefix::find_prefix(game_config, |key| std::env::var(key).ok()))
+        .context("No Wine prefix found, pass one with --prefix")?;
+
+    let mut check = PrefixCheck::run(&prefix, &game_config.requires);
+    println!("{}: {}", prefix.display(), check);
+
+    if install && !check.is_ok() {
+        prefix::install(&prefix, &check.missing, app_id.as_deref())?;
+        check = PrefixCheck::run(&prefix, &game_config.requires);
+        println!("{}: {}", prefix.display(), check);
+    }
+
+    anyhow::ensure!(check.is_ok(), "Prefix is missing required components");
+    Ok(())
+}
+
+fn format_time(timestamp_ms: u64) -> String {
+    chrono::DateTime::from_timestamp_millis(timestamp_ms as i64)
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|| timestamp_ms.to_string())
+}
+
+/// Log what Steam passed, so a broken command line can be told apart
+fn check_command(args: &[String], launcher: Option<&Launcher>) -> Result<()> {
+    let problem = match launcher {
+        None => command::diagnose(args),
+        Some(launcher) if launcher.game_config().backend == LaunchBackend::Exec => {
+            command::check_exec(launcher.exec(), std::env::var("PATH").ok().as_deref())
+        }
+        Some(_) => None,
+    };
+
+    if let Some(problem) = problem {
+        error!("Received command: {:?}", args);
+        anyhow::bail!("{}", problem);
+    }
+    Ok(())
+}
+
+fn print_command(args: Vec<String>, env: &[(String, String)]) -> Result<()> {
+    println!("Received:   {:?}", args);
+    check_command(&args, None)?;
+
+    let config = Config::load()?;
+    let mut launcher = Launcher::new(args.clone(), &config);
+    launcher.override_env(env);
+    let section = if config.game.contains_key(launcher.game_name()) {
+        format!("[game.{}]", launcher.game_name())
+    } else {
+        "none, defaults apply".to_string()
+    };
+
+    println!("Executable: {}", launcher.exec());
+    println!("Arguments:  {:?}", launcher.args());
+    println!("Game:       {}", launcher.game_name());
+    println!("Section:    {}", section);
+    println!("Backend:    {:?}", launcher.game_config().backend);
+    if let Some(e) = launcher.driver_error() {
+        println!("Driver:     {}", e);
+    }
+    for (key, val) in launcher.env_overrides() {
+        println!("Override:   {}={}", key, val);
     }
+    check_command(&args, Some(&launcher))
+}
+
+async fn run(args: Vec<String>, timing: bool, force: bool, env: &[(String, String)]) -> Result<()> {
+    if let Some(outer) = outer_session(|key| std::env::var(key).ok()) {
+        return Err(exec_nested(&args, outer));
+    }
+    check_command(&args, None)?;
 
-    info!("Starting nvprime");
+    info!("Starting nvprime session {}", std::process::id());
+    let mut timer = LaunchTimer::start();
     let config = Config::load()?;
+    let paths = config.paths()?;
+    timer.lap("config");
 
     let conn = Connection::system()
         .await
@@ -25,31 +905,532 @@ async fn main() -> Result<()> {
     let proxy = NvPrimeClientProxy::new(&conn)
         .await
         .context("Failed to create D-Bus proxy")?;
+    let api = DaemonApi::query(&proxy).await;
+    if let Some(warning) = api.version_warning() {
+        warn!("{}", warning);
+    }
+    timer.lap("connect");
+
+    // Nothing is tuned yet, a hook aborting needs no cleanup
+    let mut hooks = HookRunner::new(&config.hook);
+    hooks.set_strict(config.sys.strict);
+    hooks.run(HookContext::PreEnv { command: &args })?;
+
+    let mut launcher = Launcher::new(args.clone(), &config);
+    launcher.override_env(env);
+    check_command(&args, Some(&launcher))?;
+    if let Some(e) = launcher.driver_error() {
+        anyhow::bail!("{}", e);
+    }
+    hooks.run(HookContext::PostEnv {
+        game: launcher.game_name(),
+        env: launcher.env_vars(),
+    })?;
+    timer.lap("env");
+
+    match prefix::preflight(launcher.game_config()) {
+        Ok(Some(check)) if check.is_ok() => info!("Wine prefix: {}", check),
+        Ok(Some(check)) if launcher.game_config().strict => {
+            anyhow::bail!("Wine prefix is {}", check)
+        }
+        Ok(Some(check)) => warn!("Wine prefix is {}", check),
+        Ok(None) => {}
+        Err(e) if launcher.game_config().strict => return Err(e),
+        Err(e) => error!("{:#}", e),
+    }
+    timer.lap("prefix");
+
+    let game_config = launcher.game_config();
+    if !game_config.wait_for.is_empty() {
+        wait_for(
+            &game_config.wait_for,
+            Duration::from_secs(game_config.wait_timeout_sec),
+        )?;
+        timer.lap("wait");
+    }
+
+    let mut sys = config.sys_for(launcher.game_config());
+    let strict = sys.strict;
+    if strict {
+        info!("Strict mode enabled, any tuning failure aborts the launch");
+    }
+
+    let mut cpu = config.cpu_for(launcher.game_config());
+    let mut gpu = config.gpu_for(launcher.game_config());
+    let mut memory_protect_gb = launcher.game_config().memory_protect_gb;
+    for dropped in api.adapt(&mut cpu, &mut gpu, &mut sys, &mut memory_protect_gb) {
+        warn!("Skipping {}", dropped);
+    }
+
+    if gpu.enabled && !force {
+        check_gpu_busy(&gpu, strict)?;
+        timer.lap("gpu-load");
+    }
+
+    if let Some(threshold_mib) = launcher.game_config().vram_pressure_mib {
+        check_vram_pressure(threshold_mib, launcher.game_config(), gpu.gpu_uuid.clone()).await;
+        timer.lap("vram");
+    }
 
     let tuning_config = serde_json::json!({
-        "cpu": config.cpu,
-        "gpu": config.gpu,
-        "sys": config.sys,
+        "cpu": cpu,
+        "gpu": gpu,
+        "sys": sys,
+        "mounts": launcher.game_config().mount,
+        "inhibit_sleep": launcher.game_config().inhibit_sleep,
+        "oom_protect": launcher.game_config().oom_protect,
+        "oom_sacrifice": launcher.game_config().oom_sacrifice,
+        "memory_protect_gb": memory_protect_gb,
+        "temp_alert_c": config.hook.on_temp_above.as_ref().map(|hook| hook.temp),
+        "game": launcher.game_name(),
+        "game_exe": launcher.exe_name(),
     });
 
     let config_json =
         serde_json::to_string(&tuning_config).context("Failed to serialize config")?;
 
     let pid = std::process::id();
+    let started_ms = telemetry::now_ms();
 
-    proxy
-        .apply_tuning(pid, config_json)
+    let applied = match api.apply_tuning(&proxy, pid, config_json).await {
+        Ok(applied) => applied,
+        Err(e) => {
+            error!("Failed to apply tuning: {}", e);
+            abort_launch(&api, &proxy, pid).await;
+        }
+    };
+    for change in &applied.policy {
+        warn!("Daemon policy: {}", change);
+    }
+
+    if api.version >= 2 {
+        info!("Applied tuning: {}", applied);
+        let missing = applied.verify(&cpu, &gpu, &sys, pid);
+        for problem in &missing {
+            warn!("Tuning not applied: {}", problem);
+        }
+        if strict && !missing.is_empty() {
+            error!("Strict mode needs every part of the tuning applied");
+            abort_launch(&api, &proxy, pid).await;
+        }
+    } else {
+        info!("Applied tuning configuration");
+    }
+    timer.lap("daemon");
+
+    hooks.set_strict(strict);
+    if let Err(e) = hooks.run(HookContext::PreSpawn {
+        game: launcher.game_name(),
+    }) {
+        error!("{:#}", e);
+        abort_launch(&api, &proxy, pid).await;
+    }
+    timer.lap("hooks");
+
+    // logind's idle lock taken by the daemon is lost on some compositors
+    #[cfg(feature = "wayland")]
+    let idle_inhibitor = launcher
+        .game_config()
+        .inhibit_sleep
+        .then(nvprime::runner::WaylandIdleInhibitor::start)
+        .flatten();
+
+    let pipewire_force = PipewireForce::apply(&launcher.game_config().audio);
+    let do_not_disturb = launcher
+        .game_config()
+        .do_not_disturb
+        .then(DoNotDisturb::enable)
+        .flatten();
+
+    let condition_hooks = if hooks.has_condition_hooks() {
+        watch_conditions(&proxy, &config.hook).await
+    } else {
+        None
+    };
+
+    let report = LaunchReport::new(&launcher, &args, started_ms)
+        .with_tuning(
+            tuning_config,
+            serde_json::json!({ "applied": true, "policy": applied.policy, "result": applied }),
+        )
+        .with_timings(timer.phases());
+    match report.write(&paths.sessions_dir()) {
+        Ok(path) => info!("Launch report written to {}", path.display()),
+        Err(e) => warn!("{:#}", e),
+    }
+
+    let oom_watch = OomWatch::start();
+    let game = launcher.game_name().to_string();
+    let exit_code = launcher.execute(|pid| {
+        if let Err(e) = hooks.run(HookContext::PostSpawn { game: &game, pid }) {
+            error!("{:#}", e);
+        }
+    })?;
+
+    if let Some(task) = condition_hooks {
+        task.abort();
+    }
+
+    #[cfg(feature = "wayland")]
+    drop(idle_inhibitor);
+    drop(pipewire_force);
+    drop(do_not_disturb);
+
+    if let Some(spawn_time) = launcher.spawn_time() {
+        timer.record("spawn", spawn_time);
+    }
+    if timing {
+        timer.log();
+    }
+
+    let record = SessionRecord::new(
+        launcher.game_name(),
+        started_ms,
+        exit_code,
+        launcher.game_config().is_allowed_exit(exit_code),
+        launcher.env_vars(),
+        &serde_json::json!({
+            "cpu": config.cpu_for(launcher.game_config()),
+            "gpu": config.gpu_for(launcher.game_config()),
+            "sys": sys,
+            "game": launcher.game_config(),
+        }),
+    )
+    .with_timings(timer.phases())
+    .with_env_overrides(launcher.env_overrides());
+    let history = SessionHistory::open(&paths);
+    if let Err(e) = history.record(&record) {
+        error!("Failed to record session history: {:#}", e);
+    }
+
+    let samples = fetch_telemetry(&proxy, started_ms).await;
+    if let Some(format) = sys.telemetry_export {
+        match export_telemetry(
+            history.dir(),
+            launcher.game_name(),
+            started_ms,
+            format,
+            &samples,
+        ) {
+            Ok(path) => info!("Session telemetry written to {}", path.display()),
+            Err(e) => error!("Failed to export telemetry: {:#}", e),
+        }
+    }
+
+    let summary = SessionSummary::new(launcher.game_name(), exit_code, started_ms)
+        .with_frametime(launcher.game_config(), &samples)
+        .with_throttle(&samples)
+        .with_vram(&samples)
+        .with_oom_kills(oom_watch.map_or(0, |w| w.kills(exit_code)))
+        .with_warnings(launcher.path_warnings());
+    summary.log();
+
+    if summary.oom_killed() {
+        let body = format!(
+            "'{}' was killed by the kernel because the system ran out of memory",
+            launcher.game_name()
+        );
+        if let Err(e) = notify("Game ran out of memory", &body).await {
+            warn!("{:#}", e);
+        }
+    }
+
+    match summary.write_json() {
+        Ok(Some(path)) => info!("Session summary written to {}", path.display()),
+        Ok(None) => {}
+        Err(e) => error!("{:#}", e),
+    }
+
+    if let Some(dir) = launcher.capture_dir()
+        && launcher.game_config().capture.archive
+        && let Err(e) = archive_captures(dir, launcher.game_config(), started_ms)
+    {
+        error!("Failed to archive captures: {:#}", e);
+    }
+
+    if let Err(e) = hooks.run(HookContext::PostExit {
+        game: &game,
+        exit_code,
+    }) {
+        error!("{:#}", e);
+    }
+
+    if let Err(e) = api.end_session(&proxy, pid).await {
+        error!("Failed to end session: {}", e);
+    }
+
+    std::process::exit(exit_code);
+}
+
+async fn autotune(
+    args: Vec<String>,
+    limits: Vec<u32>,
+    segment_secs: u64,
+    goal: AutotuneGoal,
+    yes: bool,
+) -> Result<()> {
+    anyhow::ensure!(
+        limits.len() >= 2,
+        "Autotune needs at least two power limits"
+    );
+
+    let config = Config::load()?;
+    let conn = Connection::system()
+        .await
+        .context("Failed to connect to system bus")?;
+    let proxy = NvPrimeClientProxy::new(&conn)
+        .await
+        .context("Failed to create D-Bus proxy")?;
+
+    let mut launcher = Launcher::new(args, &config);
+    let game = launcher.game_name().to_string();
+
+    // The session asks for no limit, each segment's goes through
+    // ApplyGpuTuning and is released at the end
+    let mut gpu = config.gpu.clone();
+    gpu.enabled = true;
+    gpu.set_max_pwr = false;
+    gpu.pwr_limit_tune = None;
+
+    let tuning_config = serde_json::json!({
+        "cpu": config.cpu_for(launcher.game_config()),
+        "gpu": gpu,
+        "sys": config.sys,
+    });
+    let api = DaemonApi::query(&proxy).await;
+    api.apply_tuning(&proxy, std::process::id(), tuning_config.to_string())
         .await
         .context("Failed to apply tuning")?;
 
-    info!("Applied tuning configuration");
+    let started_ms = telemetry::now_ms();
+    launcher.spawn()?;
 
-    let mut launcher = Launcher::new(args, &config);
-    let exit_code = launcher.execute()?;
+    info!(
+        "Autotuning '{}' over {} limits, {}s each, keep playing a representative scene",
+        game,
+        limits.len(),
+        segment_secs
+    );
+
+    let mut segments = Vec::new();
+    let mut exit_code = None;
+    for limit_mw in limits {
+        gpu.pwr_limit_tune = Some(limit_mw);
+        let gpu_json = serde_json::to_string(&gpu).context("Failed to serialize config")?;
+        if let Err(e) = proxy.apply_gpu_tuning(gpu_json).await {
+            error!("Failed to set power limit: {}", e);
+            break;
+        }
+
+        info!("Playing at {}", autotune::format_limit(limit_mw));
+        let start_ms = telemetry::now_ms();
+        let deadline = start_ms + segment_secs * 1000;
+        while exit_code.is_none() && telemetry::now_ms() < deadline {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            exit_code = launcher.try_wait()?;
+        }
 
-    if let Err(e) = proxy.reset_tuning().await {
-        error!("Failed to reset tuning: {}", e);
+        segments.push(Segment {
+            limit_mw,
+            start_ms,
+            end_ms: telemetry::now_ms(),
+        });
+
+        if exit_code.is_some() {
+            warn!("Game exited before all limits were tried");
+            break;
+        }
     }
 
-    std::process::exit(exit_code);
+    // Older daemons applied the limit without holding it
+    if api.version >= 3
+        && let Err(e) = proxy.release_profile(MANUAL_GPU_PROFILE.to_string()).await
+    {
+        error!("Failed to release the autotune power limit: {}", e);
+    }
+    if let Err(e) = api.end_session(&proxy, std::process::id()).await {
+        error!("Failed to end session: {}", e);
+    }
+
+    if exit_code.is_none() {
+        info!("Autotune finished, quit the game to see the results");
+        launcher.wait()?;
+    }
+
+    let samples = fetch_telemetry(&proxy, started_ms).await;
+    let frames = autotune::read_frames(launcher.game_config(), started_ms)?;
+    let results = autotune::evaluate(&segments, &frames, &samples);
+
+    for result in &results {
+        info!("{}", result);
+    }
+
+    let Some(best) = autotune::recommend(&results, goal) else {
+        anyhow::bail!("Not enough data collected to make a recommendation");
+    };
+
+    let limit = autotune::format_limit(best.limit_mw);
+    info!("Recommended power limit for '{}': {}", game, limit);
+
+    if !yes
+        && !confirm(&format!(
+            "Write pwr_limit_tune = \"{}\" to [game.{}]?",
+            limit, game
+        ))?
+    {
+        info!("Config left unchanged");
+        return Ok(());
+    }
+
+    let path = Config::path()?;
+    autotune::save_power_limit(&path, &game, best.limit_mw)?;
+    info!("Saved recommendation to {}", path.display());
+    Ok(())
+}
+
+fn confirm(prompt: &str) -> Result<bool> {
+    print!("{} [y/N] ", prompt);
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Warn before tuning a GPU that something else already keeps busy. Asks
+/// when run from a terminal, strict mode refuses without `--force`.
+fn check_gpu_busy(gpu: &GpuTune, strict: bool) -> Result<()> {
+    if gpu.busy_util_percent.is_none() && gpu.busy_vram_percent.is_none() {
+        return Ok(());
+    }
+
+    let load = match NvGpu::init(gpu.gpu_uuid.clone()).and_then(|nvgpu| nvgpu.load()) {
+        Ok(load) => load,
+        Err(e) => {
+            warn!("Failed to check the GPU load: {}", e);
+            return Ok(());
+        }
+    };
+
+    let reasons = load.busy_reasons(gpu.busy_util_percent, gpu.busy_vram_percent);
+    if reasons.is_empty() {
+        return Ok(());
+    }
+    for reason in &reasons {
+        warn!("{}", reason);
+    }
+    if !load.processes.is_empty() {
+        let processes: Vec<String> = load
+            .processes
+            .iter()
+            .map(|process| process.to_string())
+            .collect();
+        warn!("Already running on the GPU: {}", processes.join(", "));
+    }
+
+    if std::io::stdin().is_terminal() {
+        anyhow::ensure!(
+            confirm("The GPU is already busy, tune it anyway?")?,
+            "Launch cancelled, the GPU is busy"
+        );
+    } else if strict {
+        anyhow::bail!("The GPU is busy, run with --force to tune it anyway");
+    }
+    Ok(())
+}
+
+/// Point out what holds VRAM before a heavy game starts, so it can be closed
+/// first. With `vram_pressure_signal` the user's own holders get that signal.
+async fn check_vram_pressure(threshold_mib: u32, game: &GameConfig, gpu_uuid: Option<String>) {
+    let load = match NvGpu::init(gpu_uuid).and_then(|nvgpu| nvgpu.load()) {
+        Ok(load) => load,
+        Err(e) => {
+            warn!("Failed to check VRAM use: {}", e);
+            return;
+        }
+    };
+
+    let held_mib = load.process_vram_mib();
+    if held_mib < u64::from(threshold_mib) {
+        debug!("Other processes hold {}MiB of VRAM", held_mib);
+        return;
+    }
+
+    let holders: Vec<String> = load.vram_holders().iter().map(|p| p.to_string()).collect();
+    warn!(
+        "Other processes hold {}MiB of VRAM: {}",
+        held_mib,
+        holders.join(", ")
+    );
+    if let Err(e) = notify(
+        "VRAM already in use",
+        &format!("{}MiB held by {}", held_mib, holders.join(", ")),
+    )
+    .await
+    {
+        warn!("{:#}", e);
+    }
+
+    let Some(name) = &game.vram_pressure_signal else {
+        return;
+    };
+    if game.vram_pressure_targets.is_empty() {
+        warn!("vram_pressure_signal is set but vram_pressure_targets names no process");
+        return;
+    }
+    let signal = match Signal::from_str(name) {
+        Ok(signal) => signal,
+        Err(_) => {
+            warn!("Unknown vram_pressure_signal '{}'", name);
+            return;
+        }
+    };
+
+    let uid = nix::unistd::getuid().as_raw();
+    for process in load.vram_holders() {
+        let owned =
+            std::fs::metadata(format!("/proc/{}", process.pid)).is_ok_and(|meta| meta.uid() == uid);
+        if !owned
+            || process.pid == std::process::id()
+            || !game.vram_pressure_targets.contains(&process.name)
+        {
+            continue;
+        }
+        match kill(Pid::from_raw(process.pid as i32), signal) {
+            Ok(()) => info!("Sent {} to {}", signal, process),
+            Err(e) => warn!("Failed to send {} to {}: {}", signal, process, e),
+        }
+    }
+}
+
+/// Restore whatever was applied so far and exit without launching the game
+async fn abort_launch(api: &DaemonApi, proxy: &NvPrimeClientProxy<'_>, pid: u32) -> ! {
+    error!("Aborting launch");
+
+    if let Err(e) = api.end_session(proxy, pid).await {
+        error!("Failed to end session: {}", e);
+    }
+
+    std::process::exit(1);
+}
+
+async fn fetch_telemetry(proxy: &NvPrimeClientProxy<'_>, since_ms: u64) -> Vec<TelemetrySample> {
+    let json = match proxy.get_telemetry(since_ms).await {
+        Ok(json) => json,
+        Err(e) => {
+            error!("Failed to fetch telemetry: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut samples: Vec<TelemetrySample> = serde_json::from_str(&json).unwrap_or_else(|e| {
+        error!("Invalid telemetry from daemon: {}", e);
+        Vec::new()
+    });
+
+    // The VRAM of other sessions' games is theirs to report
+    for sample in &mut samples {
+        sample.retain_session(std::process::id());
+    }
+    samples
 }
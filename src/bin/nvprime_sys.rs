@@ -1,44 +1,141 @@
 use anyhow::{Context, Result};
-use log::{error, info};
-use nvprime::common::{Config, ipc::NvPrimeService, logging};
-use nvprime::service::DaemonState;
+use clap::Parser;
+use log::{LevelFilter, debug, error, info, warn};
+use nvprime::common::ipc::{self, NvPrimeService};
+use nvprime::common::{Config, config::DaemonConfig, logging};
+use nvprime::service::{
+    DaemonPolicy, DaemonState, HANDOFF_FILE, Handoff, start_dynamic_epp, start_pid_watchdog,
+    start_scheduler, start_telemetry_sampler,
+};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use tokio::signal::unix::{SignalKind, signal};
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    logging::init(true).context("Failed to initialize logging")?;
+const TELEMETRY_INTERVAL_MS: u64 = 1000;
+const SCHEDULE_INTERVAL_SEC: u64 = 30;
+
+#[derive(Parser)]
+#[command(name = "nvprime-sys", version, about = "NvPrime system daemon")]
+struct Args {
+    /// Take over the sessions a previous instance left behind, e.g. when an
+    /// upgrade restarted the daemon while a game was running
+    #[arg(long)]
+    adopt: bool,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(LevelFilter::Debug);
 
     info!("Starting nvprime system daemon");
 
-    let config = Config::load().context("Failed to load configuration")?;
+    let config_path = Config::path()?;
+    let config = Config::load_file(config_path.clone()).context("Failed to load configuration")?;
+
+    // Must happen before the runtime spawns any threads that could read the environment
+    apply_daemon_env(&config.daemon);
+
+    tokio::runtime::Runtime::new()
+        .context("Failed to start async runtime")?
+        .block_on(serve(config, config_path, args.adopt))
+}
 
-    let state = Arc::new(Mutex::new(DaemonState::new()));
+fn apply_daemon_env(daemon: &DaemonConfig) {
+    for (key, value) in &daemon.env {
+        let value = value.to_string();
+        debug!("Daemon ENV: '{}' with '{}'", key, value);
+        // SAFETY: called from main before any other thread exists
+        unsafe { std::env::set_var(key, value) };
+    }
+}
+
+async fn serve(config: Config, config_path: PathBuf, adopt: bool) -> Result<()> {
+    // What the next reload is compared against
+    let mut loaded = std::fs::read_to_string(&config_path).unwrap_or_default();
+
+    let mut state = DaemonState::new();
+    state.policy = DaemonPolicy::load().context("Failed to load daemon policy")?;
+    let (power_tx, power_rx) = tokio::sync::mpsc::unbounded_channel();
+    state.power_changes = Some(power_tx);
+    let (condition_tx, condition_rx) = tokio::sync::mpsc::unbounded_channel();
+    state.condition_events = Some(condition_tx);
+    #[cfg(feature = "mqtt")]
+    if config.daemon.mqtt.broker.is_some() {
+        let (event_tx, event_rx) = tokio::sync::mpsc::unbounded_channel();
+        state.session_events = Some(event_tx);
+        nvprime::service::mqtt::start_publisher(config.daemon.mqtt.clone(), event_rx);
+    }
+    #[cfg(not(feature = "mqtt"))]
+    if config.daemon.mqtt.broker.is_some() {
+        warn!("Ignoring [daemon.mqtt], nvprime-sys was built without the mqtt feature");
+    }
+    let state = Arc::new(Mutex::new(state));
 
     if config.gpu.enabled {
         let mut state_lock = state.lock().unwrap();
         state_lock
-            .init_gpu(config.gpu.gpu_uuid.clone())
+            .init_gpu(config.gpu.gpu_uuid.clone(), config.daemon.nvml_retry())
             .context("Failed to initialize GPU")?;
     }
 
+    let handoff_path = PathBuf::from(HANDOFF_FILE);
+    state.lock().unwrap().handoff_path = Some(handoff_path.clone());
+    if adopt {
+        adopt_sessions(&state, &handoff_path, config.sys.watchdog_interval_sec).await;
+    } else if handoff_path.exists() {
+        warn!(
+            "Not adopting the sessions in {}, start with --adopt to take them over",
+            handoff_path.display()
+        );
+    }
+
+    if config.gpu.enabled {
+        start_telemetry_sampler(Arc::clone(&state), TELEMETRY_INTERVAL_MS);
+        start_dynamic_epp(Arc::clone(&state));
+    }
+
+    // Always running, a reload may bring in profiles
+    info!("Starting scheduler with {} profiles", config.schedule.len());
+    state
+        .lock()
+        .unwrap()
+        .load_schedule(config.schedule.clone(), &config.cpu.amd_epp_base);
+    start_scheduler(Arc::clone(&state), SCHEDULE_INTERVAL_SEC);
+
     let service = NvPrimeService::new(Arc::clone(&state));
 
-    let _conn = zbus::connection::Builder::system()?
+    let conn = zbus::connection::Builder::system()?
         .name("com.github.nvprime")?
-        .serve_at("/com/github/nvprime", service)?
+        .serve_at(ipc::SERVICE_PATH, service)?
         .build()
         .await?;
+    tokio::spawn(ipc::emit_power_changes(conn.clone(), power_rx));
+    tokio::spawn(ipc::emit_gpu_conditions(conn.clone(), condition_rx));
 
     info!("D-Bus service started on system bus");
     info!("Waiting for requests...");
 
     let mut sigterm = signal(SignalKind::terminate())?;
     let mut sigint = signal(SignalKind::interrupt())?;
+    let mut sighup = signal(SignalKind::hangup())?;
 
-    tokio::select! {
-        _ = sigterm.recv() => info!("Received SIGTERM, shutting down"),
-        _ = sigint.recv() => info!("Received SIGINT, shutting down"),
+    loop {
+        tokio::select! {
+            _ = sighup.recv() => {
+                info!("Received SIGHUP, reloading configuration");
+                if let Err(e) = reload(&state, &conn, &config_path, &mut loaded).await {
+                    error!("Failed to reload configuration: {:#}", e);
+                }
+            }
+            _ = sigterm.recv() => {
+                info!("Received SIGTERM, shutting down");
+                break;
+            }
+            _ = sigint.recv() => {
+                info!("Received SIGINT, shutting down");
+                break;
+            }
+        }
     }
 
     info!("Restoring system defaults...");
@@ -52,7 +149,100 @@ async fn main() -> Result<()> {
         error!("Failed to restore CPU defaults: {}", e);
     }
 
+    if let Err(e) = state_lock.restore_net_defaults() {
+        error!("Failed to restore network defaults: {}", e);
+    }
+
+    if let Err(e) = state_lock.restore_cpu_isolation() {
+        error!("Failed to restore background CPUs: {}", e);
+    }
+
+    if let Err(e) = state_lock.restore_swap_tuning() {
+        error!("Failed to restore swap settings: {}", e);
+    }
+
+    if let Err(e) = state_lock.release_all_mounts() {
+        error!("Failed to release mounts: {}", e);
+    }
+
+    if let Err(e) = state_lock.restore_all_oom_scores() {
+        error!("Failed to restore OOM scores: {}", e);
+    }
+
+    if let Err(e) = state_lock.release_all_oom_avoidance() {
+        error!("Failed to release OOM killer exclusions: {}", e);
+    }
+
+    if let Err(e) = state_lock.release_all_memory_protection() {
+        error!("Failed to release memory protection: {}", e);
+    }
+
     info!("Shutdown complete");
 
     Ok(())
 }
+
+/// Resume managing the sessions of the previous instance whose games still
+/// run, restoring the defaults for those that ended in between
+async fn adopt_sessions(state: &Arc<Mutex<DaemonState>>, path: &Path, watchdog_interval_sec: u64) {
+    let handoff = match Handoff::load(path) {
+        Ok(Some(handoff)) => handoff,
+        Ok(None) => {
+            info!("No sessions to adopt");
+            return;
+        }
+        Err(e) => {
+            error!("{:#}, not adopting sessions", e);
+            return;
+        }
+    };
+
+    let keys = handoff.adopt(&mut state.lock().unwrap());
+    for key in keys {
+        start_pid_watchdog(Arc::clone(state), key, watchdog_interval_sec).await;
+    }
+}
+
+/// Pick up a changed config and policy, then tell clients which sections
+/// changed. A config that does not parse leaves everything as it was.
+async fn reload(
+    state: &Mutex<DaemonState>,
+    conn: &zbus::Connection,
+    config_path: &Path,
+    loaded: &mut String,
+) -> Result<()> {
+    let content = std::fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read {}", config_path.display()))?;
+    let config: Config = toml::from_str(&content).context("Failed to parse configuration")?;
+    let policy = DaemonPolicy::load().context("Failed to load daemon policy")?;
+    let changed = Config::changed_sections(loaded, &content)?;
+
+    {
+        let mut state = state.lock().unwrap();
+        state.policy = policy;
+        state.load_schedule(config.schedule, &config.cpu.amd_epp_base);
+    }
+
+    // Read once at startup, before the GPU and the environment are set up
+    let restart: Vec<&String> = changed
+        .iter()
+        .filter(|section| ["daemon", "gpu"].contains(&section.as_str()))
+        .collect();
+    if !restart.is_empty() {
+        warn!(
+            "Changes to {:?} take effect once the daemon restarts",
+            restart
+        );
+    }
+
+    let checksum = Config::checksum(&content);
+    info!(
+        "Reloaded configuration {}, changed: {:?}",
+        checksum, changed
+    );
+    *loaded = content;
+
+    ipc::emit_config_reloaded(conn, &checksum, &changed)
+        .await
+        .context("Failed to send ConfigReloaded signal")
+}
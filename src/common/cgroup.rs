@@ -0,0 +1,112 @@
+use anyhow::Context;
+use log::debug;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use std::path::{Path, PathBuf};
+
+use crate::common::proc::is_descendant;
+
+/// Root of the unified cgroup hierarchy
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Directory of the cgroup v2 a process runs in, `None` without cgroup v2
+pub fn cgroup_of(pid: u32) -> Option<PathBuf> {
+    let cgroup = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    Some(Path::new(CGROUP_ROOT).join(parse_cgroup_path(&cgroup)?.trim_start_matches('/')))
+}
+
+/// Directory of the current process's cgroup v2
+pub fn own_cgroup() -> Option<PathBuf> {
+    cgroup_of(std::process::id())
+}
+
+/// The cgroup v2 path from `/proc/<pid>/cgroup`, the `0::` entry
+pub fn parse_cgroup_path(cgroup: &str) -> Option<&str> {
+    cgroup.lines().find_map(|line| line.strip_prefix("0::"))
+}
+
+/// The cgroup of `pid` when it holds nothing but that process and its
+/// descendants, e.g. when the game was started with `systemd-run --scope`.
+/// A shared cgroup, like the one of a terminal or Steam, never empties
+/// when the game exits.
+pub fn session_cgroup(pid: u32) -> Option<PathBuf> {
+    let dir = cgroup_of(pid)?;
+    let procs = std::fs::read_to_string(dir.join("cgroup.procs")).ok()?;
+
+    let dedicated = procs
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .all(|p| is_descendant(p, pid));
+
+    dedicated.then_some(dir)
+}
+
+/// The `populated` flag from `cgroup.events`
+pub fn parse_populated(events: &str) -> Option<bool> {
+    events.lines().find_map(|line| match line.split_once(' ')? {
+        ("populated", value) => Some(value.trim() == "1"),
+        _ => None,
+    })
+}
+
+/// Block until no process is left in the cgroup. The kernel signals every
+/// change of `cgroup.events` through inotify, so this never polls.
+pub fn wait_until_empty(dir: &Path) -> anyhow::Result<()> {
+    let events = dir.join("cgroup.events");
+    let inotify = Inotify::init(InitFlags::IN_CLOEXEC).context("Failed to initialize inotify")?;
+    inotify
+        .add_watch(&events, AddWatchFlags::IN_MODIFY)
+        .with_context(|| format!("Failed to watch {}", events.display()))?;
+
+    loop {
+        // A removed cgroup cannot hold any process
+        let Ok(content) = std::fs::read_to_string(&events) else {
+            return Ok(());
+        };
+        if parse_populated(&content) != Some(true) {
+            debug!("{} is no longer populated", dir.display());
+            return Ok(());
+        }
+
+        inotify
+            .read_events()
+            .context("Failed to read inotify events")?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cgroup_path() {
+        let cgroup = "0::/user.slice/user-1000.slice/app-steam.scope\n";
+        assert_eq!(
+            parse_cgroup_path(cgroup),
+            Some("/user.slice/user-1000.slice/app-steam.scope")
+        );
+        assert_eq!(parse_cgroup_path("1:name=systemd:/init.scope\n"), None);
+    }
+
+    #[test]
+    fn test_parse_populated() {
+        assert_eq!(parse_populated("populated 1\nfrozen 0\n"), Some(true));
+        assert_eq!(parse_populated("populated 0\nfrozen 0\n"), Some(false));
+        assert_eq!(parse_populated("frozen 0\n"), None);
+    }
+
+    #[test]
+    fn test_wait_until_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let events = dir.path().join("cgroup.events");
+        std::fs::write(&events, "populated 1\nfrozen 0\n").unwrap();
+
+        let waiter = {
+            let dir = dir.path().to_path_buf();
+            std::thread::spawn(move || wait_until_empty(&dir))
+        };
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        std::fs::write(&events, "populated 0\nfrozen 0\n").unwrap();
+        waiter.join().unwrap().unwrap();
+    }
+}
@@ -1,8 +1,18 @@
-use log::{debug, error, info};
+use chrono::NaiveTime;
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf};
-
-const CONFIG_FILE: &str = "nvprime.conf";
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    hash::Hasher,
+    path::PathBuf,
+};
+
+use crate::common::config_edit::ConfigEdit;
+use crate::common::matcher::GameMatcher;
+use crate::common::nvgpu::NvmlRetry;
+use crate::common::paths::{self, Paths};
+use crate::common::resolve::{GAME_ENV, GameHints, normalize_name};
+use crate::runner::{EnvBuilder, EnvConflict};
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
@@ -23,10 +33,246 @@ pub struct Config {
 
     #[serde(default)]
     pub hook: HooksConfig,
+
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+
+    #[serde(default)]
+    pub paths: PathsConfig,
+
+    #[serde(default)]
+    pub updates: UpdatesConfig,
+
+    #[serde(default)]
+    pub budget: BudgetConfig,
+
+    #[serde(default)]
+    pub resolve: ResolveConfig,
+
+    /// Baseline profiles the daemon applies at times of day
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub schedule: Vec<ScheduleEntry>,
+}
+
+/// Config section for the system daemon itself
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// Environment set in the daemon before it touches NVML, for driver setups
+    /// where the default library discovery fails
+    pub env: BTreeMap<String, EnvValue>,
+
+    /// Times an NVML call failing with a transient error, like a GPU still
+    /// waking up, is tried again, at most 5
+    /// Default: 3
+    pub nvml_retries: u32,
+
+    /// Milliseconds before the first retry, doubled for each one after it
+    /// Default: 100
+    pub nvml_retry_ms: u64,
+
+    /// Broker the daemon publishes session state to, with the `mqtt` feature
+    pub mqtt: MqttConfig,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            env: BTreeMap::new(),
+            nvml_retries: 3,
+            nvml_retry_ms: 100,
+            mqtt: MqttConfig::default(),
+        }
+    }
+}
+
+impl DaemonConfig {
+    pub fn nvml_retry(&self) -> NvmlRetry {
+        NvmlRetry {
+            retries: self.nvml_retries,
+            delay: std::time::Duration::from_millis(self.nvml_retry_ms),
+        }
+    }
+}
+
+/// Config section for publishing sessions and GPU state to an MQTT broker,
+/// for home automation to follow
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MqttConfig {
+    /// `host` or `host:port` of the broker, nothing is published when unset
+    pub broker: Option<String>,
+
+    /// Prefix of every topic published
+    /// Default: "nvprime"
+    pub topic: String,
+
+    /// Default: "nvprime"
+    pub client_id: String,
+
+    pub username: Option<String>,
+    pub password: Option<String>,
+
+    /// Seconds between GPU readings published during a session
+    /// Default: 5
+    pub interval_sec: u64,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            broker: None,
+            topic: "nvprime".to_string(),
+            client_id: "nvprime".to_string(),
+            username: None,
+            password: None,
+            interval_sec: 5,
+        }
+    }
+}
+
+/// Config section overriding where nvprime keeps its files, each
+/// directory defaults to its XDG base directory
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct PathsConfig {
+    /// Session history and exported telemetry
+    pub data_dir: Option<PathBuf>,
+
+    /// Logs
+    pub state_dir: Option<PathBuf>,
+
+    pub cache_dir: Option<PathBuf>,
+
+    /// Sockets, must be on a filesystem that supports them
+    pub runtime_dir: Option<PathBuf>,
+}
+
+/// Config section for how a launch is matched to its `[game.<name>]`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ResolveConfig {
+    /// Resolvers tried in order, the first one naming a game wins. The
+    /// executable's stem is the name when none does.
+    /// Default: ["env", "appid", "stem", "match"]
+    pub resolvers: Vec<Resolver>,
+}
+
+impl Default for ResolveConfig {
+    fn default() -> Self {
+        Self {
+            resolvers: vec![
+                Resolver::Env,
+                Resolver::Appid,
+                Resolver::Stem,
+                Resolver::Match,
+            ],
+        }
+    }
+}
+
+/// One way of telling which game a launch starts
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolver {
+    /// `NVPRIME_GAME` names the section, whether it exists or not
+    Env,
+
+    /// The section whose `app_id` holds the Steam app ID of the launch
+    Appid,
+
+    /// The section named after the product name in the executable's
+    /// version resource, compared without case, spaces or punctuation
+    Product,
+
+    /// The section named after the executable's lowercased stem
+    Stem,
+
+    /// The first section, by name, whose `match` or `match_path` accepts
+    /// the executable
+    Match,
+}
+
+impl fmt::Display for Resolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Env => "env",
+            Self::Appid => "appid",
+            Self::Product => "product",
+            Self::Stem => "stem",
+            Self::Match => "match",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Config section for fetching workarounds published between releases
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct UpdatesConfig {
+    /// Check for an updated workaround manifest, nothing is fetched unless set
+    pub check: bool,
+
+    /// Manifest location, its minisign signature is expected at `<url>.minisig`
+    /// Default: None (no manifest is published yet)
+    pub url: Option<String>,
+
+    /// Minisign public key the manifest must be signed with
+    /// Default: None
+    pub public_key: Option<String>,
+
+    /// Hours before the cached manifest is fetched again
+    /// Default: 24
+    pub interval_hours: u64,
+}
+
+impl Default for UpdatesConfig {
+    fn default() -> Self {
+        Self {
+            check: false,
+            url: None,
+            public_key: None,
+            interval_hours: 24,
+        }
+    }
+}
+
+/// Config section sharing one power budget between the CPU and the GPU, for
+/// laptops where both draw from the same TDP
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BudgetConfig {
+    /// Combined CPU package and GPU power in watts
+    /// Default: None (no shared budget)
+    pub total_w: Option<u32>,
+
+    /// Share of the budget that goes to the GPU, from 0.0 to 1.0
+    /// Default: 0.7
+    pub gpu_bias: f64,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self {
+            total_w: None,
+            gpu_bias: 0.7,
+        }
+    }
+}
+
+impl BudgetConfig {
+    /// GPU and CPU limits in milliwatts, a GPU limit set for the game takes
+    /// its share and leaves the rest to the CPU
+    pub fn split(&self, gpu_mw: Option<u32>) -> Option<(u32, u32)> {
+        let total_mw = self.total_w? * 1000;
+        let share = (total_mw as f64 * self.gpu_bias.clamp(0.0, 1.0)).round() as u32;
+        let gpu_mw = gpu_mw.unwrap_or(share).min(total_mw);
+        Some((gpu_mw, total_mw - gpu_mw))
+    }
 }
 
 /// Config section for AMD Zen EPP tuning
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(default)]
 pub struct CpuTune {
     /// Flag for tuning status
@@ -38,6 +284,67 @@ pub struct CpuTune {
 
     /// Default (baseline) power profile
     pub amd_epp_base: String,
+
+    /// Maximum CPU wakeup latency in microseconds while gaming, held through
+    /// `/dev/cpu_dma_latency` to keep the CPU out of deep C-states
+    /// Default: None (no restriction)
+    pub cpu_dma_latency_us: Option<i32>,
+
+    /// Highest CPU core frequency in MHz while gaming, set through
+    /// `scaling_max_freq` for quieter fans in GPU-bound games
+    /// Default: None (no cap)
+    pub cpu_max_freq_mhz: Option<u32>,
+
+    /// Switch EPP with what holds the game back while it runs
+    pub dynamic: DynamicEpp,
+
+    /// How the power profile is set, straight through sysfs or by holding
+    /// a power-profiles-daemon profile
+    pub cpu_backend: CpuBackend,
+
+    /// Long-term CPU package power limit (PL1, PPT) while gaming, set
+    /// through the RAPL powercap zones. Filled in from `[budget]`.
+    /// Default: None (firmware managed)
+    #[serde(deserialize_with = "power_limit_mw")]
+    pub ppt_limit: Option<u32>,
+
+    /// Simultaneous multithreading while gaming, switched through
+    /// `/sys/devices/system/cpu/smt/control`
+    /// Default: None (left as it is)
+    pub smt: Option<SmtMode>,
+}
+
+/// Whether the sibling threads of each core are online
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtMode {
+    On,
+    Off,
+}
+
+impl SmtMode {
+    /// What `smt/control` takes
+    pub fn control_value(self) -> &'static str {
+        match self {
+            Self::On => "on",
+            Self::Off => "off",
+        }
+    }
+}
+
+/// What the CPU power profile is set through
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CpuBackend {
+    /// power-profiles-daemon when it runs, sysfs otherwise
+    #[default]
+    Auto,
+
+    /// Write EPP to sysfs, even with a daemon managing it
+    Sysfs,
+
+    /// Hold a power-profiles-daemon profile for the session
+    Ppd,
 }
 
 /// Default state for AMD Zen EPP tuning
@@ -47,12 +354,57 @@ impl Default for CpuTune {
             enabled: false,
             amd_epp_tune: "performance".to_string(),
             amd_epp_base: "balance_performance".to_string(),
+            cpu_dma_latency_us: None,
+            cpu_max_freq_mhz: None,
+            dynamic: DynamicEpp::default(),
+            cpu_backend: CpuBackend::Auto,
+            ppt_limit: None,
+            smt: None,
+        }
+    }
+}
+
+/// Config section `[cpu.dynamic]`, relaxing EPP while the game waits on the
+/// GPU so the CPU leaves thermal headroom to a GPU sharing its cooling
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DynamicEpp {
+    /// Flag for dynamic EPP switching, needs GPU tuning for the utilization
+    pub enabled: bool,
+
+    /// Power profile while GPU-bound, `amd_epp_tune` applies otherwise
+    pub gpu_bound_epp: String,
+
+    /// GPU utilization in percent from which the game counts as GPU-bound
+    pub gpu_util_percent: u32,
+
+    /// Utilization of the busiest CPU core in percent from which the game
+    /// counts as CPU-bound
+    pub cpu_util_percent: u32,
+
+    /// Percentage points past a threshold needed to switch back, so EPP
+    /// does not flip on every sample near it
+    pub hysteresis_percent: u32,
+
+    /// Seconds a state has to last before EPP is switched
+    pub hold_sec: u32,
+}
+
+impl Default for DynamicEpp {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            gpu_bound_epp: "balance_performance".to_string(),
+            gpu_util_percent: 95,
+            cpu_util_percent: 85,
+            hysteresis_percent: 5,
+            hold_sec: 5,
         }
     }
 }
 
 /// Config section for NVIDIA GPU and any related tuning flag
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(default)]
 pub struct GpuTune {
     /// Flag to enable power tuning
@@ -70,11 +422,37 @@ pub struct GpuTune {
     /// We set it with the default value just to be sure
     pub gpu_vlk_icd: String,
 
+    /// Path to the 32-bit Vulkan ICD JSON file, added to `gpu_vlk_icd` for
+    /// 32-bit games. Default: the one installed next to it, if any
+    pub gpu_vlk_icd32: Option<String>,
+
     /// Set the GPU power limit to highest
     pub set_max_pwr: bool,
 
-    /// Set custom power limit for the GPU
+    /// Set custom power limit for the GPU, stored in milliwatts
+    /// Accepts `"110W"`, `"110000mW"` or a bare integer in milliwatts
+    #[serde(deserialize_with = "power_limit_mw")]
     pub pwr_limit_tune: Option<u32>,
+
+    /// Take the PRIME offload environment from switcheroo-control, the
+    /// one desktops use to launch apps on the discrete GPU, over the
+    /// builtin defaults
+    pub prefer_switcheroo: bool,
+
+    /// Lowest memory clock in MHz while gaming, locked through NVML so VRAM
+    /// does not clock down in menus and hitch once the load returns.
+    /// Rounded up to a clock the GPU supports.
+    /// Default: None (driver managed)
+    pub min_mem_clock_mhz: Option<u32>,
+
+    /// Utilization in percent above which the GPU counts as already busy
+    /// (another game, an AI workload) when a launch starts.
+    /// Default: None (not checked)
+    pub busy_util_percent: Option<u32>,
+
+    /// VRAM use in percent above which the GPU counts as already busy.
+    /// Default: None (not checked)
+    pub busy_vram_percent: Option<u32>,
 }
 
 /// Default state for NVIDIA GPU tuning
@@ -84,14 +462,105 @@ impl Default for GpuTune {
             enabled: false,
             gpu_name: None,
             gpu_uuid: None,
-            gpu_vlk_icd: "/usr/share/vulkan/icd.d/nvidia_icd.json".to_string(),
+            gpu_vlk_icd: paths::NVIDIA_ICD.to_string(),
+            gpu_vlk_icd32: None,
             set_max_pwr: false,
             pwr_limit_tune: None,
+            prefer_switcheroo: false,
+            min_mem_clock_mhz: None,
+            busy_util_percent: None,
+            busy_vram_percent: None,
         }
     }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+/// Bare numbers below this can't be milliwatts, no GPU runs on less than a watt
+const MIN_PLAUSIBLE_MW: u32 = 1_000;
+
+/// Anything above this is far beyond what a single consumer GPU can draw
+const MAX_PLAUSIBLE_MW: u32 = 1_000_000;
+
+/// Parse a power limit with explicit units (`W` or `mW`) into milliwatts.
+/// Only a bare number is taken for watts when it is implausibly low, a unit
+/// is taken as written.
+pub fn parse_power_limit(value: &str) -> Result<u32, String> {
+    let value = value.trim();
+    let lower = value.to_ascii_lowercase();
+
+    let (number, scale) = if let Some(n) = lower.strip_suffix("mw") {
+        (n, Some(1.0))
+    } else if let Some(n) = lower.strip_suffix('w') {
+        (n, Some(1000.0))
+    } else {
+        (lower.as_str(), None)
+    };
+
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid power limit '{}', expected e.g. \"110W\"", value))?;
+
+    let mw = number * scale.unwrap_or(1.0);
+    if !mw.is_finite() || mw < 0.0 || mw > u32::MAX as f64 {
+        return Err(format!("power limit '{}' is out of range", value));
+    }
+
+    let mw = mw.round() as u32;
+    if scale.is_none() {
+        return Ok(check_power_limit(mw));
+    }
+    if mw < MIN_PLAUSIBLE_MW {
+        return Err(format!(
+            "power limit '{}' is out of range, below {}W",
+            value,
+            MIN_PLAUSIBLE_MW / 1000
+        ));
+    }
+    Ok(check_power_limit(mw))
+}
+
+/// Warn about values that are almost certainly a unit mix-up
+fn check_power_limit(mw: u32) -> u32 {
+    if mw < MIN_PLAUSIBLE_MW {
+        warn!(
+            "Power limit {}mW is implausibly low, assuming {}W was meant",
+            mw, mw
+        );
+        return mw * 1000;
+    }
+
+    if mw > MAX_PLAUSIBLE_MW {
+        warn!(
+            "Power limit {}mW ({}W) is implausibly high, the driver will clamp it",
+            mw,
+            mw / 1000
+        );
+    }
+
+    mw
+}
+
+pub(crate) fn power_limit_mw<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawLimit {
+        Milliwatts(u32),
+        WithUnit(String),
+    }
+
+    match Option::<RawLimit>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(RawLimit::Milliwatts(mw)) => Ok(Some(check_power_limit(mw))),
+        Some(RawLimit::WithUnit(s)) => parse_power_limit(&s)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(default)]
 pub struct SysTune {
     /// Enable or disable system-level tuning
@@ -99,10 +568,14 @@ pub struct SysTune {
     pub enabled: bool,
 
     /// IO priority level for processes (0-7, lower is higher priority)
-    /// Uses ionice best-effort class where 0 is highest, 7 is lowest
+    /// Within `proc_ioprio_class`, 0 is highest, 7 is lowest
     /// Default: 4 (middle priority)
     pub proc_ioprio: i32,
 
+    /// IO scheduling class of the game's threads, the realtime one only
+    /// when the daemon policy allows it
+    pub proc_ioprio_class: IoprioClass,
+
     /// Nice value adjustment for process CPU priority (-20 to 19)
     /// Negative values increase priority (root only), positive values decrease it
     /// Default: 0 (no adjustment)
@@ -115,39 +588,898 @@ pub struct SysTune {
     /// Interval in seconds for the daemon to poll process status
     /// Default: 10 seconds
     pub watchdog_interval_sec: u64,
+
+    /// Cap all inbound bandwidth of the whole link (kbit/s) while gaming.
+    /// Inbound packets are not told apart by process, the game's own
+    /// traffic is policed with the rest and dropped above the cap.
+    #[serde(alias = "download_limit_kbit")]
+    pub link_limit_kbit: Option<u32>,
+
+    /// Interface to limit, defaults to the one carrying the default route
+    #[serde(alias = "download_iface")]
+    pub link_iface: Option<String>,
+
+    /// CPUs the system and user slices are confined to while gaming, e.g.
+    /// `0-1`, leaving the others to the game
+    pub background_cpus: Option<String>,
+
+    /// zswap and zram settings while gaming, `[sys.memory]`
+    pub memory: MemoryTune,
+
+    /// Abort the launch when any tuning step or the init hook fails
+    pub strict: bool,
+
+    /// Save each session's full telemetry series in this format
+    pub telemetry_export: Option<TelemetryFormat>,
+
+    /// Length of the launch phase in seconds, where shader compilation and
+    /// asset unpacking run under `launch_renice` and `launch_ioprio` before
+    /// the game switches to `proc_renice` and `proc_ioprio`
+    pub launch_phase_sec: Option<u64>,
+
+    /// Nice value during the launch phase, 0 leaves it alone
+    pub launch_renice: i32,
+
+    /// IO priority level during the launch phase (0-7)
+    /// Default: 0 (highest best-effort priority)
+    pub launch_ioprio: i32,
+
+    /// End the launch phase early once GPU utilization reaches this percent
+    pub launch_gpu_util: Option<u32>,
+
+    /// What sets the game's CPU and IO priorities
+    pub priority_backend: PriorityBackend,
+}
+
+/// Swap settings for machines short on RAM, applied by the daemon for the
+/// sessions and put back after the last one
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct MemoryTune {
+    /// Share of RAM zswap may fill with compressed pages, in percent
+    pub zswap_max_pool_percent: Option<u32>,
+
+    /// zswap compressor, e.g. `zstd` or `lz4`
+    pub zswap_compressor: Option<String>,
+
+    /// Swap priority of the zram devices, above disk swap to use them first
+    pub zram_priority: Option<i32>,
+}
+
+impl MemoryTune {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// IO scheduling class, as `ionice -c` takes it
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IoprioClass {
+    /// Served before everything else, can starve the rest of the system
+    Rt,
+    /// Served by priority level, what every process starts with
+    #[default]
+    Be,
+    /// Only served when nothing else wants the disk, ignores the level
+    Idle,
+}
+
+/// Who prioritizes the game's process over the rest of the system
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PriorityBackend {
+    /// system76-scheduler or a sched_ext scheduler when one runs, nvprime
+    /// itself otherwise
+    #[default]
+    Auto,
+
+    /// Renice and ionice the game, even with a scheduler doing the same
+    Direct,
+
+    /// Hand the game to system76-scheduler as the foreground process
+    System76,
+}
+
+/// Vulkan driver a game runs on, picked by its ICD manifest
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum VulkanDriver {
+    /// NVIDIA's own userspace driver, the same with the open kernel modules
+    #[serde(alias = "nvidia-open")]
+    NvidiaProprietary,
+
+    /// Mesa's NVK on top of nouveau
+    Nvk,
+}
+
+/// How the game's command is started
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LaunchBackend {
+    /// Run the command directly, also covers AppImages
+    #[default]
+    Exec,
+
+    /// `flatpak run`, the command is the application ID
+    Flatpak,
+
+    /// `podman run` of `container_image`, the command runs inside it
+    Podman,
+}
+
+/// File format for exported session telemetry
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TelemetryFormat {
+    Csv,
+    /// Needs the `parquet` feature, otherwise falls back to CSV
+    Parquet,
+}
+
+impl Default for SysTune {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            proc_ioprio: 4,
+            proc_ioprio_class: IoprioClass::Be,
+            proc_renice: 0,
+            splitlock_hack: false,
+            watchdog_interval_sec: 10,
+            link_limit_kbit: None,
+            link_iface: None,
+            background_cpus: None,
+            memory: MemoryTune::default(),
+            strict: false,
+            telemetry_export: None,
+            launch_phase_sec: None,
+            launch_renice: 0,
+            launch_ioprio: 0,
+            launch_gpu_util: None,
+            priority_backend: PriorityBackend::Auto,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// Run before the `pre_spawn` commands, kept from before there were
+    /// stages
+    pub init: Option<String>,
+
+    /// Run before the `post_exit` commands
+    pub shutdown: Option<String>,
+
+    /// Before the game's environment is resolved
+    pub pre_env: StageHooks,
+
+    /// Once the environment is resolved, with it set for the commands
+    pub post_env: StageHooks,
+
+    /// After the daemon applied the tuning, right before the game starts
+    pub pre_spawn: StageHooks,
+
+    /// Once the game started, with its PID in `NVPRIME_GAME_PID`
+    pub post_spawn: StageHooks,
+
+    /// After the game exited, with its exit code in `NVPRIME_EXIT_CODE`
+    pub post_exit: StageHooks,
+
+    /// Run when the GPU starts throttling for temperature during a session
+    pub on_thermal_throttle: Option<String>,
+
+    /// Run when the GPU starts throttling at its power limit during a session
+    pub on_power_throttle: Option<String>,
+
+    /// Run when the GPU reaches a temperature during a session
+    pub on_temp_above: Option<TempHook>,
+
+    /// Conditions that switch the game's MangoHud to its detailed preset,
+    /// e.g. `["temp_above", "thermal_throttle"]`
+    pub hud_detailed_on: Vec<String>,
+
+    /// When running elevated, give hooks the invoking user's HOME, XDG and
+    /// display variables instead of root's
+    /// Default: true
+    pub user_env: bool,
+
+    /// Extra variables set for every hook, applied last
+    pub env: BTreeMap<String, EnvValue>,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            init: None,
+            shutdown: None,
+            pre_env: StageHooks::default(),
+            post_env: StageHooks::default(),
+            pre_spawn: StageHooks::default(),
+            post_spawn: StageHooks::default(),
+            post_exit: StageHooks::default(),
+            on_thermal_throttle: None,
+            on_power_throttle: None,
+            on_temp_above: None,
+            hud_detailed_on: Vec::new(),
+            user_env: true,
+            env: BTreeMap::new(),
+        }
+    }
+}
+
+/// A hook started once the GPU reaches `temp` degrees Celsius
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct TempHook {
+    pub temp: u32,
+    pub cmd: String,
+}
+
+/// Commands of a hook stage, run in order. Given as a single command, an
+/// array of them or a table with `cmd` and `on_failure`.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(from = "RawStageHooks")]
+pub struct StageHooks {
+    pub cmd: Vec<String>,
+
+    /// What a failing command does to the rest of the stage and the launch
+    /// Default: `abort` before the game starts in strict mode, `continue`
+    /// otherwise
+    pub on_failure: Option<HookFailure>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawStageHooks {
+    One(String),
+    Many(Vec<String>),
+    Table {
+        #[serde(deserialize_with = "one_or_many")]
+        cmd: Vec<String>,
+        on_failure: Option<HookFailure>,
+    },
+}
+
+impl From<RawStageHooks> for StageHooks {
+    fn from(raw: RawStageHooks) -> Self {
+        match raw {
+            RawStageHooks::One(cmd) => Self {
+                cmd: vec![cmd],
+                on_failure: None,
+            },
+            RawStageHooks::Many(cmd) => Self {
+                cmd,
+                on_failure: None,
+            },
+            RawStageHooks::Table { cmd, on_failure } => Self { cmd, on_failure },
+        }
+    }
+}
+
+/// What happens once a hook command fails
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HookFailure {
+    /// Log it and run the stage's next command
+    Continue,
+
+    /// Log it and skip the rest of the stage
+    Stop,
+
+    /// Abort the launch, only before the game started; later stages stop
+    Abort,
+}
+
+use std::fmt;
+
+// ...
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct GameConfig {
+    /// Executable names this section also applies to, matched against the
+    /// lowercased stem, e.g. `regex:^ffxiv(_dx11)?$`
+    #[serde(rename = "match")]
+    pub match_name: Option<GameMatcher>,
+
+    /// Executable paths this section applies to, e.g. `glob:**/FFXIV/**`
+    pub match_path: Option<GameMatcher>,
+
+    /// Steam app IDs this section applies to, for the `appid` resolver
+    #[serde(deserialize_with = "one_or_many")]
+    pub app_id: Vec<u32>,
+
+    pub mangohud: bool,
+    pub mangohud_conf: Option<String>,
+    pub proton_log: bool,
+    pub proton_ntsync: bool,
+    pub proton_wayland: bool,
+
+    /// `WINEDLLOVERRIDES` as written for Wine, e.g. `"dinput8=n,b"`, checked
+    /// like `dll_overrides` when the config loads
+    #[serde(
+        deserialize_with = "wine_dll_overrides",
+        serialize_with = "serialize_wine_dll_overrides"
+    )]
+    pub wine_dll_overrides: BTreeMap<String, DllMode>,
+
+    /// Load order per DLL, e.g. `{ dinput8 = "native,builtin" }`, checked
+    /// when the config loads. Wins over `wine_dll_overrides` for the same
+    /// DLL.
+    #[serde(deserialize_with = "dll_overrides")]
+    pub dll_overrides: BTreeMap<String, DllMode>,
+
+    /// Use fsync, Proton's default when unset. Sets `PROTON_NO_FSYNC`.
+    pub proton_fsync: Option<bool>,
+
+    /// Use esync, Proton's default when unset. Sets `PROTON_NO_ESYNC`.
+    pub proton_esync: Option<bool>,
+
+    /// Pretend to run on a Steam Deck, which makes some games offer their
+    /// Deck presets or FSR modes. Sets `SteamDeck` and `SteamOS`.
+    pub spoof_steamdeck: bool,
+
+    /// Start from nvprime's built-in environment defaults. When `false`
+    /// only the variables from the config apply, for native Vulkan titles
+    /// the NVIDIA and Proton defaults get in the way of.
+    /// Default: true
+    pub inherit_defaults: bool,
+
+    /// Start the game with exactly nvprime's environment on top of the
+    /// inherited one, checking the driver manifests it names right before
+    /// the game is executed
+    pub strict_env: bool,
+
+    /// Restart the game when it exits with an unexpected code
+    pub restart_on_crash: bool,
+
+    /// Maximum number of restarts within a single session
+    /// Default: 3
+    pub max_restarts: u32,
+
+    /// Exit codes treated as a clean exit in addition to 0
+    pub allowed_exit_codes: Vec<i32>,
+
+    /// Adopt the processes a launcher leaves behind when it double-forks,
+    /// so the session lasts until the game itself exits
+    pub subreaper: bool,
+
+    /// Rewrites the command line, split at whitespace, e.g.
+    /// `gamescope -W 3440 -H 1440 -- {command}`. `{command}` is the whole
+    /// original command, `{exe}` its program and `{args}` the rest.
+    pub command_template: Option<String>,
+
+    /// How the command is started
+    pub backend: LaunchBackend,
+
+    /// Image the game runs in with the `podman` backend
+    pub container_image: Option<String>,
+
+    /// Extra arguments for `podman run`, e.g. volumes holding the game
+    pub container_args: Vec<String>,
+
+    /// Directories put in front of `LD_LIBRARY_PATH` for this game
+    pub extra_library_paths: Vec<PathBuf>,
+
+    /// Directories put in front of `PATH` for this game
+    pub prepend_path: Vec<PathBuf>,
+
+    /// Drives mounted by the daemon before launch and unmounted afterward,
+    /// by their name in the daemon policy's `[mounts]`
+    #[serde(deserialize_with = "one_or_many")]
+    pub mount: Vec<String>,
+
+    /// Conditions met before the game starts, in order, e.g.
+    /// `[{ unit = "mod-manager.service" }, { port = 27015 }, { delay_ms = 3000 }]`
+    pub wait_for: Vec<WaitCondition>,
+
+    /// Seconds each `wait_for` condition may take before the launch fails
+    /// Default: 30
+    pub wait_timeout_sec: u64,
+
+    /// Block idle screen blanking and system sleep while the game runs
+    pub inhibit_sleep: bool,
+
+    /// Turn on the desktop's Do Not Disturb (GNOME or KDE Plasma) while the
+    /// game runs, then restore the previous notification state
+    pub do_not_disturb: bool,
+
+    /// Let Proton map the game's library to drive `S:` through
+    /// `PROTON_SET_GAME_DRIVE`, on by default
+    pub game_drive: Option<bool>,
+
+    /// Drive letters mapped to directories in the game's prefix, e.g.
+    /// `d = "/mnt/games"`, `[game.<name>.drives]`
+    pub drives: BTreeMap<String, PathBuf>,
+
+    /// Display connector the game opens on, e.g. `DP-1` for the VRR monitor,
+    /// through gamescope's `--prefer-output` and SDL's display priority
+    pub preferred_output: Option<String>,
+
+    /// Vulkan driver for this game, `nvidia-proprietary` or `nvk`
+    /// Default: None (the NVIDIA ICD of the offload defaults)
+    pub vulkan_driver: Option<VulkanDriver>,
+
+    /// Abort the launch when tuning fails, on top of the global `[sys] strict`
+    pub strict: bool,
+
+    /// Make the game the OOM killer's last choice, applied by the daemon
+    pub oom_protect: bool,
+
+    /// Process names of the same user made the OOM killer's first choice
+    /// while `oom_protect` is active, e.g. `steamwebhelper`
+    pub oom_sacrifice: Vec<String>,
+
+    /// MiB of VRAM other processes may hold before the launch points them
+    /// out with a warning and a desktop notification
+    pub vram_pressure_mib: Option<u32>,
+
+    /// Signal sent to the processes named in `vram_pressure_targets` once
+    /// `vram_pressure_mib` is exceeded, e.g. `SIGTERM`
+    #[serde(deserialize_with = "signal_name")]
+    pub vram_pressure_signal: Option<String>,
+
+    /// Names of the user's own processes `vram_pressure_signal` is sent to
+    /// when they hold VRAM, e.g. `firefox`. No other process is signalled.
+    pub vram_pressure_targets: Vec<String>,
+
+    /// Gigabytes of the game's memory the kernel reclaims from last, through
+    /// `memory.low` on its cgroup, must be below the total RAM
+    pub memory_protect_gb: Option<u32>,
+
+    /// Winetricks components the game's prefix needs, e.g. `vcrun2022`
+    pub requires: Vec<String>,
+
+    /// Install missing `requires` components before launch
+    pub install_missing: bool,
+
+    /// Wine prefix used for the checks when not launched through Steam
+    pub wine_prefix: Option<String>,
+
+    /// GPU tuning for this game, overrides `[gpu] gpu_tuning`
+    pub tune_gpu: Option<bool>,
+
+    /// CPU tuning for this game, overrides `[cpu] cpu_tuning`
+    pub tune_cpu: Option<bool>,
+
+    /// Process priorities and the rest of the system tuning for this game,
+    /// overrides `[sys] sys_tuning`. Some anti-cheats misbehave when the
+    /// game's priority changes.
+    pub tune_proc: Option<bool>,
+
+    /// Power limit for this game, overrides `[gpu] pwr_limit_tune` and `set_max_pwr`
+    #[serde(deserialize_with = "power_limit_mw")]
+    pub pwr_limit_tune: Option<u32>,
+
+    /// Memory clock floor in MHz for this game, overrides
+    /// `[gpu] min_mem_clock_mhz`
+    pub min_mem_clock_mhz: Option<u32>,
+
+    /// CPU frequency ceiling in MHz for this game, overrides
+    /// `[cpu] cpu_max_freq_mhz`
+    pub cpu_max_freq_mhz: Option<u32>,
+
+    /// SMT for this game, overrides `[cpu] smt`
+    pub smt: Option<SmtMode>,
+
+    /// IO priority level for this game, overrides `[sys] proc_ioprio`
+    pub proc_ioprio: Option<i32>,
+
+    /// IO scheduling class for this game, overrides `[sys] proc_ioprio_class`
+    pub proc_ioprio_class: Option<IoprioClass>,
+
+    /// PipeWire buffer size, rate and latency, `[game.<name>.audio]`
+    pub audio: AudioConfig,
+
+    /// Options written to a `dxvk.conf` for the game, `[game.<name>.dxvk]`
+    pub dxvk: DxvkConfig,
+
+    /// Screenshot and recording directory, `[game.<name>.capture]`
+    pub capture: CaptureConfig,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            match_name: None,
+            match_path: None,
+            app_id: Vec::new(),
+            mangohud: false,
+            mangohud_conf: None,
+            proton_log: false,
+            proton_ntsync: false,
+            proton_fsync: None,
+            proton_esync: None,
+            proton_wayland: false,
+            wine_dll_overrides: BTreeMap::new(),
+            dll_overrides: BTreeMap::new(),
+            spoof_steamdeck: false,
+            inherit_defaults: true,
+            strict_env: false,
+            restart_on_crash: false,
+            max_restarts: 3,
+            allowed_exit_codes: Vec::new(),
+            subreaper: false,
+            command_template: None,
+            backend: LaunchBackend::Exec,
+            container_image: None,
+            container_args: Vec::new(),
+            extra_library_paths: Vec::new(),
+            prepend_path: Vec::new(),
+            mount: Vec::new(),
+            wait_for: Vec::new(),
+            wait_timeout_sec: 30,
+            inhibit_sleep: false,
+            do_not_disturb: false,
+            game_drive: None,
+            drives: BTreeMap::new(),
+            preferred_output: None,
+            vulkan_driver: None,
+            strict: false,
+            oom_protect: false,
+            oom_sacrifice: Vec::new(),
+            vram_pressure_mib: None,
+            vram_pressure_signal: None,
+            vram_pressure_targets: Vec::new(),
+            memory_protect_gb: None,
+            requires: Vec::new(),
+            install_missing: false,
+            wine_prefix: None,
+            tune_gpu: None,
+            tune_cpu: None,
+            tune_proc: None,
+            pwr_limit_tune: None,
+            min_mem_clock_mhz: None,
+            cpu_max_freq_mhz: None,
+            smt: None,
+            proc_ioprio: None,
+            proc_ioprio_class: None,
+            audio: AudioConfig::default(),
+            dxvk: DxvkConfig::default(),
+            capture: CaptureConfig::default(),
+        }
+    }
+}
+
+impl GameConfig {
+    /// Whether one of the section's matchers accepts the executable
+    pub fn matches(&self, stem: &str, path: &str) -> bool {
+        self.match_name.as_ref().is_some_and(|m| m.is_match(stem))
+            || self.match_path.as_ref().is_some_and(|m| m.is_match(path))
+    }
+
+    /// Whether the given exit code counts as a clean exit for this game
+    pub fn is_allowed_exit(&self, exit_code: i32) -> bool {
+        exit_code == 0 || self.allowed_exit_codes.contains(&exit_code)
+    }
+}
+
+/// A baseline profile applied by the daemon between two times of day,
+/// while no game session overrides it
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduleEntry {
+    /// Local time the profile starts, `"HH:MM"`
+    #[serde(deserialize_with = "time_of_day")]
+    pub start: NaiveTime,
+
+    /// Local time the profile ends, may be past midnight
+    #[serde(deserialize_with = "time_of_day")]
+    pub end: NaiveTime,
+
+    /// GPU power limit while the profile is active, same forms as `[gpu]`
+    #[serde(default, deserialize_with = "power_limit_mw")]
+    pub pwr_limit_tune: Option<u32>,
+
+    /// EPP hint while the profile is active
+    #[serde(default)]
+    pub amd_epp_tune: Option<String>,
+}
+
+impl ScheduleEntry {
+    /// Whether `now` falls inside the window, which wraps around midnight
+    /// when it ends before it starts
+    pub fn is_active(&self, now: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= now && now < self.end
+        } else {
+            now >= self.start || now < self.end
+        }
+    }
+}
+
+/// Where Wine loads a DLL from, in the order it tries
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DllMode {
+    Native,
+    Builtin,
+    NativeBuiltin,
+    BuiltinNative,
+    Disabled,
+}
+
+impl DllMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Native => "native",
+            Self::Builtin => "builtin",
+            Self::NativeBuiltin => "native,builtin",
+            Self::BuiltinNative => "builtin,native",
+            Self::Disabled => "disabled",
+        }
+    }
+
+    /// What `WINEDLLOVERRIDES` takes
+    pub fn wine_value(self) -> &'static str {
+        match self {
+            Self::Native => "n",
+            Self::Builtin => "b",
+            Self::NativeBuiltin => "n,b",
+            Self::BuiltinNative => "b,n",
+            Self::Disabled => "",
+        }
+    }
+}
+
+impl std::str::FromStr for DllMode {
+    type Err = String;
+
+    /// Wine itself only looks at the first letter of each token and turns
+    /// anything else into a disabled DLL, so this is stricter
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim().to_lowercase();
+        if matches!(value.as_str(), "" | "d" | "disabled") {
+            return Ok(Self::Disabled);
+        }
+
+        let tokens: Vec<&str> = value.split(',').map(str::trim).collect();
+        let native = |token: &str| matches!(token, "n" | "native");
+        let builtin = |token: &str| matches!(token, "b" | "builtin");
+        match tokens[..] {
+            [a] if native(a) => Ok(Self::Native),
+            [a] if builtin(a) => Ok(Self::Builtin),
+            [a, b] if native(a) && builtin(b) => Ok(Self::NativeBuiltin),
+            [a, b] if builtin(a) && native(b) => Ok(Self::BuiltinNative),
+            _ => Err(format!(
+                "invalid load order '{}', expected \"native\", \"builtin\", \"native,builtin\", \"builtin,native\" or \"disabled\"",
+                s
+            )),
+        }
+    }
+}
+
+impl Serialize for DllMode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Parse the load order of one DLL, refusing names that would break up
+/// `WINEDLLOVERRIDES`
+fn parse_dll_override(dll: &str, mode: &str) -> Result<(String, DllMode), String> {
+    let dll = dll.trim();
+    if dll.is_empty() || dll.contains(['=', ';', ',', ' ']) {
+        return Err(format!("invalid DLL name '{}'", dll));
+    }
+    let mode = mode.parse().map_err(|e| format!("{} for {}", e, dll))?;
+    Ok((dll.to_string(), mode))
+}
+
+/// Parse a `WINEDLLOVERRIDES` string, `dll[,dll...]=mode` entries separated
+/// by `;`
+pub fn parse_wine_dll_overrides(value: &str) -> Result<BTreeMap<String, DllMode>, String> {
+    let mut dlls = BTreeMap::new();
+    for entry in value.split(';').filter(|entry| !entry.trim().is_empty()) {
+        let (names, mode) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("invalid entry '{}', expected \"dll=mode\"", entry))?;
+        for name in names.split(',') {
+            let (dll, mode) = parse_dll_override(name, mode)?;
+            dlls.insert(dll, mode);
+        }
+    }
+    Ok(dlls)
+}
+
+/// `WINEDLLOVERRIDES` for a set of load orders
+pub fn wine_dll_overrides_value(dlls: &BTreeMap<String, DllMode>) -> String {
+    dlls.iter()
+        .map(|(dll, mode)| format!("{}={}", dll, mode.wine_value()))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn dll_overrides<'de, D>(deserializer: D) -> Result<BTreeMap<String, DllMode>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    BTreeMap::<String, String>::deserialize(deserializer)?
+        .iter()
+        .map(|(dll, mode)| parse_dll_override(dll, mode))
+        .collect::<Result<_, _>>()
+        .map_err(|e| serde::de::Error::custom(format!("{} in dll_overrides", e)))
+}
+
+fn wine_dll_overrides<'de, D>(deserializer: D) -> Result<BTreeMap<String, DllMode>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse_wine_dll_overrides(&value)
+        .map_err(|e| serde::de::Error::custom(format!("{} in wine_dll_overrides", e)))
+}
+
+fn serialize_wine_dll_overrides<S>(
+    dlls: &BTreeMap<String, DllMode>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&wine_dll_overrides_value(dlls))
+}
+
+fn time_of_day<'de, D>(deserializer: D) -> Result<NaiveTime, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(value.trim(), "%H:%M").map_err(|_| {
+        serde::de::Error::custom(format!("invalid time '{}', expected \"HH:MM\"", value))
+    })
+}
+
+fn signal_name<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    match value.parse::<nix::sys::signal::Signal>() {
+        Ok(_) => Ok(Some(value)),
+        Err(_) => Err(serde::de::Error::custom(format!(
+            "unknown signal '{}', expected e.g. \"SIGTERM\"",
+            value
+        ))),
+    }
+}
+
+/// A drive games live on, identified by device path or filesystem UUID.
+/// Only defined by the admin in the daemon policy.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MountConfig {
+    /// Block device or network share, e.g. `/dev/sdb1` or `//nas/games`
+    pub device: Option<String>,
+
+    /// Filesystem UUID, used instead of `device`
+    pub uuid: Option<String>,
+
+    /// Where the drive is mounted for the session
+    pub mountpoint: String,
+
+    /// Filesystem type passed to `mount -t`, detected when omitted
+    pub fstype: Option<String>,
+
+    /// Mount options passed to `mount -o`, always with `nosuid,nodev`
+    pub options: Option<String>,
+}
+
+/// Something the game needs before it starts, e.g. a mod manager or a
+/// server emulator
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum WaitCondition {
+    /// A systemd unit of the user or the system to be active
+    Unit { unit: String },
+
+    /// A TCP port to accept connections, on localhost unless `host` is set
+    Port { port: u16, host: Option<String> },
+
+    /// A fixed delay
+    Delay { delay_ms: u64 },
 }
 
-impl Default for SysTune {
-    fn default() -> Self {
-        Self {
-            enabled: false,
-            proc_ioprio: 4,
-            proc_renice: 0,
-            splitlock_hack: false,
-            watchdog_interval_sec: 10,
+impl std::fmt::Display for WaitCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unit { unit } => write!(f, "unit {}", unit),
+            Self::Port { port, host } => {
+                write!(
+                    f,
+                    "port {}:{}",
+                    host.as_deref().unwrap_or("127.0.0.1"),
+                    port
+                )
+            }
+            Self::Delay { delay_ms } => write!(f, "{} ms", delay_ms),
         }
     }
 }
 
-#[derive(Deserialize, Debug, Default)]
-pub struct HooksConfig {
-    pub init: Option<String>,
-    pub shutdown: Option<String>,
+/// Audio settings against crackling, through PipeWire and its PulseAudio
+/// server
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// Buffer size in samples, larger ones crackle less at the cost of
+    /// latency. Sets `PIPEWIRE_QUANTUM`.
+    pub quantum: Option<u32>,
+
+    /// Sample rate in Hz, e.g. 48000. Sets `PIPEWIRE_RATE`.
+    pub rate: Option<u32>,
+
+    /// Latency PulseAudio clients ask for, in milliseconds. Sets
+    /// `PULSE_LATENCY_MSEC`.
+    pub pulse_latency_msec: Option<u32>,
+
+    /// Also force `quantum` and `rate` on the whole graph with `pw-metadata`
+    /// for the session
+    pub force: bool,
 }
 
-use std::fmt;
+/// DXVK settings rendered into a generated `dxvk.conf`
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct DxvkConfig {
+    /// Frames the CPU may queue ahead of the GPU, lower cuts input latency.
+    /// Sets `dxgi.maxFrameLatency` and `d3d9.maxFrameLatency`.
+    pub max_frame_latency: Option<u32>,
 
-// ...
+    /// Report the GPU as AMD to D3D11/D3D12 games, `false` lets them see the
+    /// NVIDIA GPU and offer DLSS. Sets `dxgi.hideNvidiaGpu`.
+    pub hide_nvidia_gpu: Option<bool>,
+
+    /// VRAM reported to the game in MB. Sets `dxgi.maxDeviceMemory`.
+    pub max_device_memory_mb: Option<u32>,
+
+    /// Shared memory reported to the game in MB. Sets `dxgi.maxSharedMemory`.
+    pub max_shared_memory_mb: Option<u32>,
+
+    /// Any other `dxvk.conf` option as written there, e.g.
+    /// `"dxgi.syncInterval" = "0"`
+    pub options: BTreeMap<String, String>,
+}
 
-#[derive(Deserialize, Debug, Clone, Default)]
+/// Per-game directory for screenshots and recordings
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
 #[serde(default)]
-pub struct GameConfig {
-    pub mangohud: bool,
-    pub mangohud_conf: Option<String>,
-    pub proton_log: bool,
-    pub proton_ntsync: bool,
-    pub proton_wayland: bool,
-    pub wine_dll_overrides: Option<String>,
+pub struct CaptureConfig {
+    /// Create the game's capture directory and export it as
+    /// `NVPRIME_CAPTURE_DIR`
+    pub enable: bool,
+
+    /// Directory holding a capture directory per game, defaults to
+    /// `captures` in the data directory
+    pub dir: Option<PathBuf>,
+
+    /// Move what the session captured into a folder of its own once the
+    /// game exits
+    pub archive: bool,
+
+    /// Other directories whose files written during the session are
+    /// archived too, e.g. where OBS or gamescope save recordings
+    pub sources: Vec<PathBuf>,
+}
+
+/// Accept either a single table or an array of tables
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(item) => vec![item],
+        OneOrMany::Many(items) => items,
+    })
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -177,15 +1509,170 @@ impl EnvValue {
 
 impl Config {
     pub fn load() -> anyhow::Result<Self> {
+        Self::load_file(Self::path()?)
+    }
+
+    /// Location of the user's config file
+    pub fn path() -> anyhow::Result<PathBuf> {
         debug!("Locating configuration directory");
-        let config_path = dirs::config_dir()
-            .ok_or_else(|| {
-                error!("Could not find system config directory");
-                anyhow::anyhow!("Could not find config directory")
-            })?
-            .join(CONFIG_FILE);
+        Ok(Paths::detect()?.config_file)
+    }
+
+    /// Open the user's config file for changes that keep its comments
+    pub fn edit() -> anyhow::Result<ConfigEdit> {
+        ConfigEdit::open(&Self::path()?)
+    }
+
+    /// The user's directories with the `[paths]` overrides applied
+    pub fn paths(&self) -> anyhow::Result<Paths> {
+        Ok(Paths::detect()?.with_overrides(&self.paths))
+    }
+
+    /// Name of the `[game.<name>]` section for a launch, from the first of
+    /// the `[resolve]` resolvers that names one. Falls back to the stem.
+    pub fn resolve_game(&self, hints: &GameHints) -> String {
+        for resolver in &self.resolve.resolvers {
+            if let Some(name) = self.resolve_with(*resolver, hints) {
+                info!("Game '{}' identified by the {} resolver", name, resolver);
+                return name;
+            }
+        }
+
+        debug!("No resolver named the game, using '{}'", hints.stem);
+        hints.stem.clone()
+    }
+
+    fn resolve_with(&self, resolver: Resolver, hints: &GameHints) -> Option<String> {
+        match resolver {
+            Resolver::Env => {
+                let name = hints.env_name.clone()?;
+                if !self.game.contains_key(&name) {
+                    warn!(
+                        "{} names '{}', which has no [game.{}]",
+                        GAME_ENV, name, name
+                    );
+                }
+                Some(name)
+            }
+            Resolver::Appid => {
+                let app_id = hints.app_id?;
+                self.first_game(|game| game.app_id.contains(&app_id))
+            }
+            Resolver::Product => {
+                let product = normalize_name(&hints.product_name()?);
+                self.first_game_named(|name| normalize_name(name) == product)
+            }
+            Resolver::Stem => self
+                .game
+                .contains_key(&hints.stem)
+                .then(|| hints.stem.clone()),
+            Resolver::Match => self.first_game(|game| game.matches(&hints.stem, &hints.path)),
+        }
+    }
+
+    /// The first section, by name, `accept` takes, warning when it is not
+    /// the only one
+    fn first_game(&self, accept: impl Fn(&GameConfig) -> bool) -> Option<String> {
+        self.first_game_named(|name| accept(&self.game[name]))
+    }
+
+    fn first_game_named(&self, accept: impl Fn(&str) -> bool) -> Option<String> {
+        let mut matches: Vec<&String> = self.game.keys().filter(|name| accept(name)).collect();
+        matches.sort();
+
+        match matches.as_slice() {
+            [] => None,
+            [name] => Some(name.to_string()),
+            [name, others @ ..] => {
+                warn!(
+                    "Launch matches [game.{}] and {:?}, using [game.{}]",
+                    name, others, name
+                );
+                Some(name.to_string())
+            }
+        }
+    }
+
+    /// CPU tuning for a game, with its own frequency cap, SMT mode and share
+    /// of the power budget taking precedence
+    pub fn cpu_for(&self, game: &GameConfig) -> CpuTune {
+        let mut cpu = self.cpu.clone();
+        if let Some(enabled) = game.tune_cpu {
+            cpu.enabled = enabled;
+        }
+        if let Some(mhz) = game.cpu_max_freq_mhz {
+            cpu.cpu_max_freq_mhz = Some(mhz);
+        }
+        if let Some(smt) = game.smt {
+            cpu.smt = Some(smt);
+        }
+        if let Some((_, cpu_mw)) = self.budget.split(game.pwr_limit_tune)
+            && cpu_mw > 0
+        {
+            cpu.ppt_limit = Some(cpu_mw);
+        }
+        cpu
+    }
+
+    pub fn sys_for(&self, game: &GameConfig) -> SysTune {
+        let mut sys = self.sys.clone();
+        sys.strict |= game.strict;
+        if let Some(enabled) = game.tune_proc {
+            sys.enabled = enabled;
+        }
+        if let Some(level) = game.proc_ioprio {
+            sys.proc_ioprio = level;
+        }
+        if let Some(class) = game.proc_ioprio_class {
+            sys.proc_ioprio_class = class;
+        }
+        sys
+    }
+
+    pub fn gpu_for(&self, game: &GameConfig) -> GpuTune {
+        let mut gpu = self.gpu.clone();
+        if let Some(enabled) = game.tune_gpu {
+            gpu.enabled = enabled;
+        }
+        let budget = self.budget.split(None).map(|(gpu_mw, _)| gpu_mw);
+        if let Some(limit) = game.pwr_limit_tune.or(budget) {
+            gpu.pwr_limit_tune = Some(limit);
+            gpu.set_max_pwr = false;
+        }
+        if let Some(mhz) = game.min_mem_clock_mhz {
+            gpu.min_mem_clock_mhz = Some(mhz);
+        }
+        gpu
+    }
+
+    /// Top-level sections that differ between two versions of the config,
+    /// sorted. Game sections are told apart as `game.<name>`.
+    pub fn changed_sections(old: &str, new: &str) -> anyhow::Result<Vec<String>> {
+        let old: toml::Table = toml::from_str(old)?;
+        let new: toml::Table = toml::from_str(new)?;
+        let empty = toml::Table::new();
+
+        let mut changed = BTreeSet::new();
+        for key in changed_keys(&old, &new) {
+            if key != "game" {
+                changed.insert(key.clone());
+                continue;
+            }
+
+            let games = |table: &toml::Table| table.get("game").and_then(|g| g.as_table()).cloned();
+            let (old_games, new_games) = (games(&old), games(&new));
+            let old_games = old_games.as_ref().unwrap_or(&empty);
+            let new_games = new_games.as_ref().unwrap_or(&empty);
+            changed.extend(changed_keys(old_games, new_games).map(|name| format!("game.{}", name)));
+        }
+        Ok(changed.into_iter().collect())
+    }
 
-        Self::load_file(config_path)
+    /// Short stable checksum of the config file's content
+    pub fn checksum(content: &str) -> String {
+        let mut hasher = fnv::FnvHasher::default();
+        hasher.write(content.as_bytes());
+        format!("{:016x}", hasher.finish())
     }
 
     pub fn load_file(config_path: PathBuf) -> anyhow::Result<Self> {
@@ -216,8 +1703,45 @@ impl Config {
             debug!("  Shutdown hook: {}", shutdown_hook);
         }
 
+        for conflict in config.env_conflicts() {
+            warn!("{}", conflict);
+        }
+
         Ok(config)
     }
+
+    /// Variables the options of a game and its environment section set to
+    /// different values, for every game in order of their names
+    pub fn env_conflicts(&self) -> Vec<EnvConflict> {
+        let mut games: Vec<&String> = self.game.keys().collect();
+        games.sort();
+        games
+            .into_iter()
+            .flat_map(|game| EnvBuilder::find_conflicts(self, game))
+            .collect()
+    }
+
+    /// Refuse a config whose game options contradict their environment
+    /// sections, which loading it only warns about
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let conflicts = self.env_conflicts();
+        if conflicts.is_empty() {
+            return Ok(());
+        }
+
+        let lines: Vec<String> = conflicts.iter().map(ToString::to_string).collect();
+        anyhow::bail!("{}", lines.join("\n"))
+    }
+}
+
+/// Keys of either table whose values differ, a missing key counts as changed
+fn changed_keys<'a>(
+    old: &'a toml::Table,
+    new: &'a toml::Table,
+) -> impl Iterator<Item = &'a String> {
+    old.keys()
+        .chain(new.keys().filter(|key| !old.contains_key(*key)))
+        .filter(|key| old.get(*key) != new.get(*key))
 }
 
 #[cfg(test)]
@@ -232,6 +1756,9 @@ mod tests {
         assert!(!cpu.enabled);
         assert_eq!(cpu.amd_epp_tune, "performance");
         assert_eq!(cpu.amd_epp_base, "balance_performance");
+        assert!(cpu.cpu_dma_latency_us.is_none());
+        assert!(!cpu.dynamic.enabled);
+        assert_eq!(cpu.dynamic.gpu_bound_epp, "balance_performance");
     }
 
     #[test]
@@ -245,13 +1772,64 @@ mod tests {
         assert!(gpu.pwr_limit_tune.is_none());
     }
 
+    #[test]
+    fn test_parse_power_limit() {
+        assert_eq!(parse_power_limit("110W"), Ok(110_000));
+        assert_eq!(parse_power_limit("110 w"), Ok(110_000));
+        assert_eq!(parse_power_limit("112.5W"), Ok(112_500));
+        assert_eq!(parse_power_limit("110000mW"), Ok(110_000));
+        assert_eq!(parse_power_limit("110000 MW"), Ok(110_000));
+        assert_eq!(parse_power_limit("350000"), Ok(350_000));
+        assert!(parse_power_limit("fast").is_err());
+        assert!(parse_power_limit("-5W").is_err());
+    }
+
+    #[test]
+    fn test_power_limit_implausible_values() {
+        assert_eq!(parse_power_limit("450"), Ok(450_000));
+        assert!(
+            parse_power_limit("450mW")
+                .unwrap_err()
+                .contains("out of range")
+        );
+        assert_eq!(parse_power_limit("2000W"), Ok(2_000_000));
+    }
+
+    #[test]
+    fn test_power_limit_config_forms() {
+        let parse = |value: &str| -> Option<u32> {
+            let config: Config =
+                toml::from_str(&format!("[gpu]\npwr_limit_tune = {}", value)).unwrap();
+            config.gpu.pwr_limit_tune
+        };
+
+        assert_eq!(parse("350000"), Some(350_000));
+        assert_eq!(parse("350"), Some(350_000));
+        assert_eq!(parse(r#""110W""#), Some(110_000));
+        assert_eq!(parse(r#""95000mW""#), Some(95_000));
+        assert!(toml::from_str::<Config>("[gpu]\npwr_limit_tune = \"lots\"").is_err());
+    }
+
     #[test]
     fn test_sys_tune_defaults() {
         let sys = SysTune::default();
         assert!(!sys.enabled);
         assert_eq!(sys.proc_ioprio, 4);
+        assert_eq!(sys.proc_ioprio_class, IoprioClass::Be);
         assert_eq!(sys.proc_renice, 0);
         assert!(!sys.splitlock_hack);
+        assert!(sys.link_limit_kbit.is_none());
+        assert!(sys.link_iface.is_none());
+        assert!(!sys.strict);
+    }
+
+    #[test]
+    fn test_sys_tune_link_limit_old_names() {
+        let config: Config =
+            toml::from_str("[sys]\ndownload_limit_kbit = 20000\ndownload_iface = \"eth0\"")
+                .unwrap();
+        assert_eq!(config.sys.link_limit_kbit, Some(20_000));
+        assert_eq!(config.sys.link_iface.as_deref(), Some("eth0"));
     }
 
     #[test]
@@ -262,7 +1840,331 @@ mod tests {
         assert!(!game.proton_log);
         assert!(!game.proton_ntsync);
         assert!(!game.proton_wayland);
-        assert!(game.wine_dll_overrides.is_none());
+        assert!(game.wine_dll_overrides.is_empty());
+        assert!(!game.restart_on_crash);
+        assert_eq!(game.max_restarts, 3);
+        assert!(game.allowed_exit_codes.is_empty());
+        assert!(game.mount.is_empty());
+        assert!(!game.inhibit_sleep);
+        assert!(!game.do_not_disturb);
+        assert!(!game.strict);
+        assert!(!game.subreaper);
+        assert!(game.inherit_defaults);
+        assert!(!game.strict_env);
+        assert_eq!(game.backend, LaunchBackend::Exec);
+        assert!(game.extra_library_paths.is_empty());
+        assert!(game.prepend_path.is_empty());
+        assert_eq!(game.audio, AudioConfig::default());
+        assert_eq!(game.dxvk, DxvkConfig::default());
+        assert_eq!(game.capture, CaptureConfig::default());
+    }
+
+    #[test]
+    fn test_resolve_game_matchers() {
+        let config: Config = toml::from_str(
+            r#"
+            [game.ffxiv]
+            match = "regex:^ffxiv(_dx11)?$"
+
+            [game.ffxivlauncher]
+            mangohud = true
+
+            [game.witcher]
+            match_path = "glob:**/The Witcher 3/**"
+            "#,
+        )
+        .unwrap();
+
+        let resolve = |stem: &str, path: &str| config.resolve_game(&GameHints::new(stem, path));
+        let path = "/games/FFXIV/game/ffxiv_dx11.exe";
+        assert_eq!(resolve("ffxiv_dx11", path), "ffxiv");
+        assert_eq!(resolve("ffxivlauncher", path), "ffxivlauncher");
+        assert_eq!(
+            resolve("witcher3", "/games/The Witcher 3/bin/x64/witcher3.exe"),
+            "witcher"
+        );
+        assert_eq!(resolve("other", "/games/other.exe"), "other");
+
+        let mut hints = GameHints::new("ffxiv_dx11", path);
+        hints.env_name = Some("manual".to_string());
+        assert_eq!(config.resolve_game(&hints), "manual");
+
+        assert!(toml::from_str::<Config>("[game.bad]\nmatch = \"regex:(\"").is_err());
+    }
+
+    #[test]
+    fn test_game_config_mount_single_and_many() {
+        let toml_content = r#"
+[game.single]
+mount = "games"
+
+[game.many]
+mount = ["games", "nas"]
+        "#;
+
+        let config: Config = toml::from_str(toml_content).unwrap();
+
+        assert_eq!(config.game.get("single").unwrap().mount, vec!["games"]);
+        assert_eq!(config.game.get("many").unwrap().mount, vec!["games", "nas"]);
+        assert!(toml::from_str::<Config>("[game.bad.mount]\nmountpoint = \"/etc\"").is_err());
+    }
+
+    #[test]
+    fn test_game_config_vram_pressure_signal() {
+        let toml_content = r#"
+[game.cyberpunk]
+vram_pressure_signal = "SIGTERM"
+vram_pressure_targets = ["firefox"]
+        "#;
+
+        let config: Config = toml::from_str(toml_content).unwrap();
+        let game = config.game.get("cyberpunk").unwrap();
+
+        assert_eq!(game.vram_pressure_signal.as_deref(), Some("SIGTERM"));
+        assert_eq!(game.vram_pressure_targets, vec!["firefox"]);
+        assert!(toml::from_str::<Config>("[game.bad]\nvram_pressure_signal = \"TERM\"").is_err());
+    }
+
+    #[test]
+    fn test_resolve_game_order() {
+        let config: Config = toml::from_str(
+            r#"
+            [resolve]
+            resolvers = ["appid", "stem"]
+
+            [game.eldenring]
+            app_id = 1245620
+
+            [game.start_protected_game]
+            "#,
+        )
+        .unwrap();
+
+        let mut hints = GameHints::new("start_protected_game", "/games/start_protected_game.exe");
+        assert_eq!(config.resolve_game(&hints), "start_protected_game");
+        hints.app_id = Some(1245620);
+        assert_eq!(config.resolve_game(&hints), "eldenring");
+
+        assert!(toml::from_str::<Config>("[resolve]\nresolvers = [\"guess\"]").is_err());
+    }
+
+    #[test]
+    fn test_game_config_wait_for() {
+        let toml_content = r#"
+[game.modded]
+wait_for = [{ unit = "mo2.service" }, { port = 27015 }, { delay_ms = 3000 }]
+wait_timeout_sec = 10
+        "#;
+
+        let config: Config = toml::from_str(toml_content).unwrap();
+        let game = config.game.get("modded").unwrap();
+        assert_eq!(
+            game.wait_for,
+            [
+                WaitCondition::Unit {
+                    unit: "mo2.service".to_string()
+                },
+                WaitCondition::Port {
+                    port: 27015,
+                    host: None
+                },
+                WaitCondition::Delay { delay_ms: 3000 },
+            ]
+        );
+        assert_eq!(game.wait_for[1].to_string(), "port 127.0.0.1:27015");
+        assert_eq!(game.wait_timeout_sec, 10);
+    }
+
+    #[test]
+    fn test_gpu_for_game_power_limit() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [gpu]
+            set_max_pwr = true
+
+            [game.cyberpunk2077]
+            pwr_limit_tune = "280W"
+            "#,
+        )
+        .unwrap();
+
+        let gpu = config.gpu_for(&config.game["cyberpunk2077"]);
+        assert_eq!(gpu.pwr_limit_tune, Some(280_000));
+        assert!(!gpu.set_max_pwr);
+
+        config.game.clear();
+        let gpu = config.gpu_for(&GameConfig::default());
+        assert_eq!(gpu.pwr_limit_tune, None);
+        assert!(gpu.set_max_pwr);
+    }
+
+    #[test]
+    fn test_cpu_for_game_freq_cap() {
+        let config: Config = toml::from_str(
+            r#"
+            [cpu]
+            cpu_max_freq_mhz = 4000
+
+            [game.quiet]
+            cpu_max_freq_mhz = 3200
+            smt = "off"
+            "#,
+        )
+        .unwrap();
+
+        let cpu = config.cpu_for(&config.game["quiet"]);
+        assert_eq!(cpu.cpu_max_freq_mhz, Some(3200));
+        assert_eq!(cpu.smt, Some(SmtMode::Off));
+        let cpu = config.cpu_for(&GameConfig::default());
+        assert_eq!(cpu.cpu_max_freq_mhz, Some(4000));
+        assert_eq!(cpu.smt, None);
+    }
+
+    #[test]
+    fn test_budget_split() {
+        let config: Config = toml::from_str(
+            r#"
+            [gpu]
+            pwr_limit_tune = "150W"
+
+            [budget]
+            total_w = 95
+
+            [game.capped]
+            pwr_limit_tune = "50W"
+            "#,
+        )
+        .unwrap();
+
+        let game = GameConfig::default();
+        assert_eq!(config.gpu_for(&game).pwr_limit_tune, Some(66_500));
+        assert_eq!(config.cpu_for(&game).ppt_limit, Some(28_500));
+
+        let capped = &config.game["capped"];
+        assert_eq!(config.gpu_for(capped).pwr_limit_tune, Some(50_000));
+        assert_eq!(config.cpu_for(capped).ppt_limit, Some(45_000));
+
+        let all_gpu = BudgetConfig {
+            total_w: Some(80),
+            gpu_bias: 1.5,
+        };
+        assert_eq!(all_gpu.split(None), Some((80_000, 0)));
+        assert_eq!(BudgetConfig::default().split(None), None);
+    }
+
+    #[test]
+    fn test_subsystem_toggles_per_game() {
+        let config: Config = toml::from_str(
+            r#"
+            [gpu]
+            gpu_tuning = true
+
+            [cpu]
+            cpu_tuning = true
+
+            [sys]
+            sys_tuning = true
+
+            [game.anticheat]
+            tune_proc = false
+            "#,
+        )
+        .unwrap();
+
+        let game = &config.game["anticheat"];
+        assert!(config.gpu_for(game).enabled);
+        assert!(config.cpu_for(game).enabled);
+        assert!(!config.sys_for(game).enabled);
+        assert!(config.sys_for(&GameConfig::default()).enabled);
+    }
+
+    #[test]
+    fn test_sys_for_game_ioprio() {
+        let config: Config = toml::from_str(
+            r#"
+            [sys]
+            proc_ioprio = 2
+
+            [game.streaming]
+            proc_ioprio = 0
+            proc_ioprio_class = "rt"
+            strict = true
+            "#,
+        )
+        .unwrap();
+
+        let sys = config.sys_for(&config.game["streaming"]);
+        assert_eq!(sys.proc_ioprio, 0);
+        assert_eq!(sys.proc_ioprio_class, IoprioClass::Rt);
+        assert!(sys.strict);
+        let sys = config.sys_for(&GameConfig::default());
+        assert_eq!(sys.proc_ioprio, 2);
+        assert_eq!(sys.proc_ioprio_class, IoprioClass::Be);
+    }
+
+    #[test]
+    fn test_daemon_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [daemon.env]
+            __EGL_VENDOR_LIBRARY_FILENAMES = "/usr/share/glvnd/egl_vendor.d/10_nvidia.json"
+            __NV_PRIME_RENDER_OFFLOAD = 1
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.daemon.env.len(), 2);
+        assert_eq!(
+            config.daemon.env["__NV_PRIME_RENDER_OFFLOAD"].to_string(),
+            "1"
+        );
+        assert!(!config.env.contains_key("daemon"));
+    }
+
+    #[test]
+    fn test_schedule_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [[schedule]]
+            start = "22:30"
+            end = "07:00"
+            pwr_limit_tune = "150W"
+            amd_epp_tune = "power"
+
+            [[schedule]]
+            start = "09:00"
+            end = "17:00"
+            "#,
+        )
+        .unwrap();
+
+        let night = &config.schedule[0];
+        assert_eq!(night.pwr_limit_tune, Some(150_000));
+        assert_eq!(night.amd_epp_tune.as_deref(), Some("power"));
+        assert!(config.schedule[1].pwr_limit_tune.is_none());
+        assert!(!config.env.contains_key("schedule"));
+
+        let at = |hm: &str| NaiveTime::parse_from_str(hm, "%H:%M").unwrap();
+        assert!(night.is_active(at("23:00")));
+        assert!(night.is_active(at("06:59")));
+        assert!(!night.is_active(at("07:00")));
+        assert!(!night.is_active(at("12:00")));
+        assert!(config.schedule[1].is_active(at("09:00")));
+        assert!(!config.schedule[1].is_active(at("17:00")));
+
+        assert!(toml::from_str::<Config>("[schedule]\nstart = \"late\"\nend = \"07:00\"").is_err());
+    }
+
+    #[test]
+    fn test_game_config_allowed_exit() {
+        let game = GameConfig {
+            allowed_exit_codes: vec![1, 42],
+            ..Default::default()
+        };
+        assert!(game.is_allowed_exit(0));
+        assert!(game.is_allowed_exit(42));
+        assert!(!game.is_allowed_exit(-1));
+        assert!(!game.is_allowed_exit(2));
     }
 
     #[test]
@@ -290,6 +2192,11 @@ mod tests {
 cpu_tuning = true
 amd_epp_tune = "performance"
 amd_epp_base = "balance_performance"
+cpu_dma_latency_us = 0
+
+[cpu.dynamic]
+enabled = true
+hold_sec = 3
 
 [gpu]
 gpu_tuning = true
@@ -308,6 +2215,8 @@ splitlock_hack = true
 [hook]
 init = "echo 'Starting game'"
 shutdown = "echo 'Game ended'"
+post_spawn = ["renice -n 5 -p $NVPRIME_GAME_PID", "obs-cli start"]
+post_exit = { cmd = "backup-saves", on_failure = "stop" }
 
 [game.testgame]
 mangohud = true
@@ -316,12 +2225,19 @@ proton_log = true
 proton_ntsync = true
 proton_wayland = false
 wine_dll_overrides = "dinput8=n,b"
+restart_on_crash = true
+max_restarts = 5
+allowed_exit_codes = [1, 3]
         "#;
 
         let config: Config = toml::from_str(toml_content).unwrap();
 
         assert!(config.cpu.enabled);
         assert_eq!(config.cpu.amd_epp_tune, "performance");
+        assert_eq!(config.cpu.cpu_dma_latency_us, Some(0));
+        assert!(config.cpu.dynamic.enabled);
+        assert_eq!(config.cpu.dynamic.hold_sec, 3);
+        assert_eq!(config.cpu.dynamic.gpu_util_percent, 95);
 
         assert!(config.gpu.enabled);
         assert_eq!(
@@ -339,11 +2255,71 @@ wine_dll_overrides = "dinput8=n,b"
 
         assert_eq!(config.hook.init, Some("echo 'Starting game'".to_string()));
         assert_eq!(config.hook.shutdown, Some("echo 'Game ended'".to_string()));
+        assert_eq!(config.hook.post_spawn.cmd.len(), 2);
+        assert_eq!(config.hook.post_spawn.on_failure, None);
+        assert_eq!(config.hook.post_exit.cmd, ["backup-saves"]);
+        assert_eq!(config.hook.post_exit.on_failure, Some(HookFailure::Stop));
+        assert!(config.hook.pre_env.cmd.is_empty());
 
         let game = config.game.get("testgame").unwrap();
         assert!(game.mangohud);
         assert_eq!(game.mangohud_conf, Some("fps_only=1".to_string()));
         assert!(game.proton_log);
+        assert!(game.restart_on_crash);
+        assert_eq!(game.max_restarts, 5);
+        assert_eq!(game.allowed_exit_codes, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_game_config_dll_overrides() {
+        let config: Config = toml::from_str(
+            r#"
+[game.eldenring]
+dll_overrides = { dinput8 = "native,builtin", xaudio2_7 = "n", winemenubuilder = "" }
+"#,
+        )
+        .unwrap();
+        let game = &config.game["eldenring"];
+        assert_eq!(game.dll_overrides["dinput8"], DllMode::NativeBuiltin);
+        assert_eq!(game.dll_overrides["xaudio2_7"], DllMode::Native);
+        assert_eq!(game.dll_overrides["winemenubuilder"], DllMode::Disabled);
+
+        assert_eq!("B, N".parse(), Ok(DllMode::BuiltinNative));
+        for invalid in ["nb", "native+builtin", "n;b", "n,n", "bultin"] {
+            assert!(invalid.parse::<DllMode>().is_err(), "{}", invalid);
+        }
+
+        let err = toml::from_str::<Config>("[game.doom]\ndll_overrides = { dinput8 = \"nb\" }\n")
+            .unwrap_err();
+        assert!(err.to_string().contains("for dinput8"), "{}", err);
+        assert!(
+            toml::from_str::<Config>("[game.doom]\ndll_overrides = { \"d3d9,dxgi\" = \"n\" }\n")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_wine_dll_overrides() {
+        let dlls = parse_wine_dll_overrides("d3d9,dxgi=n;winemenubuilder.exe=d;").unwrap();
+        assert_eq!(
+            dlls,
+            BTreeMap::from([
+                ("d3d9".to_string(), DllMode::Native),
+                ("dxgi".to_string(), DllMode::Native),
+                ("winemenubuilder.exe".to_string(), DllMode::Disabled),
+            ])
+        );
+        assert_eq!(
+            wine_dll_overrides_value(&dlls),
+            "d3d9=n;dxgi=n;winemenubuilder.exe="
+        );
+
+        let err = parse_wine_dll_overrides("dinput8=native+builtin").unwrap_err();
+        assert!(err.contains("for dinput8"), "{}", err);
+        assert!(parse_wine_dll_overrides("dinput8").is_err());
+        assert!(
+            toml::from_str::<Config>("[game.doom]\nwine_dll_overrides = \"dinput8=nb\"\n").is_err()
+        );
     }
 
     #[test]
@@ -364,6 +2340,42 @@ gpu_name = "Test GPU"
         assert_eq!(config.gpu.gpu_name, Some("Test GPU".to_string()));
     }
 
+    #[test]
+    fn test_config_load_file_env_conflicts() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(
+            temp_file,
+            "[game.zeta]\nproton_log = true\n\n[zeta]\nPROTON_LOG = 0\n\n\
+             [game.alpha]\nmangohud = false\n\n[alpha]\nMANGOHUD = 1\n"
+        )
+        .unwrap();
+
+        // Loading only warns, validating refuses it
+        let config = Config::load_file(temp_file.path().to_path_buf()).unwrap();
+        let conflicts: Vec<(String, String)> = config
+            .env_conflicts()
+            .into_iter()
+            .map(|conflict| (conflict.game, conflict.key))
+            .collect();
+        assert_eq!(
+            conflicts,
+            vec![
+                ("alpha".to_string(), "MANGOHUD".to_string()),
+                ("zeta".to_string(), "PROTON_LOG".to_string())
+            ]
+        );
+        let error = config.validate().unwrap_err().to_string();
+        assert!(
+            error.contains("[game.alpha] sets '0', [alpha] sets '1'"),
+            "{}",
+            error
+        );
+
+        let config: Config =
+            toml::from_str("[game.alpha]\nmangohud = true\n\n[alpha]\nMANGOHUD = 1\n").unwrap();
+        assert!(config.validate().is_ok());
+    }
+
     #[test]
     fn test_config_load_file_nonexistent() {
         let result = Config::load_file(PathBuf::from("/nonexistent/config.toml"));
@@ -379,6 +2391,27 @@ gpu_name = "Test GPU"
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_changed_sections() {
+        let old = "[sys]\nsys_tuning = true\n\n[game.elden]\nmangohud = true\n\n[game.doom]\nmangohud = true\n";
+        let new = "[sys]\nsys_tuning = true\n\n[game.elden]\nmangohud = false\n\n[cpu]\ncpu_tuning = true\n";
+
+        assert_eq!(
+            Config::changed_sections(old, new).unwrap(),
+            ["cpu", "game.doom", "game.elden"]
+        );
+        assert!(Config::changed_sections(old, old).unwrap().is_empty());
+        assert!(Config::changed_sections(old, "[sys").is_err());
+    }
+
+    #[test]
+    fn test_config_checksum() {
+        let checksum = Config::checksum("[sys]\nsys_tuning = true\n");
+        assert_eq!(checksum.len(), 16);
+        assert_eq!(checksum, Config::checksum("[sys]\nsys_tuning = true\n"));
+        assert_ne!(checksum, Config::checksum("[sys]\nsys_tuning = false\n"));
+    }
+
     #[test]
     fn test_config_serialization() {
         let gpu = GpuTune {
@@ -386,8 +2419,13 @@ gpu_name = "Test GPU"
             gpu_name: Some("Test".to_string()),
             gpu_uuid: None,
             gpu_vlk_icd: "/test.json".to_string(),
+            gpu_vlk_icd32: None,
             set_max_pwr: true,
             pwr_limit_tune: Some(400000),
+            prefer_switcheroo: false,
+            min_mem_clock_mhz: None,
+            busy_util_percent: None,
+            busy_vram_percent: None,
         };
 
         let json = serde_json::to_string(&gpu).unwrap();
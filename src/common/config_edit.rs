@@ -0,0 +1,166 @@
+use anyhow::Context;
+use log::debug;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::common::Config;
+
+/// Top-level tables of the config that are not environment groups
+pub const CONFIG_SECTIONS: &[&str] = &[
+    "cpu", "gpu", "sys", "game", "hook", "daemon", "paths", "schedule", "updates", "budget",
+    "resolve",
+];
+
+/// The config file opened for changes from the commands, keeping the user's
+/// comments and layout. Nothing is written until [`ConfigEdit::save`].
+pub struct ConfigEdit {
+    path: PathBuf,
+    doc: DocumentMut,
+}
+
+impl ConfigEdit {
+    /// Open a config file, a missing one starts out empty
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read config {}", path.display()));
+            }
+        };
+        Self::parse(path, &content)
+    }
+
+    fn parse(path: &Path, content: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            doc: content.parse().context("Failed to parse config")?,
+        })
+    }
+
+    /// Set an option in the game's `[game.<name>]` section, creating it when
+    /// needed
+    pub fn set_game_option(
+        &mut self,
+        game: &str,
+        key: &str,
+        value: impl Into<toml_edit::Value>,
+    ) -> anyhow::Result<()> {
+        let games = self
+            .doc
+            .entry("game")
+            .or_insert_with(|| {
+                let mut table = Table::new();
+                table.set_implicit(true);
+                Item::Table(table)
+            })
+            .as_table_mut()
+            .context("`game` in config is not a table")?;
+
+        let section = games
+            .entry(game)
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .with_context(|| format!("`game.{}` in config is not a table", game))?;
+
+        section[key] = toml_edit::value(value);
+        Ok(())
+    }
+
+    /// Set a variable in the game's environment group
+    pub fn set_env(&mut self, game: &str, key: &str, value: &str) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !CONFIG_SECTIONS.contains(&game),
+            "'{}' is a config section, not a game",
+            game
+        );
+
+        let section = self
+            .doc
+            .entry(game)
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .with_context(|| format!("`{}` in config is not a table", game))?;
+
+        section[key] = toml_edit::value(value);
+        Ok(())
+    }
+
+    /// Write the config back. The result must still load, and replaces the
+    /// file in one rename so a crash never leaves half a config behind.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let content = self.to_string();
+        toml::from_str::<Config>(&content)
+            .map_err(|e| anyhow::anyhow!("Edited config would not load: {}", e))?;
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        std::fs::write(&tmp, &content)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace config {}", self.path.display()))?;
+
+        debug!("Saved config {}", self.path.display());
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ConfigEdit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_game_option_keeps_comments() {
+        let content =
+            "# my config\n[gpu]\ngpu_tuning = true # keep\n\n[game.elden]\nmangohud = true\n";
+        let mut edit = ConfigEdit::parse(Path::new("nvprime.conf"), content).unwrap();
+
+        edit.set_game_option("elden", "strict", true).unwrap();
+        edit.set_game_option("doom", "proc_ioprio", 2).unwrap();
+        let updated = edit.to_string();
+
+        assert!(updated.starts_with("# my config\n[gpu]\ngpu_tuning = true # keep\n"));
+        assert!(updated.contains("mangohud = true\nstrict = true\n"));
+        assert!(updated.ends_with("[game.doom]\nproc_ioprio = 2\n"));
+    }
+
+    #[test]
+    fn test_set_env_rejects_sections() {
+        let mut edit = ConfigEdit::parse(Path::new("nvprime.conf"), "").unwrap();
+        edit.set_env("mygame", "A", "1").unwrap();
+        assert_eq!(edit.to_string(), "[mygame]\nA = \"1\"\n");
+
+        assert!(edit.set_env("sys", "A", "1").is_err());
+    }
+
+    #[test]
+    fn test_save_replaces_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nvprime.conf");
+        std::fs::write(&path, "# kept\n[sys]\nsys_tuning = true\n").unwrap();
+
+        let mut edit = ConfigEdit::open(&path).unwrap();
+        edit.set_game_option("doom", "mangohud", true).unwrap();
+        edit.save().unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# kept\n"));
+        assert!(Config::load_file(path.clone()).unwrap().game["doom"].mangohud);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        // An edit that would break loading leaves the file alone
+        let mut edit = ConfigEdit::open(&path).unwrap();
+        edit.set_game_option("doom", "mangohud", "sometimes")
+            .unwrap();
+        assert!(edit.save().is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+    }
+}
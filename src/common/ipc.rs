@@ -1,9 +1,317 @@
 use crate::common::config::{CpuTune, GpuTune, SysTune};
-use crate::service::daemon::{DaemonState, start_pid_watchdog};
-use log::{error, info};
+use crate::service::conditions;
+use crate::service::daemon::{
+    BOOST_PROFILE, DaemonState, MANUAL_CPU_PROFILE, MANUAL_GPU_PROFILE, PowerLimitChange,
+    SessionEvent, SessionKey, start_boost_window, start_pid_watchdog, start_priority_phases,
+};
+use crate::service::inhibit::SleepInhibitor;
+use crate::service::policy::{self, DaemonPolicy, PolicyFeature};
+use crate::service::ryzen::{EppProfile, RyzenEPPManager};
+use log::{debug, error, info, warn};
+use std::collections::BTreeMap;
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use zbus::message::Header;
+use zbus::object_server::SignalEmitter;
 use zbus::{interface, proxy};
 
+/// Object path the daemon serves its interface at
+pub const SERVICE_PATH: &str = "/com/github/nvprime";
+
+/// Version of the D-Bus protocol, raised whenever a method or payload
+/// changes in a way the other side cannot ignore
+pub const API_VERSION: u32 = 3;
+
+/// What the daemon can tune on its machine, sent as a bitmap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// GPU power limits and memory clock locking through NVML
+    pub const GPU_TUNING: Self = Self(1 << 0);
+
+    /// cgroup v2, for memory protection and background CPU isolation
+    pub const CGROUPS: Self = Self(1 << 1);
+
+    /// amd-pstate EPP hints
+    pub const AMD_EPP: Self = Self(1 << 2);
+
+    /// What a daemon from before `GetCapabilities` is taken to support,
+    /// leaving it to fail the way it always did
+    pub const LEGACY: Self = Self(Self::GPU_TUNING.0 | Self::CGROUPS.0 | Self::AMD_EPP.0);
+
+    const NAMES: [(Self, &'static str); 3] = [
+        (Self::GPU_TUNING, "gpu-tuning"),
+        (Self::CGROUPS, "cgroups"),
+        (Self::AMD_EPP, "amd-epp"),
+    ];
+
+    pub fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Bits of a newer daemon unknown here are kept, and ignored
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    /// Names of the known capabilities that are set
+    pub fn names(&self) -> Vec<&'static str> {
+        Self::NAMES
+            .iter()
+            .filter(|(cap, _)| self.contains(*cap))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+/// Protocol version and capabilities of the running daemon
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DaemonApi {
+    /// 0 for a daemon from before `ApiVersion`
+    pub version: u32,
+    pub capabilities: Capabilities,
+}
+
+impl DaemonApi {
+    /// Ask the daemon, taking one that does not know the methods for the
+    /// last version without them
+    pub async fn query(proxy: &NvPrimeClientProxy<'_>) -> Self {
+        let version = match proxy.api_version().await {
+            Ok(version) => version,
+            Err(e) => {
+                debug!("Daemon has no ApiVersion: {}", e);
+                return Self {
+                    version: 0,
+                    capabilities: Capabilities::LEGACY,
+                };
+            }
+        };
+        let capabilities = match proxy.get_capabilities().await {
+            Ok(bits) => Capabilities::from_bits(bits),
+            Err(e) => {
+                warn!("Failed to get daemon capabilities: {}", e);
+                Capabilities::LEGACY
+            }
+        };
+
+        let api = Self {
+            version,
+            capabilities,
+        };
+        debug!("{}", api);
+        api
+    }
+
+    /// Mismatch between the daemon and this client worth a warning
+    pub fn version_warning(&self) -> Option<String> {
+        match self.version.cmp(&API_VERSION) {
+            std::cmp::Ordering::Less => Some(format!(
+                "The daemon is older than this client (API v{} < v{}), restart nvprime_sys after upgrading",
+                self.version, API_VERSION
+            )),
+            std::cmp::Ordering::Greater => Some(format!(
+                "The daemon is newer than this client (API v{} > v{}), some options may be ignored",
+                self.version, API_VERSION
+            )),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    /// Send a launch's tuning in the form this daemon takes
+    pub async fn apply_tuning(
+        &self,
+        proxy: &NvPrimeClientProxy<'_>,
+        pid: u32,
+        config_json: String,
+    ) -> zbus::Result<AppliedTuning> {
+        if self.version < 2 {
+            let policy = proxy.apply_tuning_v1(pid, config_json).await?;
+            return Ok(AppliedTuning::from_policy(policy));
+        }
+
+        let json = proxy.apply_tuning(pid, config_json).await?;
+        serde_json::from_str(&json).map_err(|e| {
+            zbus::Error::Failure(format!("Invalid ApplyTuning answer from the daemon: {}", e))
+        })
+    }
+
+    /// End the launch session of `pid`, leaving the caller's other sessions
+    /// running. Daemons before API v3 can only reset all of them.
+    pub async fn end_session(&self, proxy: &NvPrimeClientProxy<'_>, pid: u32) -> zbus::Result<()> {
+        if self.version < 3 {
+            return proxy.reset_tuning().await;
+        }
+        proxy.end_session(pid).await
+    }
+
+    /// Drop what the daemon cannot do from a launch request, so it is
+    /// skipped instead of failing the launch. Returns what was dropped.
+    pub fn adapt(
+        &self,
+        cpu: &mut CpuTune,
+        gpu: &mut GpuTune,
+        sys: &mut SysTune,
+        memory_protect_gb: &mut Option<u32>,
+    ) -> Vec<String> {
+        let caps = self.capabilities;
+        let mut dropped = Vec::new();
+
+        if gpu.enabled && !caps.contains(Capabilities::GPU_TUNING) {
+            gpu.enabled = false;
+            dropped.push("GPU tuning, the daemon has no NVML GPU".to_string());
+        }
+        if cpu.dynamic.enabled && !caps.contains(Capabilities::AMD_EPP) {
+            cpu.dynamic.enabled = false;
+            dropped.push("dynamic EPP, the daemon has no amd-pstate EPP".to_string());
+        }
+        if !caps.contains(Capabilities::CGROUPS) {
+            if memory_protect_gb.take().is_some() {
+                dropped.push("memory_protect_gb, the daemon has no cgroup v2".to_string());
+            }
+            if sys.background_cpus.take().is_some() {
+                dropped.push("background_cpus, the daemon has no cgroup v2".to_string());
+            }
+        }
+        dropped
+    }
+}
+
+impl std::fmt::Display for DaemonApi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = self.capabilities.names();
+        write!(
+            f,
+            "API v{}, capabilities: {}",
+            self.version,
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(", ")
+            }
+        )
+    }
+}
+
+/// What `ApplyTuning` actually did, as opposed to what was asked for
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AppliedTuning {
+    /// What the daemon policy changed in the request
+    pub policy: Vec<String>,
+
+    /// GPU power limit in effect afterwards, in milliwatts
+    pub power_limit_mw: Option<u32>,
+
+    /// EPP hint the CPUs report afterwards, or the power-profiles-daemon
+    /// profile held instead
+    pub epp: Option<String>,
+
+    /// Niceness each process ended up with, by PID
+    pub renice: BTreeMap<u32, i32>,
+
+    /// What was left out, with the reason
+    pub skipped: BTreeMap<String, String>,
+
+    /// What was left to another service, with the service
+    pub delegated: BTreeMap<String, String>,
+}
+
+impl AppliedTuning {
+    /// The answer of a daemon from before API v2, which only told the
+    /// policy changes
+    pub fn from_policy(policy: Vec<String>) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    pub fn skip(&mut self, what: &str, reason: impl std::fmt::Display) {
+        self.skipped.insert(what.to_string(), reason.to_string());
+    }
+
+    pub fn delegate(&mut self, what: &str, to: impl std::fmt::Display) {
+        self.delegated.insert(what.to_string(), to.to_string());
+    }
+
+    /// What was asked for and did not happen, for strict mode to fail on.
+    /// Means nothing for a daemon before API v2, which does not tell.
+    pub fn verify(&self, cpu: &CpuTune, gpu: &GpuTune, sys: &SysTune, pid: u32) -> Vec<String> {
+        let mut missing = Vec::new();
+        if gpu.enabled
+            && (gpu.pwr_limit_tune.is_some() || gpu.set_max_pwr)
+            && self.power_limit_mw.is_none()
+        {
+            missing.push("no GPU power limit is in effect".to_string());
+        }
+        if cpu.enabled && self.epp.is_none() && !self.skipped.contains_key("cpu") {
+            missing.push(format!("EPP {} was not written", cpu.amd_epp_tune));
+        }
+        let renice = sys
+            .launch_phase_sec
+            .map_or(sys.proc_renice, |_| sys.launch_renice);
+        if sys.enabled
+            && renice != 0
+            && self.renice.get(&pid) != Some(&renice)
+            && !self.skipped.contains_key("renice")
+            && !self.delegated.contains_key("renice")
+        {
+            missing.push(format!(
+                "PID {} has niceness {} instead of {}",
+                pid,
+                self.renice
+                    .get(&pid)
+                    .map_or("unknown".to_string(), i32::to_string),
+                renice
+            ));
+        }
+        for (what, reason) in &self.skipped {
+            missing.push(format!("{} was skipped: {}", what, reason));
+        }
+        missing
+    }
+}
+
+impl std::fmt::Display for AppliedTuning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(mw) = self.power_limit_mw {
+            parts.push(format!("power limit {}W", mw / 1000));
+        }
+        if let Some(epp) = &self.epp {
+            parts.push(format!("EPP {}", epp));
+        }
+        for (pid, nice) in &self.renice {
+            parts.push(format!("PID {} at niceness {}", pid, nice));
+        }
+        for (what, to) in &self.delegated {
+            parts.push(format!("{} left to {}", what, to));
+        }
+        for (what, reason) in &self.skipped {
+            parts.push(format!("{} skipped ({})", what, reason));
+        }
+
+        if parts.is_empty() {
+            write!(f, "nothing reported")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
 pub struct NvPrimeService {
     pub state: Arc<Mutex<DaemonState>>,
 }
@@ -12,71 +320,407 @@ impl NvPrimeService {
     pub fn new(state: Arc<Mutex<DaemonState>>) -> Self {
         Self { state }
     }
+
+    /// Refuse users and requests the policy does not allow, and clamp the
+    /// rest to it. Returns what was changed, for the caller to be told.
+    fn enforce_policy(
+        &self,
+        uid: u32,
+        constrain: impl FnOnce(&DaemonPolicy) -> anyhow::Result<Vec<String>>,
+    ) -> zbus::fdo::Result<Vec<String>> {
+        let state = self.state.lock().unwrap();
+        let changes = state
+            .policy
+            .check_uid(uid)
+            .and_then(|()| constrain(&state.policy))
+            .map_err(|e| {
+                error!("{}", e);
+                zbus::fdo::Error::AccessDenied(e.to_string())
+            })?;
+
+        policy::report(uid, &changes);
+        Ok(changes)
+    }
+
+    /// Refuse resets outside a session's own teardown unless the caller is
+    /// root or the policy enables them
+    fn check_reset(&self, uid: u32) -> zbus::fdo::Result<()> {
+        self.enforce_policy(uid, |policy| {
+            if uid != 0 {
+                policy.check_feature(PolicyFeature::Reset)?;
+            }
+            Ok(Vec::new())
+        })
+        .map(|_| ())
+    }
+
+    /// Take a reference on a named profile of `uid`, within the policy
+    fn acquire_profile(&self, uid: u32, name: &str, config_json: &str) -> zbus::fdo::Result<u32> {
+        let mut config: ProfileConfig = parse_json(config_json)?;
+        self.enforce_policy(uid, |policy| {
+            let mut changes = policy.constrain_gpu(&mut config.gpu)?;
+            changes.extend(policy.constrain_cpu(&mut config.cpu));
+            Ok(changes)
+        })?;
+
+        let mut state = self.state.lock().unwrap();
+        state
+            .acquire_named_session(uid, name, &config.cpu, config.gpu)
+            .map_err(|e| {
+                error!("Failed to apply profile '{}': {}", name, e);
+                failed("Applying profile failed", e)
+            })
+    }
 }
 
 #[interface(name = "com.github.nvprime.Service")]
 impl NvPrimeService {
-    async fn apply_tuning(&mut self, pid: u32, config_json: String) -> zbus::fdo::Result<()> {
-        info!("Received tuning request for PID {}", pid);
+    async fn apply_tuning(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+        pid: u32,
+        config_json: String,
+    ) -> zbus::fdo::Result<String> {
+        let key = SessionKey::new(caller_uid(conn, &header).await?, pid);
+        info!("Received tuning request for PID {} of UID {}", pid, key.uid);
 
-        let config: TuningConfig = serde_json::from_str(&config_json)
-            .map_err(|e| zbus::fdo::Error::Failed(format!("Invalid config JSON: {}", e)))?;
+        let mut config: TuningConfig = parse_json(&config_json)?;
+        let mut oom_avoid = false;
+        let changes = self.enforce_policy(key.uid, |policy| {
+            policy::check_process_owner(key.uid, pid)?;
+            let mut changes = policy.constrain_gpu(&mut config.gpu)?;
+            changes.extend(policy.constrain_cpu(&mut config.cpu));
+            changes.extend(policy.constrain_sys(&mut config.sys));
+            changes.extend(policy.constrain_protection(
+                &mut config.oom_protect,
+                &mut config.memory_protect_gb,
+                &mut config.oom_sacrifice,
+            ));
+            oom_avoid = config.oom_protect;
+            changes.extend(policy.constrain_oom_avoid(&mut oom_avoid));
+            changes.extend(policy.constrain_mounts(&mut config.mounts));
+            Ok(changes)
+        })?;
+        let strict = config.sys.strict;
+        let mut skipped = AppliedTuning::default();
 
-        {
+        // Taken before locking the state since it needs a D-Bus round trip
+        let inhibitor = if config.inhibit_sleep {
+            match SleepInhibitor::acquire(conn, pid).await {
+                Ok(inhibitor) => Some(inhibitor),
+                Err(e) if strict => return Err(failed("Inhibiting idle and sleep failed", e)),
+                Err(e) => {
+                    error!("Failed to inhibit idle and sleep: {:#}", e);
+                    skipped.skip("inhibit_sleep", format!("{:#}", e));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let applied = {
             let mut state = self.state.lock().unwrap();
 
+            // Registered first so a reset after a failure below cleans up
+            state.add_session(key, config.gpu.clone());
+            state.send_event(SessionEvent::Started {
+                pid,
+                game: config.game.clone(),
+            });
+
             if let Err(e) = state.apply_cpu_tuning(&config.cpu) {
                 error!("Failed to apply CPU tuning: {}", e);
-                // We don't return error here, just log it, as CPU tuning is optional/best-effort
+                // CPU tuning is best-effort unless the client asked for strict mode
+                if strict {
+                    return Err(failed("CPU tuning failed", e));
+                }
+                skipped.skip("cpu", e);
             }
 
-            if let Err(e) = state.apply_gpu_tuning(&config.gpu) {
+            if let Err(e) = state.apply_effective_gpu_tuning() {
                 error!("Failed to apply GPU tuning: {}", e);
-                return Err(zbus::fdo::Error::Failed(format!(
-                    "GPU tuning failed: {}",
-                    e
-                )));
+                return Err(failed("GPU tuning failed", e));
             }
 
-            if let Err(e) = state.apply_process_priority(pid, &config.sys) {
+            if let Err(e) = state.apply_process_priority(key, &config.sys) {
                 error!("Failed to apply process priority: {}", e);
-                return Err(zbus::fdo::Error::Failed(format!(
-                    "Process priority failed: {}",
-                    e
-                )));
+                return Err(failed("Process priority failed", e));
             }
 
-            state.add_active_pid(pid);
-        }
+            if let Err(e) = state.apply_net_shaping(&config.sys) {
+                error!("Failed to limit inbound traffic: {}", e);
+                if strict {
+                    return Err(failed("Limiting inbound traffic failed", e));
+                }
+                skipped.skip("link_limit_kbit", e);
+            }
+
+            if let Err(e) = state.apply_cpu_isolation(pid, &config.sys) {
+                error!("Failed to confine background CPUs: {}", e);
+                if strict {
+                    return Err(failed("CPU isolation failed", e));
+                }
+                skipped.skip("background_cpus", e);
+            }
+
+            if let Err(e) = state.apply_swap_tuning(&config.sys) {
+                error!("Failed to tune swap: {}", e);
+                if strict {
+                    return Err(failed("Swap tuning failed", e));
+                }
+                skipped.skip("memory", e);
+            }
+
+            if let Err(e) = state.apply_mounts(key, &config.mounts) {
+                error!("Failed to mount game drives: {}", e);
+                return Err(failed("Mounting game drives failed", e));
+            }
+
+            if config.oom_protect
+                && let Err(e) = state.apply_oom_protection(key, &config.oom_sacrifice)
+            {
+                error!("Failed to protect PID {} from the OOM killer: {}", pid, e);
+                if strict {
+                    return Err(failed("OOM protection failed", e));
+                }
+                skipped.skip("oom_protect", e);
+            }
+
+            if oom_avoid && let Err(e) = state.apply_oom_avoidance(key, config.game_exe.as_deref())
+            {
+                error!(
+                    "Failed to exclude PID {} from userspace OOM killers: {}",
+                    pid, e
+                );
+                if strict {
+                    return Err(failed("OOM killer exclusion failed", e));
+                }
+                skipped.skip("oom_avoid", e);
+            }
+
+            if let Some(gb) = config.memory_protect_gb
+                && let Err(e) = state.apply_memory_protection(key, gb)
+            {
+                error!("Failed to protect memory of PID {}: {}", pid, e);
+                if strict {
+                    return Err(failed("Memory protection failed", e));
+                }
+                skipped.skip("memory_protect_gb", e);
+            }
+
+            if let Some(inhibitor) = inhibitor {
+                state.add_inhibitor(key, inhibitor);
+            }
+
+            if let Some(temp) = config.temp_alert_c {
+                state.temp_alerts.insert(key, temp);
+            }
+            state.persist();
+
+            let mut applied = state.applied_tuning(key);
+            applied.policy = changes;
+            applied.skipped.append(&mut skipped.skipped);
+            applied
+        };
+
+        start_priority_phases(Arc::clone(&self.state), key, config.sys.clone());
 
         start_pid_watchdog(
             Arc::clone(&self.state),
-            pid,
+            key,
             config.sys.watchdog_interval_sec,
         )
         .await;
 
-        info!("Applied tuning for PID {}", pid);
-        Ok(())
+        info!("Applied tuning for PID {}: {}", pid, applied);
+        serde_json::to_string(&applied)
+            .map_err(|e| zbus::fdo::Error::Failed(format!("Failed to serialize result: {}", e)))
+    }
+
+    /// Held as the named profile `manual-gpu` of the caller and merged with
+    /// the other sessions, until released or reset
+    async fn apply_gpu_tuning(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+        config_json: String,
+    ) -> zbus::fdo::Result<Vec<String>> {
+        info!("Received GPU tuning request");
+
+        let uid = caller_uid(conn, &header).await?;
+        let mut config: GpuTune = parse_json(&config_json)?;
+        let changes = self.enforce_policy(uid, |policy| policy.constrain_gpu(&mut config))?;
+        let mut state = self.state.lock().unwrap();
+
+        state.set_named_session(uid, MANUAL_GPU_PROFILE, config);
+        state.apply_effective_gpu_tuning().map_err(|e| {
+            error!("Failed to apply GPU tuning: {}", e);
+            failed("GPU tuning failed", e)
+        })?;
+        Ok(changes)
+    }
+
+    /// Held as the named profile `manual-cpu` of the caller, so the CPU
+    /// defaults wait for its release or a reset
+    async fn apply_cpu_tuning(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+        config_json: String,
+    ) -> zbus::fdo::Result<Vec<String>> {
+        info!("Received CPU tuning request");
+
+        let uid = caller_uid(conn, &header).await?;
+        let mut config: CpuTune = parse_json(&config_json)?;
+        let changes = self.enforce_policy(uid, |policy| Ok(policy.constrain_cpu(&mut config)))?;
+        let mut state = self.state.lock().unwrap();
+
+        state.set_named_session(uid, MANUAL_CPU_PROFILE, GpuTune::default());
+        state.apply_cpu_tuning(&config).map_err(|e| {
+            error!("Failed to apply CPU tuning: {}", e);
+            failed("CPU tuning failed", e)
+        })?;
+        Ok(changes)
+    }
+
+    async fn apply_process_tuning(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+        pid: u32,
+        config_json: String,
+    ) -> zbus::fdo::Result<Vec<String>> {
+        info!("Received process tuning request for PID {}", pid);
+
+        let key = SessionKey::new(caller_uid(conn, &header).await?, pid);
+        let mut config: SysTune = parse_json(&config_json)?;
+        let changes = self.enforce_policy(key.uid, |policy| {
+            policy::check_process_owner(key.uid, pid)?;
+            Ok(policy.constrain_sys(&mut config))
+        })?;
+        // Without a session there is nothing to end the launch phase
+        config.launch_phase_sec = None;
+        let mut state = self.state.lock().unwrap();
+
+        state.apply_process_priority(key, &config).map_err(|e| {
+            error!("Failed to apply process priority: {}", e);
+            failed("Process priority failed", e)
+        })?;
+        Ok(changes)
+    }
+
+    /// Apply the tuning of a named profile for an application that is not
+    /// launched through nvprime, returns the references now held on it
+    async fn apply_profile(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+        name: String,
+        config_json: String,
+    ) -> zbus::fdo::Result<u32> {
+        let uid = caller_uid(conn, &header).await?;
+        info!("Received profile request '{}' of UID {}", name, uid);
+        self.acquire_profile(uid, &name, &config_json)
+    }
+
+    /// Apply tuning for `minutes` without a game, held as the named profile
+    /// `boost` and released when the time is up. Returns the references now
+    /// held on it.
+    async fn boost(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+        minutes: u32,
+        config_json: String,
+    ) -> zbus::fdo::Result<u32> {
+        if minutes == 0 {
+            return Err(zbus::fdo::Error::InvalidArgs(
+                "A boost needs at least a minute".to_string(),
+            ));
+        }
+
+        let uid = caller_uid(conn, &header).await?;
+        info!("Received {} minute boost of UID {}", minutes, uid);
+
+        let refs = self.acquire_profile(uid, BOOST_PROFILE, &config_json)?;
+        start_boost_window(Arc::clone(&self.state), uid, minutes);
+        Ok(refs)
     }
 
-    async fn reset_tuning(&mut self) -> zbus::fdo::Result<()> {
-        info!("Resetting tuning");
+    /// Drop one reference on a named profile, returns the references left
+    async fn release_profile(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+        name: String,
+    ) -> zbus::fdo::Result<u32> {
+        let uid = caller_uid(conn, &header).await?;
         let mut state = self.state.lock().unwrap();
 
+        match state.release_named_session(uid, &name) {
+            Ok(Some(refs)) => Ok(refs),
+            Ok(None) => Err(zbus::fdo::Error::Failed(format!(
+                "Profile '{}' is not applied",
+                name
+            ))),
+            Err(e) => {
+                error!("Failed to release profile '{}': {}", name, e);
+                Err(failed("Releasing profile failed", e))
+            }
+        }
+    }
+
+    /// End the calling user's session of one process, the tuning its other
+    /// sessions and profiles need is merged again and stays applied
+    async fn end_session(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+        pid: u32,
+    ) -> zbus::fdo::Result<()> {
+        let key = SessionKey::new(caller_uid(conn, &header).await?, pid);
+        info!("Ending session of UID {} PID {}", key.uid, pid);
+
+        let mut state = self.state.lock().unwrap();
+        state.end_session(key).map_err(|e| {
+            error!("Failed to end session of PID {}: {}", pid, e);
+            failed("Ending session failed", e)
+        })
+    }
+
+    /// Reset the sessions and profiles of the calling user, or of everyone
+    /// when called by root. Only for an explicit `nvprime reset`, a game
+    /// exiting ends its own session with `EndSession`. Settings shared with
+    /// other users' sessions stay applied.
+    async fn reset_tuning(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let uid = caller_uid(conn, &header).await?;
+        info!("Resetting tuning for UID {}", uid);
+
+        let mut state = self.state.lock().unwrap();
         let mut success = true;
 
-        if let Err(e) = state.restore_gpu_defaults() {
-            error!("Failed to restore GPU defaults: {}", e);
-            success = false;
+        let owner = (uid != 0).then_some(uid);
+        state.discard_named_sessions(owner);
+        for key in state.session_keys(owner) {
+            if let Err(e) = state.end_session(key) {
+                error!("Failed to reset session of PID {}: {}", key.pid, e);
+                success = false;
+            }
         }
 
-        if let Err(e) = state.restore_cpu_defaults() {
-            error!("Failed to restore CPU defaults: {}", e);
+        // Nothing of this user was tracked, still make sure an idle system
+        // ends up back at its defaults
+        if let Err(e) = state.reconcile_shared_tuning() {
+            error!("Failed to restore shared tuning: {}", e);
             success = false;
         }
 
-        state.active_pids.clear();
         info!("Tuning reset complete");
 
         if !success {
@@ -88,9 +732,274 @@ impl NvPrimeService {
         Ok(())
     }
 
+    async fn reset_gpu_tuning(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let uid = caller_uid(conn, &header).await?;
+        self.check_reset(uid)?;
+
+        info!("Resetting GPU tuning for UID {}", uid);
+        let mut state = self.state.lock().unwrap();
+        if state.gpu.is_none() {
+            return Err(zbus::fdo::Error::Failed(
+                "GPU tuning is not enabled in the daemon".to_string(),
+            ));
+        }
+
+        state.restore_gpu_defaults().map_err(|e| {
+            error!("Failed to restore GPU defaults: {}", e);
+            failed("GPU reset failed", e)
+        })
+    }
+
+    async fn reset_cpu_tuning(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let uid = caller_uid(conn, &header).await?;
+        self.check_reset(uid)?;
+
+        info!("Resetting CPU tuning for UID {}", uid);
+        let mut state = self.state.lock().unwrap();
+
+        state.restore_cpu_defaults().map_err(|e| {
+            error!("Failed to restore CPU defaults: {}", e);
+            failed("CPU reset failed", e)
+        })
+    }
+
+    /// Unlock GPU clocks whether or not a session exists, for recovering
+    /// from other tools that crashed with them pinned
+    async fn reset_gpu_clocks(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let uid = caller_uid(conn, &header).await?;
+        self.check_reset(uid)?;
+
+        info!("Resetting GPU clocks for UID {}", uid);
+        let mut state = self.state.lock().unwrap();
+        state
+            .reset_gpu_clocks()
+            .map_err(|e| failed("GPU clock reset failed", e))
+    }
+
+    /// Set the EPP hint whether or not a session exists. The caller passes
+    /// its `amd_epp_base`, the daemon only knows it once a session ran.
+    async fn reset_epp(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+        mut epp: String,
+    ) -> zbus::fdo::Result<()> {
+        let uid = caller_uid(conn, &header).await?;
+        self.check_reset(uid)?;
+
+        if EppProfile::from_str(&epp).is_err() {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "Invalid EPP profile '{}'",
+                epp
+            )));
+        }
+        self.enforce_policy(uid, |policy| {
+            Ok(policy.constrain_epp(&mut epp).into_iter().collect())
+        })?;
+
+        info!("Resetting CPU EPP to {} for UID {}", epp, uid);
+        let mut state = self.state.lock().unwrap();
+        state.dynamic_epp = None;
+        RyzenEPPManager::set_epp(&epp).map_err(|e| failed("EPP reset failed", e))
+    }
+
+    /// Discard every session the daemon tracks and restore all defaults,
+    /// for when it lost track of a game. Root only, since it ends other
+    /// users' sessions too.
+    async fn force_reset_tuning(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> zbus::fdo::Result<u32> {
+        let uid = caller_uid(conn, &header).await?;
+        if uid != 0 {
+            error!("UID {} tried to force a reset", uid);
+            return Err(zbus::fdo::Error::AccessDenied(
+                "Forcing a reset needs root".to_string(),
+            ));
+        }
+
+        warn!("Forcing a reset of all tuning");
+        let mut state = self.state.lock().unwrap();
+        let discarded = state
+            .discard_all_sessions()
+            .map_err(|e| failed("Failed to fully reset tuning", e))?;
+
+        info!(
+            "Forced reset complete, discarded {} sessions",
+            discarded.len()
+        );
+        Ok(discarded.len() as u32)
+    }
+
+    async fn reset_process_tuning(
+        &mut self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+        pid: u32,
+    ) -> zbus::fdo::Result<()> {
+        info!("Resetting process tuning for PID {}", pid);
+        let key = SessionKey::new(caller_uid(conn, &header).await?, pid);
+        self.enforce_policy(key.uid, |_| {
+            policy::check_process_owner(key.uid, pid)?;
+            Ok(Vec::new())
+        })?;
+        let mut state = self.state.lock().unwrap();
+
+        state.restore_process_priority(key).map_err(|e| {
+            error!("Failed to restore process priority: {}", e);
+            failed("Process priority reset failed", e)
+        })
+    }
+
+    async fn get_telemetry(&self, since_ms: u64) -> zbus::fdo::Result<String> {
+        let state = self.state.lock().unwrap();
+        let samples = state.telemetry.since(since_ms);
+
+        serde_json::to_string(&samples)
+            .map_err(|e| zbus::fdo::Error::Failed(format!("Failed to serialize telemetry: {}", e)))
+    }
+
+    /// Sent whenever the daemon moves the GPU power limit, `reason` is one
+    /// of `tuning`, `schedule` or `restore`
+    #[zbus(signal)]
+    async fn power_limit_changed(
+        emitter: &SignalEmitter<'_>,
+        old_mw: u32,
+        new_mw: u32,
+        reason: &str,
+    ) -> zbus::Result<()>;
+
+    /// Sent when a GPU condition starts during a session, `kind` is one of
+    /// `thermal_throttle`, `power_throttle` or `temp_above`, with the
+    /// threshold a session asked for in `threshold_c`
+    #[zbus(signal)]
+    async fn gpu_condition(
+        emitter: &SignalEmitter<'_>,
+        kind: &str,
+        temperature_c: u32,
+        threshold_c: u32,
+    ) -> zbus::Result<()>;
+
+    /// Sent after the daemon reloaded its config on SIGHUP, with the
+    /// checksum of the new config and the sections that changed
+    #[zbus(signal)]
+    async fn config_reloaded(
+        emitter: &SignalEmitter<'_>,
+        checksum: &str,
+        changed: &[String],
+    ) -> zbus::Result<()>;
+
     async fn ping(&self) -> String {
         "pong".to_string()
     }
+
+    /// Version of this protocol, see [`API_VERSION`]
+    async fn api_version(&self) -> u32 {
+        API_VERSION
+    }
+
+    /// Bitmap of the [`Capabilities`] of this machine
+    async fn get_capabilities(&self) -> u32 {
+        self.state.lock().unwrap().capabilities().bits()
+    }
+}
+
+/// Tell clients the daemon reloaded its config, so they re-resolve profiles
+pub async fn emit_config_reloaded(
+    conn: &zbus::Connection,
+    checksum: &str,
+    changed: &[String],
+) -> zbus::Result<()> {
+    let emitter = SignalEmitter::new(conn, SERVICE_PATH)?;
+    NvPrimeService::config_reloaded(&emitter, checksum, changed).await
+}
+
+/// Send a `PowerLimitChanged` signal for every change the daemon reports
+pub async fn emit_power_changes(
+    conn: zbus::Connection,
+    mut changes: tokio::sync::mpsc::UnboundedReceiver<PowerLimitChange>,
+) {
+    let emitter = match SignalEmitter::new(&conn, SERVICE_PATH) {
+        Ok(emitter) => emitter,
+        Err(e) => {
+            error!("Failed to create signal emitter: {}", e);
+            return;
+        }
+    };
+
+    while let Some(change) = changes.recv().await {
+        let reason = change.reason.to_string();
+        if let Err(e) =
+            NvPrimeService::power_limit_changed(&emitter, change.old_mw, change.new_mw, &reason)
+                .await
+        {
+            warn!("Failed to send PowerLimitChanged signal: {}", e);
+        }
+    }
+}
+
+/// Send a `GpuCondition` signal for every condition the daemon reports
+pub async fn emit_gpu_conditions(
+    conn: zbus::Connection,
+    mut conditions: tokio::sync::mpsc::UnboundedReceiver<conditions::GpuCondition>,
+) {
+    let emitter = match SignalEmitter::new(&conn, SERVICE_PATH) {
+        Ok(emitter) => emitter,
+        Err(e) => {
+            error!("Failed to create signal emitter: {}", e);
+            return;
+        }
+    };
+
+    while let Some(condition) = conditions.recv().await {
+        let kind = condition.kind.to_string();
+        if let Err(e) = NvPrimeService::gpu_condition(
+            &emitter,
+            &kind,
+            condition.temperature_c,
+            condition.kind.threshold_c(),
+        )
+        .await
+        {
+            warn!("Failed to send GpuCondition signal: {}", e);
+        }
+    }
+}
+
+/// UID of the process that sent the message, as known to the bus
+async fn caller_uid(conn: &zbus::Connection, header: &Header<'_>) -> zbus::fdo::Result<u32> {
+    let sender = header
+        .sender()
+        .ok_or_else(|| zbus::fdo::Error::Failed("Message has no sender".to_string()))?;
+
+    zbus::fdo::DBusProxy::new(conn)
+        .await?
+        .get_connection_unix_user(sender.clone().into())
+        .await
+}
+
+/// Deserialize a JSON payload received over D-Bus
+fn parse_json<T: serde::de::DeserializeOwned>(json: &str) -> zbus::fdo::Result<T> {
+    serde_json::from_str(json)
+        .map_err(|e| zbus::fdo::Error::Failed(format!("Invalid config JSON: {}", e)))
+}
+
+/// Wrap an internal error into a D-Bus failure with context
+fn failed(context: &str, e: anyhow::Error) -> zbus::fdo::Error {
+    zbus::fdo::Error::Failed(format!("{}: {}", context, e))
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -98,6 +1007,31 @@ struct TuningConfig {
     pub cpu: CpuTune,
     pub gpu: GpuTune,
     pub sys: SysTune,
+    #[serde(default)]
+    pub mounts: Vec<String>,
+    #[serde(default)]
+    pub inhibit_sleep: bool,
+    #[serde(default)]
+    pub oom_protect: bool,
+    #[serde(default)]
+    pub oom_sacrifice: Vec<String>,
+    #[serde(default)]
+    pub memory_protect_gb: Option<u32>,
+    #[serde(default)]
+    pub temp_alert_c: Option<u32>,
+    /// Name of the game, for what the daemon publishes
+    #[serde(default)]
+    pub game: Option<String>,
+    /// File name of the game's executable, for the userspace OOM killers
+    #[serde(default)]
+    pub game_exe: Option<String>,
+}
+
+/// What a named profile may tune, there is no process to prioritize
+#[derive(serde::Deserialize, serde::Serialize)]
+struct ProfileConfig {
+    pub cpu: CpuTune,
+    pub gpu: GpuTune,
 }
 
 #[proxy(
@@ -106,14 +1040,52 @@ struct TuningConfig {
     default_path = "/com/github/nvprime"
 )]
 pub trait NvPrimeClient {
-    async fn apply_tuning(&self, pid: u32, config_json: String) -> zbus::Result<()>;
+    /// Returns the [`AppliedTuning`] as JSON
+    async fn apply_tuning(&self, pid: u32, config_json: String) -> zbus::Result<String>;
+
+    /// `ApplyTuning` of daemons before API v2
+    #[zbus(name = "ApplyTuning")]
+    async fn apply_tuning_v1(&self, pid: u32, config_json: String) -> zbus::Result<Vec<String>>;
+
+    /// Each returns what the daemon policy changed in the request
+    async fn apply_gpu_tuning(&self, config_json: String) -> zbus::Result<Vec<String>>;
+    async fn apply_cpu_tuning(&self, config_json: String) -> zbus::Result<Vec<String>>;
+    async fn apply_process_tuning(
+        &self,
+        pid: u32,
+        config_json: String,
+    ) -> zbus::Result<Vec<String>>;
+    async fn apply_profile(&self, name: String, config_json: String) -> zbus::Result<u32>;
+    async fn release_profile(&self, name: String) -> zbus::Result<u32>;
+    async fn boost(&self, minutes: u32, config_json: String) -> zbus::Result<u32>;
+    async fn end_session(&self, pid: u32) -> zbus::Result<()>;
     async fn reset_tuning(&self) -> zbus::Result<()>;
+    async fn reset_gpu_tuning(&self) -> zbus::Result<()>;
+    async fn reset_cpu_tuning(&self) -> zbus::Result<()>;
+    async fn reset_gpu_clocks(&self) -> zbus::Result<()>;
+    async fn reset_epp(&self, epp: String) -> zbus::Result<()>;
+    async fn force_reset_tuning(&self) -> zbus::Result<u32>;
+    async fn reset_process_tuning(&self, pid: u32) -> zbus::Result<()>;
+    async fn get_telemetry(&self, since_ms: u64) -> zbus::Result<String>;
     async fn ping(&self) -> zbus::Result<String>;
+    async fn api_version(&self) -> zbus::Result<u32>;
+    async fn get_capabilities(&self) -> zbus::Result<u32>;
+
+    #[zbus(signal)]
+    fn power_limit_changed(&self, old_mw: u32, new_mw: u32, reason: String) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn gpu_condition(&self, kind: String, temperature_c: u32, threshold_c: u32)
+    -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn config_reloaded(&self, checksum: String, changed: Vec<String>) -> zbus::Result<()>;
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::config::{CpuBackend, IoprioClass, PriorityBackend};
 
     #[test]
     fn test_tuning_config_serialization() {
@@ -121,6 +1093,12 @@ mod tests {
             enabled: true,
             amd_epp_tune: "performance".to_string(),
             amd_epp_base: "balance".to_string(),
+            cpu_dma_latency_us: None,
+            cpu_max_freq_mhz: None,
+            smt: None,
+            dynamic: Default::default(),
+            cpu_backend: CpuBackend::Sysfs,
+            ppt_limit: None,
         };
 
         let gpu = GpuTune {
@@ -128,16 +1106,33 @@ mod tests {
             gpu_name: Some("Test GPU".to_string()),
             gpu_uuid: Some("GPU-123".to_string()),
             gpu_vlk_icd: "/test.json".to_string(),
+            gpu_vlk_icd32: None,
             set_max_pwr: true,
             pwr_limit_tune: Some(350000),
+            prefer_switcheroo: false,
+            min_mem_clock_mhz: None,
+            busy_util_percent: None,
+            busy_vram_percent: None,
         };
 
         let sys = SysTune {
             enabled: true,
             proc_ioprio: 2,
+            proc_ioprio_class: IoprioClass::Be,
             proc_renice: -5,
             splitlock_hack: true,
             watchdog_interval_sec: 10,
+            link_limit_kbit: None,
+            link_iface: None,
+            background_cpus: None,
+            memory: Default::default(),
+            strict: false,
+            telemetry_export: None,
+            launch_phase_sec: None,
+            launch_renice: 0,
+            launch_ioprio: 0,
+            launch_gpu_util: None,
+            priority_backend: PriorityBackend::Direct,
         };
 
         let config_json = serde_json::json!({
@@ -166,6 +1161,9 @@ mod tests {
         assert!(!parsed.cpu.enabled);
         assert!(!parsed.gpu.enabled);
         assert!(!parsed.sys.enabled);
+        assert!(parsed.mounts.is_empty());
+        assert!(!parsed.inhibit_sleep);
+        assert!(!parsed.oom_protect);
     }
 
     #[test]
@@ -175,7 +1173,7 @@ mod tests {
 
         let state_lock = service.state.lock().unwrap();
         assert!(state_lock.gpu.is_none());
-        assert!(state_lock.active_pids.is_empty());
+        assert!(state_lock.active_sessions.is_empty());
     }
 
     #[test]
@@ -187,16 +1185,41 @@ mod tests {
                 gpu_name: Some("RTX 4090".to_string()),
                 gpu_uuid: None,
                 gpu_vlk_icd: "/nvidia.json".to_string(),
+                gpu_vlk_icd32: None,
                 set_max_pwr: false,
                 pwr_limit_tune: Some(400000),
+                prefer_switcheroo: false,
+                min_mem_clock_mhz: None,
+                busy_util_percent: None,
+                busy_vram_percent: None,
             },
             sys: SysTune {
                 enabled: true,
                 proc_ioprio: 1,
+                proc_ioprio_class: IoprioClass::Be,
                 proc_renice: -10,
                 splitlock_hack: false,
                 watchdog_interval_sec: 15,
+                link_limit_kbit: None,
+                link_iface: None,
+                background_cpus: None,
+                memory: Default::default(),
+                strict: false,
+                telemetry_export: None,
+                launch_phase_sec: None,
+                launch_renice: 0,
+                launch_ioprio: 0,
+                launch_gpu_util: None,
+                priority_backend: PriorityBackend::Direct,
             },
+            mounts: vec!["games".to_string()],
+            inhibit_sleep: true,
+            oom_protect: true,
+            oom_sacrifice: vec!["steamwebhelper".to_string()],
+            memory_protect_gb: Some(8),
+            temp_alert_c: Some(87),
+            game: Some("eldenring".to_string()),
+            game_exe: Some("eldenring.exe".to_string()),
         };
 
         let json = serde_json::to_string(&original).unwrap();
@@ -206,5 +1229,128 @@ mod tests {
         assert_eq!(deserialized.gpu.gpu_name, original.gpu.gpu_name);
         assert_eq!(deserialized.gpu.pwr_limit_tune, original.gpu.pwr_limit_tune);
         assert_eq!(deserialized.sys.proc_renice, original.sys.proc_renice);
+        assert_eq!(deserialized.mounts, original.mounts);
+        assert!(deserialized.inhibit_sleep);
+        assert_eq!(deserialized.temp_alert_c, Some(87));
+        assert!(deserialized.oom_protect);
+        assert_eq!(deserialized.oom_sacrifice, original.oom_sacrifice);
+        assert_eq!(deserialized.memory_protect_gb, Some(8));
+        assert_eq!(deserialized.game, original.game);
+        assert_eq!(deserialized.game_exe, original.game_exe);
+    }
+
+    #[test]
+    fn test_parse_json_single_subsystem() {
+        let cpu: CpuTune =
+            parse_json(r#"{"cpu_tuning": true, "amd_epp_tune": "performance"}"#).unwrap();
+        assert!(cpu.enabled);
+        assert_eq!(cpu.amd_epp_tune, "performance");
+        assert_eq!(cpu.amd_epp_base, "balance_performance");
+    }
+
+    #[test]
+    fn test_parse_json_invalid() {
+        let result: zbus::fdo::Result<GpuTune> = parse_json("not json");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_daemon_api_adapt() {
+        let mut cpu = CpuTune::default();
+        cpu.dynamic.enabled = true;
+        let mut gpu = GpuTune {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut sys = SysTune {
+            background_cpus: Some("0-1".to_string()),
+            ..Default::default()
+        };
+        let mut memory_protect_gb = Some(8);
+
+        let legacy = DaemonApi {
+            version: 0,
+            capabilities: Capabilities::LEGACY,
+        };
+        assert!(
+            legacy
+                .adapt(&mut cpu, &mut gpu, &mut sys, &mut memory_protect_gb)
+                .is_empty()
+        );
+        assert!(legacy.version_warning().unwrap().contains("older"));
+
+        // A newer daemon's unknown bits are kept but not named
+        let api = DaemonApi {
+            version: API_VERSION,
+            capabilities: Capabilities::from_bits(Capabilities::AMD_EPP.bits() | 1 << 31),
+        };
+        assert_eq!(api.to_string(), "API v3, capabilities: amd-epp");
+        assert_eq!(api.version_warning(), None);
+
+        let dropped = api.adapt(&mut cpu, &mut gpu, &mut sys, &mut memory_protect_gb);
+        assert_eq!(dropped.len(), 3);
+        assert!(!gpu.enabled);
+        assert!(cpu.dynamic.enabled);
+        assert_eq!(sys.background_cpus, None);
+        assert_eq!(memory_protect_gb, None);
+    }
+
+    #[test]
+    fn test_applied_tuning_verify() {
+        let cpu = CpuTune {
+            enabled: true,
+            ..Default::default()
+        };
+        let gpu = GpuTune {
+            enabled: true,
+            pwr_limit_tune: Some(300_000),
+            ..Default::default()
+        };
+        let sys = SysTune {
+            enabled: true,
+            proc_renice: -10,
+            ..Default::default()
+        };
+
+        let mut applied = AppliedTuning {
+            power_limit_mw: Some(300_000),
+            epp: Some("performance".to_string()),
+            renice: BTreeMap::from([(1234, -10)]),
+            ..Default::default()
+        };
+        assert!(applied.verify(&cpu, &gpu, &sys, 1234).is_empty());
+        assert_eq!(
+            applied.to_string(),
+            "power limit 300W, EPP performance, PID 1234 at niceness -10"
+        );
+
+        // A JSON round trip keeps the PIDs, which are object keys
+        let json = serde_json::to_string(&applied).unwrap();
+        assert_eq!(
+            serde_json::from_str::<AppliedTuning>(&json).unwrap(),
+            applied
+        );
+
+        applied.power_limit_mw = None;
+        applied.renice.insert(1234, 0);
+        applied.skip("oom_protect", "no such process");
+        assert_eq!(
+            applied.verify(&cpu, &gpu, &sys, 1234),
+            [
+                "no GPU power limit is in effect",
+                "PID 1234 has niceness 0 instead of -10",
+                "oom_protect was skipped: no such process",
+            ]
+        );
+
+        // Delegated priorities are not the daemon's to verify
+        applied.delegate("renice", "system76-scheduler");
+        assert_eq!(
+            applied.verify(&cpu, &gpu, &sys, 1234),
+            [
+                "no GPU power limit is in effect",
+                "oom_protect was skipped: no such process",
+            ]
+        );
     }
 }
@@ -1,22 +1,42 @@
-use anyhow::Result;
 use env_logger::Builder;
 use log::LevelFilter;
 use std::io::Write;
+use std::sync::Once;
 
-/// Initialize the logging system with pretty formatting
-pub fn init(verbose: bool) -> Result<()> {
-    let level = if verbose {
-        LevelFilter::Debug
-    } else {
-        LevelFilter::Info
-    };
+/// Overrides the default level, in `env_logger` syntax, e.g. `debug` or
+/// `info,nvprime::service=trace`
+pub const LOG_ENV: &str = "NVPRIME_LOG";
+
+static INIT: Once = Once::new();
 
-    Builder::new()
-        .filter_level(level)
-        .format(format_log)
-        .try_init()?;
+/// Initialize the logging system with pretty formatting at `default_level`,
+/// unless `NVPRIME_LOG` asks for another. Only the first call does anything,
+/// a logger installed by someone else is kept.
+pub fn init(default_level: LevelFilter) {
+    INIT.call_once(|| {
+        let filters = std::env::var(LOG_ENV).ok();
+        if builder(default_level, filters.as_deref())
+            .try_init()
+            .is_err()
+        {
+            log::debug!("A logger is already installed, keeping it");
+        }
+    });
+}
+
+/// Log everything into the output the test harness captures, for tests
+/// running code that logs. Safe to call from every test.
+pub fn try_init_for_tests() {
+    let _ = builder(LevelFilter::Debug, None).is_test(true).try_init();
+}
 
-    Ok(())
+fn builder(default_level: LevelFilter, filters: Option<&str>) -> Builder {
+    let mut builder = Builder::new();
+    builder.filter_level(default_level).format(format_log);
+    if let Some(filters) = filters {
+        builder.parse_filters(filters);
+    }
+    builder
 }
 
 /// Shared log formatter function that can be used in production and tests
@@ -48,3 +68,28 @@ fn format_log(buf: &mut env_logger::fmt::Formatter, record: &log::Record) -> std
         record.args()
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_levels() {
+        let level = |filters| builder(LevelFilter::Info, filters).build().filter();
+        assert_eq!(level(None), LevelFilter::Info);
+        assert_eq!(level(Some("debug")), LevelFilter::Debug);
+        assert_eq!(
+            level(Some("warn,nvprime::service=trace")),
+            LevelFilter::Trace
+        );
+    }
+
+    #[test]
+    fn test_init_twice() {
+        try_init_for_tests();
+        init(LevelFilter::Info);
+        init(LevelFilter::Debug);
+        try_init_for_tests();
+        log::info!("Still logging");
+    }
+}
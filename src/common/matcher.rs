@@ -0,0 +1,142 @@
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Pattern a game's executable is matched against, written as
+/// `regex:<pattern>`, `glob:<pattern>` or a plain name compared as is
+#[derive(Clone)]
+pub struct GameMatcher {
+    source: String,
+    kind: MatcherKind,
+}
+
+#[derive(Clone)]
+enum MatcherKind {
+    Exact(String),
+    Regex(Regex),
+    Glob(GlobMatcher),
+}
+
+impl GameMatcher {
+    pub fn is_match(&self, text: &str) -> bool {
+        match &self.kind {
+            MatcherKind::Exact(name) => name.eq_ignore_ascii_case(text),
+            MatcherKind::Regex(regex) => regex.is_match(text),
+            MatcherKind::Glob(glob) => glob.is_match(text),
+        }
+    }
+}
+
+impl FromStr for GameMatcher {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let kind = if let Some(pattern) = s.strip_prefix("regex:") {
+            MatcherKind::Regex(Regex::new(pattern)?)
+        } else if let Some(pattern) = s.strip_prefix("glob:") {
+            // Paths come from Proton with whatever case the game ships
+            let glob = GlobBuilder::new(pattern)
+                .case_insensitive(true)
+                .literal_separator(true)
+                .build()?;
+            MatcherKind::Glob(glob.compile_matcher())
+        } else {
+            MatcherKind::Exact(s.to_string())
+        };
+
+        Ok(Self {
+            source: s.to_string(),
+            kind,
+        })
+    }
+}
+
+impl fmt::Display for GameMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl fmt::Debug for GameMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GameMatcher({:?})", self.source)
+    }
+}
+
+impl PartialEq for GameMatcher {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl<'de> Deserialize<'de> for GameMatcher {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for GameMatcher {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(s: &str) -> GameMatcher {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_matcher_exact() {
+        let exact = matcher("ffxiv");
+        assert!(exact.is_match("ffxiv"));
+        assert!(exact.is_match("FFXIV"));
+        assert!(!exact.is_match("ffxiv_dx11"));
+    }
+
+    #[test]
+    fn test_matcher_regex() {
+        let regex = matcher("regex:^ffxiv(_dx11)?$");
+        assert!(regex.is_match("ffxiv"));
+        assert!(regex.is_match("ffxiv_dx11"));
+        assert!(!regex.is_match("ffxivboot"));
+        assert!("regex:(".parse::<GameMatcher>().is_err());
+    }
+
+    #[test]
+    fn test_matcher_glob() {
+        let glob = matcher("glob:**/FFXIV/**");
+        assert!(glob.is_match("/games/SquareEnix/FFXIV/game/ffxiv_dx11.exe"));
+        assert!(glob.is_match("/games/ffxiv/boot/ffxivboot.exe"));
+        assert!(!glob.is_match("/games/FFXIV_old/game.exe"));
+
+        // A single star stays within one path component
+        assert!(!matcher("glob:/games/*.exe").is_match("/games/ffxiv/game.exe"));
+    }
+
+    #[test]
+    fn test_matcher_round_trip() {
+        let toml = "matcher = \"regex:^ffxiv(_dx11)?$\"\n";
+
+        #[derive(Deserialize, Serialize)]
+        struct Wrapper {
+            matcher: GameMatcher,
+        }
+
+        let wrapper: Wrapper = toml::from_str(toml).unwrap();
+        assert_eq!(wrapper.matcher, matcher("regex:^ffxiv(_dx11)?$"));
+        assert_eq!(toml::to_string(&wrapper).unwrap(), toml);
+    }
+}
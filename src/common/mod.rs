@@ -1,8 +1,19 @@
+pub mod cgroup;
 pub mod config;
+pub mod config_edit;
 pub mod ipc;
 pub mod logging;
+pub mod matcher;
 pub mod nvgpu;
+pub mod paths;
+pub mod proc;
+pub mod resolve;
+pub mod steam;
+pub mod telemetry;
+pub mod user;
 
 pub use config::Config;
+pub use config_edit::ConfigEdit;
 pub use ipc::{NvPrimeClientProxy, NvPrimeService};
-pub use nvgpu::NvGpu;
+pub use nvgpu::{DriverVersion, GpuLoad, GpuProcess, LinkInfo, NvGpu, NvmlRetry};
+pub use telemetry::{TelemetryRing, TelemetrySample};
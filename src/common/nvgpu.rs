@@ -1,12 +1,139 @@
+use crate::common::proc;
+use crate::common::telemetry::{TelemetrySample, now_ms};
 use log::{debug, error, info, warn};
 use nvml_wrapper::Nvml;
+use nvml_wrapper::bitmasks::device::ThrottleReasons;
 use nvml_wrapper::enum_wrappers::device::Clock;
+use nvml_wrapper::enum_wrappers::device::PerformancePolicy;
+use nvml_wrapper::enum_wrappers::device::PerformanceState;
 use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
+use nvml_wrapper::enum_wrappers::device::TemperatureThreshold;
+use nvml_wrapper::enums::device::UsedGpuMemory;
 use nvml_wrapper::error::NvmlError;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+/// Where the kernel module reports the loaded driver version
+const DRIVER_VERSION_PATH: &str = "/sys/module/nvidia/version";
+
+/// BAR1 aperture without resizable BAR
+const LEGACY_BAR1_MIB: u64 = 256;
+
+/// Links this narrow are Thunderbolt enclosures or chipset slots
+const NARROW_LINK_WIDTH: u32 = 4;
+
+/// Degrees below the slowdown threshold from where the GPU counts as
+/// limited by temperature
+const THERMAL_MARGIN_C: u32 = 5;
+
+/// Share of the power limit in percent from where the GPU counts as
+/// limited by power
+const POWER_LIMITED_PERCENT: u32 = 95;
+
+/// Most tries after the first one, whatever the config asks for
+const MAX_RETRIES: u32 = 5;
+
+/// Longest total wait for the retries of one NVML call. Callers like the
+/// daemon hold their state while they talk to NVML, a GPU that does not
+/// recover by then is not waited for.
+const MAX_RETRY_WAIT: Duration = Duration::from_millis(1500);
 
 pub struct NvGpu {
     nvml: Nvml,
     gpu_id: GpuId,
+    retry: NvmlRetry,
+}
+
+/// How NVML calls failing with an error the GPU recovers from are tried
+/// again, with the wait doubling after each try
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NvmlRetry {
+    /// Tries after the first one, none when zero
+    pub retries: u32,
+    /// Wait before the first retry
+    pub delay: Duration,
+}
+
+impl Default for NvmlRetry {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            delay: Duration::from_millis(100),
+        }
+    }
+}
+
+impl NvmlRetry {
+    /// Waits before each retry, doubling, within `MAX_RETRIES` and
+    /// `MAX_RETRY_WAIT`
+    fn delays(&self) -> Vec<Duration> {
+        let mut delays = Vec::new();
+        let mut delay = self.delay;
+        let mut left = MAX_RETRY_WAIT;
+        for _ in 0..self.retries.min(MAX_RETRIES) {
+            let wait = delay.min(left);
+            delays.push(wait);
+            left -= wait;
+            delay *= 2;
+        }
+        delays
+    }
+
+    /// Run `op` until it succeeds, fails for good or the retries run out
+    pub fn run<T>(
+        &self,
+        what: &str,
+        mut op: impl FnMut() -> Result<T, NvmlError>,
+    ) -> Result<T, NvmlError> {
+        let delays = self.delays();
+        let mut attempt = 0;
+        loop {
+            match op() {
+                Err(e) if attempt < delays.len() && is_transient(&e) => {
+                    let delay = delays[attempt];
+                    attempt += 1;
+                    warn!(
+                        "Failed to {}: {}, retry {} of {} in {}ms",
+                        what,
+                        e,
+                        attempt,
+                        delays.len(),
+                        delay.as_millis()
+                    );
+                    backoff(delay);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Sleep between two tries. On a multi-threaded runtime the worker is
+/// handed off first, so other tasks keep running meanwhile.
+fn backoff(delay: Duration) {
+    if delay.is_zero() {
+        return;
+    }
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(|| std::thread::sleep(delay))
+        }
+        _ => std::thread::sleep(delay),
+    }
+}
+
+/// Errors a GPU returns for a moment, typically while it wakes from a low
+/// power state. Anything else fails the same way when tried again.
+pub fn is_transient(e: &NvmlError) -> bool {
+    matches!(
+        e,
+        NvmlError::Unknown
+            | NvmlError::GpuLost
+            | NvmlError::Timeout
+            | NvmlError::InUse
+            | NvmlError::IrqIssue
+    )
 }
 
 enum GpuId {
@@ -40,7 +167,22 @@ impl NvGpu {
         let device_name = device.name()?;
         info!("Initialized NVML for {}", device_name);
 
-        Ok(Self { nvml, gpu_id })
+        match nvml.sys_driver_version() {
+            Ok(version) => info!("NVIDIA driver version {}", version),
+            Err(e) => warn!("Failed to read driver version: {}", e),
+        }
+
+        Ok(Self {
+            nvml,
+            gpu_id,
+            retry: NvmlRetry::default(),
+        })
+    }
+
+    /// Retry transient NVML errors as given instead of the default
+    pub fn with_retry(mut self, retry: NvmlRetry) -> Self {
+        self.retry = retry;
+        self
     }
 
     /// Get device (helper method)
@@ -99,12 +241,171 @@ impl NvGpu {
         Ok(self)
     }
 
+    /// Current GPU utilization in percent
+    pub fn utilization(&self) -> Result<u32, NvmlError> {
+        self.retry.run("read GPU utilization", || {
+            Ok(self.get_device()?.utilization_rates()?.gpu)
+        })
+    }
+
+    /// Take a telemetry reading for the session history
+    pub fn sample(&self) -> Result<TelemetrySample, NvmlError> {
+        self.retry
+            .run("sample GPU telemetry", || self.sample_once())
+    }
+
+    fn sample_once(&self) -> Result<TelemetrySample, NvmlError> {
+        let device = self.get_device()?;
+        let reasons = device.current_throttle_reasons()?;
+
+        let thermal = ThrottleReasons::SW_THERMAL_SLOWDOWN
+            | ThrottleReasons::HW_THERMAL_SLOWDOWN
+            | ThrottleReasons::HW_SLOWDOWN;
+        let power = ThrottleReasons::SW_POWER_CAP | ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN;
+
+        Ok(TelemetrySample {
+            timestamp_ms: now_ms(),
+            temperature_c: device.temperature(TemperatureSensor::Gpu)?,
+            power_mw: device.power_usage()?,
+            thermal_throttle: reasons.intersects(thermal),
+            power_throttle: reasons.intersects(power),
+            power_throttle_ns: device
+                .violation_status(PerformancePolicy::Power)
+                .ok()
+                .map(|v| v.violation_time),
+            thermal_throttle_ns: device
+                .violation_status(PerformancePolicy::Thermal)
+                .ok()
+                .map(|v| v.violation_time),
+            // Up to the daemon, which knows the sessions
+            game_vram_mib: BTreeMap::new(),
+        })
+    }
+
+    /// Performance state and PCIe link, readable without superuser access
+    pub fn link_info(&self) -> Result<LinkInfo, NvmlError> {
+        self.retry
+            .run("read the PCIe link", || self.link_info_once())
+    }
+
+    fn link_info_once(&self) -> Result<LinkInfo, NvmlError> {
+        let device = self.get_device()?;
+        let pstate = match device.performance_state()? {
+            PerformanceState::Unknown => None,
+            state => Some(state.as_c()),
+        };
+
+        Ok(LinkInfo {
+            name: device.name()?,
+            pstate,
+            pcie_gen: device.current_pcie_link_gen()?,
+            pcie_max_gen: device.max_pcie_link_gen()?,
+            pcie_width: device.current_pcie_link_width()?,
+            pcie_max_width: device.max_pcie_link_width()?,
+            bar1_mib: device.bar1_memory_info()?.total / 1024 / 1024,
+        })
+    }
+
+    /// Power, temperature and clock against their limits, readable without
+    /// superuser access
+    pub fn headroom(&self) -> Result<Headroom, NvmlError> {
+        self.retry
+            .run("read the GPU headroom", || self.headroom_once())
+    }
+
+    fn headroom_once(&self) -> Result<Headroom, NvmlError> {
+        let device = self.get_device()?;
+
+        Ok(Headroom {
+            power_mw: device.power_usage()?,
+            power_limit_mw: device.enforced_power_limit()?,
+            max_power_limit_mw: device
+                .power_management_limit_constraints()
+                .ok()
+                .map(|pm| pm.max_limit),
+            temperature_c: device.temperature(TemperatureSensor::Gpu)?,
+            slowdown_c: device
+                .temperature_threshold(TemperatureThreshold::Slowdown)
+                .ok(),
+            clock_mhz: device.clock_info(Clock::Graphics)?,
+            max_clock_mhz: device.max_clock_info(Clock::Graphics).ok(),
+        })
+    }
+
+    /// Utilization, VRAM use and processes already on the GPU
+    pub fn load(&self) -> Result<GpuLoad, NvmlError> {
+        self.retry.run("read GPU load", || self.load_once())
+    }
+
+    fn load_once(&self) -> Result<GpuLoad, NvmlError> {
+        let device = self.get_device()?;
+        let memory = device.memory_info()?;
+
+        Ok(GpuLoad {
+            utilization: device.utilization_rates()?.gpu,
+            vram_used_mib: memory.used / 1024 / 1024,
+            vram_total_mib: memory.total / 1024 / 1024,
+            processes: self
+                .processes_once()?
+                .into_iter()
+                .map(|(pid, vram_mib)| GpuProcess {
+                    pid,
+                    name: proc::name(pid).unwrap_or_else(|| "unknown".to_string()),
+                    vram_mib,
+                })
+                .collect(),
+        })
+    }
+
+    /// VRAM in MiB of every process with a context on the GPU, by PID.
+    /// Processes the driver does not account for are left out.
+    pub fn process_vram(&self) -> Result<BTreeMap<u32, u64>, NvmlError> {
+        let processes = self
+            .retry
+            .run("read per-process VRAM", || self.processes_once())?;
+        Ok(processes
+            .into_iter()
+            .filter_map(|(pid, vram)| Some((pid, vram?)))
+            .collect())
+    }
+
+    fn processes_once(&self) -> Result<BTreeMap<u32, Option<u64>>, NvmlError> {
+        let device = self.get_device()?;
+
+        // A process rendering and computing shows up in both lists
+        let mut processes: BTreeMap<u32, Option<u64>> = BTreeMap::new();
+        for process in device
+            .running_graphics_processes()?
+            .into_iter()
+            .chain(device.running_compute_processes()?)
+        {
+            let vram = match process.used_gpu_memory {
+                UsedGpuMemory::Used(bytes) => Some(bytes / 1024 / 1024),
+                UsedGpuMemory::Unavailable => None,
+            };
+            let entry = processes.entry(process.pid).or_default();
+            *entry = (*entry).max(vram);
+        }
+        Ok(processes)
+    }
+
     /// Set the GPU power limit, need superuser access
     pub fn set_power_limit(
         &mut self,
         power_limit: Option<u32>,
         set_max_pwr: Option<bool>,
     ) -> Result<&mut Self, NvmlError> {
+        self.retry.run("set the power limit", || {
+            self.set_power_limit_once(power_limit, set_max_pwr)
+        })?;
+        Ok(self)
+    }
+
+    fn set_power_limit_once(
+        &self,
+        power_limit: Option<u32>,
+        set_max_pwr: Option<bool>,
+    ) -> Result<(), NvmlError> {
         let mut device = self.get_device()?;
         let device_name = device.name()?;
 
@@ -142,19 +443,533 @@ impl NvGpu {
         debug!("Enforced power limit: {}mW", enforced_power);
         debug!("GPU temperature: {}°C", temp);
 
-        Ok(self)
+        Ok(())
+    }
+
+    /// Power limit currently set on the GPU, in milliwatts
+    pub fn power_limit(&self) -> Result<u32, NvmlError> {
+        self.retry.run("read the power limit", || {
+            self.get_device()?.power_management_limit()
+        })
     }
 
     /// Restore GPU to default settings, need superuser access
     pub fn restore_defaults(&mut self) -> Result<&mut Self, NvmlError> {
+        self.retry.run("restore the power limit", || {
+            let mut device = self.get_device()?;
+            let device_name = device.name()?;
+            info!("Restoring NVIDIA defaults for device: {}", device_name);
+
+            let default_power = device.power_management_limit_default()?;
+            device.set_power_management_limit(default_power)?;
+            info!("Restored power limit to default: {}mW", default_power);
+            Ok(())
+        })?;
+        Ok(self)
+    }
+
+    /// Keep the memory clock at or above `min_mhz`, need superuser access.
+    /// Returns the supported clock the floor was rounded to.
+    pub fn set_min_mem_clock(&mut self, min_mhz: u32) -> Result<u32, NvmlError> {
+        self.retry.run("lock the memory clock", || {
+            self.set_min_mem_clock_once(min_mhz)
+        })
+    }
+
+    fn set_min_mem_clock_once(&self, min_mhz: u32) -> Result<u32, NvmlError> {
         let mut device = self.get_device()?;
-        let device_name = device.name()?;
-        info!("Restoring NVIDIA defaults for device: {}", device_name);
+        let supported = device.supported_memory_clocks()?;
+        let (Some(floor), Some(&max)) =
+            (mem_clock_floor(&supported, min_mhz), supported.iter().max())
+        else {
+            return Err(NvmlError::NotSupported);
+        };
 
-        let default_power = device.power_management_limit_default()?;
-        device.set_power_management_limit(default_power)?;
-        info!("Restored power limit to default: {}mW", default_power);
+        if floor != min_mhz {
+            warn!(
+                "Memory clock {}MHz is not supported, using {}MHz (supported: {:?})",
+                min_mhz, floor, supported
+            );
+        }
+        device.set_mem_locked_clocks(floor, max)?;
+        info!("Locked memory clock to at least {}MHz", floor);
+        Ok(floor)
+    }
 
+    /// Let the driver manage the memory clock again
+    pub fn reset_mem_clock(&mut self) -> Result<&mut Self, NvmlError> {
+        self.retry.run("reset the memory clock", || {
+            self.get_device()?.reset_mem_locked_clocks()
+        })?;
+        info!("Reset locked memory clock");
         Ok(self)
     }
+
+    /// Unlock clocks pinned by other tools, need superuser access
+    pub fn reset_clocks(&mut self) -> Result<&mut Self, NvmlError> {
+        self.retry
+            .run("reset locked clocks", || self.reset_clocks_once())?;
+        Ok(self)
+    }
+
+    fn reset_clocks_once(&self) -> Result<(), NvmlError> {
+        let mut device = self.get_device()?;
+        device.reset_gpu_locked_clocks()?;
+        info!("Reset locked GPU clocks");
+
+        // Many GeForce cards have neither
+        let results = [
+            ("memory", device.reset_mem_locked_clocks()),
+            ("application", device.reset_applications_clocks()),
+        ];
+        for (name, result) in results {
+            match result {
+                Ok(()) => info!("Reset {} clocks", name),
+                Err(NvmlError::NotSupported) => debug!("Resetting {} clocks not supported", name),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The lowest supported memory clock at or above `min_mhz`, the highest one
+/// when it asks for more than the GPU has
+fn mem_clock_floor(supported: &[u32], min_mhz: u32) -> Option<u32> {
+    supported
+        .iter()
+        .filter(|clock| **clock >= min_mhz)
+        .min()
+        .or_else(|| supported.iter().max())
+        .copied()
+}
+
+/// What the GPU runs at and how it is attached
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LinkInfo {
+    pub name: String,
+
+    /// Performance state, 0 is the fastest
+    pub pstate: Option<u32>,
+
+    pub pcie_gen: u32,
+    pub pcie_max_gen: u32,
+    pub pcie_width: u32,
+    pub pcie_max_width: u32,
+
+    /// Size of the BAR1 aperture in MiB
+    pub bar1_mib: u64,
+}
+
+impl LinkInfo {
+    /// The CPU can map the whole VRAM instead of a 256MiB window
+    pub fn resizable_bar(&self) -> bool {
+        self.bar1_mib > LEGACY_BAR1_MIB
+    }
+
+    /// Reasons the GPU may fall short of what it can do
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.pcie_width < self.pcie_max_width {
+            problems.push(format!(
+                "PCIe link runs at x{} instead of x{}, check the slot, riser or firmware lane settings",
+                self.pcie_width, self.pcie_max_width
+            ));
+        }
+
+        // The link trains down while idle to save power, only a slow link
+        // under load means something
+        if self.pstate == Some(0) && self.pcie_gen < self.pcie_max_gen {
+            problems.push(format!(
+                "PCIe link runs at gen {} under load instead of gen {}",
+                self.pcie_gen, self.pcie_max_gen
+            ));
+        }
+
+        if self.pcie_max_width <= NARROW_LINK_WIDTH {
+            problems.push(format!(
+                "GPU is attached through an x{} link (eGPU or chipset slot), bandwidth heavy games lose frames to it",
+                self.pcie_max_width
+            ));
+        }
+
+        if !self.resizable_bar() {
+            problems.push(
+                "Resizable BAR is off, enabling it in the firmware setup helps some games"
+                    .to_string(),
+            );
+        }
+
+        problems
+    }
+}
+
+/// How far the GPU runs from what holds it back
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Headroom {
+    pub power_mw: u32,
+
+    /// Power limit the driver enforces right now
+    pub power_limit_mw: u32,
+
+    /// Highest power limit the board allows
+    pub max_power_limit_mw: Option<u32>,
+
+    pub temperature_c: u32,
+
+    /// Temperature the hardware starts slowing down at
+    pub slowdown_c: Option<u32>,
+
+    pub clock_mhz: u32,
+    pub max_clock_mhz: Option<u32>,
+}
+
+impl Headroom {
+    /// Draw in percent of the enforced power limit
+    pub fn power_percent(&self) -> u32 {
+        percent(u64::from(self.power_mw), u64::from(self.power_limit_mw))
+    }
+
+    /// Clock in percent of the highest boost clock
+    pub fn clock_percent(&self) -> Option<u32> {
+        self.max_clock_mhz
+            .map(|max| percent(u64::from(self.clock_mhz), u64::from(max)))
+    }
+
+    /// Whether raising the power limit could make the GPU faster
+    pub fn verdict(&self) -> String {
+        if let Some(slowdown) = self.slowdown_c
+            && self.temperature_c + THERMAL_MARGIN_C >= slowdown
+        {
+            return format!(
+                "limited by temperature ({}°C of {}°C), a higher power limit will not help",
+                self.temperature_c, slowdown
+            );
+        }
+
+        if self.power_percent() >= POWER_LIMITED_PERCENT {
+            return match self.max_power_limit_mw {
+                Some(max) if max > self.power_limit_mw => format!(
+                    "limited by power, raising the limit up to {}W can help",
+                    max / 1000
+                ),
+                _ => "limited by power at the highest limit the board allows".to_string(),
+            };
+        }
+
+        "not limited by power or temperature, a higher power limit will not help".to_string()
+    }
+}
+
+fn percent(value: u64, max: u64) -> u32 {
+    (value * 100).checked_div(max).unwrap_or(0) as u32
+}
+
+/// What keeps the GPU busy before a game starts
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GpuLoad {
+    /// Utilization in percent
+    pub utilization: u32,
+    pub vram_used_mib: u64,
+    pub vram_total_mib: u64,
+
+    /// Processes with a context on the GPU
+    pub processes: Vec<GpuProcess>,
+}
+
+/// A process with a context on the GPU
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpuProcess {
+    pub pid: u32,
+    pub name: String,
+
+    /// VRAM it holds in MiB, unknown when the driver does not tell
+    pub vram_mib: Option<u64>,
+}
+
+impl fmt::Display for GpuProcess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.vram_mib {
+            Some(vram) => write!(f, "{} ({}, {}MiB)", self.name, self.pid, vram),
+            None => write!(f, "{} ({})", self.name, self.pid),
+        }
+    }
+}
+
+impl GpuLoad {
+    pub fn vram_percent(&self) -> u32 {
+        (self.vram_used_mib * 100)
+            .checked_div(self.vram_total_mib)
+            .unwrap_or_default() as u32
+    }
+
+    /// VRAM held by the listed processes in MiB
+    pub fn process_vram_mib(&self) -> u64 {
+        self.processes.iter().filter_map(|p| p.vram_mib).sum()
+    }
+
+    /// The processes holding VRAM, the largest first
+    pub fn vram_holders(&self) -> Vec<&GpuProcess> {
+        let mut holders: Vec<&GpuProcess> = self
+            .processes
+            .iter()
+            .filter(|p| p.vram_mib.is_some_and(|vram| vram > 0))
+            .collect();
+        holders.sort_by_key(|p| std::cmp::Reverse(p.vram_mib));
+        holders
+    }
+
+    /// Thresholds in percent the load reached, `None` skips a threshold
+    pub fn busy_reasons(
+        &self,
+        util_percent: Option<u32>,
+        vram_percent: Option<u32>,
+    ) -> Vec<String> {
+        let mut reasons = Vec::new();
+
+        if let Some(threshold) = util_percent.filter(|t| self.utilization >= *t) {
+            reasons.push(format!(
+                "GPU is already {}% utilized (threshold {}%)",
+                self.utilization, threshold
+            ));
+        }
+        if let Some(threshold) = vram_percent.filter(|t| self.vram_percent() >= *t) {
+            reasons.push(format!(
+                "{}MiB of {}MiB VRAM are already in use (threshold {}%)",
+                self.vram_used_mib, self.vram_total_mib, threshold
+            ));
+        }
+
+        reasons
+    }
+}
+
+/// Major and minor part of an NVIDIA driver version, e.g. 550.54
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DriverVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl DriverVersion {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+
+    /// Parse `550.54.14` style versions, ignoring the patch level
+    pub fn parse(version: &str) -> Option<Self> {
+        let mut parts = version.trim().split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next().map_or(Some(0), |m| m.parse().ok())?;
+        Some(Self::new(major, minor))
+    }
+
+    /// Version of the loaded driver, `None` when no NVIDIA driver is found
+    pub fn detect() -> Option<Self> {
+        driver_version_string().as_deref().and_then(Self::parse)
+    }
+}
+
+impl fmt::Display for DriverVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:02}", self.major, self.minor)
+    }
+}
+
+/// Full driver version string, read from the kernel module with NVML as fallback
+pub fn driver_version_string() -> Option<String> {
+    if let Ok(version) = std::fs::read_to_string(DRIVER_VERSION_PATH)
+        && !version.trim().is_empty()
+    {
+        return Some(version.trim().to_string());
+    }
+
+    debug!("Driver version not in sysfs, asking NVML");
+    Nvml::init().ok()?.sys_driver_version().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nvml_retry() {
+        let retry = NvmlRetry {
+            retries: 2,
+            delay: Duration::ZERO,
+        };
+
+        let mut calls = 0;
+        let result = retry.run("wake", || {
+            calls += 1;
+            if calls < 3 {
+                Err(NvmlError::GpuLost)
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // Out of retries
+        let mut calls = 0;
+        let result: Result<(), _> = retry.run("wake", || {
+            calls += 1;
+            Err(NvmlError::Unknown)
+        });
+        assert!(matches!(result, Err(NvmlError::Unknown)));
+        assert_eq!(calls, 3);
+
+        // Not worth trying again
+        let mut calls = 0;
+        let result: Result<(), _> = retry.run("wake", || {
+            calls += 1;
+            Err(NvmlError::NoPermission)
+        });
+        assert!(matches!(result, Err(NvmlError::NoPermission)));
+        assert_eq!(calls, 1);
+
+        // Retries and total wait stay bounded
+        let retry = NvmlRetry {
+            retries: 100,
+            delay: Duration::from_millis(400),
+        };
+        let delays = retry.delays();
+        assert_eq!(delays.len(), MAX_RETRIES as usize);
+        assert_eq!(delays.iter().sum::<Duration>(), MAX_RETRY_WAIT);
+        assert_eq!(delays[..3], [400, 800, 300].map(Duration::from_millis));
+    }
+
+    #[test]
+    fn test_driver_version_parse() {
+        assert_eq!(
+            DriverVersion::parse("550.54.14\n"),
+            Some(DriverVersion::new(550, 54))
+        );
+        assert_eq!(
+            DriverVersion::parse("575"),
+            Some(DriverVersion::new(575, 0))
+        );
+        assert_eq!(DriverVersion::parse("nouveau"), None);
+        assert_eq!(DriverVersion::parse("550.x"), None);
+    }
+
+    #[test]
+    fn test_mem_clock_floor() {
+        let supported = [10501, 9501, 5001, 810, 405];
+        assert_eq!(mem_clock_floor(&supported, 9501), Some(9501));
+        assert_eq!(mem_clock_floor(&supported, 6000), Some(9501));
+        assert_eq!(mem_clock_floor(&supported, 12000), Some(10501));
+        assert_eq!(mem_clock_floor(&[], 6000), None);
+    }
+
+    #[test]
+    fn test_link_info_problems() {
+        let healthy = LinkInfo {
+            pstate: Some(0),
+            pcie_gen: 4,
+            pcie_max_gen: 4,
+            pcie_width: 16,
+            pcie_max_width: 16,
+            bar1_mib: 16384,
+            ..Default::default()
+        };
+        assert!(healthy.resizable_bar());
+        assert!(healthy.problems().is_empty());
+
+        // Trained down while idle is fine
+        let idle = LinkInfo {
+            pstate: Some(8),
+            pcie_gen: 1,
+            ..healthy.clone()
+        };
+        assert!(idle.problems().is_empty());
+
+        let egpu = LinkInfo {
+            pcie_gen: 3,
+            pcie_width: 2,
+            pcie_max_width: 4,
+            bar1_mib: 256,
+            ..healthy
+        };
+        let problems = egpu.problems();
+        assert_eq!(problems.len(), 4);
+        assert!(problems[0].contains("x2 instead of x4"));
+        assert!(problems[1].contains("gen 3"));
+        assert!(problems[2].contains("eGPU"));
+        assert!(problems[3].contains("Resizable BAR"));
+    }
+
+    #[test]
+    fn test_headroom_verdict() {
+        let mut headroom = Headroom {
+            power_mw: 280_000,
+            power_limit_mw: 285_000,
+            max_power_limit_mw: Some(350_000),
+            temperature_c: 70,
+            slowdown_c: Some(90),
+            clock_mhz: 2520,
+            max_clock_mhz: Some(2800),
+        };
+        assert_eq!(headroom.power_percent(), 98);
+        assert_eq!(headroom.clock_percent(), Some(90));
+        assert!(headroom.verdict().contains("up to 350W can help"));
+
+        headroom.power_limit_mw = 350_000;
+        headroom.power_mw = 345_000;
+        assert!(headroom.verdict().contains("at the highest limit"));
+
+        headroom.temperature_c = 86;
+        assert!(headroom.verdict().contains("limited by temperature"));
+
+        headroom.temperature_c = 50;
+        headroom.power_mw = 90_000;
+        assert!(headroom.verdict().starts_with("not limited"));
+        assert_eq!(Headroom::default().power_percent(), 0);
+    }
+
+    #[test]
+    fn test_gpu_load_busy_reasons() {
+        let load = GpuLoad {
+            utilization: 85,
+            vram_used_mib: 6144,
+            vram_total_mib: 8192,
+            processes: vec![
+                GpuProcess {
+                    pid: 1200,
+                    name: "Xorg".to_string(),
+                    vram_mib: Some(300),
+                },
+                GpuProcess {
+                    pid: 4242,
+                    name: "python3".to_string(),
+                    vram_mib: Some(5600),
+                },
+                GpuProcess {
+                    pid: 4300,
+                    name: "firefox".to_string(),
+                    vram_mib: None,
+                },
+            ],
+        };
+        assert_eq!(load.vram_percent(), 75);
+        assert!(load.busy_reasons(None, None).is_empty());
+        assert!(load.busy_reasons(Some(90), Some(80)).is_empty());
+        assert_eq!(load.busy_reasons(Some(80), Some(75)).len(), 2);
+        assert!(load.busy_reasons(None, Some(50))[0].contains("6144MiB of 8192MiB"));
+        assert_eq!(GpuLoad::default().vram_percent(), 0);
+
+        assert_eq!(load.process_vram_mib(), 5900);
+        let holders: Vec<String> = load.vram_holders().iter().map(|p| p.to_string()).collect();
+        assert_eq!(holders, ["python3 (4242, 5600MiB)", "Xorg (1200, 300MiB)"]);
+        assert_eq!(load.processes[2].to_string(), "firefox (4300)");
+    }
+
+    #[test]
+    fn test_driver_version_ordering() {
+        assert!(DriverVersion::new(550, 54) < DriverVersion::new(570, 0));
+        assert!(DriverVersion::new(570, 124) > DriverVersion::new(570, 86));
+        assert_eq!(DriverVersion::new(570, 9).to_string(), "570.09");
+    }
 }
@@ -0,0 +1,295 @@
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::common::config::PathsConfig;
+
+/// Directory nvprime keeps under each base directory
+const APP_DIR: &str = "nvprime";
+
+const CONFIG_FILE: &str = "nvprime.conf";
+
+/// Set to `1` to keep everything next to the executable, as `--portable` does
+pub const PORTABLE_ENV: &str = "NVPRIME_PORTABLE";
+
+/// Directory of the executable once `--portable` was given
+static PORTABLE_ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Where Vulkan drivers install their ICD manifests
+pub const ICD_DIR: &str = "/usr/share/vulkan/icd.d";
+
+/// Manifest of the NVIDIA Vulkan driver
+pub const NVIDIA_ICD: &str = "/usr/share/vulkan/icd.d/nvidia_icd.json";
+
+/// Every file and directory nvprime reads or writes for a user, following
+/// the XDG base directory spec
+#[derive(Debug, Clone, PartialEq)]
+pub struct Paths {
+    /// The config file, `$NVPRIME_CONFIG` when set
+    pub config_file: PathBuf,
+
+    /// Snapshots and other files kept next to the config
+    pub config_dir: PathBuf,
+
+    /// Session history and exported telemetry
+    pub data_dir: PathBuf,
+
+    /// Logs
+    pub state_dir: PathBuf,
+
+    pub cache_dir: PathBuf,
+
+    /// Sockets, only valid while the user is logged in
+    pub runtime_dir: PathBuf,
+}
+
+impl Paths {
+    /// Directories of the current user, from the process environment, or
+    /// next to the executable in portable mode
+    pub fn detect() -> anyhow::Result<Self> {
+        let uid = nix::unistd::getuid().as_raw();
+        if let Some(root) = portable_root()? {
+            return Ok(Self::portable(&root, |key| std::env::var(key).ok(), uid));
+        }
+
+        let home = dirs::home_dir().context("Could not find home directory")?;
+        Ok(Self::from_env(
+            |key| std::env::var(key).ok(),
+            &home,
+            uid,
+            |path| path.exists(),
+        ))
+    }
+
+    /// Resolve the directories from `XDG_*` variables, falling back to the
+    /// spec's defaults under `home`
+    pub fn from_env(
+        var: impl Fn(&str) -> Option<String>,
+        home: &Path,
+        uid: u32,
+        exists: impl Fn(&Path) -> bool,
+    ) -> Self {
+        let base = |key: &str, default: &str| {
+            var(key)
+                .map(PathBuf::from)
+                // The spec says relative paths are invalid and must be ignored
+                .filter(|path| path.is_absolute())
+                .unwrap_or_else(|| home.join(default))
+        };
+
+        let config_home = base("XDG_CONFIG_HOME", ".config");
+        let config_dir = config_home.join(APP_DIR);
+        let config_file = var("NVPRIME_CONFIG").map(PathBuf::from).unwrap_or_else(|| {
+            // Older releases read the file straight from the config home
+            let legacy = config_home.join(CONFIG_FILE);
+            let current = config_dir.join(CONFIG_FILE);
+            if !exists(&current) && exists(&legacy) {
+                legacy
+            } else {
+                current
+            }
+        });
+
+        Self {
+            config_file,
+            config_dir,
+            data_dir: base("XDG_DATA_HOME", ".local/share").join(APP_DIR),
+            state_dir: base("XDG_STATE_HOME", ".local/state").join(APP_DIR),
+            cache_dir: base("XDG_CACHE_HOME", ".cache").join(APP_DIR),
+            runtime_dir: var("XDG_RUNTIME_DIR")
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+                .unwrap_or_else(|| user_runtime_dir(uid))
+                .join(APP_DIR),
+        }
+    }
+
+    /// Everything under `root` for a setup carried between machines, except
+    /// the runtime directory since sockets do not work on most removable
+    /// drives
+    pub fn portable(root: &Path, var: impl Fn(&str) -> Option<String>, uid: u32) -> Self {
+        Self {
+            config_file: var("NVPRIME_CONFIG")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| root.join(CONFIG_FILE)),
+            config_dir: root.to_path_buf(),
+            data_dir: root.join("data"),
+            state_dir: root.join("state"),
+            cache_dir: root.join("cache"),
+            runtime_dir: var("XDG_RUNTIME_DIR")
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+                .unwrap_or_else(|| user_runtime_dir(uid))
+                .join(APP_DIR),
+        }
+    }
+
+    /// Apply the `[paths]` section of the config
+    pub fn with_overrides(mut self, overrides: &PathsConfig) -> Self {
+        let apply = |dir: &mut PathBuf, value: &Option<PathBuf>| {
+            if let Some(value) = value {
+                *dir = value.clone();
+            }
+        };
+
+        apply(&mut self.data_dir, &overrides.data_dir);
+        apply(&mut self.state_dir, &overrides.state_dir);
+        apply(&mut self.cache_dir, &overrides.cache_dir);
+        apply(&mut self.runtime_dir, &overrides.runtime_dir);
+        self
+    }
+
+    pub fn sessions_dir(&self) -> PathBuf {
+        self.data_dir.join("sessions")
+    }
+
+    /// Screenshots and recordings, a directory per game
+    pub fn captures_dir(&self) -> PathBuf {
+        self.data_dir.join("captures")
+    }
+
+    pub fn snapshots_dir(&self) -> PathBuf {
+        self.config_dir.join("snapshots")
+    }
+
+    pub fn log_dir(&self) -> PathBuf {
+        self.state_dir.join("logs")
+    }
+}
+
+/// Keep everything next to the executable for the rest of the process
+pub fn enable_portable() -> anyhow::Result<()> {
+    let root = executable_dir()?;
+    let _ = PORTABLE_ROOT.set(root);
+    Ok(())
+}
+
+/// Where portable mode keeps everything, `None` unless `--portable` was
+/// given or `NVPRIME_PORTABLE` is set
+pub fn portable_root() -> anyhow::Result<Option<PathBuf>> {
+    if let Some(root) = PORTABLE_ROOT.get() {
+        return Ok(Some(root.clone()));
+    }
+    match std::env::var(PORTABLE_ENV) {
+        Ok(value) if is_enabled(&value) => executable_dir().map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn is_enabled(value: &str) -> bool {
+    matches!(value.trim(), "1" | "true" | "yes")
+}
+
+fn executable_dir() -> anyhow::Result<PathBuf> {
+    let exe = std::env::current_exe().context("Could not find the nvprime executable")?;
+    exe.parent()
+        .map(Path::to_path_buf)
+        .with_context(|| format!("{} has no parent directory", exe.display()))
+}
+
+/// The runtime directory systemd-logind creates for a logged in user
+pub fn user_runtime_dir(uid: u32) -> PathBuf {
+    PathBuf::from(format!("/run/user/{}", uid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars<'a>(pairs: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        |key| {
+            pairs
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        }
+    }
+
+    #[test]
+    fn test_paths_defaults() {
+        let paths = Paths::from_env(vars(&[]), Path::new("/home/user"), 1000, |_| false);
+
+        assert_eq!(
+            paths.config_file,
+            Path::new("/home/user/.config/nvprime/nvprime.conf")
+        );
+        assert_eq!(
+            paths.sessions_dir(),
+            Path::new("/home/user/.local/share/nvprime/sessions")
+        );
+        assert_eq!(
+            paths.log_dir(),
+            Path::new("/home/user/.local/state/nvprime/logs")
+        );
+        assert_eq!(paths.cache_dir, Path::new("/home/user/.cache/nvprime"));
+        assert_eq!(paths.runtime_dir, Path::new("/run/user/1000/nvprime"));
+    }
+
+    #[test]
+    fn test_paths_xdg() {
+        let env = [
+            ("XDG_CONFIG_HOME", "/cfg"),
+            ("XDG_DATA_HOME", "/data"),
+            ("XDG_STATE_HOME", "relative/state"),
+            ("XDG_RUNTIME_DIR", "/tmp/run"),
+        ];
+        let paths = Paths::from_env(vars(&env), Path::new("/home/user"), 1000, |_| false);
+
+        assert_eq!(paths.config_file, Path::new("/cfg/nvprime/nvprime.conf"));
+        assert_eq!(paths.snapshots_dir(), Path::new("/cfg/nvprime/snapshots"));
+        assert_eq!(paths.data_dir, Path::new("/data/nvprime"));
+        assert_eq!(
+            paths.state_dir,
+            Path::new("/home/user/.local/state/nvprime")
+        );
+        assert_eq!(paths.runtime_dir, Path::new("/tmp/run/nvprime"));
+
+        let env = [("NVPRIME_CONFIG", "/etc/games.conf")];
+        let paths = Paths::from_env(vars(&env), Path::new("/home/user"), 1000, |_| false);
+        assert_eq!(paths.config_file, Path::new("/etc/games.conf"));
+    }
+
+    #[test]
+    fn test_paths_legacy_config() {
+        let legacy = Path::new("/home/user/.config/nvprime.conf");
+        let paths = Paths::from_env(vars(&[]), Path::new("/home/user"), 1000, |p| p == legacy);
+        assert_eq!(paths.config_file, legacy);
+    }
+
+    #[test]
+    fn test_paths_portable() {
+        let env = [("XDG_CONFIG_HOME", "/cfg"), ("XDG_RUNTIME_DIR", "/tmp/run")];
+        let paths = Paths::portable(Path::new("/media/usb/nvprime"), vars(&env), 1000);
+
+        assert_eq!(
+            paths.config_file,
+            Path::new("/media/usb/nvprime/nvprime.conf")
+        );
+        assert_eq!(
+            paths.snapshots_dir(),
+            Path::new("/media/usb/nvprime/snapshots")
+        );
+        assert_eq!(
+            paths.sessions_dir(),
+            Path::new("/media/usb/nvprime/data/sessions")
+        );
+        assert_eq!(paths.cache_dir, Path::new("/media/usb/nvprime/cache"));
+        assert_eq!(paths.runtime_dir, Path::new("/tmp/run/nvprime"));
+
+        assert!(is_enabled("1"));
+        assert!(!is_enabled("0"));
+    }
+
+    #[test]
+    fn test_paths_overrides() {
+        let overrides = PathsConfig {
+            data_dir: Some(PathBuf::from("/games/nvprime")),
+            ..Default::default()
+        };
+        let paths = Paths::from_env(vars(&[]), Path::new("/home/user"), 1000, |_| false)
+            .with_overrides(&overrides);
+
+        assert_eq!(paths.sessions_dir(), Path::new("/games/nvprime/sessions"));
+        assert_eq!(paths.cache_dir, Path::new("/home/user/.cache/nvprime"));
+    }
+}
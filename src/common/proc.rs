@@ -0,0 +1,179 @@
+use std::collections::BTreeSet;
+
+/// Whether `pid` is `ancestor` or one of its descendants
+pub fn is_descendant(mut pid: u32, ancestor: u32) -> bool {
+    while pid > 1 {
+        if pid == ancestor {
+            return true;
+        }
+        match parent_pid(pid) {
+            Some(parent) => pid = parent,
+            None => return false,
+        }
+    }
+    pid == ancestor
+}
+
+/// The closest ancestor of `pid` with the short name `comm`
+pub fn ancestor_named(pid: u32, comm: &str) -> Option<u32> {
+    let mut pid = parent_pid(pid)?;
+    while pid > 1 {
+        if name(pid).is_some_and(|name| name == comm) {
+            return Some(pid);
+        }
+        pid = parent_pid(pid)?;
+    }
+    None
+}
+
+/// Whether any process has the short name `comm`
+pub fn is_running(comm: &str) -> bool {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return false;
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .any(|pid| name(pid).is_some_and(|name| name == comm))
+}
+
+/// Short name of the process as the kernel keeps it
+pub fn name(pid: u32) -> Option<String> {
+    let comm = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    Some(comm.trim().to_string())
+}
+
+pub fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    parse_parent_pid(&stat)
+}
+
+/// The PPID from `/proc/<pid>/stat`, after the parenthesized command name
+/// which may itself contain spaces and parentheses
+fn parse_parent_pid(stat: &str) -> Option<u32> {
+    stat_field(stat, 4)?.parse().ok()
+}
+
+/// When the process started, in clock ticks since boot. A PID reused by a
+/// later process comes with a later start time.
+pub fn start_time(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    stat_field(&stat, 22)?.parse().ok()
+}
+
+/// Field of `/proc/<pid>/stat` by its 1-based number in proc(5), counting
+/// from the state, the third one
+fn stat_field(stat: &str, field: usize) -> Option<&str> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_whitespace().nth(field.checked_sub(3)?)
+}
+
+/// Processes whose parent is `pid`, zombies included
+pub fn children(pid: u32) -> BTreeSet<u32> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return BTreeSet::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|p| parent_pid(*p) == Some(pid))
+        .collect()
+}
+
+/// Every thread of `pid` and of its descendants. Nice values and IO
+/// priorities are per thread on Linux, and only new threads inherit them.
+pub fn tree_threads(pid: u32) -> Vec<u32> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|p| is_descendant(*p, pid))
+        .flat_map(threads)
+        .collect()
+}
+
+fn threads(pid: u32) -> Vec<u32> {
+    let Ok(entries) = std::fs::read_dir(format!("/proc/{}/task", pid)) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_parent_pid() {
+        assert_eq!(
+            parse_parent_pid("1234 (game) S 1000 1234 1234 0"),
+            Some(1000)
+        );
+        assert_eq!(
+            parse_parent_pid("1234 (my (odd) game) R 42 1234 1234 0"),
+            Some(42)
+        );
+        assert_eq!(parse_parent_pid("garbage"), None);
+    }
+
+    #[test]
+    fn test_start_time() {
+        let stat = "1234 (my game) S 1000 1234 1234 0 -1 4194560 100 0 0 0 5 2 0 0 20 0 4 0 987654 1000000";
+        assert_eq!(stat_field(stat, 22), Some("987654"));
+        assert_eq!(stat_field(stat, 2), None);
+
+        let pid = std::process::id();
+        assert!(start_time(pid).is_some());
+        assert_eq!(start_time(pid), start_time(pid));
+    }
+
+    #[test]
+    fn test_is_running() {
+        let own = name(std::process::id()).unwrap();
+        assert!(is_running(&own));
+        assert!(!is_running("nvprime-missing"));
+    }
+
+    #[test]
+    fn test_is_descendant_of_self_and_parent() {
+        let pid = std::process::id();
+        assert!(is_descendant(pid, pid));
+        assert!(is_descendant(pid, parent_pid(pid).unwrap()));
+        assert!(!is_descendant(1, pid));
+
+        let parent = parent_pid(pid).unwrap();
+        let parent_name = name(parent).unwrap();
+        assert_eq!(ancestor_named(pid, &parent_name), Some(parent));
+        assert_eq!(ancestor_named(pid, "nvprime-missing"), None);
+    }
+
+    #[test]
+    fn test_children() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("5")
+            .spawn()
+            .unwrap();
+        assert!(children(std::process::id()).contains(&child.id()));
+        assert!(!children(std::process::id()).contains(&std::process::id()));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn test_tree_threads_includes_own_threads() {
+        let pid = std::process::id();
+        let tids = tree_threads(pid);
+        assert!(tids.contains(&pid));
+        assert!(tids.len() >= threads(pid).len());
+    }
+}
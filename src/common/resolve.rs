@@ -0,0 +1,180 @@
+use log::debug;
+use std::cell::OnceCell;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::common::steam::SteamCompat;
+
+/// Names the game's section directly, for the `env` resolver
+pub const GAME_ENV: &str = "NVPRIME_GAME";
+
+/// `ProductName` as a key of a version resource string, UTF-16LE with its
+/// terminating NUL
+const PRODUCT_KEY: &[u8] = b"P\0r\0o\0d\0u\0c\0t\0N\0a\0m\0e\0\0\0";
+
+/// Executables are read this far looking for their version resource
+const MAX_SCAN_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Longest product name read, in UTF-16 units
+const MAX_PRODUCT_CHARS: usize = 128;
+
+const CHUNK_BYTES: usize = 1024 * 1024;
+
+/// What a launch tells about the game it starts, for the resolvers
+#[derive(Debug, Default)]
+pub struct GameHints {
+    /// Lowercased stem of the executable
+    pub stem: String,
+
+    /// Path of the executable as launched
+    pub path: String,
+
+    /// Steam app ID of the launch
+    pub app_id: Option<u32>,
+
+    /// Section named in `NVPRIME_GAME`
+    pub env_name: Option<String>,
+
+    /// Read once, only when the `product` resolver asks for it
+    product: OnceCell<Option<String>>,
+}
+
+impl GameHints {
+    pub fn new(stem: &str, path: &str) -> Self {
+        Self {
+            stem: stem.to_string(),
+            path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Add what the environment tells, `NVPRIME_GAME` and Steam's app ID
+    pub fn with_env(mut self, env: impl Fn(&str) -> Option<String>) -> Self {
+        self.env_name = env(GAME_ENV)
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty());
+        self.app_id = SteamCompat::parse(env).app_id;
+        self
+    }
+
+    /// Product name from the executable's version resource
+    pub fn product_name(&self) -> Option<String> {
+        self.product
+            .get_or_init(|| {
+                let name = product_name(Path::new(&self.path));
+                debug!("Product name of {}: {:?}", self.path, name);
+                name
+            })
+            .clone()
+    }
+}
+
+/// A game name without case, spaces or punctuation, `ELDEN RING™` and
+/// `elden_ring` are both `eldenring`
+pub fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric() && c.is_ascii())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// `ProductName` from the version resource of a Windows executable, found
+/// by its key instead of walking the resource tree
+pub fn product_name(path: &Path) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let mut reader = file.take(MAX_SCAN_BYTES);
+
+    // Chunks overlap by what a key and its value take, so neither is
+    // missed when it straddles two reads
+    let overlap = PRODUCT_KEY.len() + 4 + MAX_PRODUCT_CHARS * 2;
+    let mut buffer = Vec::with_capacity(CHUNK_BYTES + overlap);
+    let mut offset = 0;
+    let mut chunk = vec![0; CHUNK_BYTES];
+
+    loop {
+        let read = reader.read(&mut chunk).ok()?;
+        buffer.extend_from_slice(&chunk[..read]);
+
+        if let Some(name) = find_product(&buffer, offset) {
+            return Some(name);
+        }
+        if read == 0 {
+            return None;
+        }
+
+        let keep = buffer.len().min(overlap);
+        let drop = buffer.len() - keep;
+        buffer.drain(..drop);
+        offset += drop;
+    }
+}
+
+/// Product name after the first key in `data`, which starts at `offset` of
+/// the file. Values are 32-bit aligned within the file.
+fn find_product(data: &[u8], offset: usize) -> Option<String> {
+    let mut start = 0;
+    while let Some(found) = data[start..]
+        .windows(PRODUCT_KEY.len())
+        .position(|window| window == PRODUCT_KEY)
+    {
+        let key = start + found;
+        let value = (offset + key + PRODUCT_KEY.len()).next_multiple_of(4) - offset;
+
+        let units: Vec<u16> = data
+            .get(value..)?
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .take(MAX_PRODUCT_CHARS)
+            .take_while(|unit| *unit != 0)
+            .collect();
+        let name = String::from_utf16_lossy(&units).trim().to_string();
+        if !name.is_empty() {
+            return Some(name);
+        }
+        start = key + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn test_product_name() {
+        // A String structure: length, value length, type, key, padding, value
+        let mut exe = b"MZ".to_vec();
+        exe.resize(0x103, 0);
+        exe.extend_from_slice(&[0x40, 0, 0x0b, 0, 1, 0]);
+        exe.extend_from_slice(PRODUCT_KEY);
+        exe.resize(exe.len().next_multiple_of(4), 0);
+        exe.extend(utf16("ELDEN RING™\0"));
+        exe.extend(utf16("ProductVersion"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("eldenring.exe");
+        std::fs::write(&path, &exe).unwrap();
+        assert_eq!(product_name(&path).as_deref(), Some("ELDEN RING™"));
+        assert_eq!(normalize_name("ELDEN RING™"), "eldenring");
+
+        std::fs::write(&path, b"MZ no resources").unwrap();
+        assert_eq!(product_name(&path), None);
+    }
+
+    #[test]
+    fn test_game_hints_env() {
+        let env = |key: &str| match key {
+            GAME_ENV => Some(" doom ".to_string()),
+            "SteamAppId" => Some("2280".to_string()),
+            _ => None,
+        };
+        let hints = GameHints::new("doomx64vk", "/games/doomx64vk.exe").with_env(env);
+        assert_eq!(hints.env_name.as_deref(), Some("doom"));
+        assert_eq!(hints.app_id, Some(2280));
+    }
+}
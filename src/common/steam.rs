@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// What Steam tells a compatibility tool about the game it launches, from
+/// `STEAM_COMPAT_*` and `SteamAppId`. Empty and relative paths are dropped,
+/// so a stray variable from a shell does not point anything at the wrong
+/// directory.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SteamCompat {
+    /// `STEAM_COMPAT_APP_ID`, then `SteamAppId`
+    pub app_id: Option<u32>,
+
+    /// `STEAM_COMPAT_DATA_PATH`, the `compatdata/<appid>` directory
+    pub data_path: Option<PathBuf>,
+
+    /// `STEAM_COMPAT_INSTALL_PATH`, the game's install directory
+    pub install_path: Option<PathBuf>,
+
+    /// `STEAM_COMPAT_CLIENT_INSTALL_PATH`, the Steam client itself
+    pub client_path: Option<PathBuf>,
+
+    /// `STEAM_COMPAT_LIBRARY_PATHS`, the Steam libraries
+    pub library_paths: Vec<PathBuf>,
+
+    /// `STEAM_COMPAT_TOOL_PATHS`, Proton and the runtime it runs in
+    pub tool_paths: Vec<PathBuf>,
+}
+
+impl SteamCompat {
+    /// From the environment nvprime was started with
+    pub fn from_env() -> Self {
+        Self::parse(|key| std::env::var(key).ok())
+    }
+
+    pub fn parse(env: impl Fn(&str) -> Option<String>) -> Self {
+        let app_id = ["STEAM_COMPAT_APP_ID", "SteamAppId"]
+            .iter()
+            .filter_map(|key| env(key)?.trim().parse::<u32>().ok())
+            .find(|id| *id != 0);
+
+        Self {
+            app_id,
+            data_path: env("STEAM_COMPAT_DATA_PATH").and_then(|path| absolute(&path)),
+            install_path: env("STEAM_COMPAT_INSTALL_PATH").and_then(|path| absolute(&path)),
+            client_path: env("STEAM_COMPAT_CLIENT_INSTALL_PATH").and_then(|path| absolute(&path)),
+            library_paths: path_list(env("STEAM_COMPAT_LIBRARY_PATHS").as_deref()),
+            tool_paths: path_list(env("STEAM_COMPAT_TOOL_PATHS").as_deref()),
+        }
+    }
+
+    /// Whether Steam launched the game
+    pub fn is_steam(&self) -> bool {
+        self.app_id.is_some() || self.data_path.is_some()
+    }
+
+    /// The Proton prefix inside the compat data
+    pub fn prefix(&self) -> Option<PathBuf> {
+        self.data_path.as_ref().map(|data| data.join("pfx"))
+    }
+
+    /// Variables for hooks, `NVPRIME_STEAM_APP_ID`, `NVPRIME_STEAM_PREFIX`
+    /// and `NVPRIME_STEAM_GAME_DIR`, each only when known
+    pub fn hook_env(&self) -> BTreeMap<String, String> {
+        let mut env = BTreeMap::new();
+        if let Some(app_id) = self.app_id {
+            env.insert("NVPRIME_STEAM_APP_ID".to_string(), app_id.to_string());
+        }
+        if let Some(prefix) = self.prefix() {
+            env.insert(
+                "NVPRIME_STEAM_PREFIX".to_string(),
+                prefix.display().to_string(),
+            );
+        }
+        if let Some(dir) = &self.install_path {
+            env.insert(
+                "NVPRIME_STEAM_GAME_DIR".to_string(),
+                dir.display().to_string(),
+            );
+        }
+        env
+    }
+}
+
+fn absolute(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path.trim());
+    path.is_absolute().then(|| path.to_path_buf())
+}
+
+/// A colon separated list of paths, without duplicates
+fn path_list(list: Option<&str>) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for path in list.unwrap_or_default().split(':').filter_map(absolute) {
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |key| {
+            vars.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        }
+    }
+
+    #[test]
+    fn test_parse_steam_compat() {
+        let steam = SteamCompat::parse(env(&[
+            ("STEAM_COMPAT_APP_ID", "0"),
+            ("SteamAppId", "1245620"),
+            ("STEAM_COMPAT_DATA_PATH", "/steam/compatdata/1245620"),
+            ("STEAM_COMPAT_INSTALL_PATH", "relative/dir"),
+            (
+                "STEAM_COMPAT_LIBRARY_PATHS",
+                "/steam/steamapps:/mnt/games/steamapps::/steam/steamapps",
+            ),
+        ]));
+
+        assert_eq!(steam.app_id, Some(1245620));
+        assert_eq!(
+            steam.prefix(),
+            Some(PathBuf::from("/steam/compatdata/1245620/pfx"))
+        );
+        assert_eq!(steam.install_path, None);
+        assert_eq!(
+            steam.library_paths,
+            [
+                PathBuf::from("/steam/steamapps"),
+                PathBuf::from("/mnt/games/steamapps")
+            ]
+        );
+        assert_eq!(
+            steam.hook_env().keys().collect::<Vec<_>>(),
+            ["NVPRIME_STEAM_APP_ID", "NVPRIME_STEAM_PREFIX"]
+        );
+
+        let outside = SteamCompat::parse(env(&[("STEAM_COMPAT_DATA_PATH", "")]));
+        assert!(!outside.is_steam());
+        assert!(outside.hook_env().is_empty());
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One hour of history at the default one second sampling interval
+const DEFAULT_CAPACITY: usize = 3600;
+
+/// A single GPU telemetry reading taken by the daemon
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TelemetrySample {
+    /// Wall clock time of the reading in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+
+    /// GPU core temperature in degrees Celsius
+    pub temperature_c: u32,
+
+    /// Board power draw in milliwatts
+    pub power_mw: u32,
+
+    /// Clocks were reduced by a software or hardware thermal slowdown
+    pub thermal_throttle: bool,
+
+    /// Clocks were reduced by the power limit or a power brake
+    pub power_throttle: bool,
+
+    /// NVML's running total of time spent power throttled, in nanoseconds,
+    /// `None` on GPUs that do not keep it
+    #[serde(default)]
+    pub power_throttle_ns: Option<u64>,
+
+    /// NVML's running total of time spent thermally throttled, in nanoseconds
+    #[serde(default)]
+    pub thermal_throttle_ns: Option<u64>,
+
+    /// VRAM in MiB held by each session's game and its children, by the
+    /// PID of the session. Sessions without a process on the GPU are left
+    /// out.
+    #[serde(default)]
+    pub game_vram_mib: BTreeMap<u32, u64>,
+}
+
+impl TelemetrySample {
+    /// Keep the VRAM of a single session, for the client that owns it
+    pub fn retain_session(&mut self, pid: u32) {
+        self.game_vram_mib.retain(|session, _| *session == pid);
+    }
+
+    /// VRAM held by the games of the sample, `None` when none was on the GPU
+    pub fn game_vram(&self) -> Option<u64> {
+        (!self.game_vram_mib.is_empty()).then(|| self.game_vram_mib.values().sum())
+    }
+}
+
+/// Fixed size buffer keeping the most recent telemetry samples
+#[derive(Debug)]
+pub struct TelemetryRing {
+    samples: VecDeque<TelemetrySample>,
+    capacity: usize,
+}
+
+impl TelemetryRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, sample: TelemetrySample) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// All samples taken at or after the given timestamp
+    pub fn since(&self, timestamp_ms: u64) -> Vec<TelemetrySample> {
+        self.samples
+            .iter()
+            .filter(|s| s.timestamp_ms >= timestamp_ms)
+            .cloned()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+impl Default for TelemetryRing {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+/// Current wall clock time in milliseconds since the Unix epoch
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp_ms: u64) -> TelemetrySample {
+        TelemetrySample {
+            timestamp_ms,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_ring_drops_oldest() {
+        let mut ring = TelemetryRing::new(2);
+        ring.push(sample(1));
+        ring.push(sample(2));
+        ring.push(sample(3));
+
+        assert_eq!(ring.len(), 2);
+        assert_eq!(ring.since(0), vec![sample(2), sample(3)]);
+    }
+
+    #[test]
+    fn test_ring_since() {
+        let mut ring = TelemetryRing::default();
+        for ts in [100, 200, 300] {
+            ring.push(sample(ts));
+        }
+
+        assert_eq!(ring.since(200), vec![sample(200), sample(300)]);
+        assert!(ring.since(301).is_empty());
+    }
+
+    #[test]
+    fn test_ring_zero_capacity() {
+        let mut ring = TelemetryRing::new(0);
+        ring.push(sample(1));
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_sample_serialization() {
+        let original = TelemetrySample {
+            timestamp_ms: 42,
+            temperature_c: 80,
+            power_mw: 150000,
+            thermal_throttle: true,
+            power_throttle: false,
+            power_throttle_ns: Some(1_500_000_000),
+            thermal_throttle_ns: None,
+            game_vram_mib: BTreeMap::from([(4242, 6144)]),
+        };
+
+        let json = serde_json::to_string(&original).unwrap();
+        let parsed: TelemetrySample = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, original);
+    }
+}
@@ -0,0 +1,137 @@
+use log::debug;
+use nix::unistd::{Uid, User};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::common::paths;
+
+/// The user who started nvprime through `sudo` or `pkexec`, as opposed to
+/// root, which the process is running as
+#[derive(Debug, Clone, PartialEq)]
+pub struct OriginalUser {
+    pub uid: u32,
+    pub name: String,
+    pub home: PathBuf,
+}
+
+impl OriginalUser {
+    /// Detect the invoking user, `None` when not running elevated
+    pub fn detect() -> Option<Self> {
+        if !Uid::effective().is_root() {
+            return None;
+        }
+
+        let uid = original_uid(|key| std::env::var(key).ok())?;
+        let user = User::from_uid(Uid::from_raw(uid)).ok()??;
+        debug!("Running elevated on behalf of {} (UID {})", user.name, uid);
+
+        Some(Self {
+            uid,
+            name: user.name,
+            home: user.dir,
+        })
+    }
+
+    /// Session variables of this user that root's environment got wrong or
+    /// dropped. Display variables are kept when set and guessed from the
+    /// user's sockets otherwise.
+    pub fn session_env(
+        &self,
+        current: impl Fn(&str) -> Option<String>,
+        exists: impl Fn(&Path) -> bool,
+    ) -> BTreeMap<String, String> {
+        let runtime_dir = paths::user_runtime_dir(self.uid);
+        let mut env = BTreeMap::from([
+            ("HOME".to_string(), self.home.display().to_string()),
+            ("USER".to_string(), self.name.clone()),
+            ("LOGNAME".to_string(), self.name.clone()),
+            (
+                "XDG_RUNTIME_DIR".to_string(),
+                runtime_dir.display().to_string(),
+            ),
+            (
+                "XDG_CONFIG_HOME".to_string(),
+                self.home.join(".config").display().to_string(),
+            ),
+            (
+                "XDG_DATA_HOME".to_string(),
+                self.home.join(".local/share").display().to_string(),
+            ),
+        ]);
+
+        let wayland = current("WAYLAND_DISPLAY")
+            .or_else(|| exists(&runtime_dir.join("wayland-0")).then(|| "wayland-0".to_string()));
+        let display = current("DISPLAY")
+            .or_else(|| exists(Path::new("/tmp/.X11-unix/X0")).then(|| ":0".to_string()));
+
+        env.extend(wayland.map(|w| ("WAYLAND_DISPLAY".to_string(), w)));
+        env.extend(display.map(|d| ("DISPLAY".to_string(), d)));
+        env
+    }
+}
+
+/// `pkexec` and `sudo` each leave the invoking UID in their own variable
+fn original_uid(var: impl Fn(&str) -> Option<String>) -> Option<u32> {
+    ["PKEXEC_UID", "SUDO_UID"]
+        .iter()
+        .find_map(|key| var(key)?.parse().ok())
+        .filter(|uid| *uid != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars<'a>(pairs: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |key| {
+            pairs
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        }
+    }
+
+    fn user() -> OriginalUser {
+        OriginalUser {
+            uid: 1000,
+            name: "gamer".to_string(),
+            home: PathBuf::from("/home/gamer"),
+        }
+    }
+
+    #[test]
+    fn test_original_uid() {
+        assert_eq!(original_uid(vars(&[("SUDO_UID", "1000")])), Some(1000));
+        assert_eq!(
+            original_uid(vars(&[("PKEXEC_UID", "1001"), ("SUDO_UID", "1000")])),
+            Some(1001)
+        );
+        assert_eq!(original_uid(vars(&[("SUDO_UID", "0")])), None);
+        assert_eq!(original_uid(vars(&[("SUDO_UID", "bogus")])), None);
+        assert_eq!(original_uid(vars(&[])), None);
+    }
+
+    #[test]
+    fn test_session_env_keeps_display() {
+        let env = user().session_env(
+            vars(&[("DISPLAY", ":1"), ("WAYLAND_DISPLAY", "wayland-1")]),
+            |_| false,
+        );
+
+        assert_eq!(env["HOME"], "/home/gamer");
+        assert_eq!(env["USER"], "gamer");
+        assert_eq!(env["XDG_RUNTIME_DIR"], "/run/user/1000");
+        assert_eq!(env["XDG_CONFIG_HOME"], "/home/gamer/.config");
+        assert_eq!(env["DISPLAY"], ":1");
+        assert_eq!(env["WAYLAND_DISPLAY"], "wayland-1");
+    }
+
+    #[test]
+    fn test_session_env_guesses_display() {
+        let env = user().session_env(vars(&[]), |path| {
+            path == Path::new("/run/user/1000/wayland-0")
+        });
+        assert_eq!(env["WAYLAND_DISPLAY"], "wayland-0");
+        assert!(!env.contains_key("DISPLAY"));
+    }
+}
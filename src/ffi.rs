@@ -0,0 +1,190 @@
+//! C API for frontends that want to talk to the daemon without spawning the
+//! CLI. Every call blocks on its own system bus connection. Functions
+//! return 0 on success and -1 on failure, with the reason available from
+//! `nvprime_last_error` on the same thread.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::ptr;
+
+use anyhow::Context;
+use log::warn;
+
+use crate::common::ipc::{DaemonApi, NvPrimeClientProxy};
+use crate::common::telemetry::now_ms;
+
+/// How far back `nvprime_get_status` looks for the latest telemetry sample
+const STATUS_TELEMETRY_WINDOW_MS: u64 = 5_000;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(e: anyhow::Error) {
+    let message = CString::new(format!("{:#}", e).replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn status_code(result: anyhow::Result<()>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            set_last_error(e);
+            -1
+        }
+    }
+}
+
+fn with_proxy<T>(
+    f: impl AsyncFnOnce(&NvPrimeClientProxy<'_>) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start async runtime")?;
+
+    runtime.block_on(async {
+        let conn = zbus::Connection::system()
+            .await
+            .context("Failed to connect to system bus")?;
+        let proxy = NvPrimeClientProxy::new(&conn)
+            .await
+            .context("Failed to create D-Bus proxy")?;
+        f(&proxy).await
+    })
+}
+
+/// Apply the tuning described by `config_json` for the process `pid`, in the
+/// form the running daemon's API version takes. The JSON has the same shape
+/// the CLI sends: `{"cpu": ..., "gpu": ..., "sys": ...}`. End it with
+/// `nvprime_end_session` once the game exits.
+///
+/// # Safety
+/// `config_json` must be a valid NUL terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nvprime_apply_tuning(pid: u32, config_json: *const c_char) -> i32 {
+    status_code((|| {
+        anyhow::ensure!(!config_json.is_null(), "config_json is NULL");
+        // SAFETY: checked for NULL above, validity is the caller's contract
+        let json = unsafe { CStr::from_ptr(config_json) }
+            .to_str()
+            .context("config_json is not valid UTF-8")?;
+
+        with_proxy(async |proxy| {
+            let api = DaemonApi::query(proxy).await;
+            if let Some(warning) = api.version_warning() {
+                warn!("{}", warning);
+            }
+            api.apply_tuning(proxy, pid, json.to_string())
+                .await
+                .map(|_| ())
+                .context("Failed to apply tuning")
+        })
+    })())
+}
+
+/// End the session of `pid` once its game exited, the tuning of the
+/// caller's other games and profiles stays applied
+#[unsafe(no_mangle)]
+pub extern "C" fn nvprime_end_session(pid: u32) -> i32 {
+    status_code(with_proxy(async |proxy| {
+        DaemonApi::query(proxy)
+            .await
+            .end_session(proxy, pid)
+            .await
+            .context("Failed to end session")
+    }))
+}
+
+/// End every session of the caller and release its profiles, like
+/// `nvprime reset`. Not meant for a game exiting, see `nvprime_end_session`.
+#[unsafe(no_mangle)]
+pub extern "C" fn nvprime_reset_tuning() -> i32 {
+    status_code(with_proxy(async |proxy| {
+        proxy.reset_tuning().await.context("Failed to reset tuning")
+    }))
+}
+
+/// Daemon status as a JSON string, or NULL on failure.
+/// The result must be released with `nvprime_string_free`.
+#[unsafe(no_mangle)]
+pub extern "C" fn nvprime_get_status() -> *mut c_char {
+    let status = with_proxy(async |proxy| {
+        let daemon = proxy.ping().await.context("Daemon is not responding")?;
+        let telemetry = proxy
+            .get_telemetry(now_ms().saturating_sub(STATUS_TELEMETRY_WINDOW_MS))
+            .await
+            .context("Failed to fetch telemetry")?;
+        let samples: Vec<serde_json::Value> =
+            serde_json::from_str(&telemetry).context("Invalid telemetry from daemon")?;
+
+        let status = serde_json::json!({
+            "daemon": daemon,
+            "telemetry": samples.last(),
+        });
+        CString::new(status.to_string()).context("Status contains a NUL byte")
+    });
+
+    match status {
+        Ok(status) => status.into_raw(),
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Release a string returned by this library. NULL is ignored.
+///
+/// # Safety
+/// `s` must come from this library and must not be used afterward.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nvprime_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: the pointer was produced by `CString::into_raw` in this library
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Message of the last error on this thread, or NULL if there was none.
+/// The pointer stays valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn nvprime_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        let e = nvprime_last_error();
+        assert!(!e.is_null());
+        unsafe { CStr::from_ptr(e) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_apply_tuning_null_config() {
+        assert_eq!(unsafe { nvprime_apply_tuning(1, ptr::null()) }, -1);
+        assert_eq!(last_error(), "config_json is NULL");
+    }
+
+    #[test]
+    fn test_last_error_is_per_thread() {
+        set_last_error(anyhow::anyhow!("boom"));
+        assert_eq!(last_error(), "boom");
+
+        let other = std::thread::spawn(|| nvprime_last_error().is_null())
+            .join()
+            .unwrap();
+        assert!(other);
+    }
+
+    #[test]
+    fn test_string_free() {
+        unsafe {
+            nvprime_string_free(ptr::null_mut());
+            nvprime_string_free(CString::new("status").unwrap().into_raw());
+        }
+    }
+}
@@ -1,3 +1,4 @@
 pub mod common;
+pub mod ffi;
 pub mod runner;
 pub mod service;
@@ -0,0 +1,135 @@
+use anyhow::Context;
+use log::{debug, info, warn};
+use std::collections::BTreeMap;
+use std::process::Command;
+
+use crate::common::config::AudioConfig;
+
+const PIPEWIRE_QUANTUM: &str = "PIPEWIRE_QUANTUM";
+const PIPEWIRE_RATE: &str = "PIPEWIRE_RATE";
+const PULSE_LATENCY: &str = "PULSE_LATENCY_MSEC";
+
+/// PipeWire's default graph rate, used for the quantum when none is set
+const DEFAULT_RATE: u32 = 48_000;
+
+const FORCE_QUANTUM: &str = "clock.force-quantum";
+const FORCE_RATE: &str = "clock.force-rate";
+
+/// Variables asking PipeWire and its PulseAudio server for the configured
+/// buffer size, rate and latency
+pub fn env_vars(audio: &AudioConfig) -> BTreeMap<&'static str, String> {
+    let mut vars = BTreeMap::new();
+    let rate = audio.rate.unwrap_or(DEFAULT_RATE);
+
+    if let Some(quantum) = audio.quantum {
+        vars.insert(PIPEWIRE_QUANTUM, format!("{}/{}", quantum, rate));
+    }
+    if let Some(rate) = audio.rate {
+        vars.insert(PIPEWIRE_RATE, format!("1/{}", rate));
+    }
+    if let Some(latency) = audio.pulse_latency_msec {
+        vars.insert(PULSE_LATENCY, latency.to_string());
+    }
+
+    vars
+}
+
+/// `settings` metadata keys forcing the quantum and rate on the whole graph
+fn force_settings(audio: &AudioConfig) -> Vec<(&'static str, u32)> {
+    [(FORCE_QUANTUM, audio.quantum), (FORCE_RATE, audio.rate)]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value?)))
+        .collect()
+}
+
+/// Forces the game's quantum and rate on the user's PipeWire graph through
+/// `pw-metadata`, for games whose audio goes through clients the variables
+/// do not reach. The graph goes back to its own choice when dropped.
+pub struct PipewireForce {
+    keys: Vec<&'static str>,
+}
+
+impl PipewireForce {
+    /// `None` unless the game's `force` is set and `pw-metadata` took a value
+    pub fn apply(audio: &AudioConfig) -> Option<Self> {
+        if !audio.force {
+            return None;
+        }
+
+        let keys: Vec<&'static str> = force_settings(audio)
+            .into_iter()
+            .filter(|(key, value)| match set(key, *value) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("{:#}", e);
+                    false
+                }
+            })
+            .map(|(key, _)| key)
+            .collect();
+        if keys.is_empty() {
+            return None;
+        }
+
+        info!("Forced PipeWire {} for the session", keys.join(" and "));
+        Some(Self { keys })
+    }
+}
+
+impl Drop for PipewireForce {
+    fn drop(&mut self) {
+        // Zero hands the setting back to the graph
+        for key in &self.keys {
+            if let Err(e) = set(key, 0) {
+                warn!("{:#}", e);
+            }
+        }
+        debug!("Released forced PipeWire settings");
+    }
+}
+
+fn set(key: &str, value: u32) -> anyhow::Result<()> {
+    let status = Command::new("pw-metadata")
+        .args(["-n", "settings", "0", key, &value.to_string()])
+        .stdout(std::process::Stdio::null())
+        .status()
+        .context("Failed to run pw-metadata")?;
+    anyhow::ensure!(status.success(), "pw-metadata failed to set {}", key);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_env_vars() {
+        assert!(env_vars(&AudioConfig::default()).is_empty());
+
+        let audio = AudioConfig {
+            quantum: Some(512),
+            pulse_latency_msec: Some(60),
+            ..Default::default()
+        };
+        assert_eq!(
+            env_vars(&audio),
+            BTreeMap::from([
+                (PIPEWIRE_QUANTUM, "512/48000".to_string()),
+                (PULSE_LATENCY, "60".to_string()),
+            ])
+        );
+
+        let audio = AudioConfig {
+            quantum: Some(256),
+            rate: Some(44_100),
+            ..Default::default()
+        };
+        let vars = env_vars(&audio);
+        assert_eq!(vars[PIPEWIRE_QUANTUM], "256/44100");
+        assert_eq!(vars[PIPEWIRE_RATE], "1/44100");
+        assert_eq!(
+            force_settings(&audio),
+            [(FORCE_QUANTUM, 256), (FORCE_RATE, 44_100)]
+        );
+    }
+}
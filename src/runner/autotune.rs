@@ -0,0 +1,260 @@
+use anyhow::Context;
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+
+use crate::common::config::GameConfig;
+use crate::common::{ConfigEdit, TelemetrySample};
+use crate::runner::frametime::{self, Frame};
+
+/// Data from the first seconds after a limit change is dropped while clocks settle
+const SETTLE_MS: u64 = 5_000;
+
+/// What the recommended power limit should optimize for
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutotuneGoal {
+    /// Highest frames per watt
+    Efficiency,
+    /// Highest average FPS, preferring the lower limit on a tie
+    MaxFps,
+}
+
+/// A stretch of the autotune run played at a single power limit
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub limit_mw: u32,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+/// Average performance and power draw measured during a segment
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SegmentResult {
+    pub limit_mw: u32,
+    pub avg_fps: f64,
+    pub avg_power_mw: f64,
+}
+
+impl SegmentResult {
+    pub fn fps_per_watt(&self) -> f64 {
+        if self.avg_power_mw > 0.0 {
+            self.avg_fps / (self.avg_power_mw / 1000.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for SegmentResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>4}W limit: {:6.1} FPS at {:5.1}W ({:.2} FPS/W)",
+            self.limit_mw / 1000,
+            self.avg_fps,
+            self.avg_power_mw / 1000.0,
+            self.fps_per_watt()
+        )
+    }
+}
+
+/// Frames from the MangoHud log written during the autotune run
+pub fn read_frames(game: &GameConfig, since_ms: u64) -> anyhow::Result<Vec<Frame>> {
+    let dir = game
+        .mangohud_conf
+        .as_deref()
+        .and_then(frametime::mangohud_output_folder)
+        .context("Autotune needs MangoHud logging, set `output_folder` in `mangohud_conf`")?;
+
+    let log = frametime::find_latest_log(&dir, since_ms)
+        .with_context(|| format!("No MangoHud log was written to {}", dir.display()))?;
+
+    frametime::read_log(&log)
+}
+
+/// Average FPS and power for each segment, skipping segments without data
+pub fn evaluate(
+    segments: &[Segment],
+    frames: &[Frame],
+    samples: &[TelemetrySample],
+) -> Vec<SegmentResult> {
+    segments
+        .iter()
+        .filter_map(|segment| {
+            let start = (segment.start_ms + SETTLE_MS).min(segment.end_ms);
+            let in_window = |ts: u64| ts >= start && ts < segment.end_ms;
+
+            let frametimes: Vec<f64> = frames
+                .iter()
+                .filter(|f| in_window(f.timestamp_ms))
+                .map(|f| f.frametime_ms)
+                .collect();
+            let power: Vec<u32> = samples
+                .iter()
+                .filter(|s| in_window(s.timestamp_ms))
+                .map(|s| s.power_mw)
+                .collect();
+
+            let total_ms: f64 = frametimes.iter().sum();
+            if total_ms <= 0.0 || power.is_empty() {
+                return None;
+            }
+
+            Some(SegmentResult {
+                limit_mw: segment.limit_mw,
+                avg_fps: frametimes.len() as f64 * 1000.0 / total_ms,
+                avg_power_mw: power.iter().map(|&p| p as f64).sum::<f64>() / power.len() as f64,
+            })
+        })
+        .collect()
+}
+
+/// Pick the segment that best matches the goal
+pub fn recommend(results: &[SegmentResult], goal: AutotuneGoal) -> Option<&SegmentResult> {
+    results.iter().max_by(|a, b| match goal {
+        AutotuneGoal::Efficiency => a.fps_per_watt().total_cmp(&b.fps_per_watt()),
+        AutotuneGoal::MaxFps => a
+            .avg_fps
+            .total_cmp(&b.avg_fps)
+            .then(b.limit_mw.cmp(&a.limit_mw)),
+    })
+}
+
+/// Write `pwr_limit_tune` into the game's section, keeping the rest of the file intact
+pub fn save_power_limit(config_path: &Path, game: &str, limit_mw: u32) -> anyhow::Result<()> {
+    let mut config = ConfigEdit::open(config_path)?;
+    config.set_game_option(game, "pwr_limit_tune", format_limit(limit_mw))?;
+    config.save()
+}
+
+/// Render a limit in watts when it divides evenly, milliwatts otherwise
+pub fn format_limit(limit_mw: u32) -> String {
+    if limit_mw.is_multiple_of(1000) {
+        format!("{}W", limit_mw / 1000)
+    } else {
+        format!("{}mW", limit_mw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(start_ms: u64, end_ms: u64, frametime_ms: f64) -> Vec<Frame> {
+        (start_ms..end_ms)
+            .step_by(frametime_ms as usize)
+            .map(|timestamp_ms| Frame {
+                timestamp_ms,
+                frametime_ms,
+            })
+            .collect()
+    }
+
+    fn samples(start_ms: u64, end_ms: u64, power_mw: u32) -> Vec<TelemetrySample> {
+        (start_ms..end_ms)
+            .step_by(1000)
+            .map(|timestamp_ms| TelemetrySample {
+                timestamp_ms,
+                power_mw,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn result(limit_w: u32, avg_fps: f64, avg_power_w: f64) -> SegmentResult {
+        SegmentResult {
+            limit_mw: limit_w * 1000,
+            avg_fps,
+            avg_power_mw: avg_power_w * 1000.0,
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let segments = vec![
+            Segment {
+                limit_mw: 200_000,
+                start_ms: 0,
+                end_ms: 20_000,
+            },
+            Segment {
+                limit_mw: 300_000,
+                start_ms: 20_000,
+                end_ms: 40_000,
+            },
+            Segment {
+                limit_mw: 350_000,
+                start_ms: 40_000,
+                end_ms: 41_000,
+            },
+        ];
+
+        // The unsettled start of each segment must not skew the averages
+        let mut all_frames = frames(0, 5_000, 100.0);
+        all_frames.extend(frames(5_000, 20_000, 20.0));
+        all_frames.extend(frames(20_000, 40_000, 10.0));
+        let mut all_samples = samples(0, 5_000, 50_000);
+        all_samples.extend(samples(5_000, 20_000, 190_000));
+        all_samples.extend(samples(20_000, 40_000, 290_000));
+
+        let results = evaluate(&segments, &all_frames, &all_samples);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].limit_mw, 200_000);
+        assert!((results[0].avg_fps - 50.0).abs() < 0.01);
+        assert!((results[0].avg_power_mw - 190_000.0).abs() < 0.01);
+        assert!((results[1].avg_fps - 100.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_recommend() {
+        let results = vec![
+            result(200, 80.0, 190.0),
+            result(250, 95.0, 240.0),
+            result(300, 98.0, 290.0),
+            result(350, 98.0, 330.0),
+        ];
+
+        let best = recommend(&results, AutotuneGoal::Efficiency).unwrap();
+        assert_eq!(best.limit_mw, 200_000);
+
+        let best = recommend(&results, AutotuneGoal::MaxFps).unwrap();
+        assert_eq!(best.limit_mw, 300_000);
+
+        assert!(recommend(&[], AutotuneGoal::MaxFps).is_none());
+    }
+
+    #[test]
+    fn test_save_power_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nvprime.conf");
+        let content = "# my config\n[gpu]\ngpu_tuning = true\n\n[game.elden]\nmangohud = true\n";
+        std::fs::write(&path, content).unwrap();
+        let read = || std::fs::read_to_string(&path).unwrap();
+
+        save_power_limit(&path, "elden", 250_000).unwrap();
+        assert!(read().starts_with("# my config\n"));
+        assert!(read().contains("mangohud = true\npwr_limit_tune = \"250W\""));
+
+        save_power_limit(&path, "elden", 262_500).unwrap();
+        assert!(read().contains("pwr_limit_tune = \"262500mW\""));
+        assert!(!read().contains("250W"));
+
+        let empty = dir.path().join("empty.conf");
+        save_power_limit(&empty, "doom", 300_000).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&empty).unwrap(),
+            "[game.doom]\npwr_limit_tune = \"300W\"\n"
+        );
+    }
+
+    #[test]
+    fn test_save_power_limit_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nvprime.conf");
+        std::fs::write(&path, "[gpu]\nset_max_pwr = true\n").unwrap();
+
+        save_power_limit(&path, "doom", 275_000).unwrap();
+        let config = crate::common::Config::load_file(path).unwrap();
+        assert_eq!(config.game["doom"].pwr_limit_tune, Some(275_000));
+    }
+}
@@ -0,0 +1,184 @@
+use anyhow::Context;
+use log::{debug, info, warn};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::common::config::{CaptureConfig, GameConfig};
+use crate::runner::frametime;
+
+/// The game's capture directory, for screenshot tools and recorders
+pub const CAPTURE_DIR_VAR: &str = "NVPRIME_CAPTURE_DIR";
+
+/// Create the game's capture directory under `captures_dir`, or the
+/// configured one, and export it to the game
+pub fn apply(
+    vars: &mut BTreeMap<String, String>,
+    capture: &CaptureConfig,
+    game: &str,
+    captures_dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    let dir = capture.dir.as_deref().unwrap_or(captures_dir).join(game);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    debug!("Capture directory for the game: {}", dir.display());
+    vars.insert(CAPTURE_DIR_VAR.to_string(), dir.display().to_string());
+    Ok(dir)
+}
+
+/// Move what the session captured into a folder of its own: files written
+/// to the capture directory, the `sources` and the MangoHud `output_folder`
+/// since the session started
+pub fn archive(dir: &Path, game: &GameConfig, started_ms: u64) -> anyhow::Result<Option<PathBuf>> {
+    let mut sources = game.capture.sources.clone();
+    sources.extend(
+        game.mangohud_conf
+            .as_deref()
+            .and_then(frametime::mangohud_output_folder),
+    );
+    archive_files(dir, &sources, started_ms)
+}
+
+/// Move the files written to `dir` and `sources` since `since_ms` into a
+/// folder named after the session start. Returns the folder, `None` when
+/// the session captured nothing.
+fn archive_files(
+    dir: &Path,
+    sources: &[PathBuf],
+    since_ms: u64,
+) -> anyhow::Result<Option<PathBuf>> {
+    let files: Vec<PathBuf> = std::iter::once(dir)
+        .chain(sources.iter().map(PathBuf::as_path))
+        .flat_map(|source| written_since(source, since_ms))
+        .collect();
+    if files.is_empty() {
+        debug!("Nothing captured this session");
+        return Ok(None);
+    }
+
+    let session_dir = dir.join(session_name(since_ms));
+    std::fs::create_dir_all(&session_dir)
+        .with_context(|| format!("Failed to create {}", session_dir.display()))?;
+
+    let mut moved = 0;
+    for file in &files {
+        let Some(name) = file.file_name() else {
+            continue;
+        };
+        match move_file(file, &session_dir.join(name)) {
+            Ok(()) => moved += 1,
+            Err(e) => warn!("Failed to archive {}: {:#}", file.display(), e),
+        }
+    }
+
+    info!(
+        "Archived {} captured files to {}",
+        moved,
+        session_dir.display()
+    );
+    Ok(Some(session_dir))
+}
+
+/// Regular files directly in `dir` modified at or after `since_ms`
+fn written_since(dir: &Path, since_ms: u64) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .map(|entry| entry.path())
+        .filter(|path| modified_ms(path).is_some_and(|mtime| mtime >= since_ms))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Folder name of a session, its local start time
+fn session_name(started_ms: u64) -> String {
+    chrono::DateTime::from_timestamp_millis(started_ms as i64)
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d_%H-%M-%S")
+                .to_string()
+        })
+        .unwrap_or_else(|| started_ms.to_string())
+}
+
+/// Rename, falling back to a copy when the file is on another filesystem
+fn move_file(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to).with_context(|| format!("Failed to copy to {}", to.display()))?;
+    std::fs::remove_file(from)?;
+    Ok(())
+}
+
+fn modified_ms(path: &Path) -> Option<u64> {
+    let mtime = std::fs::metadata(path).ok()?.modified().ok()?;
+    Some(mtime.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_capture_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut vars = BTreeMap::new();
+
+        let game_dir = apply(&mut vars, &CaptureConfig::default(), "game", dir.path()).unwrap();
+        assert_eq!(game_dir, dir.path().join("game"));
+        assert!(game_dir.is_dir());
+        assert_eq!(vars[CAPTURE_DIR_VAR], game_dir.display().to_string());
+
+        let custom = CaptureConfig {
+            dir: Some(dir.path().join("custom")),
+            ..Default::default()
+        };
+        let game_dir = apply(&mut vars, &custom, "game", dir.path()).unwrap();
+        assert_eq!(game_dir, dir.path().join("custom").join("game"));
+    }
+
+    #[test]
+    fn test_archive_captures() {
+        let dir = tempfile::tempdir().unwrap();
+        let game_dir = dir.path().join("game");
+        let logs = dir.path().join("mangohud");
+        std::fs::create_dir_all(&game_dir).unwrap();
+        std::fs::create_dir_all(&logs).unwrap();
+        std::fs::write(game_dir.join("old.png"), "").unwrap();
+
+        let since_ms = modified_ms(&game_dir.join("old.png")).unwrap() + 1;
+        assert_eq!(
+            archive_files(&game_dir, std::slice::from_ref(&logs), since_ms).unwrap(),
+            None
+        );
+
+        let now_ms = since_ms + 60_000;
+        let touch = |path: &Path| {
+            std::fs::write(path, "").unwrap();
+            let mtime = UNIX_EPOCH + std::time::Duration::from_millis(now_ms);
+            std::fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+        };
+        touch(&game_dir.join("shot.png"));
+        touch(&logs.join("game_2026.csv"));
+
+        let session_dir = archive_files(&game_dir, std::slice::from_ref(&logs), since_ms)
+            .unwrap()
+            .unwrap();
+        assert_eq!(session_dir.parent(), Some(game_dir.as_path()));
+        assert!(session_dir.join("shot.png").is_file());
+        assert!(session_dir.join("game_2026.csv").is_file());
+        assert!(game_dir.join("old.png").is_file());
+        assert!(!logs.join("game_2026.csv").exists());
+    }
+}
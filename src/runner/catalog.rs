@@ -0,0 +1,206 @@
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::common::{Config, ConfigEdit};
+
+/// Workarounds shipped with nvprime
+const BUILTIN_CATALOG: &str = include_str!("catalog.toml");
+
+/// A known fix for one game, as a set of environment variables
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Workaround {
+    pub name: String,
+    pub description: String,
+
+    /// Lowercased executable stems the workaround applies to
+    #[serde(default)]
+    pub exe: Vec<String>,
+
+    /// Steam app IDs the workaround applies to
+    #[serde(default)]
+    pub app_id: Vec<u32>,
+
+    pub env: BTreeMap<String, String>,
+}
+
+impl Workaround {
+    pub fn applies_to(&self, exe: &str, app_id: Option<u32>) -> bool {
+        self.exe.iter().any(|e| e.eq_ignore_ascii_case(exe))
+            || app_id.is_some_and(|id| self.app_id.contains(&id))
+    }
+
+    /// Variables the game's environment group does not set to the same value yet
+    pub fn missing<'a>(&'a self, config: &Config, game: &str) -> BTreeMap<&'a str, &'a str> {
+        let current = config.env.get(game);
+        self.env
+            .iter()
+            .filter(|(key, val)| {
+                current
+                    .and_then(|env| env.get(*key))
+                    .is_none_or(|set| set.to_string() != **val)
+            })
+            .map(|(key, val)| (key.as_str(), val.as_str()))
+            .collect()
+    }
+}
+
+/// Collection of per-game workarounds
+#[derive(Deserialize, Debug, Default)]
+pub struct Catalog {
+    #[serde(default, rename = "workaround")]
+    pub workarounds: Vec<Workaround>,
+}
+
+impl Catalog {
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN_CATALOG).expect("builtin workaround catalog is valid")
+    }
+
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        toml::from_str(content).context("Failed to parse workaround catalog")
+    }
+
+    /// Add published workarounds, replacing shipped ones of the same name.
+    /// Returns the names of those that are new or changed.
+    pub fn merge(&mut self, workarounds: Vec<Workaround>) -> Vec<String> {
+        let mut updated = Vec::new();
+        for workaround in workarounds {
+            match self
+                .workarounds
+                .iter_mut()
+                .find(|w| w.name == workaround.name)
+            {
+                Some(existing) if *existing == workaround => continue,
+                Some(existing) => *existing = workaround.clone(),
+                None => self.workarounds.push(workaround.clone()),
+            }
+            updated.push(workaround.name);
+        }
+        updated
+    }
+
+    /// Workarounds for a game, by executable stem or Steam app ID
+    pub fn find(&self, exe: &str, app_id: Option<u32>) -> Vec<&Workaround> {
+        self.workarounds
+            .iter()
+            .filter(|w| w.applies_to(exe, app_id))
+            .collect()
+    }
+}
+
+/// Add variables to the game's environment group, keeping the rest of the
+/// file intact
+pub fn save_env(config_path: &Path, game: &str, vars: &BTreeMap<&str, &str>) -> anyhow::Result<()> {
+    let mut config = ConfigEdit::open(config_path)?;
+    for (key, val) in vars {
+        config.set_env(game, key, val)?;
+    }
+    config.save()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_catalog() {
+        let catalog = Catalog::builtin();
+        assert!(!catalog.workarounds.is_empty());
+
+        for workaround in &catalog.workarounds {
+            assert!(!workaround.env.is_empty(), "{}", workaround.name);
+            assert!(
+                !workaround.exe.is_empty() || !workaround.app_id.is_empty(),
+                "{}",
+                workaround.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_catalog_find() {
+        let catalog = Catalog::parse(
+            r#"
+            [[workaround]]
+            name = "Fix"
+            description = "Fixes the game"
+            exe = ["game"]
+            app_id = [42]
+            env = { FIX = "1" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(catalog.find("game", None).len(), 1);
+        assert_eq!(catalog.find("Game", None).len(), 1);
+        assert_eq!(catalog.find("launcher", Some(42)).len(), 1);
+        assert!(catalog.find("other", Some(7)).is_empty());
+    }
+
+    #[test]
+    fn test_workaround_missing() {
+        let config: Config = toml::from_str(
+            r#"
+            [mygame]
+            A = 1
+            B = "old"
+            "#,
+        )
+        .unwrap();
+        let workaround = Workaround {
+            name: "Fix".to_string(),
+            description: String::new(),
+            exe: vec!["game".to_string()],
+            app_id: Vec::new(),
+            env: [("A", "1"), ("B", "new"), ("C", "1")]
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        };
+
+        let missing = workaround.missing(&config, "mygame");
+        assert_eq!(missing.keys().copied().collect::<Vec<_>>(), ["B", "C"]);
+        assert_eq!(workaround.missing(&config, "other").len(), 3);
+    }
+
+    #[test]
+    fn test_catalog_merge() {
+        let mut catalog = Catalog::builtin();
+        let shipped = catalog.workarounds[0].clone();
+        let count = catalog.workarounds.len();
+
+        let mut changed = shipped.clone();
+        changed.env.insert("EXTRA".to_string(), "1".to_string());
+        let new = Workaround {
+            name: "New fix".to_string(),
+            ..shipped.clone()
+        };
+
+        let updated = catalog.merge(vec![shipped.clone(), changed, new]);
+        assert_eq!(updated, [shipped.name.clone(), "New fix".to_string()]);
+        assert_eq!(catalog.workarounds.len(), count + 1);
+        assert_eq!(catalog.workarounds[0].env["EXTRA"], "1");
+    }
+
+    #[test]
+    fn test_save_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nvprime.conf");
+        let content = "# My config\n[sys]\nsys_tuning = true\n\n[mygame]\nA = \"1\"\n";
+        std::fs::write(&path, content).unwrap();
+        let vars = BTreeMap::from([("B", "2")]);
+
+        save_env(&path, "mygame", &vars).unwrap();
+        let updated = std::fs::read_to_string(&path).unwrap();
+        assert!(updated.starts_with("# My config\n[sys]\nsys_tuning = true\n"));
+        assert!(updated.contains("A = \"1\"\nB = \"2\"\n"));
+
+        save_env(&path, "other", &vars).unwrap();
+        let config = crate::common::Config::load_file(path.clone()).unwrap();
+        assert_eq!(config.env["other"]["B"].to_string(), "2");
+
+        assert!(save_env(&path, "sys", &vars).is_err());
+    }
+}
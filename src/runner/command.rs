@@ -0,0 +1,120 @@
+use regex::Regex;
+use std::fmt;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// `%command%` left as it is, or misspelled like `%comand%` or `%cmd%`
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)%c(om|md)[a-z]*%?").expect("placeholder regex is valid"));
+
+/// `KEY=VALUE` words, which the shell only treats as assignments in front of
+/// the program
+static ASSIGNMENT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*=").expect("assignment regex is valid"));
+
+/// What is wrong with the game command nvprime was given
+#[derive(Debug, PartialEq)]
+pub enum CommandProblem {
+    /// A placeholder was left as it is
+    Unexpanded(String),
+    /// An environment variable was given where the program belongs
+    Assignment(String),
+    /// The program is neither a file nor found in `PATH`
+    NotFound(String),
+}
+
+impl fmt::Display for CommandProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unexpanded(word) => write!(
+                f,
+                "nvprime received '{}' as it is, Steam only replaces `%command%` spelled exactly like that",
+                word
+            ),
+            Self::Assignment(word) => write!(
+                f,
+                "nvprime received '{}' as the program, variables go in front: `{} nvprime %command%`",
+                word, word
+            ),
+            Self::NotFound(exec) => write!(
+                f,
+                "nvprime received '{}' as the program, which is neither a file nor in PATH. \
+                 Check the launch options for text glued to `%command%`",
+                exec
+            ),
+        }
+    }
+}
+
+/// Check the command line as Steam passed it, before anything runs
+pub fn diagnose(args: &[String]) -> Option<CommandProblem> {
+    if let Some(word) = args.iter().find_map(|arg| PLACEHOLDER.find(arg)) {
+        return Some(CommandProblem::Unexpanded(word.as_str().to_string()));
+    }
+
+    args.first()
+        .filter(|first| ASSIGNMENT.is_match(first))
+        .map(|first| CommandProblem::Assignment(first.clone()))
+}
+
+/// Check that the program to execute exists, `path` being the `PATH` to
+/// search when it is a bare name
+pub fn check_exec(exec: &str, path: Option<&str>) -> Option<CommandProblem> {
+    let found = if exec.contains('/') {
+        Path::new(exec).is_file()
+    } else {
+        path.unwrap_or_default()
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .any(|dir| Path::new(dir).join(exec).is_file())
+    };
+
+    (!found).then(|| CommandProblem::NotFound(exec.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_diagnose() {
+        assert_eq!(
+            diagnose(&args(&["%comand%"])),
+            Some(CommandProblem::Unexpanded("%comand%".to_string()))
+        );
+        assert_eq!(
+            diagnose(&args(&["/games/run.sh", "-w", "%command"])),
+            Some(CommandProblem::Unexpanded("%command".to_string()))
+        );
+        assert_eq!(
+            diagnose(&args(&["DXVK_HUD=1", "/games/run.sh"])),
+            Some(CommandProblem::Assignment("DXVK_HUD=1".to_string()))
+        );
+
+        // Arguments of the game may look like anything
+        assert_eq!(
+            diagnose(&args(&["/games/run.sh", "--fps=60", "100%", "%APPDATA%"])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_check_exec() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("run.sh");
+        std::fs::write(&script, "").unwrap();
+        let path = dir.path().display().to_string();
+
+        assert_eq!(check_exec(&script.display().to_string(), None), None);
+        assert_eq!(check_exec("run.sh", Some(&path)), None);
+        assert_eq!(
+            check_exec("run.sh", Some("/nonexistent")),
+            Some(CommandProblem::NotFound("run.sh".to_string()))
+        );
+        assert!(check_exec("/games/missing.sh", Some(&path)).is_some());
+    }
+}
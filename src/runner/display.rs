@@ -0,0 +1,120 @@
+use log::{debug, info};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Where the kernel lists the connectors of every GPU
+pub const DRM_DIR: &str = "/sys/class/drm";
+
+/// SDL 3 tries the displays named here first, by connector
+const SDL_DISPLAY_PRIORITY: &str = "SDL_VIDEO_DISPLAY_PRIORITY";
+
+/// Connectors with a display plugged in, `DP-1`, `HDMI-A-1` and so on,
+/// sorted and without their card prefix
+pub fn connected_outputs(drm_dir: &Path) -> Vec<String> {
+    let mut outputs: Vec<String> = std::fs::read_dir(drm_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| {
+            std::fs::read_to_string(entry.path().join("status"))
+                .is_ok_and(|status| status.trim() == "connected")
+        })
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let (card, connector) = name.split_once('-')?;
+            card.starts_with("card").then(|| connector.to_string())
+        })
+        .collect();
+    outputs.sort();
+    outputs.dedup();
+    outputs
+}
+
+/// Open the game on `output`: gamescope gets `--prefer-output` when it
+/// runs the game, SDL the display priority hint. Returns a warning instead
+/// when the output is not connected, the game then opens wherever the
+/// desktop puts it.
+pub fn apply(
+    vars: &mut BTreeMap<String, String>,
+    argv: &mut Vec<String>,
+    output: &str,
+    drm_dir: &Path,
+) -> Option<String> {
+    let connected = connected_outputs(drm_dir);
+    if !connected.iter().any(|name| name == output) {
+        return Some(format!(
+            "preferred_output {} is not connected (connected: {}), leaving the display to the desktop",
+            output,
+            if connected.is_empty() {
+                "none".to_string()
+            } else {
+                connected.join(", ")
+            }
+        ));
+    }
+
+    let is_gamescope = argv
+        .first()
+        .and_then(|exe| Path::new(exe).file_name())
+        .is_some_and(|name| name == "gamescope");
+    let has_output = argv
+        .iter()
+        .any(|arg| arg == "-O" || arg.starts_with("--prefer-output"));
+    if is_gamescope && !has_output {
+        argv.splice(1..1, ["--prefer-output".to_string(), output.to_string()]);
+        debug!("gamescope prefers output {}", output);
+    }
+
+    vars.insert(SDL_DISPLAY_PRIORITY.to_string(), output.to_string());
+    info!("Opening the game on {}", output);
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_connector(dir: &Path, name: &str, status: &str) {
+        let connector = dir.join(name);
+        std::fs::create_dir_all(&connector).unwrap();
+        std::fs::write(connector.join("status"), format!("{}\n", status)).unwrap();
+    }
+
+    #[test]
+    fn test_connected_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        fake_connector(dir.path(), "card1-eDP-1", "connected");
+        fake_connector(dir.path(), "card1-DP-1", "connected");
+        fake_connector(dir.path(), "card1-HDMI-A-1", "disconnected");
+        std::fs::create_dir_all(dir.path().join("card1")).unwrap();
+        std::fs::create_dir_all(dir.path().join("renderD128")).unwrap();
+
+        assert_eq!(connected_outputs(dir.path()), ["DP-1", "eDP-1"]);
+    }
+
+    #[test]
+    fn test_apply_preferred_output() {
+        let dir = tempfile::tempdir().unwrap();
+        fake_connector(dir.path(), "card1-DP-1", "connected");
+
+        let mut vars = BTreeMap::new();
+        let mut argv: Vec<String> = ["/usr/bin/gamescope", "-W", "2560", "--", "/game/run"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(apply(&mut vars, &mut argv, "DP-1", dir.path()), None);
+        assert_eq!(argv[..3], ["/usr/bin/gamescope", "--prefer-output", "DP-1"]);
+        assert_eq!(vars[SDL_DISPLAY_PRIORITY], "DP-1");
+
+        // Already chosen in the command
+        let before = argv.clone();
+        apply(&mut vars, &mut argv, "DP-1", dir.path());
+        assert_eq!(argv, before);
+
+        let mut vars = BTreeMap::new();
+        let mut argv = vec!["/game/run".to_string()];
+        let warning = apply(&mut vars, &mut argv, "HDMI-A-1", dir.path()).unwrap();
+        assert!(warning.contains("connected: DP-1"));
+        assert!(vars.is_empty());
+        assert_eq!(argv, ["/game/run"]);
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::Context;
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::process::Command;
+use zbus::proxy;
+use zbus::zvariant::Value;
+
+const GNOME_SCHEMA: &str = "org.gnome.desktop.notifications";
+const GNOME_KEY: &str = "show-banners";
+
+/// Plasma's extension of the notification interface
+#[proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait NotificationInhibit {
+    fn inhibit(
+        &self,
+        desktop_entry: &str,
+        reason: &str,
+        hints: HashMap<&str, &Value<'_>>,
+    ) -> zbus::Result<u32>;
+
+    fn un_inhibit(&self, cookie: u32) -> zbus::Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Desktop {
+    Gnome,
+    Kde,
+}
+
+/// The desktop from `XDG_CURRENT_DESKTOP`, a colon separated list
+fn detect(current_desktop: &str) -> Option<Desktop> {
+    current_desktop
+        .split(':')
+        .find_map(|name| match name.to_ascii_uppercase().as_str() {
+            "GNOME" | "UBUNTU" => Some(Desktop::Gnome),
+            "KDE" => Some(Desktop::Kde),
+            _ => None,
+        })
+}
+
+/// Keeps the desktop's Do Not Disturb on for as long as it is alive, then
+/// puts the notification state back as it was
+pub enum DoNotDisturb {
+    /// Banners were shown before the session
+    Gnome,
+    /// Plasma drops the inhibition by itself should the connection close
+    Kde {
+        conn: zbus::blocking::Connection,
+        cookie: u32,
+    },
+}
+
+impl DoNotDisturb {
+    /// `None` on other desktops, when Do Not Disturb was already on, or when
+    /// it could not be turned on, which is only logged
+    pub fn enable() -> Option<Self> {
+        let desktop = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+        let result = match detect(&desktop) {
+            Some(Desktop::Gnome) => Self::gnome(),
+            Some(Desktop::Kde) => Self::kde().map(Some),
+            None => {
+                warn!("Do Not Disturb is not supported on '{}'", desktop);
+                return None;
+            }
+        };
+
+        match result {
+            Ok(Some(dnd)) => {
+                info!("Enabled Do Not Disturb for the session");
+                Some(dnd)
+            }
+            Ok(None) => {
+                debug!("Do Not Disturb is already on");
+                None
+            }
+            Err(e) => {
+                warn!("{:#}", e);
+                None
+            }
+        }
+    }
+
+    fn gnome() -> anyhow::Result<Option<Self>> {
+        if gsettings(&["get", GNOME_SCHEMA, GNOME_KEY])?.trim() == "false" {
+            return Ok(None);
+        }
+        gsettings(&["set", GNOME_SCHEMA, GNOME_KEY, "false"])?;
+        Ok(Some(Self::Gnome))
+    }
+
+    fn kde() -> anyhow::Result<Self> {
+        let conn =
+            zbus::blocking::Connection::session().context("Failed to connect to session bus")?;
+        let cookie = NotificationInhibitProxyBlocking::new(&conn)
+            .context("Failed to create notifications proxy")?
+            .inhibit("nvprime", "Game session", HashMap::new())
+            .context("Failed to inhibit notifications")?;
+        Ok(Self::Kde { conn, cookie })
+    }
+
+    fn disable(&self) -> anyhow::Result<()> {
+        match self {
+            Self::Gnome => gsettings(&["set", GNOME_SCHEMA, GNOME_KEY, "true"]).map(drop),
+            Self::Kde { conn, cookie } => NotificationInhibitProxyBlocking::new(conn)
+                .context("Failed to create notifications proxy")?
+                .un_inhibit(*cookie)
+                .context("Failed to release the notification inhibition"),
+        }
+    }
+}
+
+impl Drop for DoNotDisturb {
+    fn drop(&mut self) {
+        match self.disable() {
+            Ok(()) => info!("Restored notifications"),
+            Err(e) => warn!("{:#}", e),
+        }
+    }
+}
+
+fn gsettings(args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("gsettings")
+        .args(args)
+        .output()
+        .context("Failed to run gsettings")?;
+    anyhow::ensure!(
+        output.status.success(),
+        "gsettings {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_desktop() {
+        assert_eq!(detect("GNOME"), Some(Desktop::Gnome));
+        assert_eq!(detect("ubuntu:GNOME"), Some(Desktop::Gnome));
+        assert_eq!(detect("KDE"), Some(Desktop::Kde));
+        assert_eq!(detect("Hyprland"), None);
+        assert_eq!(detect(""), None);
+    }
+}
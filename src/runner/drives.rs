@@ -0,0 +1,163 @@
+use log::{debug, info, warn};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Paths pressure-vessel makes visible inside the Steam Linux Runtime
+const COMPAT_MOUNTS: &str = "STEAM_COMPAT_MOUNTS";
+
+/// Wine's own drives, `C:` holds the prefix and `Z:` the root filesystem
+const RESERVED: &[char] = &['c', 'z'];
+
+/// Map the game's `drives` to letters in the Wine prefix and add their
+/// directories to `STEAM_COMPAT_MOUNTS`, so the container running Proton
+/// can reach them. Returns a warning for each drive left out.
+pub fn apply(
+    vars: &mut BTreeMap<String, String>,
+    drives: &BTreeMap<String, PathBuf>,
+    prefix: Option<&Path>,
+    inherited: impl Fn(&str) -> Option<String>,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut mapped: Vec<(char, &Path)> = Vec::new();
+
+    for (letter, dir) in drives {
+        match check_drive(letter, dir) {
+            Ok(letter) => mapped.push((letter, dir)),
+            Err(reason) => warnings.push(format!("drive {} {}", letter, reason)),
+        }
+    }
+    if mapped.is_empty() {
+        return log_warnings(warnings);
+    }
+
+    let base = vars
+        .get(COMPAT_MOUNTS)
+        .cloned()
+        .or_else(|| inherited(COMPAT_MOUNTS));
+    let dirs: Vec<&Path> = mapped.iter().map(|(_, dir)| *dir).collect();
+    let mounts = append(base.as_deref(), &dirs);
+    debug!("{} for the game: {}", COMPAT_MOUNTS, mounts);
+    vars.insert(COMPAT_MOUNTS.to_string(), mounts);
+
+    match prefix.map(|prefix| prefix.join("dosdevices")) {
+        Some(dosdevices) if dosdevices.is_dir() => {
+            for (letter, dir) in &mapped {
+                if let Err(reason) = link_drive(&dosdevices, *letter, dir) {
+                    warnings.push(format!("drive {} {}", letter, reason));
+                }
+            }
+        }
+        _ => warnings.push(
+            "drives are not mapped, the Wine prefix does not exist yet or was not found"
+                .to_string(),
+        ),
+    }
+
+    log_warnings(warnings)
+}
+
+fn log_warnings(warnings: Vec<String>) -> Vec<String> {
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+    warnings
+}
+
+/// The drive letter in lowercase, as Wine names the links
+fn check_drive(letter: &str, dir: &Path) -> Result<char, String> {
+    let mut chars = letter.chars();
+    let letter = match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => c.to_ascii_lowercase(),
+        _ => return Err("is not a single drive letter".to_string()),
+    };
+    if RESERVED.contains(&letter) {
+        return Err("is reserved by Wine".to_string());
+    }
+    if !dir.is_absolute() || !dir.is_dir() {
+        return Err(format!("{} is not an existing directory", dir.display()));
+    }
+    Ok(letter)
+}
+
+/// Point `<letter>:` in `dosdevices` at `dir`, replacing a link to another
+/// directory but never a file or directory Wine or the user put there
+fn link_drive(dosdevices: &Path, letter: char, dir: &Path) -> Result<(), String> {
+    let link = dosdevices.join(format!("{}:", letter));
+    match std::fs::read_link(&link) {
+        Ok(target) if target == dir => return Ok(()),
+        Ok(_) => std::fs::remove_file(&link).map_err(|e| e.to_string())?,
+        Err(_) if link.symlink_metadata().is_ok() => {
+            return Err(format!("{} exists and is not a link", link.display()));
+        }
+        Err(_) => {}
+    }
+
+    std::os::unix::fs::symlink(dir, &link).map_err(|e| e.to_string())?;
+    info!(
+        "Mapped drive {}: to {}",
+        letter.to_ascii_uppercase(),
+        dir.display()
+    );
+    Ok(())
+}
+
+/// Entries of `base` followed by the `dirs` not among them
+fn append(base: Option<&str>, dirs: &[&Path]) -> String {
+    let mut entries: Vec<String> = base
+        .unwrap_or_default()
+        .split(':')
+        .filter(|entry| !entry.is_empty())
+        .map(str::to_string)
+        .collect();
+    for dir in dirs {
+        let dir = dir.display().to_string();
+        if !entries.contains(&dir) {
+            entries.push(dir);
+        }
+    }
+    entries.join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_drive() {
+        let dir = std::env::temp_dir();
+        assert_eq!(check_drive("D", &dir), Ok('d'));
+        assert!(check_drive("dd", &dir).is_err());
+        assert!(check_drive("c", &dir).is_err());
+        assert!(check_drive("e", Path::new("games")).is_err());
+    }
+
+    #[test]
+    fn test_apply_drives() {
+        let dir = tempfile::tempdir().unwrap();
+        let games = dir.path().join("games");
+        let dosdevices = dir.path().join("pfx").join("dosdevices");
+        std::fs::create_dir_all(&games).unwrap();
+        std::fs::create_dir_all(&dosdevices).unwrap();
+        std::os::unix::fs::symlink("/elsewhere", dosdevices.join("d:")).unwrap();
+        std::fs::create_dir(dosdevices.join("e:")).unwrap();
+
+        let drives = BTreeMap::from([
+            ("d".to_string(), games.clone()),
+            ("e".to_string(), games.clone()),
+            ("z".to_string(), games.clone()),
+        ]);
+        let mut vars = BTreeMap::new();
+        let warnings = apply(&mut vars, &drives, Some(&dir.path().join("pfx")), |key| {
+            (key == COMPAT_MOUNTS).then(|| "/mnt/music".to_string())
+        });
+
+        assert_eq!(
+            vars[COMPAT_MOUNTS],
+            format!("/mnt/music:{}", games.display())
+        );
+        assert_eq!(std::fs::read_link(dosdevices.join("d:")).unwrap(), games);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("reserved"));
+        assert!(warnings[1].contains("not a link"));
+    }
+}
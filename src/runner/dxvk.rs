@@ -0,0 +1,184 @@
+use anyhow::Context;
+use log::{debug, info};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::common::config::DxvkConfig;
+
+const CONFIG_FILE: &str = "DXVK_CONFIG_FILE";
+
+/// Read by DXVK from the working directory when `DXVK_CONFIG_FILE` is unset
+const LOCAL_CONFIG: &str = "dxvk.conf";
+
+/// `dxvk.conf` options of the game's `[game.<name>.dxvk]` table
+pub fn options(dxvk: &DxvkConfig) -> BTreeMap<String, String> {
+    let flag = |enabled: bool| if enabled { "True" } else { "False" }.to_string();
+    let mut options = BTreeMap::new();
+
+    if let Some(latency) = dxvk.max_frame_latency {
+        options.insert("dxgi.maxFrameLatency".to_string(), latency.to_string());
+        options.insert("d3d9.maxFrameLatency".to_string(), latency.to_string());
+    }
+    if let Some(hide) = dxvk.hide_nvidia_gpu {
+        options.insert("dxgi.hideNvidiaGpu".to_string(), flag(hide));
+    }
+    if let Some(mb) = dxvk.max_device_memory_mb {
+        options.insert("dxgi.maxDeviceMemory".to_string(), mb.to_string());
+    }
+    if let Some(mb) = dxvk.max_shared_memory_mb {
+        options.insert("dxgi.maxSharedMemory".to_string(), mb.to_string());
+    }
+    options.extend(dxvk.options.clone());
+
+    options
+}
+
+/// Write the game's DXVK options to `<dir>/<game>.conf` on top of the
+/// config file DXVK would have read otherwise, and point `DXVK_CONFIG_FILE`
+/// at it. Returns the written file, `None` when the game sets no options.
+pub fn apply(
+    vars: &mut BTreeMap<String, String>,
+    dxvk: &DxvkConfig,
+    game: &str,
+    dir: &Path,
+    inherited: impl Fn(&str) -> Option<String>,
+) -> anyhow::Result<Option<PathBuf>> {
+    let options = options(dxvk);
+    if options.is_empty() {
+        return Ok(None);
+    }
+
+    let base = vars
+        .get(CONFIG_FILE)
+        .cloned()
+        .or_else(|| inherited(CONFIG_FILE))
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(LOCAL_CONFIG)).filter(|path| path.is_file()));
+    let base_content = match &base {
+        Some(path) => Some(
+            std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+        ),
+        None => None,
+    };
+
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(format!("{}.conf", game));
+    std::fs::write(&path, render(&options, base_content.as_deref()))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    match &base {
+        Some(base) => info!(
+            "DXVK config for the game merged into {} from {}",
+            path.display(),
+            base.display()
+        ),
+        None => info!("DXVK config for the game written to {}", path.display()),
+    }
+    vars.insert(CONFIG_FILE.to_string(), path.display().to_string());
+    Ok(Some(path))
+}
+
+/// The options followed by the base file, leaving out the base's global
+/// settings the options replace. Settings under an `[app.exe]` section of
+/// the base are kept as they are since they only apply to that executable.
+fn render(options: &BTreeMap<String, String>, base: Option<&str>) -> String {
+    let mut out = String::from("# Generated by nvprime\n");
+    for (key, value) in options {
+        out.push_str(&format!("{} = {}\n", key, value));
+    }
+
+    let Some(base) = base else {
+        return out;
+    };
+
+    out.push('\n');
+    let mut global = true;
+    for line in base.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            global = false;
+        }
+
+        let replaced = global
+            && !trimmed.starts_with('#')
+            && trimmed
+                .split_once('=')
+                .is_some_and(|(key, _)| options.contains_key(key.trim()));
+        if replaced {
+            debug!("Replacing '{}' of the base DXVK config", trimmed);
+            continue;
+        }
+
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dxvk() -> DxvkConfig {
+        DxvkConfig {
+            max_frame_latency: Some(1),
+            hide_nvidia_gpu: Some(false),
+            options: BTreeMap::from([("dxgi.syncInterval".to_string(), "0".to_string())]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_dxvk_options() {
+        assert!(options(&DxvkConfig::default()).is_empty());
+
+        let options = options(&dxvk());
+        assert_eq!(options["dxgi.maxFrameLatency"], "1");
+        assert_eq!(options["d3d9.maxFrameLatency"], "1");
+        assert_eq!(options["dxgi.hideNvidiaGpu"], "False");
+        assert_eq!(options["dxgi.syncInterval"], "0");
+    }
+
+    #[test]
+    fn test_render_merges_base() {
+        let base = "# mine\ndxgi.maxFrameLatency = 3\ndxvk.hud = fps\n\n[game.exe]\ndxgi.maxFrameLatency = 2\n";
+        let options = BTreeMap::from([("dxgi.maxFrameLatency".to_string(), "1".to_string())]);
+
+        assert_eq!(
+            render(&options, Some(base)),
+            "# Generated by nvprime\ndxgi.maxFrameLatency = 1\n\n# mine\ndxvk.hud = fps\n\n[game.exe]\ndxgi.maxFrameLatency = 2\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_dxvk_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("user.conf");
+        std::fs::write(&base, "dxvk.hud = fps\n").unwrap();
+
+        let mut vars = BTreeMap::new();
+        assert!(
+            apply(
+                &mut vars,
+                &DxvkConfig::default(),
+                "game",
+                dir.path(),
+                |_| None
+            )
+            .unwrap()
+            .is_none()
+        );
+        assert!(vars.is_empty());
+
+        let written = apply(&mut vars, &dxvk(), "game", dir.path(), |_| {
+            Some(base.display().to_string())
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(vars[CONFIG_FILE], written.display().to_string());
+        let content = std::fs::read_to_string(&written).unwrap();
+        assert!(content.contains("dxgi.maxFrameLatency = 1\n"));
+        assert!(content.ends_with("dxvk.hud = fps\n"));
+    }
+}
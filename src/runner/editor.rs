@@ -0,0 +1,175 @@
+use anyhow::Context;
+use log::{debug, info};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::common::Config;
+
+/// A copy of the config being edited. The live config is only replaced
+/// once the draft parses, so a typo never stops every game from launching.
+pub struct ConfigDraft {
+    live: PathBuf,
+    draft: PathBuf,
+}
+
+impl ConfigDraft {
+    /// Start a draft of `live`, picking up a draft left by an earlier
+    /// edit that never parsed
+    pub fn open(live: &Path) -> anyhow::Result<Self> {
+        let mut name = live.as_os_str().to_owned();
+        name.push(".draft");
+        let draft = PathBuf::from(name);
+
+        if draft.exists() {
+            info!("Resuming unsaved draft {}", draft.display());
+        } else {
+            let content = std::fs::read_to_string(live).unwrap_or_default();
+            std::fs::write(&draft, content)
+                .with_context(|| format!("Failed to write {}", draft.display()))?;
+        }
+
+        Ok(Self {
+            live: live.to_path_buf(),
+            draft,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.draft
+    }
+
+    /// Open the draft in `$VISUAL` or `$EDITOR`, at `line` when given
+    pub fn edit(&self, line: Option<usize>) -> anyhow::Result<()> {
+        let editor = std::env::var("VISUAL")
+            .or_else(|_| std::env::var("EDITOR"))
+            .unwrap_or_else(|_| "vi".to_string());
+
+        let mut cmd = editor_command(&editor, &self.draft, line)?;
+        debug!("Running editor: {:?}", cmd);
+
+        let status = cmd
+            .status()
+            .with_context(|| format!("Failed to run editor '{}'", editor))?;
+        anyhow::ensure!(
+            status.success(),
+            "Editor '{}' exited with {}",
+            editor,
+            status
+        );
+        Ok(())
+    }
+
+    /// Parse and validate the draft, a parse error carries the line and
+    /// column of the problem
+    pub fn validate(&self) -> anyhow::Result<Config> {
+        let content = std::fs::read_to_string(&self.draft)
+            .with_context(|| format!("Failed to read {}", self.draft.display()))?;
+        let config: Config = toml::from_str(&content).map_err(|e| anyhow::anyhow!("{}", e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Whether the draft differs from the live config
+    pub fn is_changed(&self) -> anyhow::Result<bool> {
+        let draft = std::fs::read_to_string(&self.draft)
+            .with_context(|| format!("Failed to read {}", self.draft.display()))?;
+        Ok(std::fs::read_to_string(&self.live).unwrap_or_default() != draft)
+    }
+
+    /// Replace the live config with the draft, which must be valid
+    pub fn commit(self) -> anyhow::Result<()> {
+        self.validate()?;
+        std::fs::rename(&self.draft, &self.live)
+            .with_context(|| format!("Failed to replace {}", self.live.display()))?;
+        info!("Saved {}", self.live.display());
+        Ok(())
+    }
+
+    pub fn discard(self) -> anyhow::Result<()> {
+        std::fs::remove_file(&self.draft)
+            .with_context(|| format!("Failed to remove {}", self.draft.display()))
+    }
+}
+
+/// Line of the `[game.<name>]` header, 1-based like editors count them
+pub fn game_line(content: &str, game: &str) -> Option<usize> {
+    let header = format!("[game.{}]", game);
+    content
+        .lines()
+        .position(|line| line.trim() == header)
+        .map(|i| i + 1)
+}
+
+/// The editor may come with arguments of its own, e.g. `code --wait`.
+/// Most terminal editors take `+<line>` to open at a line.
+fn editor_command(editor: &str, path: &Path, line: Option<usize>) -> anyhow::Result<Command> {
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().context("Editor command is empty")?;
+
+    let mut cmd = Command::new(program);
+    cmd.args(parts);
+    if let Some(line) = line {
+        cmd.arg(format!("+{}", line));
+    }
+    cmd.arg(path);
+    Ok(cmd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_game_line() {
+        let content = "[sys]\nsys_tuning = true\n\n[game.ffxiv]\nmangohud = true\n";
+        assert_eq!(game_line(content, "ffxiv"), Some(4));
+        assert_eq!(game_line(content, "dota2"), None);
+    }
+
+    #[test]
+    fn test_editor_command() {
+        let cmd = editor_command("code --wait", Path::new("/tmp/nvprime.conf"), Some(4)).unwrap();
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(cmd.get_program(), "code");
+        assert_eq!(args, ["--wait", "+4", "/tmp/nvprime.conf"]);
+
+        assert!(editor_command("  ", Path::new("/tmp/nvprime.conf"), None).is_err());
+    }
+
+    #[test]
+    fn test_config_draft() {
+        let dir = tempfile::tempdir().unwrap();
+        let live = dir.path().join("nvprime.conf");
+        std::fs::write(&live, "[sys]\nsys_tuning = true\n").unwrap();
+
+        let draft = ConfigDraft::open(&live).unwrap();
+        assert!(!draft.is_changed().unwrap());
+
+        std::fs::write(draft.path(), "[sys]\nsys_tuning = maybe\n").unwrap();
+        let error = draft.validate().unwrap_err().to_string();
+        assert!(error.contains("line 2"), "{}", error);
+
+        // A broken draft survives for the next edit
+        let draft = ConfigDraft::open(&live).unwrap();
+        assert!(draft.is_changed().unwrap());
+        assert!(draft.validate().is_err());
+
+        // Parses, but the options contradict the environment section
+        std::fs::write(
+            draft.path(),
+            "[game.doom]\nmangohud = false\n\n[doom]\nMANGOHUD = 1\n",
+        )
+        .unwrap();
+        let error = draft.validate().unwrap_err().to_string();
+        assert!(error.contains("MANGOHUD"), "{}", error);
+
+        std::fs::write(draft.path(), "[sys]\nsys_tuning = false\n").unwrap();
+        let path = draft.path().to_path_buf();
+        draft.commit().unwrap();
+        assert!(!path.exists());
+        assert_eq!(
+            std::fs::read_to_string(&live).unwrap(),
+            "[sys]\nsys_tuning = false\n"
+        );
+    }
+}
@@ -1,17 +1,26 @@
-use crate::common::Config;
-use crate::common::config::EnvValue;
-use log::debug;
+use crate::common::config::{self, EnvValue, GameConfig};
+use crate::common::paths;
+use crate::common::{Config, DriverVersion};
+use crate::runner::{SwitcherooGpu, audio, icd};
+use log::{debug, warn};
 use phf::{Map, phf_map};
 use std::collections::BTreeMap;
-
-const LOG: &str = "PROTON_LOG";
-const HUD: &str = "MANGOHUD";
-const HUD_CFG: &str = "MANGOHUD_CONFIG";
-const NTSYNC: &str = "PROTON_USE_NTSYNC";
-const WAYLAND: &str = "PROTON_ENABLE_WAYLAND";
-const DXVK_GPU: &str = "DXVK_FILTER_DEVICE_NAME";
-const VKD3D_GPU: &str = "VKD3D_FILTER_DEVICE_NAME";
-const WINE_DLLS: &str = "WINEDLLOVERRIDES";
+use std::path::Path;
+
+pub(crate) const LOG: &str = "PROTON_LOG";
+pub(crate) const HUD: &str = "MANGOHUD";
+pub(crate) const HUD_CFG: &str = "MANGOHUD_CONFIG";
+pub(crate) const NTSYNC: &str = "PROTON_USE_NTSYNC";
+pub(crate) const WAYLAND: &str = "PROTON_ENABLE_WAYLAND";
+pub(crate) const DXVK_GPU: &str = "DXVK_FILTER_DEVICE_NAME";
+pub(crate) const VKD3D_GPU: &str = "VKD3D_FILTER_DEVICE_NAME";
+pub(crate) const WINE_DLLS: &str = "WINEDLLOVERRIDES";
+pub(crate) const NO_FSYNC: &str = "PROTON_NO_FSYNC";
+pub(crate) const NO_ESYNC: &str = "PROTON_NO_ESYNC";
+pub(crate) const STEAM_DECK: &str = "SteamDeck";
+pub(crate) const STEAM_OS: &str = "SteamOS";
+pub(crate) const GAME_DRIVE: &str = "PROTON_SET_GAME_DRIVE";
+pub(crate) const VK_ICD: &str = "VK_ICD_FILENAMES";
 
 /// Default values for environment variables
 static ENV_DEFAULTS: Map<&'static str, &'static str> = phf_map! {
@@ -46,7 +55,7 @@ static ENV_DEFAULTS: Map<&'static str, &'static str> = phf_map! {
     "__NV_PRIME_RENDER_OFFLOAD" => "1",
     "__GLX_VENDOR_LIBRARY_NAME" => "nvidia",
     "__VK_LAYER_NV_optimus" => "NVIDIA_only",
-    "VK_ICD_FILENAMES" => "/usr/share/vulkan/icd.d/nvidia_icd.json",
+    "VK_ICD_FILENAMES" => paths::NVIDIA_ICD,
 
     // Tells the driver to prioritize performance over power saving,
     // suppose to help the case where the GPU is not boosting under
@@ -60,10 +69,39 @@ static ENV_DEFAULTS: Map<&'static str, &'static str> = phf_map! {
     "__GL_YIELD" => "USLEEP",
 };
 
+/// Oldest driver (major, minor) a default is useful on. Older drivers ignore
+/// these variables at best, so they are left out rather than set blindly.
+static ENV_MIN_DRIVER: Map<&'static str, (u32, u32)> = phf_map! {
+    // DLSS preset overrides map to DRS keys introduced with the 570 series
+    "DXVK_NVAPI_DRS_NGX_DLSS_RR_OVERRIDE" => (570, 0),
+    "DXVK_NVAPI_DRS_NGX_DLSS_RR_OVERRIDE_RENDER_PRESET_SELECTION" => (570, 0),
+    "DXVK_NVAPI_DRS_NGX_DLSS_SR_OVERRIDE" => (570, 0),
+    "DXVK_NVAPI_DRS_NGX_DLSS_SR_OVERRIDE_RENDER_PRESET_SELECTION" => (570, 0),
+
+    // VRR and G-SYNC control through these variables came with the 435 series
+    "__GL_GSYNC_ALLOWED" => (435, 0),
+    "__GL_VRR_ALLOWED" => (435, 0),
+};
+
+/// Value nvprime sets for a variable when nothing overrides it
+pub(crate) fn builtin_default(key: &str) -> Option<&'static str> {
+    ENV_DEFAULTS.get(key).copied()
+}
+
 pub struct EnvBuilder {
     vars: BTreeMap<String, String>,
 }
 
+/// A variable set both by a structured `[game.<name>]` option and by the
+/// raw environment section of the same game, the raw section always wins
+#[derive(Debug, PartialEq)]
+pub struct EnvConflict {
+    pub game: String,
+    pub key: String,
+    pub game_value: String,
+    pub env_value: String,
+}
+
 impl EnvBuilder {
     pub fn new() -> Self {
         debug!("Creating new environment builder");
@@ -76,6 +114,22 @@ impl EnvBuilder {
     }
 }
 
+impl std::fmt::Display for EnvConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Conflicting values for {} in game '{}': [game.{}] sets '{}', [{}] sets '{}', using '{}'",
+            self.key,
+            self.game,
+            self.game,
+            self.game_value,
+            self.game,
+            self.env_value,
+            self.env_value
+        )
+    }
+}
+
 impl Default for EnvBuilder {
     fn default() -> Self {
         Self::new()
@@ -91,9 +145,61 @@ impl EnvBuilder {
         self.set_str(key, if enabled { "1" } else { "0" })
     }
 
+    /// Drop defaults the given driver is too old for. An unknown driver keeps
+    /// every default, and variables set by the user are never touched.
+    pub fn with_driver(mut self, version: Option<DriverVersion>) -> Self {
+        let Some(version) = version else {
+            debug!("Driver version unknown, keeping all defaults");
+            return self;
+        };
+
+        for (key, (major, minor)) in ENV_MIN_DRIVER.entries() {
+            let required = DriverVersion::new(*major, *minor);
+            if version < required
+                && self.vars.get(*key).map(String::as_str) == ENV_DEFAULTS.get(key).copied()
+            {
+                debug!(
+                    "Skipping {} default, needs driver {} but found {}",
+                    key, required, version
+                );
+                self.vars.remove(*key);
+            }
+        }
+
+        self
+    }
+
+    /// Replace the builtin offload defaults with the environment
+    /// switcheroo-control recommends for the discrete GPU
+    pub fn with_switcheroo(mut self, gpu: Option<SwitcherooGpu>) -> Self {
+        let Some(gpu) = gpu else {
+            return self;
+        };
+
+        debug!("Using switcheroo-control environment of {}", gpu.name);
+        for (key, val) in gpu.env {
+            if let Some(default) = self.vars.get(&key)
+                && *default != val
+            {
+                debug!("{} from switcheroo-control: {} -> {}", key, default, val);
+            }
+            self.vars.insert(key, val);
+        }
+        self
+    }
+
     pub fn with_config(mut self, config: &Config, exe_name: &String) -> BTreeMap<String, String> {
         debug!("Initializing environment values for game: {}", exe_name);
 
+        if config
+            .game
+            .get(exe_name)
+            .is_some_and(|g| !g.inherit_defaults)
+        {
+            debug!("Skipping built-in environment defaults for: {}", exe_name);
+            self.vars.clear();
+        }
+
         // `config.gpu.gpu_name` is an `Option<String>` and since `String`
         // does not implement `Copy` we need to explicitly use reference
         // when performing pattern matching.
@@ -103,24 +209,37 @@ impl EnvBuilder {
             self.set_str(VKD3D_GPU, slice);
         }
 
+        // Only in place of the builtin default, not one left out or taken
+        // from switcheroo-control
+        if self.vars.get(VK_ICD).map(String::as_str) == builtin_default(VK_ICD) {
+            let icd_files = icd::nvidia_icd_files(
+                &config.gpu.gpu_vlk_icd,
+                config.gpu.gpu_vlk_icd32.as_deref(),
+                Path::new(paths::ICD_DIR),
+            );
+            self.set_str(VK_ICD, &icd_files);
+        }
+
         // `config.game` is a `HashMap`, the `get` function will return
         // `Option<&T> which already a reference itself, thus we do not
         // need to access config through its reference.
         if let Some(game) = config.game.get(exe_name) {
-            self.set_bool(HUD, game.mangohud);
-            self.set_bool(LOG, game.proton_log);
-            self.set_bool(NTSYNC, game.proton_ntsync);
-            self.set_bool(WAYLAND, game.proton_wayland);
-
-            if let Some(hud_cfg) = &game.mangohud_conf {
-                self.set_str(HUD_CFG, hud_cfg);
+            for option in sync_conflicts(game) {
+                warn!(
+                    "[game.{}] enables both proton_ntsync and {}, NTSYNC replaces it so {} should be left unset",
+                    exe_name, option, option
+                );
             }
 
-            if let Some(dll_overrides) = &game.wine_dll_overrides {
-                self.set_str(WINE_DLLS, dll_overrides);
+            for (key, val) in game_vars(game) {
+                self.set_str(key, &val);
             }
         }
 
+        for conflict in Self::find_conflicts(config, exe_name) {
+            warn!("{}", conflict);
+        }
+
         if let Some(env) = config.env.get(exe_name) {
             for (key, val) in env {
                 self.vars.insert(key.to_string(), val.to_string());
@@ -130,6 +249,26 @@ impl EnvBuilder {
         self.build()
     }
 
+    /// Find variables where the game options and the raw env section disagree
+    pub fn find_conflicts(config: &Config, exe_name: &str) -> Vec<EnvConflict> {
+        let (Some(game), Some(env)) = (config.game.get(exe_name), config.env.get(exe_name)) else {
+            return Vec::new();
+        };
+
+        game_vars(game)
+            .into_iter()
+            .filter_map(|(key, game_value)| {
+                let env_value = env.get(key)?.to_string();
+                (env_value != game_value).then(|| EnvConflict {
+                    game: exe_name.to_string(),
+                    key: key.to_string(),
+                    game_value,
+                    env_value,
+                })
+            })
+            .collect()
+    }
+
     pub fn with_env(mut self, key: &str, val: &str) -> Self {
         self.set_str(key, val);
         self
@@ -201,10 +340,68 @@ impl EnvBuilder {
     }
 }
 
+/// Environment variables implied by the structured game options
+fn game_vars(game: &GameConfig) -> BTreeMap<&'static str, String> {
+    let flag = |enabled: bool| if enabled { "1" } else { "0" }.to_string();
+    let mut vars = BTreeMap::from([
+        (HUD, flag(game.mangohud)),
+        (LOG, flag(game.proton_log)),
+        (NTSYNC, flag(game.proton_ntsync)),
+        (WAYLAND, flag(game.proton_wayland)),
+    ]);
+
+    if let Some(hud_cfg) = &game.mangohud_conf {
+        vars.insert(HUD_CFG, hud_cfg.clone());
+    }
+
+    let mut dlls = game.wine_dll_overrides.clone();
+    dlls.extend(game.dll_overrides.clone());
+    if !dlls.is_empty() {
+        vars.insert(WINE_DLLS, config::wine_dll_overrides_value(&dlls));
+    }
+
+    // Proton only has opt-out variables for these
+    if let Some(fsync) = game.proton_fsync {
+        vars.insert(NO_FSYNC, flag(!fsync));
+    }
+    if let Some(esync) = game.proton_esync {
+        vars.insert(NO_ESYNC, flag(!esync));
+    }
+
+    if let Some(game_drive) = game.game_drive {
+        vars.insert(GAME_DRIVE, flag(game_drive));
+    }
+
+    // Only ever set, an actual Deck already has them
+    if game.spoof_steamdeck {
+        vars.insert(STEAM_DECK, flag(true));
+        vars.insert(STEAM_OS, flag(true));
+    }
+
+    vars.extend(audio::env_vars(&game.audio));
+    vars
+}
+
+/// Sync options explicitly enabled next to NTSYNC, which supersedes them
+fn sync_conflicts(game: &GameConfig) -> Vec<&'static str> {
+    if !game.proton_ntsync {
+        return Vec::new();
+    }
+
+    [
+        ("proton_fsync", game.proton_fsync),
+        ("proton_esync", game.proton_esync),
+    ]
+    .into_iter()
+    .filter(|(_, enabled)| *enabled == Some(true))
+    .map(|(option, _)| option)
+    .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::common::config::{Config, GameConfig, GpuTune};
+    use crate::common::config::{Config, DllMode, GameConfig, GpuTune};
 
     #[test]
     fn test_env_builder_new() {
@@ -245,6 +442,54 @@ mod tests {
         assert_eq!(vars.get(VKD3D_GPU), Some(&"NVIDIA RTX 4090".to_string()));
     }
 
+    #[test]
+    fn test_env_builder_with_driver() {
+        const DLSS: &str = "DXVK_NVAPI_DRS_NGX_DLSS_SR_OVERRIDE";
+
+        let vars = EnvBuilder::new()
+            .with_driver(Some(DriverVersion::new(550, 54)))
+            .build();
+        assert!(!vars.contains_key(DLSS));
+        assert_eq!(vars.get("__GL_VRR_ALLOWED"), Some(&"1".to_string()));
+
+        let vars = EnvBuilder::new()
+            .with_driver(Some(DriverVersion::new(570, 86)))
+            .build();
+        assert_eq!(vars.get(DLSS), Some(&"on".to_string()));
+
+        let vars = EnvBuilder::new().with_driver(None).build();
+        assert!(vars.contains_key(DLSS));
+
+        let vars = EnvBuilder::new()
+            .with_env(DLSS, "off")
+            .with_driver(Some(DriverVersion::new(550, 54)))
+            .build();
+        assert_eq!(vars.get(DLSS), Some(&"off".to_string()));
+    }
+
+    #[test]
+    fn test_env_builder_with_switcheroo() {
+        let gpu = SwitcherooGpu {
+            name: "NVIDIA GeForce RTX 4080".to_string(),
+            env: [("__VK_LAYER_NV_optimus", "NVIDIA_only"), ("DRI_PRIME", "1")]
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            default: false,
+            discrete: true,
+        };
+
+        let vars = EnvBuilder::new().with_switcheroo(Some(gpu)).build();
+        assert_eq!(vars.get("DRI_PRIME"), Some(&"1".to_string()));
+        assert_eq!(
+            vars.get("__NV_PRIME_RENDER_OFFLOAD"),
+            Some(&"1".to_string())
+        );
+
+        let vars = EnvBuilder::new().with_switcheroo(None).build();
+        assert!(!vars.contains_key("DRI_PRIME"));
+    }
+
     #[test]
     fn test_env_builder_with_mangohud() {
         let builder = EnvBuilder::new().with_mangohud(true);
@@ -306,6 +551,12 @@ mod tests {
             env: Default::default(),
             game: Default::default(),
             hook: Default::default(),
+            daemon: Default::default(),
+            paths: Default::default(),
+            schedule: Default::default(),
+            updates: Default::default(),
+            budget: Default::default(),
+            resolve: Default::default(),
         };
 
         let vars = EnvBuilder::new().with_config(&config, &"testgame".to_string());
@@ -325,6 +576,12 @@ mod tests {
             env: Default::default(),
             game: Default::default(),
             hook: Default::default(),
+            daemon: Default::default(),
+            paths: Default::default(),
+            schedule: Default::default(),
+            updates: Default::default(),
+            budget: Default::default(),
+            resolve: Default::default(),
         };
         config.gpu.gpu_name = Some("Test GPU".to_string());
 
@@ -342,6 +599,12 @@ mod tests {
             env: Default::default(),
             game: Default::default(),
             hook: Default::default(),
+            daemon: Default::default(),
+            paths: Default::default(),
+            schedule: Default::default(),
+            updates: Default::default(),
+            budget: Default::default(),
+            resolve: Default::default(),
         };
 
         let game_config = GameConfig {
@@ -350,7 +613,15 @@ mod tests {
             proton_log: true,
             proton_ntsync: true,
             proton_wayland: false,
-            wine_dll_overrides: Some("dinput8=n,b".to_string()),
+            wine_dll_overrides: BTreeMap::from([
+                ("dinput8".to_string(), DllMode::NativeBuiltin),
+                ("xaudio2_7".to_string(), DllMode::Builtin),
+            ]),
+            dll_overrides: BTreeMap::from([
+                ("xaudio2_7".to_string(), DllMode::Native),
+                ("winemenubuilder".to_string(), DllMode::Disabled),
+            ]),
+            ..Default::default()
         };
         config.game.insert("testgame".to_string(), game_config);
 
@@ -360,7 +631,129 @@ mod tests {
         assert_eq!(vars.get(LOG), Some(&"1".to_string()));
         assert_eq!(vars.get(NTSYNC), Some(&"1".to_string()));
         assert_eq!(vars.get(WAYLAND), Some(&"0".to_string()));
-        assert_eq!(vars.get(WINE_DLLS), Some(&"dinput8=n,b".to_string()));
+        assert_eq!(
+            vars.get(WINE_DLLS),
+            Some(&"dinput8=n,b;winemenubuilder=;xaudio2_7=n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_env_builder_without_defaults() {
+        let mut config = Config {
+            cpu: Default::default(),
+            gpu: GpuTune::default(),
+            sys: Default::default(),
+            env: Default::default(),
+            game: Default::default(),
+            hook: Default::default(),
+            daemon: Default::default(),
+            paths: Default::default(),
+            schedule: Default::default(),
+            updates: Default::default(),
+            budget: Default::default(),
+            resolve: Default::default(),
+        };
+
+        let game_config = GameConfig {
+            inherit_defaults: false,
+            mangohud: true,
+            ..Default::default()
+        };
+        let expected = game_vars(&game_config).len();
+        config.game.insert("vkgame".to_string(), game_config);
+
+        // Only what the game section sets is left
+        let vars = EnvBuilder::new().with_config(&config, &"vkgame".to_string());
+        assert_eq!(vars.get(HUD), Some(&"1".to_string()));
+        assert!(!vars.contains_key(HUD_CFG));
+        assert!(!vars.contains_key("__NV_PRIME_RENDER_OFFLOAD"));
+        assert_eq!(vars.len(), expected);
+
+        let vars = EnvBuilder::new().with_config(&config, &"other".to_string());
+        assert_eq!(vars.len(), ENV_DEFAULTS.len());
+    }
+
+    #[test]
+    fn test_game_vars_sync_options() {
+        let mut game = GameConfig::default();
+        let vars = game_vars(&game);
+        assert!(!vars.contains_key(NO_FSYNC));
+        assert!(!vars.contains_key(NO_ESYNC));
+
+        game.proton_fsync = Some(false);
+        game.proton_esync = Some(true);
+        let vars = game_vars(&game);
+        assert_eq!(vars.get(NO_FSYNC), Some(&"1".to_string()));
+        assert_eq!(vars.get(NO_ESYNC), Some(&"0".to_string()));
+    }
+
+    #[test]
+    fn test_game_vars_spoof_steamdeck() {
+        let mut game = GameConfig::default();
+        assert!(!game_vars(&game).contains_key(STEAM_DECK));
+
+        game.spoof_steamdeck = true;
+        let vars = game_vars(&game);
+        assert_eq!(vars.get(STEAM_DECK), Some(&"1".to_string()));
+        assert_eq!(vars.get(STEAM_OS), Some(&"1".to_string()));
+    }
+
+    #[test]
+    fn test_game_vars_game_drive() {
+        let mut game = GameConfig::default();
+        assert!(!game_vars(&game).contains_key(GAME_DRIVE));
+
+        game.game_drive = Some(false);
+        assert_eq!(game_vars(&game).get(GAME_DRIVE), Some(&"0".to_string()));
+    }
+
+    #[test]
+    fn test_sync_conflicts_with_ntsync() {
+        let mut game = GameConfig {
+            proton_fsync: Some(true),
+            proton_esync: Some(false),
+            ..Default::default()
+        };
+        assert!(sync_conflicts(&game).is_empty());
+
+        game.proton_ntsync = true;
+        assert_eq!(sync_conflicts(&game), vec!["proton_fsync"]);
+
+        game.proton_fsync = None;
+        assert!(sync_conflicts(&game).is_empty());
+    }
+
+    #[test]
+    fn test_env_builder_conflict_env_section_wins() {
+        let mut config: Config = toml::from_str(
+            r#"
+[game.testgame]
+mangohud = false
+proton_log = true
+
+[testgame]
+MANGOHUD = "1"
+PROTON_LOG = 1
+            "#,
+        )
+        .unwrap();
+
+        let conflicts = EnvBuilder::find_conflicts(&config, "testgame");
+        assert_eq!(
+            conflicts,
+            vec![EnvConflict {
+                game: "testgame".to_string(),
+                key: HUD.to_string(),
+                game_value: "0".to_string(),
+                env_value: "1".to_string(),
+            }]
+        );
+
+        let vars = EnvBuilder::new().with_config(&config, &"testgame".to_string());
+        assert_eq!(vars.get(HUD), Some(&"1".to_string()));
+
+        config.env.clear();
+        assert!(EnvBuilder::find_conflicts(&config, "testgame").is_empty());
     }
 
     #[test]
@@ -0,0 +1,171 @@
+use anyhow::Context;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::common::config::TelemetryFormat;
+use crate::common::telemetry::TelemetrySample;
+
+const CSV_HEADER: &str =
+    "timestamp_ms,temperature_c,power_mw,thermal_throttle,power_throttle,game_vram_mib";
+
+/// Write the session's telemetry next to its history as
+/// `<game>-<started_ms>.<ext>`, returning the file written
+pub fn export_telemetry(
+    dir: &Path,
+    game: &str,
+    started_ms: u64,
+    format: TelemetryFormat,
+    samples: &[TelemetrySample],
+) -> anyhow::Result<PathBuf> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let stem = format!("{}-{}", game, started_ms);
+    match format {
+        #[cfg(feature = "parquet")]
+        TelemetryFormat::Parquet => {
+            let path = dir.join(format!("{}.parquet", stem));
+            write_parquet(&path, samples)?;
+            Ok(path)
+        }
+        #[cfg(not(feature = "parquet"))]
+        TelemetryFormat::Parquet => {
+            log::warn!("Built without Parquet support, exporting telemetry as CSV instead");
+            export_telemetry(dir, game, started_ms, TelemetryFormat::Csv, samples)
+        }
+        TelemetryFormat::Csv => {
+            let path = dir.join(format!("{}.csv", stem));
+            write_csv(&path, samples)?;
+            Ok(path)
+        }
+    }
+}
+
+/// One row per sample, throttle flags as 0 or 1 and the game's VRAM empty
+/// while it had none
+pub fn write_csv(path: &Path, samples: &[TelemetrySample]) -> anyhow::Result<()> {
+    let mut file = std::io::BufWriter::new(
+        std::fs::File::create(path)
+            .with_context(|| format!("Failed to write {}", path.display()))?,
+    );
+
+    writeln!(file, "{}", CSV_HEADER)?;
+    for s in samples {
+        writeln!(
+            file,
+            "{},{},{},{},{},{}",
+            s.timestamp_ms,
+            s.temperature_c,
+            s.power_mw,
+            s.thermal_throttle as u8,
+            s.power_throttle as u8,
+            s.game_vram()
+                .map_or_else(String::new, |mib| mib.to_string())
+        )?;
+    }
+
+    file.flush()
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(feature = "parquet")]
+pub fn write_parquet(path: &Path, samples: &[TelemetrySample]) -> anyhow::Result<()> {
+    use arrow_array::{ArrayRef, BooleanArray, RecordBatch, UInt32Array, UInt64Array};
+    use std::sync::Arc;
+
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        (
+            "timestamp_ms",
+            Arc::new(UInt64Array::from_iter_values(
+                samples.iter().map(|s| s.timestamp_ms),
+            )),
+        ),
+        (
+            "temperature_c",
+            Arc::new(UInt32Array::from_iter_values(
+                samples.iter().map(|s| s.temperature_c),
+            )),
+        ),
+        (
+            "power_mw",
+            Arc::new(UInt32Array::from_iter_values(
+                samples.iter().map(|s| s.power_mw),
+            )),
+        ),
+        (
+            "thermal_throttle",
+            Arc::new(BooleanArray::from_iter(
+                samples.iter().map(|s| Some(s.thermal_throttle)),
+            )),
+        ),
+        (
+            "power_throttle",
+            Arc::new(BooleanArray::from_iter(
+                samples.iter().map(|s| Some(s.power_throttle)),
+            )),
+        ),
+        (
+            "game_vram_mib",
+            Arc::new(UInt64Array::from_iter(
+                samples.iter().map(TelemetrySample::game_vram),
+            )),
+        ),
+    ];
+    let batch = RecordBatch::try_from_iter(columns).context("Failed to build telemetry table")?;
+
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    let mut writer = parquet::arrow::ArrowWriter::try_new(file, batch.schema(), None)
+        .context("Failed to create Parquet writer")?;
+    writer.write(&batch).context("Failed to write telemetry")?;
+    writer.close().context("Failed to finish Parquet file")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_telemetry_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let samples = [
+            TelemetrySample {
+                timestamp_ms: 1000,
+                temperature_c: 65,
+                power_mw: 250_000,
+                thermal_throttle: false,
+                power_throttle: true,
+                ..Default::default()
+            },
+            TelemetrySample {
+                timestamp_ms: 2000,
+                temperature_c: 70,
+                power_mw: 260_000,
+                game_vram_mib: [(7, 5120)].into(),
+                ..Default::default()
+            },
+        ];
+
+        let path =
+            export_telemetry(dir.path(), "game", 42, TelemetryFormat::Csv, &samples).unwrap();
+        assert_eq!(path, dir.path().join("game-42.csv"));
+
+        let csv = std::fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(lines[1], "1000,65,250000,0,1,");
+        assert_eq!(lines[2], "2000,70,260000,0,0,5120");
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_export_telemetry_parquet() {
+        let dir = tempfile::tempdir().unwrap();
+        let samples = [TelemetrySample::default(), TelemetrySample::default()];
+
+        let path =
+            export_telemetry(dir.path(), "game", 42, TelemetryFormat::Parquet, &samples).unwrap();
+        assert_eq!(path, dir.path().join("game-42.parquet"));
+        assert!(std::fs::read(path).unwrap().starts_with(b"PAR1"));
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::Context;
+use log::debug;
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::common::TelemetrySample;
+
+/// A frame counts as a spike when it takes this many times the median frame time
+const SPIKE_FACTOR: f64 = 2.0;
+
+/// Maximum distance between a spike and the telemetry sample it is matched with
+const MATCH_TOLERANCE_MS: u64 = 1500;
+
+/// A single frame from a MangoHud log with its absolute wall clock time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub timestamp_ms: u64,
+    pub frametime_ms: f64,
+}
+
+/// Summary of how frame time spikes line up with GPU throttling
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct SpikeReport {
+    pub frames: usize,
+    pub spikes: usize,
+    pub thermal: usize,
+    pub power: usize,
+    pub unexplained: usize,
+}
+
+impl SpikeReport {
+    /// Correlate frame time spikes with the nearest telemetry sample
+    pub fn correlate(frames: &[Frame], samples: &[TelemetrySample]) -> Self {
+        let mut report = Self {
+            frames: frames.len(),
+            ..Default::default()
+        };
+
+        let Some(median) = median_frametime(frames) else {
+            return report;
+        };
+        let threshold = median * SPIKE_FACTOR;
+        debug!(
+            "Median frame time {:.2}ms, spike threshold {:.2}ms",
+            median, threshold
+        );
+
+        for frame in frames.iter().filter(|f| f.frametime_ms > threshold) {
+            report.spikes += 1;
+
+            match nearest_sample(samples, frame.timestamp_ms) {
+                Some(s) if s.thermal_throttle || s.power_throttle => {
+                    report.thermal += s.thermal_throttle as usize;
+                    report.power += s.power_throttle as usize;
+                }
+                _ => report.unexplained += 1,
+            }
+        }
+
+        report
+    }
+}
+
+impl fmt::Display for SpikeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} spikes in {} frames: {} coincided with thermal throttling, {} with power cap, {} unexplained",
+            self.spikes, self.frames, self.thermal, self.power, self.unexplained
+        )
+    }
+}
+
+/// Extract the `output_folder` MangoHud option from a config string
+pub fn mangohud_output_folder(mangohud_conf: &str) -> Option<PathBuf> {
+    mangohud_conf
+        .split(',')
+        .filter_map(|opt| opt.split_once('='))
+        .find(|(key, _)| key.trim() == "output_folder")
+        .map(|(_, val)| PathBuf::from(val.trim()))
+}
+
+/// Find the newest MangoHud CSV log in a folder written after the given time
+pub fn find_latest_log(dir: &Path, since_ms: u64) -> Option<PathBuf> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "csv"))
+        .filter_map(|path| modified_ms(&path).map(|mtime| (mtime, path)))
+        .filter(|(mtime, _)| *mtime >= since_ms)
+        .max_by_key(|(mtime, _)| *mtime)
+        .map(|(_, path)| path)
+}
+
+/// Read a MangoHud CSV log, anchoring its relative timestamps on the file mtime
+pub fn read_log(path: &Path) -> anyhow::Result<Vec<Frame>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read MangoHud log {}", path.display()))?;
+    let end_ms = modified_ms(path).context("Failed to read MangoHud log modification time")?;
+
+    parse_log(&content, end_ms)
+}
+
+/// Parse a MangoHud CSV log. The `elapsed` column is nanoseconds since logging
+/// started, so the last row is anchored at `end_ms` and the rest follow from it.
+pub fn parse_log(content: &str, end_ms: u64) -> anyhow::Result<Vec<Frame>> {
+    let mut lines = content.lines();
+    let header = lines
+        .find(|line| line.starts_with("fps,"))
+        .context("MangoHud log has no frame data header")?;
+
+    let columns: Vec<&str> = header.split(',').collect();
+    let column = |name: &str| {
+        columns
+            .iter()
+            .position(|c| *c == name)
+            .with_context(|| format!("MangoHud log has no '{}' column", name))
+    };
+    let frametime_idx = column("frametime")?;
+    let elapsed_idx = column("elapsed")?;
+
+    let rows: Vec<(f64, u64)> = lines
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').collect();
+            let frametime = fields.get(frametime_idx)?.trim().parse().ok()?;
+            let elapsed = fields.get(elapsed_idx)?.trim().parse::<u64>().ok()?;
+            Some((frametime, elapsed / 1_000_000))
+        })
+        .collect();
+
+    let last_elapsed = rows.last().map(|(_, e)| *e).unwrap_or(0);
+    let start_ms = end_ms.saturating_sub(last_elapsed);
+
+    Ok(rows
+        .into_iter()
+        .map(|(frametime_ms, elapsed_ms)| Frame {
+            timestamp_ms: start_ms + elapsed_ms,
+            frametime_ms,
+        })
+        .collect())
+}
+
+fn median_frametime(frames: &[Frame]) -> Option<f64> {
+    if frames.is_empty() {
+        return None;
+    }
+
+    let mut times: Vec<f64> = frames.iter().map(|f| f.frametime_ms).collect();
+    times.sort_by(|a, b| a.total_cmp(b));
+    Some(times[times.len() / 2])
+}
+
+fn nearest_sample(samples: &[TelemetrySample], timestamp_ms: u64) -> Option<&TelemetrySample> {
+    samples
+        .iter()
+        .map(|s| (s.timestamp_ms.abs_diff(timestamp_ms), s))
+        .filter(|(distance, _)| *distance <= MATCH_TOLERANCE_MS)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, s)| s)
+}
+
+fn modified_ms(path: &Path) -> Option<u64> {
+    let mtime = std::fs::metadata(path).ok()?.modified().ok()?;
+    Some(mtime.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "os,cpu,gpu,ram,kernel,driver,cpuscheduler
+Arch,Ryzen,RTX 4090,32GB,6.8,550,
+fps,frametime,cpu_load,gpu_load,cpu_temp,gpu_temp,elapsed
+60,16.6,10,90,60,70,0
+60,16.7,10,90,60,70,1000000000
+20,50.0,10,90,60,70,2000000000
+60,16.6,10,90,60,70,3000000000
+";
+
+    fn frame(timestamp_ms: u64, frametime_ms: f64) -> Frame {
+        Frame {
+            timestamp_ms,
+            frametime_ms,
+        }
+    }
+
+    fn sample(timestamp_ms: u64, thermal: bool, power: bool) -> TelemetrySample {
+        TelemetrySample {
+            timestamp_ms,
+            thermal_throttle: thermal,
+            power_throttle: power,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_log() {
+        let frames = parse_log(LOG, 10_000).unwrap();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0], frame(7_000, 16.6));
+        assert_eq!(frames[2], frame(9_000, 50.0));
+        assert_eq!(frames[3].timestamp_ms, 10_000);
+    }
+
+    #[test]
+    fn test_parse_log_missing_header() {
+        assert!(parse_log("garbage\n1,2,3\n", 0).is_err());
+    }
+
+    #[test]
+    fn test_correlate() {
+        let frames = vec![
+            frame(1_000, 16.0),
+            frame(2_000, 40.0),
+            frame(3_000, 16.0),
+            frame(4_000, 45.0),
+            frame(5_000, 16.0),
+            frame(6_000, 16.0),
+            frame(7_000, 16.0),
+            frame(9_000, 50.0),
+        ];
+        let samples = vec![sample(2_100, true, false), sample(4_200, false, true)];
+
+        let report = SpikeReport::correlate(&frames, &samples);
+        assert_eq!(
+            report,
+            SpikeReport {
+                frames: 8,
+                spikes: 3,
+                thermal: 1,
+                power: 1,
+                unexplained: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_correlate_empty() {
+        assert_eq!(SpikeReport::correlate(&[], &[]), SpikeReport::default());
+    }
+
+    #[test]
+    fn test_mangohud_output_folder() {
+        assert_eq!(
+            mangohud_output_folder("fps_only=1, output_folder=/tmp/logs,autostart_log=1"),
+            Some(PathBuf::from("/tmp/logs"))
+        );
+        assert_eq!(mangohud_output_folder("fps_only=1"), None);
+    }
+
+    #[test]
+    fn test_find_latest_log() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("game.csv"), LOG).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "x").unwrap();
+
+        let found = find_latest_log(dir.path(), 0).unwrap();
+        assert_eq!(found.file_name().unwrap(), "game.csv");
+        assert!(find_latest_log(dir.path(), u64::MAX).is_none());
+        assert_eq!(read_log(&found).unwrap().len(), 4);
+    }
+}
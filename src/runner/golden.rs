@@ -0,0 +1,149 @@
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::common::paths::Paths;
+use crate::common::{Config, DriverVersion};
+use crate::runner::history::{Change, diff_maps};
+use crate::runner::{EnvBuilder, SwitcherooGpu};
+
+/// Canonical dump of the environment nvprime resolves for a game, kept next
+/// to the config so an upgrade that changes the defaults table shows up
+#[derive(Debug, PartialEq)]
+pub struct EnvGolden {
+    pub vars: BTreeMap<String, String>,
+}
+
+impl EnvGolden {
+    /// The environment a launch of `game` would get right now
+    pub fn resolve(config: &Config, game: &str) -> Self {
+        let vars = EnvBuilder::new()
+            .with_driver(DriverVersion::detect())
+            .with_switcheroo(
+                config
+                    .gpu
+                    .prefer_switcheroo
+                    .then(SwitcherooGpu::detect)
+                    .flatten(),
+            )
+            .with_config(config, &game.to_string());
+        Self { vars }
+    }
+
+    /// Where the snapshot of a game lives, `snapshots/<game>.env` in the
+    /// nvprime config directory
+    pub fn path(paths: &Paths, game: &str) -> PathBuf {
+        paths.snapshots_dir().join(format!("{}.env", game))
+    }
+
+    /// One sorted `KEY=VALUE` line per variable
+    pub fn render(&self) -> String {
+        self.vars
+            .iter()
+            .map(|(key, val)| format!("{}={}\n", key, val))
+            .collect()
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let vars = content
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, val)| (key.to_string(), val.to_string()))
+            .collect();
+        Self { vars }
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        std::fs::write(path, self.render())
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path).with_context(|| {
+            format!("Failed to read {}, create it with --write", path.display())
+        })?;
+        Ok(Self::parse(&content))
+    }
+
+    /// Variables that differ from the stored snapshot
+    pub fn verify(&self, stored: &Self) -> Vec<Change> {
+        diff_maps(&stored.vars, &self.vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn golden(vars: &[(&str, &str)]) -> EnvGolden {
+        EnvGolden {
+            vars: vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_golden_render_parse() {
+        let env = golden(&[("PROTON_LOG", "1"), ("DXVK_ASYNC", "1"), ("A", "b=c")]);
+        let rendered = env.render();
+
+        assert_eq!(rendered, "A=b=c\nDXVK_ASYNC=1\nPROTON_LOG=1\n");
+        assert_eq!(EnvGolden::parse(&rendered), env);
+        assert_eq!(
+            EnvGolden::parse("# comment\n\nA=1\n"),
+            golden(&[("A", "1")])
+        );
+    }
+
+    #[test]
+    fn test_golden_verify() {
+        let stored = golden(&[("A", "1"), ("B", "1")]);
+        let current = golden(&[("A", "1"), ("B", "0"), ("C", "1")]);
+
+        let changes: Vec<String> = current
+            .verify(&stored)
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(changes, vec!["B: 1 -> 0", "C: <unset> -> 1"]);
+        assert!(stored.verify(&stored).is_empty());
+    }
+
+    #[test]
+    fn test_golden_resolve_fixture() {
+        let config: Config = toml::from_str(
+            r#"
+            [game.testgame]
+            mangohud = true
+            proton_log = true
+
+            [testgame]
+            DXVK_HUD = "fps"
+            "#,
+        )
+        .unwrap();
+
+        let env = EnvGolden::resolve(&config, "testgame");
+        assert_eq!(env.vars["MANGOHUD"], "1");
+        assert_eq!(env.vars["PROTON_LOG"], "1");
+        assert_eq!(env.vars["DXVK_HUD"], "fps");
+
+        let dir = tempfile::tempdir().unwrap();
+        let paths = Paths::from_env(|_| None, dir.path(), 1000, |_| false);
+        let path = EnvGolden::path(&paths, "testgame");
+        assert_eq!(
+            path,
+            dir.path().join(".config/nvprime/snapshots/testgame.env")
+        );
+
+        env.write(&path).unwrap();
+        assert!(EnvGolden::read(&path).unwrap().verify(&env).is_empty());
+    }
+}
@@ -0,0 +1,382 @@
+use anyhow::Context;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::common::nvgpu::driver_version_string;
+use crate::common::paths::Paths;
+use crate::runner::timing::Phase;
+
+/// Older sessions beyond this are dropped from a game's history
+const MAX_RECORDS: usize = 50;
+
+/// Everything needed to tell two runs of a game apart
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionRecord {
+    pub game: String,
+    pub started_ms: u64,
+    pub exit_code: i32,
+    pub clean_exit: bool,
+    pub driver_version: Option<String>,
+    pub env: BTreeMap<String, String>,
+    pub config: BTreeMap<String, String>,
+
+    /// How long nvprime took for each launch step, missing in older records
+    #[serde(default)]
+    pub timings: Vec<Phase>,
+
+    /// Variables given with `-E` for this launch only
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env_overrides: BTreeMap<String, String>,
+}
+
+impl SessionRecord {
+    /// Record a finished session, flattening `config` into dotted keys
+    pub fn new(
+        game: &str,
+        started_ms: u64,
+        exit_code: i32,
+        clean_exit: bool,
+        env: &BTreeMap<String, String>,
+        config: &serde_json::Value,
+    ) -> Self {
+        let mut flat = BTreeMap::new();
+        flatten("", config, &mut flat);
+
+        Self {
+            game: game.to_string(),
+            started_ms,
+            exit_code,
+            clean_exit,
+            driver_version: driver_version_string(),
+            env: env.clone(),
+            config: flat,
+            timings: Vec::new(),
+            env_overrides: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_timings(mut self, timings: &[Phase]) -> Self {
+        self.timings = timings.to_vec();
+        self
+    }
+
+    pub fn with_env_overrides(mut self, overrides: &BTreeMap<String, String>) -> Self {
+        self.env_overrides = overrides.clone();
+        self
+    }
+}
+
+/// Per-game session history, one JSON record per line
+pub struct SessionHistory {
+    dir: PathBuf,
+}
+
+impl SessionHistory {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// History in the user's data directory
+    pub fn open(paths: &Paths) -> Self {
+        Self::new(paths.sessions_dir())
+    }
+
+    /// Directory holding the history, and any exported telemetry
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, game: &str) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", file_stem(game)))
+    }
+
+    /// Append a session, trimming the game's history to the newest records
+    pub fn record(&self, record: &SessionRecord) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+
+        let mut records = self.load(&record.game)?;
+        records.push(record.clone());
+        let skip = records.len().saturating_sub(MAX_RECORDS);
+
+        let path = self.path(&record.game);
+        let mut file = std::fs::File::create(&path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        for record in &records[skip..] {
+            let line = serde_json::to_string(record).context("Failed to serialize session")?;
+            writeln!(file, "{}", line)?;
+        }
+
+        debug!("Recorded session in {}", path.display());
+        Ok(())
+    }
+
+    /// All recorded sessions of a game, oldest first
+    pub fn load(&self, game: &str) -> anyhow::Result<Vec<SessionRecord>> {
+        let path = self.path(game);
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+            }
+        };
+
+        Ok(content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    /// Compare the latest session with the last one before it that exited cleanly
+    pub fn diff_latest(&self, game: &str) -> anyhow::Result<SessionDiff> {
+        let records = self.load(game)?;
+        let (latest, earlier) = records
+            .split_last()
+            .with_context(|| format!("No recorded sessions for '{}'", game))?;
+        let good = earlier
+            .iter()
+            .rev()
+            .find(|r| r.clean_exit)
+            .with_context(|| format!("No earlier working session for '{}'", game))?;
+
+        Ok(SessionDiff::between(good, latest))
+    }
+}
+
+/// A value that differs between two sessions, `None` when unset
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub key: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |v: &Option<String>| v.clone().unwrap_or_else(|| "<unset>".to_string());
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.key,
+            show(&self.before),
+            show(&self.after)
+        )
+    }
+}
+
+/// Differences between a known good session and a later one
+#[derive(Debug)]
+pub struct SessionDiff {
+    pub good_started_ms: u64,
+    pub latest_started_ms: u64,
+    pub latest_exit_code: i32,
+    pub driver: Option<Change>,
+    pub env: Vec<Change>,
+    pub config: Vec<Change>,
+}
+
+impl SessionDiff {
+    pub fn between(good: &SessionRecord, latest: &SessionRecord) -> Self {
+        let driver = (good.driver_version != latest.driver_version).then(|| Change {
+            key: "driver".to_string(),
+            before: good.driver_version.clone(),
+            after: latest.driver_version.clone(),
+        });
+
+        Self {
+            good_started_ms: good.started_ms,
+            latest_started_ms: latest.started_ms,
+            latest_exit_code: latest.exit_code,
+            driver,
+            env: diff_maps(&good.env, &latest.env),
+            config: diff_maps(&good.config, &latest.config),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.driver.is_none() && self.env.is_empty() && self.config.is_empty()
+    }
+}
+
+pub(crate) fn diff_maps(
+    before: &BTreeMap<String, String>,
+    after: &BTreeMap<String, String>,
+) -> Vec<Change> {
+    let mut keys: Vec<&String> = before.keys().chain(after.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter(|key| before.get(*key) != after.get(*key))
+        .map(|key| Change {
+            key: key.clone(),
+            before: before.get(key).cloned(),
+            after: after.get(key).cloned(),
+        })
+        .collect()
+}
+
+fn flatten(prefix: &str, value: &serde_json::Value, out: &mut BTreeMap<String, String>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&key, value, out);
+            }
+        }
+        serde_json::Value::Null => {}
+        serde_json::Value::String(s) => {
+            out.insert(prefix.to_string(), s.clone());
+        }
+        other => {
+            out.insert(prefix.to_string(), other.to_string());
+        }
+    }
+}
+
+/// A game name as a file name within the history directory, with path
+/// separators replaced and a leading dot escaped so `..` stays inside it
+fn file_stem(game: &str) -> String {
+    let stem: String = game
+        .chars()
+        .map(|c| {
+            if matches!(c, '/' | '\\' | '\0') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    if stem.is_empty() || stem.starts_with('.') {
+        format!("_{}", stem)
+    } else {
+        stem
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(started_ms: u64, clean_exit: bool, env: &[(&str, &str)]) -> SessionRecord {
+        SessionRecord {
+            game: "game".to_string(),
+            started_ms,
+            exit_code: if clean_exit { 0 } else { 1 },
+            clean_exit,
+            driver_version: Some("550.54".to_string()),
+            env: env
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            config: BTreeMap::new(),
+            timings: Vec::new(),
+            env_overrides: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_record_without_timings() {
+        let json = r#"{"game":"game","started_ms":1,"exit_code":0,"clean_exit":true,"driver_version":null,"env":{},"config":{}}"#;
+        let record: SessionRecord = serde_json::from_str(json).unwrap();
+        assert!(record.timings.is_empty());
+        assert!(record.env_overrides.is_empty());
+    }
+
+    #[test]
+    fn test_flatten_config() {
+        let config = serde_json::json!({
+            "gpu": { "pwr_limit_tune": 250000, "gpu_name": null },
+            "game": { "mangohud": true, "mangohud_conf": "fps_only=1" },
+        });
+        let record = SessionRecord::new("game", 0, 0, true, &BTreeMap::new(), &config);
+
+        assert_eq!(record.config["gpu.pwr_limit_tune"], "250000");
+        assert_eq!(record.config["game.mangohud"], "true");
+        assert_eq!(record.config["game.mangohud_conf"], "fps_only=1");
+        assert!(!record.config.contains_key("gpu.gpu_name"));
+    }
+
+    #[test]
+    fn test_diff_between() {
+        let good = record(1, true, &[("DXVK_ASYNC", "1"), ("MANGOHUD", "1")]);
+        let mut latest = record(2, false, &[("MANGOHUD", "0"), ("PROTON_LOG", "1")]);
+        latest.driver_version = Some("555.42".to_string());
+
+        let diff = SessionDiff::between(&good, &latest);
+        assert_eq!(
+            diff.driver.as_ref().unwrap().to_string(),
+            "driver: 550.54 -> 555.42"
+        );
+        let env: Vec<String> = diff.env.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            env,
+            vec![
+                "DXVK_ASYNC: 1 -> <unset>",
+                "MANGOHUD: 1 -> 0",
+                "PROTON_LOG: <unset> -> 1"
+            ]
+        );
+        assert!(diff.config.is_empty());
+        assert!(SessionDiff::between(&good, &good).is_empty());
+    }
+
+    #[test]
+    fn test_history_record_and_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = SessionHistory::new(dir.path().join("sessions"));
+        assert!(history.diff_latest("game").is_err());
+
+        history.record(&record(1, true, &[("A", "1")])).unwrap();
+        history.record(&record(2, true, &[("A", "2")])).unwrap();
+        history.record(&record(3, false, &[("A", "3")])).unwrap();
+        assert_eq!(history.load("game").unwrap().len(), 3);
+
+        let diff = history.diff_latest("game").unwrap();
+        assert_eq!(diff.good_started_ms, 2);
+        assert_eq!(diff.latest_started_ms, 3);
+        assert_eq!(diff.env[0].to_string(), "A: 2 -> 3");
+    }
+
+    #[test]
+    fn test_history_trims_old_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = SessionHistory::new(dir.path().to_path_buf());
+
+        for started_ms in 0..MAX_RECORDS as u64 + 5 {
+            history.record(&record(started_ms, true, &[])).unwrap();
+        }
+
+        let records = history.load("game").unwrap();
+        assert_eq!(records.len(), MAX_RECORDS);
+        assert_eq!(records[0].started_ms, 5);
+    }
+
+    #[test]
+    fn test_history_path_stays_in_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = SessionHistory::new(dir.path().join("sessions"));
+
+        for game in ["../x", "a/b", "/etc/passwd", "..", ""] {
+            let path = history.path(game);
+            assert_eq!(path.parent(), Some(history.dir()), "{:?}", game);
+        }
+        assert_eq!(history.path("../x"), history.dir().join("_.._x.jsonl"));
+        assert_eq!(history.path("a/b"), history.dir().join("a_b.jsonl"));
+
+        let mut escaping = record(1, true, &[]);
+        escaping.game = "../x".to_string();
+        history.record(&escaping).unwrap();
+        assert!(!dir.path().join("x.jsonl").exists());
+        assert_eq!(history.load("../x").unwrap().len(), 1);
+    }
+}
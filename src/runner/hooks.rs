@@ -0,0 +1,594 @@
+use anyhow::Context;
+use log::{debug, error, info, warn};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::common::config::{HookFailure, HooksConfig, StageHooks};
+use crate::common::steam::SteamCompat;
+use crate::common::user::OriginalUser;
+
+/// Most recent hook output kept for error messages, in bytes
+const OUTPUT_CAP: usize = 16 * 1024;
+
+/// How long to wait for output after the hook exits. A background process
+/// started by the hook may hold the pipes open for much longer.
+const OUTPUT_DRAIN: Duration = Duration::from_millis(500);
+
+/// The last lines a hook printed, capped so a chatty script cannot grow it
+/// without bound
+#[derive(Debug, Default)]
+struct OutputTail {
+    lines: VecDeque<String>,
+    bytes: usize,
+    truncated: bool,
+}
+
+impl OutputTail {
+    fn push(&mut self, mut line: String) {
+        // Room for the newline, so a single long line still fits
+        if line.len() >= OUTPUT_CAP {
+            line.truncate(line.floor_char_boundary(OUTPUT_CAP - 1));
+            self.truncated = true;
+        }
+
+        self.bytes += line.len() + 1;
+        self.lines.push_back(line);
+
+        while self.bytes > OUTPUT_CAP
+            && let Some(dropped) = self.lines.pop_front()
+        {
+            self.bytes -= dropped.len() + 1;
+            self.truncated = true;
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut output = String::new();
+        if self.truncated {
+            output.push_str("...\n");
+        }
+        for line in &self.lines {
+            output.push_str(line);
+            output.push('\n');
+        }
+        output
+    }
+}
+
+/// Log every line of a hook's output stream as it arrives, on a thread of
+/// its own. `done` is dropped when the stream closes.
+fn spawn_forward(
+    stage: &str,
+    stream: impl Read + Send + 'static,
+    is_stderr: bool,
+    tail: &Arc<Mutex<OutputTail>>,
+    done: mpsc::Sender<()>,
+) {
+    let stage = stage.to_string();
+    let tail = Arc::clone(tail);
+    std::thread::spawn(move || {
+        forward(&stage, stream, is_stderr, &tail);
+        drop(done);
+    });
+}
+
+fn forward(stage: &str, stream: impl Read, is_stderr: bool, tail: &Mutex<OutputTail>) {
+    for line in BufReader::new(stream).split(b'\n') {
+        let Ok(line) = line else {
+            break;
+        };
+        let line = String::from_utf8_lossy(&line).trim_end().to_string();
+
+        if is_stderr {
+            warn!("[{} hook] {}", stage, line);
+        } else {
+            info!("[{} hook] {}", stage, line);
+        }
+        tail.lock().unwrap().push(line);
+    }
+}
+
+/// Points of a launch where hooks run, in the order they come
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    PreEnv,
+    PostEnv,
+    PreSpawn,
+    PostSpawn,
+    PostExit,
+}
+
+impl HookStage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PreEnv => "pre_env",
+            Self::PostEnv => "post_env",
+            Self::PreSpawn => "pre_spawn",
+            Self::PostSpawn => "post_spawn",
+            Self::PostExit => "post_exit",
+        }
+    }
+
+    /// Whether the game has yet to start, so a failing hook may abort it
+    pub fn before_spawn(self) -> bool {
+        matches!(self, Self::PreEnv | Self::PostEnv | Self::PreSpawn)
+    }
+}
+
+impl fmt::Display for HookStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A stage along with what its hooks get to know about the launch
+#[derive(Debug, Clone, Copy)]
+pub enum HookContext<'a> {
+    /// The command as given to nvprime, in `NVPRIME_COMMAND`
+    PreEnv {
+        command: &'a [String],
+    },
+
+    /// The game's resolved environment, set for the hooks
+    PostEnv {
+        game: &'a str,
+        env: &'a BTreeMap<String, String>,
+    },
+
+    PreSpawn {
+        game: &'a str,
+    },
+
+    /// The game's process, in `NVPRIME_GAME_PID`
+    PostSpawn {
+        game: &'a str,
+        pid: u32,
+    },
+
+    /// How the game ended, in `NVPRIME_EXIT_CODE`
+    PostExit {
+        game: &'a str,
+        exit_code: i32,
+    },
+}
+
+impl HookContext<'_> {
+    pub fn stage(&self) -> HookStage {
+        match self {
+            Self::PreEnv { .. } => HookStage::PreEnv,
+            Self::PostEnv { .. } => HookStage::PostEnv,
+            Self::PreSpawn { .. } => HookStage::PreSpawn,
+            Self::PostSpawn { .. } => HookStage::PostSpawn,
+            Self::PostExit { .. } => HookStage::PostExit,
+        }
+    }
+
+    /// `NVPRIME_*` variables of the stage, `NVPRIME_HOOK_STAGE` always and
+    /// `NVPRIME_GAME_NAME` once the game is known
+    fn vars(&self) -> BTreeMap<String, String> {
+        let mut vars = BTreeMap::new();
+        let mut set = |key: &str, val: String| {
+            vars.insert(key.to_string(), val);
+        };
+
+        set("NVPRIME_HOOK_STAGE", self.stage().name().to_string());
+        match *self {
+            Self::PreEnv { command } => set("NVPRIME_COMMAND", command.join(" ")),
+            Self::PostEnv { game, .. } | Self::PreSpawn { game } => {
+                set("NVPRIME_GAME_NAME", game.to_string())
+            }
+            Self::PostSpawn { game, pid } => {
+                set("NVPRIME_GAME_NAME", game.to_string());
+                set("NVPRIME_GAME_PID", pid.to_string());
+            }
+            Self::PostExit { game, exit_code } => {
+                set("NVPRIME_GAME_NAME", game.to_string());
+                set("NVPRIME_EXIT_CODE", exit_code.to_string());
+            }
+        }
+        vars
+    }
+}
+
+/// Runs the user's `[hook]` commands through `sh -c`
+pub struct HookRunner<'a> {
+    hooks: &'a HooksConfig,
+    env: BTreeMap<String, String>,
+    strict: bool,
+}
+
+impl<'a> HookRunner<'a> {
+    pub fn new(hooks: &'a HooksConfig) -> Self {
+        let user = hooks.user_env.then(OriginalUser::detect).flatten();
+        Self::with_user(hooks, user.as_ref())
+    }
+
+    fn with_user(hooks: &'a HooksConfig, user: Option<&OriginalUser>) -> Self {
+        let mut env = user
+            .map(|user| user.session_env(|key| std::env::var(key).ok(), Path::exists))
+            .unwrap_or_default();
+
+        env.extend(SteamCompat::from_env().hook_env());
+        env.extend(hooks.env.iter().map(|(k, v)| (k.clone(), v.to_string())));
+        Self {
+            hooks,
+            env,
+            strict: false,
+        }
+    }
+
+    /// In strict mode a failing hook aborts the launch before the game
+    /// starts, unless its stage says otherwise
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Run the commands of a stage in order, `init` ahead of `pre_spawn`
+    /// and `shutdown` ahead of `post_exit`. Fails only when the stage's
+    /// failure policy aborts the launch, other failures are logged.
+    pub fn run(&self, context: HookContext) -> anyhow::Result<()> {
+        let stage = context.stage();
+        let (legacy, hooks) = match stage {
+            HookStage::PreEnv => (None, &self.hooks.pre_env),
+            HookStage::PostEnv => (None, &self.hooks.post_env),
+            HookStage::PreSpawn => (self.hooks.init.as_deref(), &self.hooks.pre_spawn),
+            HookStage::PostSpawn => (None, &self.hooks.post_spawn),
+            HookStage::PostExit => (self.hooks.shutdown.as_deref(), &self.hooks.post_exit),
+        };
+
+        // The legacy hooks keep their names in the log
+        let legacy_name = if stage == HookStage::PreSpawn {
+            "init"
+        } else {
+            "shutdown"
+        };
+        let commands: Vec<(&str, &str)> = legacy
+            .map(|command| (legacy_name, command))
+            .into_iter()
+            .chain(
+                hooks
+                    .cmd
+                    .iter()
+                    .map(|command| (stage.name(), command.as_str())),
+            )
+            .collect();
+        if commands.is_empty() {
+            debug!("No {} hooks configured", stage);
+            return Ok(());
+        }
+
+        let mut env = match context {
+            HookContext::PostEnv { env, .. } => env.clone(),
+            _ => BTreeMap::new(),
+        };
+        env.extend(self.env.clone());
+        env.extend(context.vars());
+
+        let policy = self.failure_policy(stage, hooks);
+        for (name, command) in commands {
+            let Err(e) = self.run_hook(name, Some(command), &env) else {
+                continue;
+            };
+            match policy {
+                HookFailure::Continue => error!("{:#}", e),
+                HookFailure::Stop => {
+                    error!("{:#}, skipping the remaining {} hooks", e, stage);
+                    return Ok(());
+                }
+                HookFailure::Abort => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn failure_policy(&self, stage: HookStage, hooks: &StageHooks) -> HookFailure {
+        match hooks.on_failure {
+            // Nothing left to abort once the game runs
+            Some(HookFailure::Abort) if !stage.before_spawn() => HookFailure::Stop,
+            Some(policy) => policy,
+            None if self.strict && stage.before_spawn() => HookFailure::Abort,
+            None => HookFailure::Continue,
+        }
+    }
+
+    /// Whether any hook reacts to GPU conditions
+    pub fn has_condition_hooks(&self) -> bool {
+        self.hooks.on_thermal_throttle.is_some()
+            || self.hooks.on_power_throttle.is_some()
+            || self.hooks.on_temp_above.is_some()
+            || !self.hooks.hud_detailed_on.is_empty()
+    }
+
+    /// Run the hook for a GPU condition the daemon reported, with the GPU
+    /// temperature in `NVPRIME_GPU_TEMP`. Temperature conditions only count
+    /// when the threshold is the one of `on_temp_above`, other sessions may
+    /// have asked for theirs.
+    pub fn run_condition(
+        &self,
+        kind: &str,
+        temperature_c: u32,
+        threshold_c: u32,
+    ) -> anyhow::Result<()> {
+        let command = match kind {
+            "thermal_throttle" => self.hooks.on_thermal_throttle.as_deref(),
+            "power_throttle" => self.hooks.on_power_throttle.as_deref(),
+            "temp_above" => self
+                .hooks
+                .on_temp_above
+                .as_ref()
+                .filter(|hook| hook.temp == threshold_c)
+                .map(|hook| hook.cmd.as_str()),
+            _ => None,
+        };
+
+        let mut env = self.env.clone();
+        env.insert("NVPRIME_GPU_TEMP".to_string(), temperature_c.to_string());
+        self.run_hook(&format!("on_{}", kind), command, &env)
+    }
+
+    fn run_hook(
+        &self,
+        stage: &str,
+        command: Option<&str>,
+        env: &BTreeMap<String, String>,
+    ) -> anyhow::Result<()> {
+        let Some(command) = command else {
+            debug!("No {} hook configured", stage);
+            return Ok(());
+        };
+
+        info!("Running {} hook: {}", stage, command);
+        for (key, val) in env {
+            debug!("  Hook ENV: '{}' with '{}'", key, val);
+        }
+
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .envs(env)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {} hook", stage))?;
+
+        // Each stream gets its own reader so neither pipe fills up and
+        // stalls the hook
+        let tail = Arc::new(Mutex::new(OutputTail::default()));
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        if let Some(stdout) = child.stdout.take() {
+            spawn_forward(stage, stdout, false, &tail, done_tx.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            spawn_forward(stage, stderr, true, &tail, done_tx.clone());
+        }
+        drop(done_tx);
+
+        let status = child
+            .wait()
+            .with_context(|| format!("Failed to wait for {} hook", stage))?;
+
+        // Disconnects once every reader is done and dropped its sender
+        if let Err(RecvTimeoutError::Timeout) = done_rx.recv_timeout(OUTPUT_DRAIN) {
+            debug!("{} hook output is still open, not waiting for it", stage);
+        }
+
+        if !status.success() {
+            let output = tail.lock().unwrap().render();
+            if output.is_empty() {
+                anyhow::bail!("{} hook failed with {}", stage, status);
+            }
+            anyhow::bail!("{} hook failed with {}, output:\n{}", stage, status, output);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::{EnvValue, TempHook};
+
+    fn hooks(init: Option<&str>, shutdown: Option<&str>) -> HooksConfig {
+        HooksConfig {
+            init: init.map(String::from),
+            shutdown: shutdown.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    const PRE_SPAWN: HookContext = HookContext::PreSpawn { game: "doom" };
+
+    fn stage(cmd: &[&str], on_failure: Option<HookFailure>) -> StageHooks {
+        StageHooks {
+            cmd: cmd.iter().map(|cmd| cmd.to_string()).collect(),
+            on_failure,
+        }
+    }
+
+    #[test]
+    fn test_hooks_unset() {
+        let config = hooks(None, None);
+        let runner = HookRunner::new(&config);
+        assert!(runner.run(PRE_SPAWN).is_ok());
+        assert!(
+            runner
+                .run(HookContext::PostExit {
+                    game: "doom",
+                    exit_code: 0
+                })
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_hooks_failure_policy() {
+        let post_exit = HookContext::PostExit {
+            game: "doom",
+            exit_code: 3,
+        };
+        let mut config = hooks(Some("exit 1"), Some("exit 3"));
+        let mut runner = HookRunner::new(&config);
+        assert!(runner.run(PRE_SPAWN).is_ok());
+        assert!(runner.run(post_exit).is_ok());
+
+        // Strict mode only aborts before the game starts
+        runner.set_strict(true);
+        let err = runner.run(PRE_SPAWN).unwrap_err();
+        assert!(err.to_string().contains("init hook failed"), "{}", err);
+        assert!(runner.run(post_exit).is_ok());
+
+        let dir = tempfile::tempdir().unwrap();
+        let ran = dir.path().join("ran");
+        let touch = format!("touch '{}'", ran.display());
+        config.init = None;
+        config.pre_spawn = stage(&["exit 2", &touch], Some(HookFailure::Continue));
+        let mut runner = HookRunner::new(&config);
+        runner.set_strict(true);
+        assert!(runner.run(PRE_SPAWN).is_ok());
+        assert!(ran.exists());
+
+        std::fs::remove_file(&ran).unwrap();
+        config.pre_spawn.on_failure = Some(HookFailure::Stop);
+        assert!(HookRunner::new(&config).run(PRE_SPAWN).is_ok());
+        assert!(!ran.exists());
+
+        config.pre_spawn.on_failure = Some(HookFailure::Abort);
+        let err = HookRunner::new(&config).run(PRE_SPAWN).unwrap_err();
+        assert!(err.to_string().contains("pre_spawn hook failed"), "{}", err);
+    }
+
+    #[test]
+    fn test_hooks_stage_context() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("context");
+        let echo = format!(
+            "echo \"$NVPRIME_HOOK_STAGE $NVPRIME_GAME_NAME $NVPRIME_GAME_PID$NVPRIME_EXIT_CODE $GAME_VAR\" >> '{}'",
+            out.display()
+        );
+        let config = HooksConfig {
+            post_env: stage(&[&echo], None),
+            post_spawn: stage(&[&echo], None),
+            post_exit: stage(&[&echo], None),
+            ..Default::default()
+        };
+        let runner = HookRunner::new(&config);
+
+        let game_env = BTreeMap::from([("GAME_VAR".to_string(), "1".to_string())]);
+        runner
+            .run(HookContext::PostEnv {
+                game: "doom",
+                env: &game_env,
+            })
+            .unwrap();
+        runner
+            .run(HookContext::PostSpawn {
+                game: "doom",
+                pid: 4242,
+            })
+            .unwrap();
+        runner
+            .run(HookContext::PostExit {
+                game: "doom",
+                exit_code: 7,
+            })
+            .unwrap();
+
+        let context = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(
+            context,
+            "post_env doom  1\npost_spawn doom 4242 \npost_exit doom 7 \n"
+        );
+    }
+
+    #[test]
+    fn test_condition_hooks() {
+        let config = HooksConfig {
+            on_temp_above: Some(TempHook {
+                temp: 87,
+                cmd: "test \"$NVPRIME_GPU_TEMP\" = 90 && exit 4".to_string(),
+            }),
+            ..Default::default()
+        };
+        let runner = HookRunner::new(&config);
+        assert!(runner.has_condition_hooks());
+
+        // Another session's threshold or an unset hook
+        assert!(runner.run_condition("temp_above", 90, 80).is_ok());
+        assert!(runner.run_condition("thermal_throttle", 90, 0).is_ok());
+
+        let err = runner.run_condition("temp_above", 90, 87).unwrap_err();
+        assert!(
+            err.to_string().contains("on_temp_above hook failed"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_hooks_output_in_error() {
+        let config = hooks(Some("echo starting; echo broken >&2; exit 1"), None);
+        let mut runner = HookRunner::new(&config);
+        runner.set_strict(true);
+        let err = runner.run(PRE_SPAWN).unwrap_err().to_string();
+
+        assert!(err.contains("init hook failed"), "{}", err);
+        assert!(err.contains("starting\n"), "{}", err);
+        assert!(err.contains("broken\n"), "{}", err);
+    }
+
+    #[test]
+    fn test_hooks_background_process_does_not_block() {
+        let config = hooks(Some("sleep 5 &"), None);
+        let started = std::time::Instant::now();
+        HookRunner::new(&config).run(PRE_SPAWN).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_output_tail_cap() {
+        let mut tail = OutputTail::default();
+        for i in 0..OUTPUT_CAP {
+            tail.push(format!("line {}", i));
+        }
+
+        assert!(tail.bytes <= OUTPUT_CAP);
+        let output = tail.render();
+        assert!(output.starts_with("...\n"));
+        assert!(output.ends_with(&format!("line {}\n", OUTPUT_CAP - 1)));
+
+        let mut tail = OutputTail::default();
+        tail.push("x".repeat(OUTPUT_CAP * 2));
+        assert_eq!(tail.lines[0].len(), OUTPUT_CAP - 1);
+    }
+
+    #[test]
+    fn test_hooks_user_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("env");
+        let mut config = hooks(
+            Some(&format!(
+                "echo \"$HOME $USER $EXTRA\" > '{}'",
+                out.display()
+            )),
+            None,
+        );
+        config.env.insert("EXTRA".to_string(), EnvValue::Integer(1));
+
+        let user = OriginalUser {
+            uid: 1000,
+            name: "gamer".to_string(),
+            home: "/home/gamer".into(),
+        };
+        let runner = HookRunner::with_user(&config, Some(&user));
+        runner.run(PRE_SPAWN).unwrap();
+
+        let env = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(env.trim(), "/home/gamer gamer 1");
+    }
+}
@@ -0,0 +1,220 @@
+use log::{debug, warn};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::common::config::VulkanDriver;
+
+/// Names distributions give the 32-bit NVIDIA manifest next to the 64-bit one
+const NVIDIA_ICD32_NAMES: &[&str] = &[
+    "nvidia_icd.i686.json",
+    "nvidia_icd32.json",
+    "nvidia_icd.i386.json",
+];
+
+/// NVIDIA offload variables, Mesa goes by `DRI_PRIME` instead
+const NVIDIA_OFFLOAD_VARS: &[&str] = &[
+    "__GLX_VENDOR_LIBRARY_NAME",
+    "__NV_PRIME_RENDER_OFFLOAD",
+    "__VK_LAYER_NV_optimus",
+];
+
+/// Start of the manifest names of a driver, followed by the architecture
+/// on most distributions
+fn manifest_prefix(driver: VulkanDriver) -> &'static str {
+    match driver {
+        VulkanDriver::NvidiaProprietary => "nvidia_icd",
+        VulkanDriver::Nvk => "nouveau_icd",
+    }
+}
+
+fn install_hint(driver: VulkanDriver) -> &'static str {
+    match driver {
+        VulkanDriver::NvidiaProprietary => "the NVIDIA userspace driver",
+        VulkanDriver::Nvk => "Mesa's Vulkan nouveau driver",
+    }
+}
+
+/// ICD manifests in `dir`, sorted
+pub fn installed(dir: &Path) -> Vec<PathBuf> {
+    let mut manifests: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    manifests.sort();
+    manifests
+}
+
+/// The 32-bit NVIDIA manifest installed in `dir`, if any
+pub fn detect_nvidia_icd32(dir: &Path) -> Option<PathBuf> {
+    NVIDIA_ICD32_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// `VK_ICD_FILENAMES` for the NVIDIA manifests, `gpu_vlk_icd` first and then
+/// `gpu_vlk_icd32` or the 32-bit manifest found in `dir`. A 32-bit manifest
+/// that does not exist is left out, the launch would fail on it.
+pub fn nvidia_icd_files(icd: &str, icd32: Option<&str>, dir: &Path) -> String {
+    if !Path::new(icd).is_file() {
+        warn!("gpu_vlk_icd {} does not exist", icd);
+    }
+
+    let icd32 = match icd32 {
+        Some(path) if Path::new(path).is_file() => Some(PathBuf::from(path)),
+        Some(path) => {
+            warn!("gpu_vlk_icd32 {} does not exist, leaving it out", path);
+            None
+        }
+        None => detect_nvidia_icd32(dir),
+    };
+
+    match icd32 {
+        Some(icd32) => format!("{}:{}", icd, icd32.display()),
+        None => {
+            // A manifest named for one architecture means the distribution
+            // splits them, one without an architecture serves both
+            if icd.contains("x86_64") {
+                warn!(
+                    "Only the 64-bit Vulkan ICD {} is configured, 32-bit games will not find the GPU, set gpu_vlk_icd32",
+                    icd
+                );
+            }
+            icd.to_string()
+        }
+    }
+}
+
+/// Point the Vulkan loader at the driver's manifests in `dir`, for NVK
+/// swapping the NVIDIA offload variables for Mesa's
+pub fn apply(
+    vars: &mut BTreeMap<String, String>,
+    driver: VulkanDriver,
+    dir: &Path,
+) -> anyhow::Result<()> {
+    let installed = installed(dir);
+    let prefix = manifest_prefix(driver);
+    let manifests: Vec<String> = installed
+        .iter()
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(prefix))
+        })
+        .map(|path| path.display().to_string())
+        .collect();
+
+    if manifests.is_empty() {
+        let names: Vec<String> = installed
+            .iter()
+            .filter_map(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        anyhow::bail!(
+            "No {}*.json in {} for vulkan_driver, install {} (found: {})",
+            prefix,
+            dir.display(),
+            install_hint(driver),
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(", ")
+            }
+        );
+    }
+
+    debug!("Vulkan driver manifests: {:?}", manifests);
+    vars.insert("VK_ICD_FILENAMES".to_string(), manifests.join(":"));
+    // Newer loaders prefer it over VK_ICD_FILENAMES
+    vars.remove("VK_DRIVER_FILES");
+
+    if driver == VulkanDriver::Nvk {
+        for key in NVIDIA_OFFLOAD_VARS {
+            vars.remove(*key);
+        }
+        vars.insert("DRI_PRIME".to_string(), "1".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_vulkan_driver() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "nvidia_icd.json",
+            "nouveau_icd.x86_64.json",
+            "nouveau_icd.i686.json",
+            "README",
+        ] {
+            std::fs::write(dir.path().join(name), "{}").unwrap();
+        }
+        let defaults = || {
+            BTreeMap::from([
+                (
+                    "__GLX_VENDOR_LIBRARY_NAME".to_string(),
+                    "nvidia".to_string(),
+                ),
+                ("VK_DRIVER_FILES".to_string(), "/old.json".to_string()),
+            ])
+        };
+
+        let mut vars = defaults();
+        apply(&mut vars, VulkanDriver::Nvk, dir.path()).unwrap();
+        assert_eq!(
+            vars["VK_ICD_FILENAMES"],
+            format!(
+                "{0}/nouveau_icd.i686.json:{0}/nouveau_icd.x86_64.json",
+                dir.path().display()
+            )
+        );
+        assert_eq!(vars["DRI_PRIME"], "1");
+        assert!(!vars.contains_key("__GLX_VENDOR_LIBRARY_NAME"));
+        assert!(!vars.contains_key("VK_DRIVER_FILES"));
+
+        let mut vars = defaults();
+        apply(&mut vars, VulkanDriver::NvidiaProprietary, dir.path()).unwrap();
+        assert!(vars["VK_ICD_FILENAMES"].ends_with("/nvidia_icd.json"));
+        assert_eq!(vars["__GLX_VENDOR_LIBRARY_NAME"], "nvidia");
+    }
+
+    #[test]
+    fn test_nvidia_icd_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let icd = dir.path().join("nvidia_icd.x86_64.json");
+        std::fs::write(&icd, "{}").unwrap();
+        let icd = icd.display().to_string();
+
+        assert_eq!(nvidia_icd_files(&icd, None, dir.path()), icd);
+        assert_eq!(
+            nvidia_icd_files(&icd, Some("/missing/nvidia_icd32.json"), dir.path()),
+            icd
+        );
+
+        let icd32 = dir.path().join("nvidia_icd.i686.json");
+        std::fs::write(&icd32, "{}").unwrap();
+        let joined = format!("{}:{}", icd, icd32.display());
+        assert_eq!(nvidia_icd_files(&icd, None, dir.path()), joined);
+        assert_eq!(
+            nvidia_icd_files(&icd, Some(&icd32.display().to_string()), dir.path()),
+            joined
+        );
+    }
+
+    #[test]
+    fn test_apply_missing_driver() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("intel_icd.x86_64.json"), "{}").unwrap();
+
+        let mut vars = BTreeMap::new();
+        let err = apply(&mut vars, VulkanDriver::Nvk, dir.path()).unwrap_err();
+        assert!(err.to_string().contains("found: intel_icd.x86_64.json"));
+        assert!(vars.is_empty());
+    }
+}
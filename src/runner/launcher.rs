@@ -1,46 +1,239 @@
+use anyhow::Context;
 use log::{debug, error, info, warn};
-use std::collections::BTreeMap;
-use std::path::Path;
-use std::process::{Child, Command, Stdio};
-
-use crate::common::Config;
-use crate::runner::EnvBuilder;
+use nix::errno::Errno;
+use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
+use nix::unistd::Pid;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::{CString, OsString};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::common::config::{GameConfig, LaunchBackend};
+use crate::common::resolve::GameHints;
+use crate::common::{Config, DriverVersion, paths, proc};
+use crate::runner::{
+    EnvBuilder, SwitcherooGpu, capture, display, drives, dxvk, icd, mangohud, prefix, search_path,
+};
+
+/// Set in the game's environment to the PID of the nvprime running it, so
+/// an nvprime started again further down the wrapper chain passes through
+pub const ACTIVE_ENV: &str = "NVPRIME_ACTIVE";
+
+/// Variables naming driver manifests the game cannot start without
+const CRITICAL_FILE_VARS: &[&str] = &["VK_ICD_FILENAMES", "VK_DRIVER_FILES"];
+
+/// How often the orphans left by the game are checked on
+const ORPHAN_POLL: Duration = Duration::from_millis(200);
 
 pub struct Launcher {
+    name: String,
+    exe_name: String,
     exec: String,
     args: Vec<String>,
     vars: BTreeMap<String, String>,
+    game: GameConfig,
+    path_warnings: Vec<String>,
+    capture_dir: Option<PathBuf>,
+    driver_error: Option<String>,
+    env_overrides: BTreeMap<String, String>,
     child: Option<Child>,
+    spawn_time: Option<Duration>,
+
+    /// Children nvprime already had when the game was spawned, which are
+    /// not the game's orphans
+    prior_children: BTreeSet<u32>,
 }
 
 impl Launcher {
     pub fn new(args: Vec<String>, config: &Config) -> Self {
-        let game_exec = detect_game_exec(&args);
-        let vars = EnvBuilder::new().with_config(config, &game_exec);
+        let game_path = detect_game_path(&args);
+        let hints = GameHints::new(&extract_stem(game_path), game_path)
+            .with_env(|key| std::env::var(key).ok());
+        let game_exec = config.resolve_game(&hints);
+        let exe_name = Path::new(game_path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut vars = EnvBuilder::new()
+            .with_driver(DriverVersion::detect())
+            .with_switcheroo(
+                config
+                    .gpu
+                    .prefer_switcheroo
+                    .then(SwitcherooGpu::detect)
+                    .flatten(),
+            )
+            .with_config(config, &game_exec);
+        let game = config.game.get(&game_exec).cloned().unwrap_or_default();
+        let mut path_warnings = search_path::apply(&mut vars, &game, |key| std::env::var(key).ok());
+        if !game.drives.is_empty() {
+            let prefix = prefix::find_prefix(&game, |key| std::env::var(key).ok());
+            path_warnings.extend(drives::apply(
+                &mut vars,
+                &game.drives,
+                prefix.as_deref(),
+                |key| std::env::var(key).ok(),
+            ));
+        }
+
+        let driver_error = game.vulkan_driver.and_then(|driver| {
+            icd::apply(&mut vars, driver, Path::new(paths::ICD_DIR))
+                .err()
+                .map(|e| format!("{:#}", e))
+        });
+
+        mangohud::enable_control(&mut vars);
+
+        // The game still runs without its DXVK options
+        if !dxvk::options(&game.dxvk).is_empty() {
+            let written = config.paths().and_then(|paths| {
+                let dir = paths.runtime_dir.join("dxvk");
+                dxvk::apply(&mut vars, &game.dxvk, &game_exec, &dir, |key| {
+                    std::env::var(key).ok()
+                })
+            });
+            if let Err(e) = written {
+                warn!("{:#}, the game's DXVK options are not applied", e);
+            }
+        }
+
+        let capture_dir = game
+            .capture
+            .enable
+            .then(|| {
+                config.paths().and_then(|paths| {
+                    capture::apply(&mut vars, &game.capture, &game_exec, &paths.captures_dir())
+                })
+            })
+            .and_then(|created| {
+                created
+                    .inspect_err(|e| warn!("{:#}, the game has no capture directory", e))
+                    .ok()
+            });
 
         debug!("Raw argument from Steam: {:?}", args);
         debug!("Detected game executable: {}", game_exec);
 
+        let mut args = match &game.command_template {
+            Some(template) => apply_template(template, args),
+            None => args,
+        };
+
+        if let Some(output) = &game.preferred_output
+            && let Some(warning) =
+                display::apply(&mut vars, &mut args, output, Path::new(display::DRM_DIR))
+        {
+            warn!("{}", warning);
+            path_warnings.push(warning);
+        }
+
         Launcher {
+            name: game_exec,
+            exe_name,
             exec: args[0].clone(),
             args: args[1..].to_vec(),
             vars,
+            game,
+            path_warnings,
+            capture_dir,
+            driver_error,
+            env_overrides: BTreeMap::new(),
             child: None,
+            spawn_time: None,
+            prior_children: BTreeSet::new(),
+        }
+    }
+
+    /// Name of the detected game, used to look up its config sections
+    pub fn game_name(&self) -> &str {
+        &self.name
+    }
+
+    /// File name of the game's executable, what its process is called
+    pub fn exe_name(&self) -> &str {
+        &self.exe_name
+    }
+
+    /// Program that is executed, after the `command_template`
+    pub fn exec(&self) -> &str {
+        &self.exec
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Resolved `[game.<name>]` config, defaults when the game has none
+    pub fn game_config(&self) -> &GameConfig {
+        &self.game
+    }
+
+    /// Environment the game is launched with on top of the inherited one
+    pub fn env_vars(&self) -> &BTreeMap<String, String> {
+        &self.vars
+    }
+
+    /// Set variables over everything the config resolved, for a single
+    /// launch
+    pub fn override_env(&mut self, overrides: &[(String, String)]) {
+        for (key, val) in overrides {
+            info!("Overriding {}={} for this launch", key, val);
+            self.vars.insert(key.clone(), val.clone());
+            self.env_overrides.insert(key.clone(), val.clone());
         }
     }
 
+    /// Variables given on the command line for this launch
+    pub fn env_overrides(&self) -> &BTreeMap<String, String> {
+        &self.env_overrides
+    }
+
+    /// Search path entries, drives and the display output that were left
+    /// out or are likely of no use
+    pub fn path_warnings(&self) -> &[String] {
+        &self.path_warnings
+    }
+
+    /// The game's capture directory when `[game.<name>.capture]` enables it
+    pub fn capture_dir(&self) -> Option<&Path> {
+        self.capture_dir.as_deref()
+    }
+
+    /// Why the game's `vulkan_driver` could not be selected, it would run
+    /// on another driver than asked for
+    pub fn driver_error(&self) -> Option<&str> {
+        self.driver_error.as_deref()
+    }
+
+    /// How long the first spawn took until the program was executed
+    pub fn spawn_time(&self) -> Option<Duration> {
+        self.spawn_time
+    }
+
     /// Spawns the process but does not wait for it.
     /// Returns the PID of the spawned process.
     pub fn spawn(&mut self) -> anyhow::Result<u32> {
         debug!("Running process '{}' with args: {:?}", self.exec, self.args);
+
+        if self.game.subreaper {
+            nix::sys::prctl::set_child_subreaper(true)
+                .context("Failed to become a child subreaper")?;
+            self.prior_children = proc::children(std::process::id());
+        }
+
         debug!("Setting environment variables from configs:");
         for (key, val) in &self.vars {
             debug!("  ENV: '{}' with '{}'", key, val);
         }
 
-        let child = Command::new(&self.exec)
-            .args(&self.args)
-            .envs(&self.vars)
+        let started = Instant::now();
+        let mut cmd = backend_command(&self.game, &self.exec, &self.args, &self.vars)?;
+        cmd.env(ACTIVE_ENV, std::process::id().to_string());
+        debug!("Launching with {:?} backend: {:?}", self.game.backend, cmd);
+
+        let child = cmd
             .stdin(Stdio::inherit())
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
@@ -50,6 +243,8 @@ impl Launcher {
                 anyhow::anyhow!(e)
             })?;
 
+        // spawn only returns once exec succeeded or failed
+        self.spawn_time.get_or_insert(started.elapsed());
         let pid = child.id();
         info!("Spawned process '{}' with PID {}", self.exec, pid);
         self.child = Some(child);
@@ -69,7 +264,7 @@ impl Launcher {
                 anyhow::anyhow!(e)
             })?;
 
-            let exit_code = status.code().unwrap_or(-1);
+            let exit_code = exit_code(status);
             if status.success() {
                 info!(
                     "Process PID {} completed successfully with exit code {}",
@@ -83,20 +278,280 @@ impl Launcher {
                     exit_code
                 );
             }
+
+            if self.game.subreaper {
+                let orphans = reap_orphans(&self.prior_children);
+                if orphans > 0 {
+                    info!("Waited for {} processes left by the launcher", orphans);
+                }
+            }
             Ok(exit_code)
         } else {
             Err(anyhow::anyhow!("No running process to wait for"))
         }
     }
 
+    /// Checks whether the spawned process has exited without blocking.
+    /// Returns its exit code once it has.
+    pub fn try_wait(&mut self) -> anyhow::Result<Option<i32>> {
+        let child = self
+            .child
+            .as_mut()
+            .context("No running process to wait for")?;
+
+        let status = child
+            .try_wait()
+            .with_context(|| format!("Failed checking on process PID {}", child.id()))?;
+
+        Ok(status.map(exit_code))
+    }
+
     /// Combined spawn and wait function for convenience.
-    pub fn execute(&mut self) -> anyhow::Result<i32> {
-        self.spawn()?;
-        self.wait()
+    /// Restarts the process on unexpected exit codes when the game config asks for it.
+    /// `on_spawn` gets the PID of every start, restarts included.
+    pub fn execute(&mut self, mut on_spawn: impl FnMut(u32)) -> anyhow::Result<i32> {
+        let mut restarts = 0;
+
+        loop {
+            on_spawn(self.spawn()?);
+            let exit_code = self.wait()?;
+
+            if !self.should_restart(exit_code, restarts) {
+                return Ok(exit_code);
+            }
+
+            restarts += 1;
+            warn!(
+                "Process '{}' crashed with exit code {}, restarting (attempt {}/{})",
+                self.exec, exit_code, restarts, self.game.max_restarts
+            );
+        }
+    }
+
+    fn should_restart(&self, exit_code: i32, restarts: u32) -> bool {
+        if !self.game.restart_on_crash || self.game.is_allowed_exit(exit_code) {
+            return false;
+        }
+
+        if restarts >= self.game.max_restarts {
+            error!(
+                "Process '{}' exited with code {} after {} restarts, giving up",
+                self.exec, exit_code, restarts
+            );
+            return false;
+        }
+
+        true
+    }
+}
+
+/// Rewrite the command line with a `command_template`, keeping the original
+/// when the template expands to nothing
+fn apply_template(template: &str, argv: Vec<String>) -> Vec<String> {
+    if !["{command}", "{exe}"].iter().any(|p| template.contains(p)) {
+        warn!(
+            "command_template '{}' runs neither {{command}} nor {{exe}}",
+            template
+        );
+    }
+
+    let expanded = expand_template(template, &argv);
+    if expanded.is_empty() {
+        warn!("command_template is empty, running the original command");
+        return argv;
+    }
+
+    debug!("Command after template: {:?}", expanded);
+    expanded
+}
+
+/// A word that is just a placeholder expands to one argument per value,
+/// placeholders inside a word are replaced with the values joined by spaces
+fn expand_template(template: &str, argv: &[String]) -> Vec<String> {
+    let exe = argv.first().map(String::as_str).unwrap_or_default();
+    let args = argv.get(1..).unwrap_or_default();
+
+    template
+        .split_whitespace()
+        .flat_map(|word| match word {
+            "{command}" => argv.to_vec(),
+            "{exe}" => vec![exe.to_string()],
+            "{args}" => args.to_vec(),
+            _ => vec![
+                word.replace("{command}", &argv.join(" "))
+                    .replace("{exe}", exe)
+                    .replace("{args}", &args.join(" ")),
+            ],
+        })
+        .collect()
+}
+
+/// PID of the nvprime session this one runs inside of, when the game's
+/// command line or a wrapper starts nvprime a second time
+pub fn outer_session(env: impl Fn(&str) -> Option<String>) -> Option<u32> {
+    env(ACTIVE_ENV)?.trim().parse().ok()
+}
+
+/// Replace this process with the command as given, the outer session
+/// already tuned the system and set the environment. Only returns on error.
+pub fn exec_nested(args: &[String], outer: u32) -> anyhow::Error {
+    let Some((exec, args)) = args.split_first() else {
+        return anyhow::anyhow!("No command to run");
+    };
+
+    info!(
+        "Running inside nvprime session {}, starting '{}' without tuning",
+        outer, exec
+    );
+    let e = Command::new(exec).args(args).exec();
+    anyhow::anyhow!(e).context(format!("Failed to execute {}", exec))
+}
+
+/// Command starting the game through its backend. Flatpak and podman do not
+/// pass the environment into the sandbox, so it goes in through their options.
+fn backend_command(
+    game: &GameConfig,
+    exec: &str,
+    args: &[String],
+    vars: &BTreeMap<String, String>,
+) -> anyhow::Result<Command> {
+    let mut cmd = match game.backend {
+        LaunchBackend::Exec => Command::new(exec),
+        LaunchBackend::Flatpak => {
+            let mut cmd = Command::new("flatpak");
+            cmd.arg("run");
+            cmd.args(
+                vars.iter()
+                    .map(|(key, val)| format!("--env={}={}", key, val)),
+            );
+            cmd.arg(exec);
+            cmd
+        }
+        LaunchBackend::Podman => {
+            let image = game
+                .container_image
+                .as_deref()
+                .context("The podman backend needs container_image")?;
+
+            let mut cmd = Command::new("podman");
+            cmd.args(["run", "--rm", "--interactive"]);
+            // GPU through the NVIDIA Container Toolkit's CDI spec
+            cmd.args(["--device", "nvidia.com/gpu=all"]);
+            cmd.args(["--security-opt", "label=disable"]);
+            // The host's X server, a bare name passes the host's value
+            cmd.args(["--volume", "/tmp/.X11-unix:/tmp/.X11-unix"]);
+            cmd.args(["--env", "DISPLAY"]);
+            for (key, val) in vars {
+                cmd.args(["--env", &format!("{}={}", key, val)]);
+            }
+            cmd.args(&game.container_args);
+            cmd.args([image, exec]);
+            cmd
+        }
+    };
+
+    cmd.args(args);
+    if game.strict_env {
+        let check_files = game.backend == LaunchBackend::Exec;
+        strict_env(&mut cmd, vars, std::env::vars_os(), check_files)?;
+    } else {
+        cmd.envs(vars);
+    }
+    Ok(cmd)
+}
+
+/// Give the command exactly the inherited environment plus `vars`. With
+/// `check_files` the driver manifests named in `vars` are checked again in
+/// the child right before it executes, so the game never starts without them.
+fn strict_env(
+    cmd: &mut Command,
+    vars: &BTreeMap<String, String>,
+    inherited: impl IntoIterator<Item = (OsString, OsString)>,
+    check_files: bool,
+) -> anyhow::Result<()> {
+    let mut env: BTreeMap<OsString, OsString> = inherited.into_iter().collect();
+    env.extend(vars.iter().map(|(key, val)| (key.into(), val.into())));
+    cmd.env_clear().envs(&env);
+
+    if !check_files {
+        return Ok(());
+    }
+
+    let mut files = Vec::new();
+    for key in CRITICAL_FILE_VARS {
+        for file in vars.get(*key).into_iter().flat_map(|val| val.split(':')) {
+            if file.is_empty() {
+                continue;
+            }
+            anyhow::ensure!(
+                Path::new(file).exists(),
+                "{} names {}, which does not exist",
+                key,
+                file
+            );
+            files.push(CString::new(file).with_context(|| format!("Invalid path in {}", key))?);
+        }
+    }
+
+    // Runs between fork and exec, so it must not allocate
+    let check = move || {
+        for file in &files {
+            if unsafe { libc::access(file.as_ptr(), libc::R_OK) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    };
+    unsafe { cmd.pre_exec(check) };
+    Ok(())
+}
+
+/// Exit code of the game, a shell's 128 plus the signal when it was killed
+fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(-1)
+}
+
+/// Block until every orphan reparented to nvprime as subreaper has exited,
+/// returns how many were reaped. Only the children that showed up after
+/// `prior` was taken are waited on, nvprime's own are left to their owners.
+fn reap_orphans(prior: &BTreeSet<u32>) -> usize {
+    let mut reaped = 0;
+    let mut lost = BTreeSet::new();
+    loop {
+        let orphans: Vec<u32> = proc::children(std::process::id())
+            .into_iter()
+            .filter(|pid| !prior.contains(pid) && !lost.contains(pid))
+            .collect();
+        if orphans.is_empty() {
+            return reaped;
+        }
+
+        for pid in orphans {
+            match waitpid(Pid::from_raw(pid as i32), Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::StillAlive) | Err(Errno::EINTR) => {}
+                Ok(status) => {
+                    debug!("Reaped orphaned process: {:?}", status);
+                    reaped += 1;
+                }
+                Err(e) => {
+                    debug!("Not waiting for PID {}: {}", pid, e);
+                    lost.insert(pid);
+                }
+            }
+        }
+        std::thread::sleep(ORPHAN_POLL);
     }
 }
 
-fn detect_game_exec(args: &[String]) -> String {
+pub fn detect_game_exec(args: &[String]) -> String {
+    extract_stem(detect_game_path(args))
+}
+
+/// The argument holding the game's executable, as passed by Steam
+pub fn detect_game_path(args: &[String]) -> &str {
     debug!("Detecting game executable from args");
 
     if let Some(i) = args.iter().position(|arg| arg == "waitforexitandrun")
@@ -106,9 +561,8 @@ fn detect_game_exec(args: &[String]) -> String {
             .skip(i + 1)
             .find(|(_, arg)| arg.ends_with(".exe"))
     {
-        let name = extract_stem(exe_arg);
-        debug!("Detected game '{}' via waitforexitandrun", name);
-        return name;
+        debug!("Detected game '{}' via waitforexitandrun", exe_arg);
+        return exe_arg;
     }
 
     if let Some((_, exe_arg)) = args
@@ -117,14 +571,12 @@ fn detect_game_exec(args: &[String]) -> String {
         .rev()
         .find(|(_, arg)| arg.ends_with(".exe"))
     {
-        let name = extract_stem(exe_arg);
-        debug!("Detected game '{}' via .exe scan", name);
-        return name;
+        debug!("Detected game '{}' via .exe scan", exe_arg);
+        return exe_arg;
     }
 
-    let name = extract_stem(&args[0]);
-    debug!("Using fallback executable name '{}'", name);
-    name
+    debug!("Using fallback executable '{}'", args[0]);
+    &args[0]
 }
 
 fn extract_stem(path: &str) -> String {
@@ -139,6 +591,17 @@ fn extract_stem(path: &str) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_outer_session() {
+        assert_eq!(outer_session(|_| Some("4242".to_string())), Some(4242));
+        assert_eq!(outer_session(|_| Some(String::new())), None);
+        assert_eq!(outer_session(|_| None), None);
+
+        let err = exec_nested(&["/nonexistent/game".to_string()], 4242);
+        assert!(err.to_string().contains("/nonexistent/game"));
+        assert!(exec_nested(&[], 4242).to_string().contains("No command"));
+    }
+
     #[test]
     fn test_extract_stem_simple() {
         assert_eq!(extract_stem("game.exe"), "game");
@@ -234,6 +697,130 @@ mod tests {
         assert_eq!(detect_game_exec(&args), "finalfantasy");
     }
 
+    #[test]
+    fn test_expand_template() {
+        let argv: Vec<String> = ["/game/run", "-fullscreen", "-dx12"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        assert_eq!(
+            expand_template("gamescope -W 3440 -H 1440 -- {command}", &argv),
+            [
+                "gamescope",
+                "-W",
+                "3440",
+                "-H",
+                "1440",
+                "--",
+                "/game/run",
+                "-fullscreen",
+                "-dx12"
+            ]
+        );
+        assert_eq!(
+            expand_template("{exe} -skipintro {args}", &argv),
+            ["/game/run", "-skipintro", "-fullscreen", "-dx12"]
+        );
+        assert_eq!(
+            expand_template("sh -c --launch={exe}", &argv),
+            ["sh", "-c", "--launch=/game/run"]
+        );
+        assert!(expand_template("  ", &argv).is_empty());
+    }
+
+    #[test]
+    fn test_launcher_command_template() {
+        let mut config = create_test_config();
+        config.game.insert(
+            "game".to_string(),
+            GameConfig {
+                command_template: Some("gamemoderun {command}".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let args = vec!["game.exe".to_string(), "-windowed".to_string()];
+        let launcher = Launcher::new(args, &config);
+        assert_eq!(launcher.game_name(), "game");
+        assert_eq!(launcher.exec, "gamemoderun");
+        assert_eq!(launcher.args, ["game.exe", "-windowed"]);
+    }
+
+    #[test]
+    fn test_backend_command() {
+        let vars = BTreeMap::from([("MANGOHUD".to_string(), "1".to_string())]);
+        let args = vec!["-windowed".to_string()];
+        let cmd_args = |cmd: &Command| -> Vec<String> {
+            cmd.get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        };
+
+        let mut game = GameConfig::default();
+        let cmd = backend_command(&game, "game.AppImage", &args, &vars).unwrap();
+        assert_eq!(cmd.get_program(), "game.AppImage");
+        assert_eq!(cmd_args(&cmd), ["-windowed"]);
+
+        game.backend = LaunchBackend::Flatpak;
+        let cmd = backend_command(&game, "com.example.Game", &args, &vars).unwrap();
+        assert_eq!(cmd.get_program(), "flatpak");
+        assert_eq!(
+            cmd_args(&cmd),
+            ["run", "--env=MANGOHUD=1", "com.example.Game", "-windowed"]
+        );
+
+        game.backend = LaunchBackend::Podman;
+        assert!(backend_command(&game, "/game/run.sh", &args, &vars).is_err());
+
+        game.container_image = Some("localhost/game".to_string());
+        game.container_args = vec!["--volume=/games:/games".to_string()];
+        let cmd = backend_command(&game, "/game/run.sh", &args, &vars).unwrap();
+        let podman = cmd_args(&cmd);
+        assert_eq!(cmd.get_program(), "podman");
+        assert!(
+            podman
+                .windows(2)
+                .any(|w| w == ["--device", "nvidia.com/gpu=all"])
+        );
+        assert!(podman.windows(2).any(|w| w == ["--env", "MANGOHUD=1"]));
+        assert!(podman.ends_with(&[
+            "--volume=/games:/games".to_string(),
+            "localhost/game".to_string(),
+            "/game/run.sh".to_string(),
+            "-windowed".to_string(),
+        ]));
+    }
+
+    #[test]
+    fn test_strict_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let icd = dir.path().join("nvidia_icd.json");
+        std::fs::write(&icd, "{}").unwrap();
+
+        let vars = BTreeMap::from([
+            ("MANGOHUD".to_string(), "1".to_string()),
+            ("VK_ICD_FILENAMES".to_string(), icd.display().to_string()),
+        ]);
+        let inherited = [
+            ("HOME".into(), "/home/user".into()),
+            ("MANGOHUD".into(), "0".into()),
+        ];
+
+        let mut cmd = Command::new("true");
+        strict_env(&mut cmd, &vars, inherited.clone(), true).unwrap();
+        let envs: Vec<_> = cmd.get_envs().collect();
+        assert_eq!(envs.len(), 3);
+        assert!(envs.contains(&("MANGOHUD".as_ref(), Some("1".as_ref()))));
+        assert!(envs.contains(&("HOME".as_ref(), Some("/home/user".as_ref()))));
+        assert!(cmd.status().unwrap().success());
+
+        // Gone between building the command and the exec
+        std::fs::remove_file(&icd).unwrap();
+        assert!(cmd.status().is_err());
+        assert!(strict_env(&mut Command::new("true"), &vars, inherited, true).is_err());
+    }
+
     fn create_test_config() -> Config {
         Config {
             cpu: Default::default(),
@@ -242,6 +829,12 @@ mod tests {
             env: Default::default(),
             game: Default::default(),
             hook: Default::default(),
+            daemon: Default::default(),
+            paths: Default::default(),
+            schedule: Default::default(),
+            updates: Default::default(),
+            budget: Default::default(),
+            resolve: Default::default(),
         }
     }
 
@@ -256,6 +849,7 @@ mod tests {
 
         let launcher = Launcher::new(args.clone(), &config);
 
+        assert_eq!(launcher.game_name(), "game");
         assert_eq!(launcher.exec, "game.exe");
         assert_eq!(launcher.args, vec!["arg1".to_string(), "arg2".to_string()]);
         assert!(!launcher.vars.is_empty());
@@ -273,6 +867,77 @@ mod tests {
         assert!(launcher.args.is_empty());
     }
 
+    #[test]
+    fn test_launcher_should_restart() {
+        let mut config = create_test_config();
+        config.game.insert(
+            "game".to_string(),
+            GameConfig {
+                restart_on_crash: true,
+                max_restarts: 2,
+                allowed_exit_codes: vec![5],
+                ..Default::default()
+            },
+        );
+
+        let launcher = Launcher::new(vec!["game.exe".to_string()], &config);
+        assert!(launcher.should_restart(1, 0));
+        assert!(launcher.should_restart(-1, 1));
+        assert!(!launcher.should_restart(1, 2));
+        assert!(!launcher.should_restart(0, 0));
+        assert!(!launcher.should_restart(5, 0));
+    }
+
+    #[test]
+    fn test_launcher_should_restart_disabled() {
+        let config = create_test_config();
+        let launcher = Launcher::new(vec!["game.exe".to_string()], &config);
+        assert!(!launcher.should_restart(1, 0));
+    }
+
+    #[test]
+    fn test_launcher_execute_restarts_until_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let counter = dir.path().join("runs");
+        let script = format!("echo run >> '{}'; exit 3", counter.display());
+
+        let mut config = create_test_config();
+        config.game.insert(
+            "sh".to_string(),
+            GameConfig {
+                restart_on_crash: true,
+                max_restarts: 2,
+                ..Default::default()
+            },
+        );
+
+        let args = vec!["sh".to_string(), "-c".to_string(), script];
+        let mut launcher = Launcher::new(args, &config);
+
+        let mut spawned = 0;
+        assert_eq!(launcher.execute(|_| spawned += 1).unwrap(), 3);
+        let runs = std::fs::read_to_string(&counter).unwrap();
+        assert_eq!(runs.lines().count(), 3);
+        assert_eq!(spawned, 3);
+    }
+
+    #[test]
+    fn test_launcher_try_wait() {
+        let config = create_test_config();
+        let args = vec!["sh".to_string(), "-c".to_string(), "exit 4".to_string()];
+        let mut launcher = Launcher::new(args, &config);
+        assert!(launcher.try_wait().is_err());
+
+        launcher.spawn().unwrap();
+        let exit_code = loop {
+            if let Some(code) = launcher.try_wait().unwrap() {
+                break code;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        };
+        assert_eq!(exit_code, 4);
+    }
+
     #[test]
     fn test_launcher_wait_without_spawn() {
         let args = vec!["test".to_string()];
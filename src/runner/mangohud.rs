@@ -0,0 +1,134 @@
+use anyhow::Context;
+use log::{debug, info};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixStream};
+
+use crate::common::proc;
+use crate::runner::env_var::{HUD, HUD_CFG};
+
+/// Abstract socket MangoHud opens with `control=`, `%p` becomes the PID of
+/// the process it is loaded into
+const CONTROL_NAME: &str = "mangohud-%p";
+
+const SOCKET_PREFIX: &str = "mangohud-";
+
+/// MangoHud's preset with every metric shown
+const DETAILED_PRESET: u32 = 4;
+
+/// What the overlay of a running game is told to do
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HudCommand {
+    /// Show or hide the overlay
+    Toggle,
+
+    /// Switch to the preset with every metric
+    Detailed,
+}
+
+impl HudCommand {
+    /// Message in MangoHud's control protocol, `:<command>[=<param>];`
+    fn message(&self) -> String {
+        match self {
+            Self::Toggle => ":hud;".to_string(),
+            Self::Detailed => format!(":preset={};", DETAILED_PRESET),
+        }
+    }
+}
+
+/// Have the overlay listen on a control socket when the game runs with it
+pub fn enable_control(vars: &mut BTreeMap<String, String>) {
+    if vars.get(HUD).map(String::as_str) != Some("1") {
+        return;
+    }
+
+    let config = vars.entry(HUD_CFG.to_string()).or_default();
+    if config
+        .split(',')
+        .any(|option| option.starts_with("control="))
+    {
+        return;
+    }
+    if !config.is_empty() {
+        config.push(',');
+    }
+    config.push_str(&format!("control={}", CONTROL_NAME));
+}
+
+/// PIDs with a MangoHud control socket in `/proc/net/unix` content
+fn socket_pids(net_unix: &str) -> Vec<u32> {
+    let mut pids: Vec<u32> = net_unix
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(7))
+        .filter_map(|path| path.strip_prefix('@')?.strip_prefix(SOCKET_PREFIX))
+        .filter_map(|pid| pid.parse().ok())
+        .collect();
+    pids.sort();
+    pids.dedup();
+    pids
+}
+
+/// Send a command to the overlays of `pid` and its descendants, or of every
+/// game when unset. Returns the PIDs it reached.
+pub fn send(command: HudCommand, pid: Option<u32>) -> anyhow::Result<Vec<u32>> {
+    let net_unix =
+        std::fs::read_to_string("/proc/net/unix").context("Failed to list UNIX sockets")?;
+    let targets: Vec<u32> = socket_pids(&net_unix)
+        .into_iter()
+        .filter(|target| pid.is_none_or(|pid| proc::is_descendant(*target, pid)))
+        .collect();
+    anyhow::ensure!(
+        !targets.is_empty(),
+        "No MangoHud control socket found, is the game running with mangohud = true?"
+    );
+
+    for target in &targets {
+        let name = format!("{}{}", SOCKET_PREFIX, target);
+        let addr = SocketAddr::from_abstract_name(&name)?;
+        let mut stream = UnixStream::connect_addr(&addr)
+            .with_context(|| format!("Failed to connect to MangoHud of PID {}", target))?;
+        stream
+            .write_all(command.message().as_bytes())
+            .with_context(|| format!("Failed to send to MangoHud of PID {}", target))?;
+        debug!("Sent {:?} to MangoHud of PID {}", command, target);
+    }
+    info!("MangoHud {:?} sent to PIDs {:?}", command, targets);
+    Ok(targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enable_control() {
+        let mut vars = BTreeMap::from([(HUD.to_string(), "1".to_string())]);
+        enable_control(&mut vars);
+        assert_eq!(vars[HUD_CFG], "control=mangohud-%p");
+
+        vars.insert(HUD_CFG.to_string(), "fps_only".to_string());
+        enable_control(&mut vars);
+        enable_control(&mut vars);
+        assert_eq!(vars[HUD_CFG], "fps_only,control=mangohud-%p");
+
+        let mut vars = BTreeMap::from([(HUD.to_string(), "0".to_string())]);
+        enable_control(&mut vars);
+        assert!(!vars.contains_key(HUD_CFG));
+    }
+
+    #[test]
+    fn test_socket_pids() {
+        let net_unix = "\
+Num       RefCount Protocol Flags    Type St Inode Path
+0000000000000000: 00000002 00000000 00010000 0001 01 41327 @mangohud-4242
+0000000000000000: 00000003 00000000 00000000 0001 03 41330 @mangohud-4242
+0000000000000000: 00000002 00000000 00010000 0001 01 41400 @mangohud-name
+0000000000000000: 00000002 00000000 00010000 0001 01 20210 /run/user/1000/bus
+0000000000000000: 00000003 00000000 00000000 0001 03 20215
+";
+        assert_eq!(socket_pids(net_unix), [4242]);
+        assert_eq!(HudCommand::Toggle.message(), ":hud;");
+    }
+}
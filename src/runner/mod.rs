@@ -1,5 +1,54 @@
+mod audio;
+pub mod autotune;
+mod capture;
+pub mod catalog;
+pub mod command;
+mod display;
+mod dnd;
+mod drives;
+mod dxvk;
+mod editor;
 mod env_var;
+mod export;
+mod frametime;
+mod golden;
+mod history;
+mod hooks;
+mod icd;
 mod launcher;
+pub mod mangohud;
+mod notify;
+mod oom;
+pub mod prefix;
+mod report;
+mod search_path;
+mod session;
+mod snapshot;
+mod switcheroo;
+mod timing;
+pub mod updates;
+mod wait;
+#[cfg(feature = "wayland")]
+mod wayland;
 
-pub use env_var::EnvBuilder;
-pub use launcher::Launcher;
+pub use audio::PipewireForce;
+pub use capture::archive as archive_captures;
+pub use dnd::DoNotDisturb;
+pub use editor::{ConfigDraft, game_line};
+pub use env_var::{EnvBuilder, EnvConflict};
+pub use export::export_telemetry;
+pub use frametime::SpikeReport;
+pub use golden::EnvGolden;
+pub use history::{SessionHistory, SessionRecord};
+pub use hooks::{HookContext, HookRunner, HookStage};
+pub use launcher::{Launcher, detect_game_exec, exec_nested, outer_session};
+pub use notify::notify;
+pub use oom::OomWatch;
+pub use report::LaunchReport;
+pub use session::SessionSummary;
+pub use snapshot::EnvSnapshot;
+pub use switcheroo::SwitcherooGpu;
+pub use timing::{LaunchTimer, Phase};
+pub use wait::wait_for;
+#[cfg(feature = "wayland")]
+pub use wayland::WaylandIdleInhibitor;
@@ -0,0 +1,40 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use zbus::proxy;
+use zbus::zvariant::Value;
+
+#[proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, &Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+}
+
+/// Show a desktop notification through the user's notification daemon
+pub async fn notify(summary: &str, body: &str) -> Result<()> {
+    let conn = zbus::Connection::session()
+        .await
+        .context("Failed to connect to session bus")?;
+    let proxy = NotificationsProxy::new(&conn)
+        .await
+        .context("Failed to create notifications proxy")?;
+
+    proxy
+        .notify("nvprime", 0, "", summary, body, &[], HashMap::new(), -1)
+        .await
+        .context("Failed to send notification")?;
+    Ok(())
+}
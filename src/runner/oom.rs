@@ -0,0 +1,104 @@
+use log::debug;
+use std::path::{Path, PathBuf};
+
+use crate::common::cgroup;
+
+/// Exit code of a game killed by SIGKILL, the signal the OOM killer sends
+pub const SIGKILL_EXIT: i32 = 128 + libc::SIGKILL;
+
+/// Counts OOM kills in the cgroup the game runs in, which it inherits from
+/// the launcher. Only a cgroup of the session's own, e.g. from
+/// `systemd-run --scope`, holds nothing but the game's tree.
+pub struct OomWatch {
+    events: PathBuf,
+    baseline: u64,
+    /// Whether the cgroup holds only this session
+    dedicated: bool,
+}
+
+impl OomWatch {
+    /// Start watching the current process's cgroup, `None` without cgroup v2
+    pub fn start() -> Option<Self> {
+        let dedicated = cgroup::session_cgroup(std::process::id()).is_some();
+        Self::watch(cgroup::own_cgroup()?.join("memory.events"), dedicated)
+    }
+
+    fn watch(events: PathBuf, dedicated: bool) -> Option<Self> {
+        let baseline = read_oom_kills(&events)?;
+        debug!(
+            "Watching {} for OOM kills, {} so far",
+            events.display(),
+            baseline
+        );
+        Some(Self {
+            events,
+            baseline,
+            dedicated,
+        })
+    }
+
+    /// OOM kills since the watch started that hit the game. In a shared
+    /// cgroup, like Steam's, a kill only counts when the game itself died
+    /// from SIGKILL.
+    pub fn kills(&self, exit_code: i32) -> u64 {
+        if !self.dedicated && exit_code != SIGKILL_EXIT {
+            return 0;
+        }
+        read_oom_kills(&self.events)
+            .unwrap_or(self.baseline)
+            .saturating_sub(self.baseline)
+    }
+}
+
+fn read_oom_kills(events: &Path) -> Option<u64> {
+    let content = std::fs::read_to_string(events).ok()?;
+    parse_oom_kills(&content)
+}
+
+fn parse_oom_kills(events: &str) -> Option<u64> {
+    events.lines().find_map(|line| {
+        let (key, value) = line.split_once(' ')?;
+        (key == "oom_kill").then(|| value.trim().parse().ok())?
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_oom_kills() {
+        let events = "low 0\nhigh 0\nmax 0\noom 2\noom_kill 1\noom_group_kill 0\n";
+        assert_eq!(parse_oom_kills(events), Some(1));
+        assert_eq!(parse_oom_kills("low 0\n"), None);
+    }
+
+    #[test]
+    fn test_oom_watch_counts_new_kills() {
+        let dir = tempfile::tempdir().unwrap();
+        let events = dir.path().join("memory.events");
+        std::fs::write(&events, "oom 3\noom_kill 3\n").unwrap();
+
+        let watch = OomWatch::watch(events.clone(), true).unwrap();
+        assert_eq!(watch.kills(0), 0);
+
+        std::fs::write(&events, "oom 4\noom_kill 4\n").unwrap();
+        assert_eq!(watch.kills(0), 1);
+
+        assert!(OomWatch::watch(dir.path().join("missing"), true).is_none());
+    }
+
+    #[test]
+    fn test_oom_watch_shared_cgroup() {
+        let dir = tempfile::tempdir().unwrap();
+        let events = dir.path().join("memory.events");
+        std::fs::write(&events, "oom_kill 0\n").unwrap();
+
+        let watch = OomWatch::watch(events.clone(), false).unwrap();
+        std::fs::write(&events, "oom_kill 1\n").unwrap();
+
+        // Something else in the launcher's cgroup was killed
+        assert_eq!(watch.kills(0), 0);
+        assert_eq!(watch.kills(SIGKILL_EXIT), 1);
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::Context;
+use log::{debug, info, warn};
+use phf::{Map, phf_map};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::common::config::GameConfig;
+use crate::common::steam::SteamCompat;
+
+/// Files a winetricks verb leaves in the prefix, relative to `drive_c`.
+/// A component counts as installed when all of them exist.
+static COMPONENT_FILES: Map<&'static str, &'static [&'static str]> = phf_map! {
+    "corefonts" => &["windows/Fonts/arial.ttf", "windows/Fonts/times.ttf", "windows/Fonts/cour.ttf"],
+    "d3dcompiler_43" => &["windows/system32/d3dcompiler_43.dll"],
+    "d3dcompiler_47" => &["windows/system32/d3dcompiler_47.dll"],
+    "d3dx9" => &["windows/system32/d3dx9_43.dll"],
+    "dotnet35" => &["windows/Microsoft.NET/Framework/v3.5"],
+    "dotnet40" => &["windows/Microsoft.NET/Framework/v4.0.30319/clr.dll"],
+    "dotnet48" => &["windows/Microsoft.NET/Framework/v4.0.30319/clr.dll"],
+    "vcrun2010" => &["windows/system32/msvcr100.dll"],
+    "vcrun2012" => &["windows/system32/msvcr110.dll"],
+    "vcrun2013" => &["windows/system32/msvcr120.dll"],
+    "vcrun2015" => &["windows/system32/vcruntime140.dll", "windows/system32/msvcp140.dll"],
+    "vcrun2017" => &["windows/system32/vcruntime140.dll", "windows/system32/msvcp140.dll"],
+    "vcrun2019" => &["windows/system32/vcruntime140.dll", "windows/system32/msvcp140.dll"],
+    "vcrun2022" => &["windows/system32/vcruntime140.dll", "windows/system32/msvcp140.dll"],
+    "xact" => &["windows/system32/xactengine3_7.dll"],
+};
+
+/// Outcome of checking a prefix against a game's `requires` list
+#[derive(Debug, Default, PartialEq)]
+pub struct PrefixCheck {
+    pub missing: Vec<String>,
+    /// Components nvprime has no way to detect
+    pub unknown: Vec<String>,
+}
+
+impl PrefixCheck {
+    /// Check which of the required components are missing from `prefix`
+    pub fn run(prefix: &Path, requires: &[String]) -> Self {
+        let drive_c = prefix.join("drive_c");
+        let mut check = Self::default();
+
+        for component in requires {
+            match COMPONENT_FILES.get(component.as_str()) {
+                Some(files) if files.iter().all(|f| drive_c.join(f).exists()) => {
+                    debug!("Prefix component {} is installed", component);
+                }
+                Some(_) => check.missing.push(component.clone()),
+                None => check.unknown.push(component.clone()),
+            }
+        }
+
+        check
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+impl fmt::Display for PrefixCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.missing.is_empty() {
+            write!(f, "all required components are installed")?;
+        } else {
+            write!(f, "missing {}", self.missing.join(", "))?;
+        }
+        if !self.unknown.is_empty() {
+            write!(f, " (cannot check {})", self.unknown.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The game's Wine prefix: `wine_prefix` from its config, then `WINEPREFIX`,
+/// then the Proton prefix Steam passes in `STEAM_COMPAT_DATA_PATH`
+pub fn find_prefix(game: &GameConfig, env: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    if let Some(prefix) = &game.wine_prefix {
+        return Some(PathBuf::from(prefix));
+    }
+    if let Some(prefix) = env("WINEPREFIX") {
+        return Some(PathBuf::from(prefix));
+    }
+    SteamCompat::parse(env).prefix()
+}
+
+/// Install components with protontricks when the Steam app ID is known,
+/// otherwise with winetricks pointed at the prefix
+pub fn install(prefix: &Path, components: &[String], app_id: Option<&str>) -> anyhow::Result<()> {
+    let mut cmd = match app_id {
+        Some(app_id) => {
+            let mut cmd = Command::new("protontricks");
+            cmd.arg(app_id);
+            cmd
+        }
+        None => {
+            let mut cmd = Command::new("winetricks");
+            cmd.env("WINEPREFIX", prefix);
+            cmd
+        }
+    };
+    cmd.arg("-q").args(components);
+
+    info!(
+        "Installing {} into {}",
+        components.join(", "),
+        prefix.display()
+    );
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to run {:?}", cmd.get_program()))?;
+
+    anyhow::ensure!(
+        status.success(),
+        "{:?} failed with {}",
+        cmd.get_program(),
+        status
+    );
+    Ok(())
+}
+
+/// Check the prefix of a game before launch, installing what is missing
+/// when the game asks for it. Returns the check after any install.
+pub fn preflight(game: &GameConfig) -> anyhow::Result<Option<PrefixCheck>> {
+    if game.requires.is_empty() {
+        return Ok(None);
+    }
+
+    let env = |key: &str| std::env::var(key).ok();
+    let Some(prefix) = find_prefix(game, env) else {
+        warn!("No Wine prefix found, skipping prefix checks");
+        return Ok(None);
+    };
+
+    let check = PrefixCheck::run(&prefix, &game.requires);
+    if check.is_ok() || !game.install_missing {
+        return Ok(Some(check));
+    }
+
+    let app_id = SteamCompat::from_env().app_id.map(|id| id.to_string());
+    install(&prefix, &check.missing, app_id.as_deref())?;
+    Ok(Some(PrefixCheck::run(&prefix, &game.requires)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requires(components: &[&str]) -> Vec<String> {
+        components.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn test_prefix_check() {
+        let prefix = tempfile::tempdir().unwrap();
+        let system32 = prefix.path().join("drive_c/windows/system32");
+        std::fs::create_dir_all(&system32).unwrap();
+        std::fs::write(system32.join("vcruntime140.dll"), "").unwrap();
+        std::fs::write(system32.join("msvcp140.dll"), "").unwrap();
+
+        let check = PrefixCheck::run(
+            prefix.path(),
+            &requires(&["vcrun2022", "corefonts", "mfc42"]),
+        );
+        assert_eq!(check.missing, vec!["corefonts"]);
+        assert_eq!(check.unknown, vec!["mfc42"]);
+        assert!(!check.is_ok());
+        assert_eq!(check.to_string(), "missing corefonts (cannot check mfc42)");
+
+        assert!(PrefixCheck::run(prefix.path(), &requires(&["vcrun2019"])).is_ok());
+    }
+
+    #[test]
+    fn test_find_prefix() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |key: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        let mut game = GameConfig::default();
+
+        assert_eq!(find_prefix(&game, env(&[])), None);
+        assert_eq!(
+            find_prefix(
+                &game,
+                env(&[("STEAM_COMPAT_DATA_PATH", "/steam/compatdata/42")])
+            ),
+            Some(PathBuf::from("/steam/compatdata/42/pfx"))
+        );
+        assert_eq!(
+            find_prefix(
+                &game,
+                env(&[
+                    ("WINEPREFIX", "/wine"),
+                    ("STEAM_COMPAT_DATA_PATH", "/steam")
+                ])
+            ),
+            Some(PathBuf::from("/wine"))
+        );
+
+        game.wine_prefix = Some("/games/prefix".to_string());
+        assert_eq!(
+            find_prefix(&game, env(&[("WINEPREFIX", "/wine")])),
+            Some(PathBuf::from("/games/prefix"))
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::common::config::LaunchBackend;
+use crate::runner::launcher::{Launcher, detect_game_path};
+use crate::runner::timing::Phase;
+
+const REPORT_FILE: &str = "launch-report.json";
+
+/// Everything that went into starting the game, written at spawn time to a
+/// fixed place so it can be attached to an issue as it is
+#[derive(Serialize, Debug)]
+pub struct LaunchReport {
+    pub version: &'static str,
+    pub game: String,
+    pub started_ms: u64,
+    /// Command line as Steam passed it
+    pub received: Vec<String>,
+    /// Command that is executed, after the `command_template`
+    pub command: Vec<String>,
+    /// Programs the game is started through, outermost first
+    pub wrappers: Vec<String>,
+    pub backend: LaunchBackend,
+    pub env: BTreeMap<String, String>,
+    pub env_overrides: BTreeMap<String, String>,
+    /// What was sent to the daemon
+    pub tuning: serde_json::Value,
+    /// What the daemon answered
+    pub daemon: serde_json::Value,
+    pub timings: Vec<Phase>,
+    pub warnings: Vec<String>,
+}
+
+impl LaunchReport {
+    pub fn new(launcher: &Launcher, received: &[String], started_ms: u64) -> Self {
+        let command: Vec<String> = std::iter::once(launcher.exec().to_string())
+            .chain(launcher.args().iter().cloned())
+            .collect();
+
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            game: launcher.game_name().to_string(),
+            started_ms,
+            received: received.to_vec(),
+            wrappers: wrapper_chain(&command),
+            command,
+            backend: launcher.game_config().backend,
+            env: launcher.env_vars().clone(),
+            env_overrides: launcher.env_overrides().clone(),
+            tuning: serde_json::Value::Null,
+            daemon: serde_json::Value::Null,
+            timings: Vec::new(),
+            warnings: launcher.path_warnings().to_vec(),
+        }
+    }
+
+    pub fn with_tuning(mut self, request: serde_json::Value, response: serde_json::Value) -> Self {
+        self.tuning = request;
+        self.daemon = response;
+        self
+    }
+
+    pub fn with_timings(mut self, timings: &[Phase]) -> Self {
+        self.timings = timings.to_vec();
+        self
+    }
+
+    /// Write the report to `<sessions_dir>/<game>/launch-report.json`,
+    /// replacing the one of the previous launch
+    pub fn write(&self, sessions_dir: &Path) -> anyhow::Result<PathBuf> {
+        let dir = sessions_dir.join(&self.game);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        let path = dir.join(REPORT_FILE);
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize report")?;
+        std::fs::write(&path, json)
+            .with_context(|| format!("Failed to write launch report {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// Programs in front of the game, e.g. Steam's reaper, the runtime entry
+/// point and Proton. Only words naming a program count, not their options.
+fn wrapper_chain(command: &[String]) -> Vec<String> {
+    let game = detect_game_path(command);
+    command
+        .iter()
+        .enumerate()
+        .take_while(|(_, word)| word.as_str() != game)
+        .filter(|(i, word)| *i == 0 || (word.starts_with('/') && !word.contains('=')))
+        .filter_map(|(_, word)| Path::new(word).file_name()?.to_str().map(str::to_string))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_wrapper_chain() {
+        let command = words(
+            "/steam/ubuntu12_32/reaper SteamLaunch AppId=42 -- \
+             /steam/SteamLinuxRuntime_sniper/_v2-entry-point --verb=waitforexitandrun -- \
+             /steam/Proton/proton waitforexitandrun /games/Game/game.exe -dx12",
+        );
+        assert_eq!(
+            wrapper_chain(&command),
+            ["reaper", "_v2-entry-point", "proton"]
+        );
+
+        assert!(wrapper_chain(&words("/games/native/run.sh")).is_empty());
+        assert_eq!(
+            wrapper_chain(&words("gamescope -W 2560 -- /games/Game/game.exe")),
+            ["gamescope"]
+        );
+    }
+}
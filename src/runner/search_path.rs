@@ -0,0 +1,168 @@
+use log::{debug, warn};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::common::config::GameConfig;
+
+const LIBRARY_PATH: &str = "LD_LIBRARY_PATH";
+const PATH: &str = "PATH";
+
+/// ELF class byte of 32-bit objects
+const ELFCLASS32: u8 = 1;
+
+/// Put the game's `extra_library_paths` and `prepend_path` in front of the
+/// search paths, keeping what the config or the inherited environment (e.g.
+/// the Steam runtime) already set. Returns a warning for each directory left
+/// out or likely of no use.
+pub fn apply(
+    vars: &mut BTreeMap<String, String>,
+    game: &GameConfig,
+    inherited: impl Fn(&str) -> Option<String>,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for (key, option, dirs) in [
+        (
+            LIBRARY_PATH,
+            "extra_library_paths",
+            &game.extra_library_paths,
+        ),
+        (PATH, "prepend_path", &game.prepend_path),
+    ] {
+        let valid: Vec<&Path> = dirs
+            .iter()
+            .filter(|dir| match check_dir(dir) {
+                Ok(()) => true,
+                Err(reason) => {
+                    warnings.push(format!("{} entry {} {}", option, dir.display(), reason));
+                    false
+                }
+            })
+            .map(PathBuf::as_path)
+            .collect();
+
+        if key == LIBRARY_PATH {
+            warnings.extend(valid.iter().filter(|dir| only_32bit(dir)).map(|dir| {
+                format!(
+                    "{} entry {} only holds 32-bit libraries, 64-bit games skip them",
+                    option,
+                    dir.display()
+                )
+            }));
+        }
+
+        if valid.is_empty() {
+            continue;
+        }
+
+        let base = vars.get(key).cloned().or_else(|| inherited(key));
+        let value = prepend(&valid, base.as_deref());
+        debug!("{} for the game: {}", key, value);
+        vars.insert(key.to_string(), value);
+    }
+
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+    warnings
+}
+
+fn check_dir(dir: &Path) -> Result<(), &'static str> {
+    if !dir.is_absolute() {
+        return Err("is not an absolute path");
+    }
+    match std::fs::metadata(dir) {
+        Ok(meta) if meta.is_dir() => Ok(()),
+        Ok(_) => Err("is not a directory"),
+        Err(_) => Err("does not exist"),
+    }
+}
+
+/// `dirs` followed by the entries of `base` that are not among them
+fn prepend(dirs: &[&Path], base: Option<&str>) -> String {
+    let mut entries: Vec<String> = dirs.iter().map(|d| d.display().to_string()).collect();
+    for entry in base.unwrap_or_default().split(':') {
+        if !entry.is_empty() && !entries.iter().any(|e| e == entry) {
+            entries.push(entry.to_string());
+        }
+    }
+    entries.join(":")
+}
+
+/// Whether every shared library in `dir` is a 32-bit ELF object, only a
+/// hint since the dynamic loader skips libraries of the wrong class
+fn only_32bit(dir: &Path) -> bool {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return false;
+    };
+
+    let classes: Vec<u8> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| is_shared_library(path))
+        .filter_map(|path| elf_class(&path))
+        .collect();
+    !classes.is_empty() && classes.iter().all(|class| *class == ELFCLASS32)
+}
+
+fn is_shared_library(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(".so") || name.contains(".so."))
+}
+
+fn elf_class(path: &Path) -> Option<u8> {
+    let mut header = [0u8; 5];
+    std::fs::File::open(path)
+        .ok()?
+        .read_exact(&mut header)
+        .ok()?;
+    (header[..4] == *b"\x7fELF").then_some(header[4])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepend() {
+        let dirs = [Path::new("/opt/lib"), Path::new("/usr/lib32")];
+        assert_eq!(prepend(&dirs, None), "/opt/lib:/usr/lib32");
+        assert_eq!(
+            prepend(&dirs, Some("/steam/lib::/opt/lib")),
+            "/opt/lib:/usr/lib32:/steam/lib"
+        );
+    }
+
+    #[test]
+    fn test_apply_search_paths() {
+        let dir = std::env::temp_dir().join(format!("nvprime-search-{}", std::process::id()));
+        let lib32 = dir.join("lib32");
+        std::fs::create_dir_all(&lib32).unwrap();
+        let mut elf = b"\x7fELF".to_vec();
+        elf.push(ELFCLASS32);
+        std::fs::write(lib32.join("libfoo.so.1"), elf).unwrap();
+
+        let game = GameConfig {
+            extra_library_paths: vec![lib32.clone(), dir.join("missing")],
+            prepend_path: vec![dir.clone()],
+            ..Default::default()
+        };
+        let mut vars = BTreeMap::from([(PATH.to_string(), "/usr/bin".to_string())]);
+        let warnings = apply(&mut vars, &game, |key| {
+            (key == LIBRARY_PATH).then(|| "/steam/lib".to_string())
+        });
+
+        assert_eq!(
+            vars[LIBRARY_PATH],
+            format!("{}:/steam/lib", lib32.display())
+        );
+        assert_eq!(vars[PATH], format!("{}:/usr/bin", dir.display()));
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("does not exist"));
+        assert!(warnings[1].contains("32-bit"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
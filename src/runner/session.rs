@@ -0,0 +1,330 @@
+use anyhow::Context;
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::common::config::GameConfig;
+use crate::common::telemetry::{TelemetrySample, now_ms};
+use crate::runner::frametime::{self, SpikeReport};
+
+/// What happened during a single game session, reported when it ends
+#[derive(Serialize, Debug)]
+pub struct SessionSummary {
+    pub game: String,
+    pub exit_code: i32,
+    pub started_ms: u64,
+    pub duration_sec: u64,
+    pub frametime_log: Option<PathBuf>,
+    pub frametime: Option<SpikeReport>,
+    pub oom_kills: u64,
+    pub throttle: ThrottleTime,
+    pub vram: Option<VramUse>,
+    pub warnings: Vec<String>,
+}
+
+/// VRAM the game itself held during a session, from daemon telemetry, to
+/// tell a game short on VRAM apart from a desktop crowding it out
+#[derive(Serialize, Debug, PartialEq)]
+pub struct VramUse {
+    pub peak_mib: u64,
+    pub average_mib: u64,
+}
+
+impl VramUse {
+    /// `None` when no sample saw the game on the GPU
+    pub fn from_samples(samples: &[TelemetrySample]) -> Option<Self> {
+        let vram: Vec<u64> = samples.iter().filter_map(|s| s.game_vram()).collect();
+        Some(Self {
+            peak_mib: *vram.iter().max()?,
+            average_mib: vram.iter().sum::<u64>() / vram.len() as u64,
+        })
+    }
+}
+
+/// Seconds the GPU spent throttled during a session, the first thing to
+/// look at before repasting or lowering the power limit
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct ThrottleTime {
+    pub power_sec: f64,
+    pub thermal_sec: f64,
+}
+
+impl ThrottleTime {
+    pub fn from_samples(samples: &[TelemetrySample]) -> Self {
+        Self {
+            power_sec: throttled_sec(samples, |s| s.power_throttle_ns, |s| s.power_throttle),
+            thermal_sec: throttled_sec(samples, |s| s.thermal_throttle_ns, |s| s.thermal_throttle),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.power_sec == 0.0 && self.thermal_sec == 0.0
+    }
+}
+
+/// Prefer NVML's running counter, which also covers the time between
+/// samples. Without it, every interval starting in a throttled sample counts.
+fn throttled_sec(
+    samples: &[TelemetrySample],
+    counter: impl Fn(&TelemetrySample) -> Option<u64>,
+    throttled: impl Fn(&TelemetrySample) -> bool,
+) -> f64 {
+    if let (Some(first), Some(last)) = (
+        samples.first().and_then(&counter),
+        samples.last().and_then(&counter),
+    ) {
+        return last.saturating_sub(first) as f64 / 1e9;
+    }
+
+    let throttled_ms: u64 = samples
+        .windows(2)
+        .filter(|pair| throttled(&pair[0]))
+        .map(|pair| pair[1].timestamp_ms.saturating_sub(pair[0].timestamp_ms))
+        .sum();
+    throttled_ms as f64 / 1000.0
+}
+
+impl SessionSummary {
+    pub fn new(game: &str, exit_code: i32, started_ms: u64) -> Self {
+        Self {
+            game: game.to_string(),
+            exit_code,
+            started_ms,
+            duration_sec: now_ms().saturating_sub(started_ms) / 1000,
+            frametime_log: None,
+            frametime: None,
+            oom_kills: 0,
+            throttle: ThrottleTime::default(),
+            vram: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Correlate the session's MangoHud log (if any) with daemon telemetry
+    pub fn with_frametime(mut self, game: &GameConfig, samples: &[TelemetrySample]) -> Self {
+        let Some(dir) = game
+            .mangohud_conf
+            .as_deref()
+            .and_then(frametime::mangohud_output_folder)
+        else {
+            debug!("No MangoHud output_folder configured, skipping frame time report");
+            return self;
+        };
+
+        let Some(log) = frametime::find_latest_log(&dir, self.started_ms) else {
+            debug!("No MangoHud log written to {} this session", dir.display());
+            return self;
+        };
+
+        match frametime::read_log(&log) {
+            Ok(frames) => {
+                self.frametime = Some(SpikeReport::correlate(&frames, samples));
+                self.frametime_log = Some(log);
+            }
+            Err(e) => warn!("Failed to analyze MangoHud log: {:#}", e),
+        }
+
+        self
+    }
+
+    /// Total up the time the GPU spent throttled from daemon telemetry
+    pub fn with_throttle(mut self, samples: &[TelemetrySample]) -> Self {
+        self.throttle = ThrottleTime::from_samples(samples);
+        self
+    }
+
+    /// Peak and average VRAM of the game from daemon telemetry
+    pub fn with_vram(mut self, samples: &[TelemetrySample]) -> Self {
+        self.vram = VramUse::from_samples(samples);
+        self
+    }
+
+    /// Record OOM kills that happened in the game's cgroup during the session
+    pub fn with_oom_kills(mut self, oom_kills: u64) -> Self {
+        self.oom_kills = oom_kills;
+        self
+    }
+
+    /// Problems with the launch setup, repeated so they are not lost in
+    /// the game's output
+    pub fn with_warnings(mut self, warnings: &[String]) -> Self {
+        self.warnings = warnings.to_vec();
+        self
+    }
+
+    /// Whether the kernel's OOM killer took down part of the session
+    pub fn oom_killed(&self) -> bool {
+        self.oom_kills > 0
+    }
+
+    /// Print the human readable summary
+    pub fn log(&self) {
+        info!(
+            "Session summary for '{}': exit code {}, ran for {}s",
+            self.game, self.exit_code, self.duration_sec
+        );
+
+        if self.oom_killed() {
+            error!(
+                "'{}' ran out of memory: the kernel OOM killer killed {} process(es) of this session",
+                self.game, self.oom_kills
+            );
+        }
+
+        if !self.throttle.is_empty() {
+            info!(
+                "GPU throttled for {:.0}s by the power limit and {:.0}s by temperature",
+                self.throttle.power_sec, self.throttle.thermal_sec
+            );
+        }
+
+        if let Some(vram) = &self.vram {
+            info!(
+                "Game used up to {}MiB of VRAM, {}MiB on average",
+                vram.peak_mib, vram.average_mib
+            );
+        }
+
+        if let Some(report) = &self.frametime {
+            info!("Frame time: {}", report);
+        }
+
+        for warning in &self.warnings {
+            warn!("Launch: {}", warning);
+        }
+    }
+
+    /// Write the summary as JSON next to the MangoHud log it was built from
+    pub fn write_json(&self) -> anyhow::Result<Option<PathBuf>> {
+        let Some(log) = &self.frametime_log else {
+            return Ok(None);
+        };
+
+        let path = log.with_extension("nvprime.json");
+        self.write_json_to(&path)?;
+        Ok(Some(path))
+    }
+
+    fn write_json_to(&self, path: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize summary")?;
+        std::fs::write(path, json)
+            .with_context(|| format!("Failed to write session summary {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_without_mangohud() {
+        let summary =
+            SessionSummary::new("game", 0, now_ms()).with_frametime(&GameConfig::default(), &[]);
+        assert!(summary.frametime.is_none());
+        assert!(summary.write_json().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_summary_oom_kills() {
+        let summary = SessionSummary::new("game", -1, now_ms());
+        assert!(!summary.oom_killed());
+
+        let summary = summary.with_oom_kills(1);
+        assert!(summary.oom_killed());
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["oom_kills"], 1);
+    }
+
+    fn sample(timestamp_ms: u64, power: bool, power_ns: Option<u64>) -> TelemetrySample {
+        TelemetrySample {
+            timestamp_ms,
+            power_throttle: power,
+            power_throttle_ns: power_ns,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_vram_use() {
+        let with_vram = |mib: &[(u32, u64)]| TelemetrySample {
+            game_vram_mib: mib.iter().copied().collect(),
+            ..Default::default()
+        };
+        let samples = [
+            with_vram(&[]),
+            with_vram(&[(7, 4000)]),
+            with_vram(&[(7, 6000)]),
+        ];
+        assert_eq!(
+            VramUse::from_samples(&samples),
+            Some(VramUse {
+                peak_mib: 6000,
+                average_mib: 5000
+            })
+        );
+        assert_eq!(VramUse::from_samples(&samples[..1]), None);
+
+        let mut shared = with_vram(&[(7, 4000), (8, 2000)]);
+        assert_eq!(shared.game_vram(), Some(6000));
+        shared.retain_session(8);
+        assert_eq!(shared.game_vram(), Some(2000));
+    }
+
+    #[test]
+    fn test_throttle_time_from_flags() {
+        let samples = [
+            sample(0, true, None),
+            sample(1000, true, None),
+            sample(2000, false, None),
+            sample(3500, true, None),
+        ];
+
+        let throttle = ThrottleTime::from_samples(&samples);
+        assert_eq!(throttle.power_sec, 2.0);
+        assert_eq!(throttle.thermal_sec, 0.0);
+        assert!(ThrottleTime::from_samples(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_throttle_time_from_counters() {
+        let samples = [
+            sample(0, false, Some(10_000_000_000)),
+            sample(1000, true, None),
+            sample(2000, false, Some(14_500_000_000)),
+        ];
+
+        let summary = SessionSummary::new("game", 0, 0).with_throttle(&samples);
+        assert_eq!(summary.throttle.power_sec, 4.5);
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["throttle"]["power_sec"], 4.5);
+    }
+
+    #[test]
+    fn test_summary_with_mangohud_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("game_2024-01-01_12-00-00.csv");
+        std::fs::write(
+            &log,
+            "fps,frametime,elapsed\n60,16.6,0\n60,16.6,1000000\n20,50.0,2000000\n",
+        )
+        .unwrap();
+
+        let game = GameConfig {
+            mangohud_conf: Some(format!("output_folder={}", dir.path().display())),
+            ..Default::default()
+        };
+
+        let summary = SessionSummary::new("game", 0, 0).with_frametime(&game, &[]);
+        let report = summary.frametime.as_ref().unwrap();
+        assert_eq!(report.frames, 3);
+        assert_eq!(report.spikes, 1);
+        assert_eq!(report.unexplained, 1);
+
+        let path = summary.write_json().unwrap().unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(json["frametime"]["spikes"], 1);
+        assert_eq!(json["game"], "game");
+    }
+}
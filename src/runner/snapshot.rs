@@ -0,0 +1,251 @@
+use anyhow::Context;
+use log::{debug, info};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::runner::EnvBuilder;
+use crate::runner::env_var::{HUD, HUD_CFG, LOG, NTSYNC, WAYLAND, WINE_DLLS};
+use crate::runner::launcher::detect_game_exec;
+
+/// Environment variables that map directly onto `[game.<name>]` options
+const GAME_OPTIONS: [(&str, &str); 6] = [
+    (HUD, "mangohud"),
+    (HUD_CFG, "mangohud_conf"),
+    (LOG, "proton_log"),
+    (NTSYNC, "proton_ntsync"),
+    (WAYLAND, "proton_wayland"),
+    (WINE_DLLS, "wine_dll_overrides"),
+];
+
+/// Options from `GAME_OPTIONS` that are booleans rather than strings
+const BOOL_OPTIONS: [&str; 4] = ["mangohud", "proton_log", "proton_ntsync", "proton_wayland"];
+
+/// Captured environment of a running game, reduced to the entries that
+/// differ from what nvprime (and the user session) would set anyway
+#[derive(Debug, Default)]
+pub struct EnvSnapshot {
+    pub game: String,
+    pub game_options: BTreeMap<String, toml::Value>,
+    pub env: BTreeMap<String, String>,
+}
+
+impl EnvSnapshot {
+    /// Read `/proc/<pid>/environ` and `/proc/<pid>/cmdline` of a running
+    /// process and diff them against the defaults and our own environment
+    pub fn capture(pid: u32, name: Option<String>) -> anyhow::Result<Self> {
+        info!("Capturing environment of PID {}", pid);
+
+        let environ = std::fs::read(format!("/proc/{}/environ", pid))
+            .with_context(|| format!("Failed to read environment of PID {}", pid))?;
+
+        let game = match name {
+            Some(name) => name,
+            None => {
+                let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid))
+                    .with_context(|| format!("Failed to read command line of PID {}", pid))?;
+                let args = split_nul(&cmdline);
+                anyhow::ensure!(!args.is_empty(), "PID {} has an empty command line", pid);
+                detect_game_exec(&args)
+            }
+        };
+
+        let target = parse_environ(&environ);
+        let session: BTreeMap<String, String> = std::env::vars().collect();
+
+        Ok(Self::diff(game, &target, &session))
+    }
+
+    /// Build a snapshot from a target environment, skipping everything
+    /// that equals nvprime's defaults or is inherited from the session
+    pub fn diff(
+        game: String,
+        target: &BTreeMap<String, String>,
+        session: &BTreeMap<String, String>,
+    ) -> Self {
+        let defaults = EnvBuilder::new().build();
+        let mut snapshot = Self {
+            game,
+            ..Default::default()
+        };
+
+        for (key, val) in target {
+            if defaults.get(key) == Some(val) {
+                continue;
+            }
+
+            if let Some((_, option)) = GAME_OPTIONS.iter().find(|(env, _)| env == key) {
+                let value = if BOOL_OPTIONS.contains(option) {
+                    toml::Value::Boolean(val == "1")
+                } else {
+                    toml::Value::String(val.clone())
+                };
+                snapshot.game_options.insert(option.to_string(), value);
+                continue;
+            }
+
+            if !defaults.contains_key(key) && session.get(key) == Some(val) {
+                continue;
+            }
+
+            snapshot.env.insert(key.clone(), val.clone());
+        }
+
+        debug!(
+            "Snapshot for '{}': {} game options, {} env entries",
+            snapshot.game,
+            snapshot.game_options.len(),
+            snapshot.env.len()
+        );
+
+        snapshot
+    }
+
+    /// Render the snapshot as config sections ready to paste into nvprime.conf
+    pub fn to_toml(&self) -> String {
+        let mut out = String::new();
+        let section = toml_key(&self.game);
+
+        if !self.game_options.is_empty() {
+            let _ = writeln!(out, "[game.{}]", section);
+            for (key, val) in &self.game_options {
+                let _ = writeln!(out, "{} = {}", key, val);
+            }
+        }
+
+        if !self.env.is_empty() {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            let _ = writeln!(out, "[{}]", section);
+            for (key, val) in &self.env {
+                let _ = writeln!(
+                    out,
+                    "{} = {}",
+                    toml_key(key),
+                    toml::Value::String(val.clone())
+                );
+            }
+        }
+
+        out
+    }
+}
+
+fn split_nul(data: &[u8]) -> Vec<String> {
+    data.split(|b| *b == 0)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect()
+}
+
+fn parse_environ(data: &[u8]) -> BTreeMap<String, String> {
+    split_nul(data)
+        .into_iter()
+        .filter_map(|entry| {
+            entry
+                .split_once('=')
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+        })
+        .collect()
+}
+
+/// Quote a TOML key unless it is a valid bare key
+fn toml_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if bare {
+        key.to_string()
+    } else {
+        toml::Value::String(key.to_string()).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Config;
+
+    fn env(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_environ() {
+        let vars = parse_environ(b"FOO=bar\0EMPTY=\0BAD\0X=a=b\0");
+        assert_eq!(vars.get("FOO"), Some(&"bar".to_string()));
+        assert_eq!(vars.get("EMPTY"), Some(&String::new()));
+        assert_eq!(vars.get("X"), Some(&"a=b".to_string()));
+        assert!(!vars.contains_key("BAD"));
+    }
+
+    #[test]
+    fn test_diff_skips_defaults_and_session() {
+        let target = env(&[
+            ("__NV_PRIME_RENDER_OFFLOAD", "1"),
+            ("HOME", "/home/user"),
+            ("DXVK_HUD", "fps"),
+        ]);
+        let session = env(&[("HOME", "/home/user")]);
+
+        let snapshot = EnvSnapshot::diff("game".to_string(), &target, &session);
+        assert!(snapshot.game_options.is_empty());
+        assert_eq!(snapshot.env, env(&[("DXVK_HUD", "fps")]));
+    }
+
+    #[test]
+    fn test_diff_maps_game_options() {
+        let target = env(&[
+            ("MANGOHUD", "1"),
+            ("PROTON_LOG", "0"),
+            ("WINEDLLOVERRIDES", "dinput8=n,b"),
+        ]);
+
+        let snapshot = EnvSnapshot::diff("game".to_string(), &target, &BTreeMap::new());
+        assert_eq!(
+            snapshot.game_options.get("mangohud"),
+            Some(&toml::Value::Boolean(true))
+        );
+        assert!(!snapshot.game_options.contains_key("proton_log"));
+        assert_eq!(
+            snapshot.game_options.get("wine_dll_overrides"),
+            Some(&toml::Value::String("dinput8=n,b".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_diff_keeps_changed_default() {
+        let target = env(&[("__GL_YIELD", "NOTHING")]);
+        let session = env(&[("__GL_YIELD", "NOTHING")]);
+
+        let snapshot = EnvSnapshot::diff("game".to_string(), &target, &session);
+        assert_eq!(snapshot.env, env(&[("__GL_YIELD", "NOTHING")]));
+    }
+
+    #[test]
+    fn test_to_toml_round_trip() {
+        let target = env(&[
+            ("MANGOHUD", "1"),
+            ("DXVK_HUD", "fps,frametimes"),
+            ("weird.key", "x"),
+        ]);
+
+        let snapshot = EnvSnapshot::diff("my game".to_string(), &target, &BTreeMap::new());
+        let config: Config = toml::from_str(&snapshot.to_toml()).unwrap();
+
+        assert!(config.game.get("my game").unwrap().mangohud);
+        let vars = config.env.get("my game").unwrap();
+        assert_eq!(vars.get("DXVK_HUD").unwrap().to_string(), "fps,frametimes");
+        assert_eq!(vars.get("weird.key").unwrap().to_string(), "x");
+    }
+
+    #[test]
+    fn test_capture_nonexistent_pid() {
+        assert!(EnvSnapshot::capture(999999, None).is_err());
+    }
+}
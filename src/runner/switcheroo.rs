@@ -0,0 +1,166 @@
+use anyhow::Context;
+use log::{debug, warn};
+use std::collections::{BTreeMap, HashMap};
+use zbus::proxy;
+use zbus::zvariant::OwnedValue;
+
+#[proxy(
+    interface = "net.hadess.SwitcherooControl",
+    default_service = "net.hadess.SwitcherooControl",
+    default_path = "/net/hadess/SwitcherooControl"
+)]
+trait SwitcherooControl {
+    #[zbus(property, name = "GPUs")]
+    fn gpus(&self) -> zbus::Result<Vec<HashMap<String, OwnedValue>>>;
+}
+
+/// A GPU as reported by switcheroo-control, with the environment desktops
+/// use for "Launch using Discrete Graphics Card"
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwitcherooGpu {
+    pub name: String,
+    pub env: BTreeMap<String, String>,
+    pub default: bool,
+    pub discrete: bool,
+}
+
+impl SwitcherooGpu {
+    /// The discrete GPU, `None` when switcheroo-control is not running.
+    /// Failures are logged since the builtin defaults remain usable.
+    pub fn detect() -> Option<Self> {
+        match query() {
+            Ok(gpus) => {
+                let gpu = pick_discrete(gpus);
+                if gpu.is_none() {
+                    warn!("switcheroo-control reports no discrete GPU");
+                }
+                gpu
+            }
+            Err(e) => {
+                warn!("{:#}, using builtin defaults", e);
+                None
+            }
+        }
+    }
+
+    fn from_properties(props: &HashMap<String, OwnedValue>) -> Option<Self> {
+        let name = props.get("Name")?.downcast_ref::<&str>().ok()?.to_string();
+        let env = props
+            .get("Environment")
+            .and_then(|value| Vec::<String>::try_from(value.try_clone().ok()?).ok())
+            .map(|list| env_pairs(&list))
+            .unwrap_or_default();
+        let flag = |key: &str| {
+            props
+                .get(key)
+                .and_then(|value| value.downcast_ref::<bool>().ok())
+        };
+
+        Some(Self {
+            name,
+            env,
+            default: flag("Default").unwrap_or(false),
+            // Older versions only tell the default GPU apart
+            discrete: flag("Discrete").unwrap_or(false),
+        })
+    }
+}
+
+fn query() -> anyhow::Result<Vec<SwitcherooGpu>> {
+    let conn = zbus::blocking::Connection::system().context("Failed to connect to system bus")?;
+    let proxy = SwitcherooControlProxyBlocking::new(&conn)
+        .context("Failed to create switcheroo-control proxy")?;
+    let gpus = proxy
+        .gpus()
+        .context("Failed to query GPUs from switcheroo-control")?;
+
+    Ok(gpus
+        .iter()
+        .filter_map(SwitcherooGpu::from_properties)
+        .inspect(|gpu| debug!("switcheroo-control GPU: {:?}", gpu))
+        .collect())
+}
+
+/// The GPU flagged as discrete, otherwise the first one that is not the default
+fn pick_discrete(gpus: Vec<SwitcherooGpu>) -> Option<SwitcherooGpu> {
+    let index = gpus
+        .iter()
+        .position(|gpu| gpu.discrete)
+        .or_else(|| gpus.iter().position(|gpu| !gpu.default))?;
+    gpus.into_iter().nth(index)
+}
+
+/// switcheroo-control lists the environment as alternating keys and values
+fn env_pairs(list: &[String]) -> BTreeMap<String, String> {
+    list.chunks_exact(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zbus::zvariant::Value;
+
+    fn gpu(name: &str, default: bool, discrete: bool) -> SwitcherooGpu {
+        SwitcherooGpu {
+            name: name.to_string(),
+            env: BTreeMap::new(),
+            default,
+            discrete,
+        }
+    }
+
+    #[test]
+    fn test_env_pairs() {
+        let list: Vec<String> = [
+            "__NV_PRIME_RENDER_OFFLOAD",
+            "1",
+            "DRI_PRIME",
+            "pci-0000_01_00_0",
+            "dangling",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let env = env_pairs(&list);
+
+        assert_eq!(env.len(), 2);
+        assert_eq!(env["__NV_PRIME_RENDER_OFFLOAD"], "1");
+        assert_eq!(env["DRI_PRIME"], "pci-0000_01_00_0");
+    }
+
+    #[test]
+    fn test_pick_discrete() {
+        let picked = pick_discrete(vec![
+            gpu("iGPU", true, false),
+            gpu("Other", false, false),
+            gpu("dGPU", false, true),
+        ]);
+        assert_eq!(picked.unwrap().name, "dGPU");
+
+        let picked = pick_discrete(vec![gpu("iGPU", true, false), gpu("dGPU", false, false)]);
+        assert_eq!(picked.unwrap().name, "dGPU");
+
+        assert_eq!(pick_discrete(vec![gpu("iGPU", true, false)]), None);
+    }
+
+    #[test]
+    fn test_from_properties() {
+        let env = vec!["__GLX_VENDOR_LIBRARY_NAME", "nvidia"];
+        let props: HashMap<String, OwnedValue> = [
+            ("Name", Value::from("NVIDIA GeForce RTX 4080")),
+            ("Environment", Value::from(env)),
+            ("Default", Value::from(false)),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.try_into().unwrap()))
+        .collect();
+
+        let gpu = SwitcherooGpu::from_properties(&props).unwrap();
+        assert_eq!(gpu.name, "NVIDIA GeForce RTX 4080");
+        assert_eq!(gpu.env["__GLX_VENDOR_LIBRARY_NAME"], "nvidia");
+        assert!(!gpu.default);
+        assert!(!gpu.discrete);
+    }
+}
@@ -0,0 +1,78 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// How long one step of the launch took
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Phase {
+    pub name: String,
+    pub micros: u64,
+}
+
+/// Times the launch steps one after the other, to see where the overhead
+/// nvprime adds in front of the game goes
+pub struct LaunchTimer {
+    last: Instant,
+    phases: Vec<Phase>,
+}
+
+impl LaunchTimer {
+    pub fn start() -> Self {
+        Self {
+            last: Instant::now(),
+            phases: Vec::new(),
+        }
+    }
+
+    /// End the running phase under `name` and start the next one
+    pub fn lap(&mut self, name: &str) {
+        let now = Instant::now();
+        self.record(name, now - self.last);
+        self.last = now;
+    }
+
+    /// Add a phase timed elsewhere
+    pub fn record(&mut self, name: &str, duration: Duration) {
+        self.phases.push(Phase {
+            name: name.to_string(),
+            micros: duration.as_micros() as u64,
+        });
+    }
+
+    pub fn phases(&self) -> &[Phase] {
+        &self.phases
+    }
+
+    pub fn log(&self) {
+        let total: u64 = self.phases.iter().map(|p| p.micros).sum();
+        info!("Launch timing, {:.1}ms in total:", millis(total));
+        for phase in &self.phases {
+            info!("  {:<12} {:>8.1}ms", phase.name, millis(phase.micros));
+        }
+    }
+}
+
+fn millis(micros: u64) -> f64 {
+    micros as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_launch_timer() {
+        let mut timer = LaunchTimer::start();
+        std::thread::sleep(Duration::from_millis(5));
+        timer.lap("config");
+        timer.lap("env");
+        timer.record("spawn", Duration::from_micros(1500));
+
+        let phases = timer.phases();
+        let names: Vec<&str> = phases.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["config", "env", "spawn"]);
+        assert!(phases[0].micros >= 5000);
+        assert!(phases[1].micros < phases[0].micros);
+        assert_eq!(phases[2].micros, 1500);
+    }
+}
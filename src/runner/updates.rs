@@ -0,0 +1,259 @@
+use anyhow::Context;
+use log::{debug, info, warn};
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use crate::common::config::UpdatesConfig;
+use crate::runner::catalog::Workaround;
+use crate::runner::env_var::builtin_default;
+
+const MANIFEST_FILE: &str = "updates.json";
+const SIGNATURE_FILE: &str = "updates.json.minisig";
+
+/// Seconds curl gets for each file
+const DOWNLOAD_TIMEOUT_SECS: &str = "10";
+
+/// Workarounds and default changes published between releases
+#[derive(Deserialize, Debug, Default)]
+pub struct UpdateManifest {
+    pub version: u64,
+
+    #[serde(default)]
+    pub workarounds: Vec<Workaround>,
+
+    /// New values for nvprime's default environment
+    #[serde(default)]
+    pub defaults: BTreeMap<String, String>,
+}
+
+impl UpdateManifest {
+    /// Parse a manifest once its signature checks out against `public_key`
+    pub fn verify(content: &[u8], signature: &str, public_key: &str) -> anyhow::Result<Self> {
+        let key = PublicKey::from_base64(public_key).context("Invalid update signing key")?;
+        let signature =
+            Signature::decode(signature).context("Failed to decode update manifest signature")?;
+        key.verify(content, &signature, false)
+            .context("Update manifest signature does not match")?;
+        serde_json::from_slice(content).context("Failed to parse update manifest")
+    }
+
+    /// Default changes this release does not ship yet
+    pub fn changed_defaults(&self) -> BTreeMap<&str, &str> {
+        self.defaults
+            .iter()
+            .filter(|(key, val)| builtin_default(key) != Some(val.as_str()))
+            .map(|(key, val)| (key.as_str(), val.as_str()))
+            .collect()
+    }
+}
+
+/// The last fetched manifest and its signature in the cache directory
+pub struct UpdateCache {
+    dir: PathBuf,
+    public_key: String,
+}
+
+impl UpdateCache {
+    /// Cache of manifests signed with the minisign `public_key`
+    pub fn new(cache_dir: &Path, public_key: &str) -> Self {
+        Self {
+            dir: cache_dir.to_path_buf(),
+            public_key: public_key.to_string(),
+        }
+    }
+
+    /// The cached manifest, verified again since anything can write to the
+    /// cache directory
+    pub fn load(&self) -> Option<UpdateManifest> {
+        let content = std::fs::read(self.dir.join(MANIFEST_FILE)).ok()?;
+        let signature = std::fs::read_to_string(self.dir.join(SIGNATURE_FILE)).ok()?;
+        UpdateManifest::verify(&content, &signature, &self.public_key)
+            .inspect_err(|e| warn!("Ignoring cached update manifest: {:#}", e))
+            .ok()
+    }
+
+    /// Whether the manifest is missing or was fetched longer than `interval` ago
+    pub fn is_stale(&self, interval: Duration) -> bool {
+        std::fs::metadata(self.dir.join(MANIFEST_FILE))
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|fetched| SystemTime::now().duration_since(fetched).ok())
+            .is_none_or(|age| age >= interval)
+    }
+
+    /// Download the manifest and its signature, replacing the cached ones
+    /// only when they verify
+    pub fn refresh(&self, url: &str) -> anyhow::Result<UpdateManifest> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+
+        let manifest = self.dir.join(format!("{}.part", MANIFEST_FILE));
+        let signature = self.dir.join(format!("{}.part", SIGNATURE_FILE));
+        download(url, &manifest)?;
+        download(&format!("{}.minisig", url), &signature)?;
+
+        let parsed = self.accept(
+            &std::fs::read(&manifest)?,
+            &std::fs::read_to_string(&signature)?,
+        );
+        if parsed.is_ok() {
+            std::fs::rename(&signature, self.dir.join(SIGNATURE_FILE))?;
+            std::fs::rename(&manifest, self.dir.join(MANIFEST_FILE))?;
+        } else {
+            let _ = std::fs::remove_file(&signature);
+            let _ = std::fs::remove_file(&manifest);
+        }
+        parsed
+    }
+
+    /// A fetched manifest that verifies and is not older than the cached
+    /// one, so a replayed old manifest cannot roll workarounds back
+    fn accept(&self, content: &[u8], signature: &str) -> anyhow::Result<UpdateManifest> {
+        let manifest = UpdateManifest::verify(content, signature, &self.public_key)?;
+        if let Some(cached) = self.load() {
+            anyhow::ensure!(
+                manifest.version >= cached.version,
+                "Update manifest version {} is older than the cached version {}",
+                manifest.version,
+                cached.version
+            );
+        }
+        Ok(manifest)
+    }
+}
+
+/// The published manifest when update checks are enabled, fetched again
+/// once the cached one is older than the check interval
+pub fn check(config: &UpdatesConfig, cache_dir: &Path) -> Option<UpdateManifest> {
+    if !config.check {
+        return None;
+    }
+    let (Some(url), Some(public_key)) = (&config.url, &config.public_key) else {
+        warn!("Update checks need both url and public_key under [updates]");
+        return None;
+    };
+
+    let cache = UpdateCache::new(cache_dir, public_key);
+    if !cache.is_stale(Duration::from_secs(config.interval_hours * 3600)) {
+        debug!("Update manifest is recent, not fetching it");
+        return cache.load();
+    }
+
+    match cache.refresh(url) {
+        Ok(manifest) => {
+            info!("Fetched update manifest version {}", manifest.version);
+            Some(manifest)
+        }
+        Err(e) => {
+            warn!("{:#}, using the cached update manifest", e);
+            cache.load()
+        }
+    }
+}
+
+fn download(url: &str, dest: &Path) -> anyhow::Result<()> {
+    debug!("Downloading {}", url);
+    let status = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .args(["--max-time", DOWNLOAD_TIMEOUT_SECS, "--output"])
+        .arg(dest)
+        .arg(url)
+        .status()
+        .context("Failed to run curl")?;
+    anyhow::ensure!(status.success(), "Failed to download {}", url);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_KEY: &str = "RWSguKSUyH1XVOoCNYKx6Ob+6d+ScVgGwq6Trg81Nm8RvD+oLeWRQ6vD";
+
+    /// A key nothing here is signed with
+    const OTHER_KEY: &str = "RWRCI0X/s6EP+T9nZKDvUxsXvA4v19hNoPDrK/cY+Dj+r/x2lbHFP3/B";
+
+    const MANIFEST: &str = r#"{"version":2,"workarounds":[{"name":"Menu stutter","description":"Caps the shader cache","exe":["game"],"env":{"DXVK_STATE_CACHE":"0"}}],"defaults":{"DXVK_LOG_LEVEL":"none"}}"#;
+
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUSguKSUyH1XVB5wiZF5ye/dWuYw7+gVs+F3JzmmTIgMnlbLhRZgixGL+7Q16SXc4W/IYgqiB9dI8jYqxTedD9oiut0tv1FeMAM=
+trusted comment: timestamp:1790000000\tfile:updates.json
+phdySY1yDI0pN9fJb42lknAMlq7Hjo0jKJ55KKNcFwk9/DxTt4eRHqMI3wQs+G2YHDcxM+vsJDP3dnwDNxwACQ==
+";
+
+    /// An older manifest, signed with the same key
+    const OLD_MANIFEST: &str = r#"{"version":1}"#;
+
+    const OLD_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUSguKSUyH1XVEkpvYoKDONWtxoavwWU9TkJoPbwxRT0iA2wA84QfPQtdX+kuOeVeVoFQ0f3plbUyDv9znWX+u+nPkx3NMdHUwI=
+trusted comment: timestamp:1780000000\tfile:updates.json
+ELcekxni7qevQitoyx4vj1OouWnpHoXtMtuHn9B9NZTD7pzE71520FcDaPGWiJqkvrJdc+raVbvjgVOBMYuYCw==
+";
+
+    #[test]
+    fn test_verify_manifest() {
+        let manifest = UpdateManifest::verify(MANIFEST.as_bytes(), SIGNATURE, TEST_KEY).unwrap();
+        assert_eq!(manifest.version, 2);
+        assert_eq!(manifest.workarounds[0].name, "Menu stutter");
+        assert!(manifest.workarounds[0].applies_to("game", None));
+
+        let tampered = MANIFEST.replace("\"0\"", "\"1\"");
+        assert!(UpdateManifest::verify(tampered.as_bytes(), SIGNATURE, TEST_KEY).is_err());
+        assert!(UpdateManifest::verify(MANIFEST.as_bytes(), SIGNATURE, OTHER_KEY).is_err());
+    }
+
+    #[test]
+    fn test_changed_defaults() {
+        let manifest = UpdateManifest {
+            defaults: BTreeMap::from([
+                ("DXVK_LOG_LEVEL".to_string(), "none".to_string()),
+                ("__GL_YIELD".to_string(), "USLEEP".to_string()),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            manifest.changed_defaults(),
+            BTreeMap::from([("DXVK_LOG_LEVEL", "none")])
+        );
+    }
+
+    #[test]
+    fn test_update_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = UpdateCache::new(dir.path(), TEST_KEY);
+        assert!(cache.load().is_none());
+        assert!(cache.is_stale(Duration::from_secs(3600)));
+
+        std::fs::write(dir.path().join(MANIFEST_FILE), MANIFEST).unwrap();
+        std::fs::write(dir.path().join(SIGNATURE_FILE), SIGNATURE).unwrap();
+        assert_eq!(cache.load().unwrap().version, 2);
+        assert!(!cache.is_stale(Duration::from_secs(3600)));
+        assert!(cache.is_stale(Duration::ZERO));
+
+        // Older than the cached one, though signed
+        assert_eq!(
+            UpdateManifest::verify(OLD_MANIFEST.as_bytes(), OLD_SIGNATURE, TEST_KEY)
+                .unwrap()
+                .version,
+            1
+        );
+        let err = cache
+            .accept(OLD_MANIFEST.as_bytes(), OLD_SIGNATURE)
+            .unwrap_err();
+        assert!(err.to_string().contains("older than the cached version 2"));
+        assert_eq!(
+            cache
+                .accept(MANIFEST.as_bytes(), SIGNATURE)
+                .unwrap()
+                .version,
+            2
+        );
+
+        std::fs::write(dir.path().join(MANIFEST_FILE), "{\"version\":3}").unwrap();
+        assert!(cache.load().is_none());
+    }
+}
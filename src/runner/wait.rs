@@ -0,0 +1,106 @@
+use log::{debug, info};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crate::common::config::WaitCondition;
+
+/// Time between checks of a condition that is not met yet
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long a single connection attempt to a port may take
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Wait for every condition in order, each given up to `timeout`
+pub fn wait_for(conditions: &[WaitCondition], timeout: Duration) -> anyhow::Result<()> {
+    for condition in conditions {
+        if let WaitCondition::Delay { delay_ms } = condition {
+            info!("Waiting {} ms before launch", delay_ms);
+            std::thread::sleep(Duration::from_millis(*delay_ms));
+            continue;
+        }
+
+        info!("Waiting for {}", condition);
+        let started = Instant::now();
+        loop {
+            let state = match check(condition) {
+                Ok(()) => break,
+                Err(state) => state,
+            };
+            if started.elapsed() >= timeout {
+                anyhow::bail!(
+                    "Gave up waiting for {} after {}s, it is {}",
+                    condition,
+                    timeout.as_secs(),
+                    state
+                );
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        debug!("{} is ready after {:?}", condition, started.elapsed());
+    }
+    Ok(())
+}
+
+/// Whether a condition is met, or what it is instead
+fn check(condition: &WaitCondition) -> Result<(), String> {
+    match condition {
+        WaitCondition::Unit { unit } => {
+            let state = unit_state(unit, true);
+            if state == "active" || unit_state(unit, false) == "active" {
+                return Ok(());
+            }
+            Err(state)
+        }
+        WaitCondition::Port { port, host } => {
+            let host = host.as_deref().unwrap_or("127.0.0.1");
+            let addrs = (host, *port)
+                .to_socket_addrs()
+                .map_err(|e| format!("unresolved ({})", e))?;
+            for addr in addrs {
+                if TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).is_ok() {
+                    return Ok(());
+                }
+            }
+            Err("not accepting connections".to_string())
+        }
+        WaitCondition::Delay { .. } => Ok(()),
+    }
+}
+
+/// `systemctl is-active` of a unit in the user or system manager
+fn unit_state(unit: &str, user: bool) -> String {
+    let mut command = Command::new("systemctl");
+    if user {
+        command.arg("--user");
+    }
+    match command.args(["is-active", unit]).output() {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        Err(e) => format!("unknown ({})", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_wait_for_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let conditions = [
+            WaitCondition::Delay { delay_ms: 1 },
+            WaitCondition::Port { port, host: None },
+        ];
+        assert!(wait_for(&conditions, Duration::from_secs(1)).is_ok());
+
+        drop(listener);
+        let err = wait_for(&conditions, Duration::ZERO).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains(&format!("port 127.0.0.1:{}", port))
+        );
+        assert!(err.to_string().contains("not accepting connections"));
+    }
+}
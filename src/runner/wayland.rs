@@ -0,0 +1,239 @@
+use anyhow::Context;
+use log::{debug, info, warn};
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use nix::sys::memfd::{MFdFlags, memfd_create};
+use std::fs::File;
+use std::os::fd::AsFd;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use wayland_client::globals::{GlobalListContents, registry_queue_init};
+use wayland_client::protocol::{
+    wl_buffer::WlBuffer, wl_compositor::WlCompositor, wl_region::WlRegion, wl_registry::WlRegistry,
+    wl_shm, wl_shm::WlShm, wl_shm_pool::WlShmPool, wl_surface::WlSurface,
+};
+use wayland_client::{Connection, Dispatch, EventQueue, QueueHandle, delegate_noop};
+use wayland_protocols::wp::idle_inhibit::zv1::client::{
+    zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1, zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1,
+};
+use wayland_protocols_wlr::layer_shell::v1::client::{
+    zwlr_layer_shell_v1::{self, ZwlrLayerShellV1},
+    zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
+};
+
+/// How often the event loop checks whether the inhibitor was released
+const POLL_INTERVAL_MS: u16 = 250;
+
+/// Keeps the Wayland compositor from blanking the screen for as long as it
+/// is alive, for compositors that ignore logind's idle lock and lack the
+/// ScreenSaver D-Bus interface. Gamepad input does not count as activity
+/// for most of them.
+pub struct WaylandIdleInhibitor {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WaylandIdleInhibitor {
+    /// `None` outside a Wayland session or when the compositor lacks the
+    /// protocols. Failures are logged since the logind lock still applies.
+    pub fn start() -> Option<Self> {
+        std::env::var_os("WAYLAND_DISPLAY")?;
+
+        let inhibit = match Inhibit::connect() {
+            Ok(inhibit) => inhibit,
+            Err(e) => {
+                warn!("{:#}, the screen may blank during the session", e);
+                return None;
+            }
+        };
+        info!("Inhibiting Wayland idle for the session");
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || inhibit.run(&stop))
+        };
+
+        Some(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for WaylandIdleInhibitor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        debug!("Released Wayland idle inhibitor");
+    }
+}
+
+/// Inhibitors only hold while their surface is visible, so a transparent
+/// 1x1 overlay that takes no input is mapped for it
+struct Inhibit {
+    queue: EventQueue<State>,
+    state: State,
+    surface: WlSurface,
+    layer: ZwlrLayerSurfaceV1,
+    buffer: WlBuffer,
+    inhibitor: ZwpIdleInhibitorV1,
+}
+
+#[derive(Default)]
+struct State {
+    configured: bool,
+    closed: bool,
+}
+
+impl Inhibit {
+    fn connect() -> anyhow::Result<Self> {
+        let conn = Connection::connect_to_env().context("Failed to connect to Wayland")?;
+        let (globals, mut queue) =
+            registry_queue_init::<State>(&conn).context("Failed to list Wayland globals")?;
+        let qh = queue.handle();
+
+        let manager: ZwpIdleInhibitManagerV1 = globals
+            .bind(&qh, 1..=1, ())
+            .context("Compositor does not support idle inhibition")?;
+        let layer_shell: ZwlrLayerShellV1 = globals
+            .bind(&qh, 1..=4, ())
+            .context("Compositor does not support layer surfaces")?;
+        let compositor: WlCompositor = globals
+            .bind(&qh, 1..=4, ())
+            .context("Compositor is missing wl_compositor")?;
+        let shm: WlShm = globals
+            .bind(&qh, 1..=1, ())
+            .context("Compositor is missing wl_shm")?;
+
+        let surface = compositor.create_surface(&qh, ());
+        let layer = layer_shell.get_layer_surface(
+            &surface,
+            None,
+            zwlr_layer_shell_v1::Layer::Overlay,
+            "nvprime".to_string(),
+            &qh,
+            (),
+        );
+        layer.set_size(1, 1);
+        layer.set_anchor(zwlr_layer_surface_v1::Anchor::Top | zwlr_layer_surface_v1::Anchor::Left);
+        surface.commit();
+
+        let mut state = State::default();
+        while !state.configured && !state.closed {
+            queue
+                .blocking_dispatch(&mut state)
+                .context("Failed to configure Wayland surface")?;
+        }
+        anyhow::ensure!(!state.closed, "Compositor closed the Wayland surface");
+
+        let region = compositor.create_region(&qh, ());
+        surface.set_input_region(Some(&region));
+        region.destroy();
+
+        let buffer = transparent_pixel(&shm, &qh)?;
+        surface.attach(Some(&buffer), 0, 0);
+        surface.commit();
+
+        let inhibitor = manager.create_inhibitor(&surface, &qh, ());
+        queue
+            .roundtrip(&mut state)
+            .context("Failed to inhibit Wayland idle")?;
+
+        Ok(Self {
+            queue,
+            state,
+            surface,
+            layer,
+            buffer,
+            inhibitor,
+        })
+    }
+
+    fn run(mut self, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) && !self.state.closed {
+            if let Err(e) = self.dispatch() {
+                warn!("Wayland idle inhibitor stopped: {:#}", e);
+                return;
+            }
+        }
+
+        self.inhibitor.destroy();
+        self.layer.destroy();
+        self.surface.destroy();
+        self.buffer.destroy();
+        let _ = self.queue.flush();
+    }
+
+    /// Handle pending events, waiting at most one poll interval for more
+    fn dispatch(&mut self) -> anyhow::Result<()> {
+        self.queue.dispatch_pending(&mut self.state)?;
+        self.queue.flush()?;
+
+        // Events were queued in the meantime, they are handled next round
+        let Some(guard) = self.queue.prepare_read() else {
+            return Ok(());
+        };
+        let mut fds = [PollFd::new(guard.connection_fd(), PollFlags::POLLIN)];
+        if poll(&mut fds, PollTimeout::from(POLL_INTERVAL_MS))? > 0 {
+            guard.read()?;
+        }
+        Ok(())
+    }
+}
+
+fn transparent_pixel(shm: &WlShm, qh: &QueueHandle<State>) -> anyhow::Result<WlBuffer> {
+    let fd = memfd_create("nvprime-idle", MFdFlags::MFD_CLOEXEC)
+        .context("Failed to create shared memory")?;
+    let file = File::from(fd);
+    file.set_len(4).context("Failed to size shared memory")?;
+
+    let pool = shm.create_pool(file.as_fd(), 4, qh, ());
+    let buffer = pool.create_buffer(0, 1, 1, 4, wl_shm::Format::Argb8888, qh, ());
+    pool.destroy();
+    Ok(buffer)
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: <WlRegistry as wayland_client::Proxy>::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        layer: &ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_layer_surface_v1::Event::Configure { serial, .. } => {
+                layer.ack_configure(serial);
+                state.configured = true;
+            }
+            zwlr_layer_surface_v1::Event::Closed => state.closed = true,
+            _ => {}
+        }
+    }
+}
+
+delegate_noop!(State: WlCompositor);
+delegate_noop!(State: WlRegion);
+delegate_noop!(State: WlShmPool);
+delegate_noop!(State: ZwlrLayerShellV1);
+delegate_noop!(State: ZwpIdleInhibitManagerV1);
+delegate_noop!(State: ZwpIdleInhibitorV1);
+delegate_noop!(State: ignore WlSurface);
+delegate_noop!(State: ignore WlShm);
+delegate_noop!(State: ignore WlBuffer);
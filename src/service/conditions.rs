@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::common::telemetry::TelemetrySample;
+
+/// The same condition is reported at most once in this window, so a GPU
+/// hovering around a limit does not start a hook every second
+const DEBOUNCE_MS: u64 = 60_000;
+
+/// A GPU condition session hooks can react to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConditionKind {
+    ThermalThrottle,
+    PowerThrottle,
+    /// Temperature reached the threshold in degrees Celsius
+    TempAbove(u32),
+}
+
+impl ConditionKind {
+    /// Threshold of `TempAbove`, `0` for the others
+    pub fn threshold_c(&self) -> u32 {
+        match self {
+            Self::TempAbove(threshold) => *threshold,
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for ConditionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ThermalThrottle => "thermal_throttle",
+            Self::PowerThrottle => "power_throttle",
+            Self::TempAbove(_) => "temp_above",
+        })
+    }
+}
+
+/// A condition that just started, broadcast as the `GpuCondition` signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpuCondition {
+    pub kind: ConditionKind,
+    pub temperature_c: u32,
+}
+
+/// Turns telemetry samples into condition events. A condition fires when it
+/// starts, and again only after it cleared and the debounce window passed.
+#[derive(Debug, Default)]
+pub struct ConditionMonitor {
+    active: HashMap<ConditionKind, bool>,
+    last_fired_ms: HashMap<ConditionKind, u64>,
+}
+
+impl ConditionMonitor {
+    pub fn update(
+        &mut self,
+        sample: &TelemetrySample,
+        thresholds: impl IntoIterator<Item = u32>,
+    ) -> Vec<GpuCondition> {
+        let mut conditions = vec![
+            (ConditionKind::ThermalThrottle, sample.thermal_throttle),
+            (ConditionKind::PowerThrottle, sample.power_throttle),
+        ];
+        conditions.extend(
+            thresholds
+                .into_iter()
+                .map(|t| (ConditionKind::TempAbove(t), sample.temperature_c >= t)),
+        );
+
+        let mut fired = Vec::new();
+        for (kind, now_active) in conditions {
+            let was_active = self.active.insert(kind, now_active).unwrap_or(false);
+            if !now_active || was_active {
+                continue;
+            }
+
+            let debounced = self
+                .last_fired_ms
+                .get(&kind)
+                .is_some_and(|last| sample.timestamp_ms.saturating_sub(*last) < DEBOUNCE_MS);
+            if debounced {
+                continue;
+            }
+
+            self.last_fired_ms.insert(kind, sample.timestamp_ms);
+            fired.push(GpuCondition {
+                kind,
+                temperature_c: sample.temperature_c,
+            });
+        }
+        fired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp_ms: u64, temperature_c: u32, thermal: bool) -> TelemetrySample {
+        TelemetrySample {
+            timestamp_ms,
+            temperature_c,
+            thermal_throttle: thermal,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_condition_monitor() {
+        let mut monitor = ConditionMonitor::default();
+        let kinds =
+            |fired: Vec<GpuCondition>| fired.into_iter().map(|c| c.kind).collect::<Vec<_>>();
+
+        assert!(monitor.update(&sample(0, 80, false), [87]).is_empty());
+        assert_eq!(
+            kinds(monitor.update(&sample(1_000, 88, true), [87])),
+            [ConditionKind::ThermalThrottle, ConditionKind::TempAbove(87)]
+        );
+
+        // Still going, nothing new
+        assert!(monitor.update(&sample(2_000, 89, true), [87]).is_empty());
+
+        // Cleared and back within the debounce window
+        assert!(monitor.update(&sample(3_000, 80, false), [87]).is_empty());
+        assert!(monitor.update(&sample(4_000, 88, true), [87]).is_empty());
+
+        // Cleared and back after it
+        assert!(monitor.update(&sample(70_000, 80, false), [87]).is_empty());
+        let fired = monitor.update(&sample(71_000, 90, false), [87]);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].kind.threshold_c(), 87);
+        assert_eq!(fired[0].temperature_c, 90);
+    }
+}
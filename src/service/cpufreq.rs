@@ -0,0 +1,178 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CPU_DIR: &str = "/sys/devices/system/cpu";
+
+/// Caps the maximum frequency of every core through `scaling_max_freq`,
+/// remembering each core's previous ceiling to put back afterwards
+#[derive(Debug)]
+pub struct CpuFreqCap {
+    max_mhz: u32,
+    /// `scaling_max_freq` files with the value they had, in kHz
+    original: Vec<(PathBuf, String)>,
+}
+
+impl CpuFreqCap {
+    /// Cap every core at `max_mhz`, need superuser access
+    pub fn apply(max_mhz: u32) -> Result<Self> {
+        Self::apply_in(Path::new(CPU_DIR), max_mhz)
+    }
+
+    fn apply_in(cpu_dir: &Path, max_mhz: u32) -> Result<Self> {
+        anyhow::ensure!(max_mhz > 0, "CPU frequency cap must not be zero");
+
+        let mut cap = Self {
+            max_mhz,
+            original: Vec::new(),
+        };
+        for freq_dir in core_freq_dirs(cpu_dir)? {
+            let path = freq_dir.join("scaling_max_freq");
+            let original = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            cap.original.push((path, original.trim().to_string()));
+        }
+        anyhow::ensure!(
+            !cap.original.is_empty(),
+            "No cpufreq scaling files found, is a cpufreq driver loaded?"
+        );
+
+        if let Err(e) = cap.set(max_mhz) {
+            let _ = cap.restore();
+            return Err(e);
+        }
+        Ok(cap)
+    }
+
+    /// Move the cap of an applied session, keeping the original ceilings
+    pub fn set(&mut self, max_mhz: u32) -> Result<()> {
+        for (path, _) in &self.original {
+            let khz = clamp_khz(path.parent().unwrap_or(path), max_mhz * 1000);
+            fs::write(path, khz.to_string())
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+
+        self.max_mhz = max_mhz;
+        info!(
+            "Capped CPU frequency at {}MHz on {} cores",
+            max_mhz,
+            self.original.len()
+        );
+        Ok(())
+    }
+
+    pub fn max_mhz(&self) -> u32 {
+        self.max_mhz
+    }
+
+    /// Put every core's previous ceiling back
+    pub fn restore(self) -> Result<()> {
+        let mut failed = 0;
+        for (path, original) in &self.original {
+            if let Err(e) = fs::write(path, original) {
+                debug!("Failed to restore {}: {}", path.display(), e);
+                failed += 1;
+            }
+        }
+
+        anyhow::ensure!(
+            failed == 0,
+            "Failed to restore the frequency ceiling of {} cores",
+            failed
+        );
+        info!("Restored CPU frequency ceilings");
+        Ok(())
+    }
+}
+
+/// `cpufreq` directories of the online cores, cpu0, cpu1 and so on
+fn core_freq_dirs(cpu_dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries =
+        fs::read_dir(cpu_dir).with_context(|| format!("Failed to read {}", cpu_dir.display()))?;
+
+    let mut dirs: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_prefix("cpu"))
+                .is_some_and(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+        })
+        .map(|entry| entry.path().join("cpufreq"))
+        .filter(|dir| dir.join("scaling_max_freq").exists())
+        .collect();
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Keep the requested ceiling within what the core supports, the kernel
+/// rejects anything outside of it
+fn clamp_khz(freq_dir: &Path, khz: u32) -> u32 {
+    let read = |name: &str| {
+        fs::read_to_string(freq_dir.join(name))
+            .ok()
+            .and_then(|v| v.trim().parse::<u32>().ok())
+    };
+
+    match (read("cpuinfo_min_freq"), read("cpuinfo_max_freq")) {
+        (Some(min), Some(max)) if min <= max => {
+            let clamped = khz.clamp(min, max);
+            if clamped != khz {
+                warn!(
+                    "CPU frequency cap {}MHz is out of range, using {}MHz",
+                    khz / 1000,
+                    clamped / 1000
+                );
+            }
+            clamped
+        }
+        _ => khz,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_core(cpu_dir: &Path, name: &str, max_khz: u32) -> PathBuf {
+        let dir = cpu_dir.join(name).join("cpufreq");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("scaling_max_freq"), format!("{}\n", max_khz)).unwrap();
+        fs::write(dir.join("cpuinfo_min_freq"), "400000\n").unwrap();
+        fs::write(dir.join("cpuinfo_max_freq"), format!("{}\n", max_khz)).unwrap();
+        dir.join("scaling_max_freq")
+    }
+
+    #[test]
+    fn test_cap_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let cpu0 = fake_core(dir.path(), "cpu0", 5_100_000);
+        let cpu1 = fake_core(dir.path(), "cpu1", 4_800_000);
+        fs::create_dir_all(dir.path().join("cpufreq")).unwrap();
+        fs::create_dir_all(dir.path().join("cpuidle")).unwrap();
+
+        let mut cap = CpuFreqCap::apply_in(dir.path(), 3500).unwrap();
+        assert_eq!(cap.max_mhz(), 3500);
+        assert_eq!(fs::read_to_string(&cpu0).unwrap(), "3500000");
+        assert_eq!(fs::read_to_string(&cpu1).unwrap(), "3500000");
+
+        // Above what cpu1 supports
+        cap.set(5000).unwrap();
+        assert_eq!(fs::read_to_string(&cpu0).unwrap(), "5000000");
+        assert_eq!(fs::read_to_string(&cpu1).unwrap(), "4800000");
+
+        cap.restore().unwrap();
+        assert_eq!(fs::read_to_string(&cpu0).unwrap(), "5100000");
+        assert_eq!(fs::read_to_string(&cpu1).unwrap(), "4800000");
+    }
+
+    #[test]
+    fn test_cap_without_cpufreq() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("cpu0")).unwrap();
+        assert!(CpuFreqCap::apply_in(dir.path(), 3500).is_err());
+        assert!(CpuFreqCap::apply_in(dir.path(), 0).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::{Context, Result};
+use log::{debug, error, info, warn};
+use zbus::proxy;
+use zbus::zvariant::{OwnedObjectPath, Value};
+
+use crate::common::cgroup;
+
+/// Units squeezed onto the background CPUs, a unit holding the game is left
+/// alone since a cgroup never gets CPUs its parent lacks
+const BACKGROUND_UNITS: &[&str] = &["system.slice", "user.slice", "init.scope"];
+
+const CPU_ONLINE: &str = "/sys/devices/system/cpu/online";
+
+#[proxy(
+    interface = "org.freedesktop.systemd1.Manager",
+    default_service = "org.freedesktop.systemd1",
+    default_path = "/org/freedesktop/systemd1"
+)]
+trait SystemdManager {
+    fn get_unit(&self, name: &str) -> zbus::Result<OwnedObjectPath>;
+    fn set_unit_properties(
+        &self,
+        name: &str,
+        runtime: bool,
+        properties: &[(&str, Value<'_>)],
+    ) -> zbus::Result<()>;
+}
+
+/// Background units confined to a few CPUs through systemd's `AllowedCPUs`,
+/// leaving the rest to the game. Set at runtime only, so a reboot drops it
+/// even when the daemon never got to put the originals back.
+pub struct CpuIsolation {
+    conn: zbus::blocking::Connection,
+    cpus: String,
+    /// Confined units with their original CPU mask, empty meaning all CPUs
+    originals: Vec<(String, Vec<u8>)>,
+}
+
+impl CpuIsolation {
+    /// Confine the background units to the CPUs in `cpus`, a list like
+    /// `0-1,8`, skipping the one the game of `pid` runs in
+    pub fn apply(cpus: &str, pid: u32) -> Result<Self> {
+        let mask = parse_cpu_list(cpus)?;
+        let online = std::fs::read_to_string(CPU_ONLINE)
+            .with_context(|| format!("Failed to read {}", CPU_ONLINE))?;
+        check_leaves_cpus(&mask, &parse_cpu_list(online.trim())?)?;
+
+        let game_cgroup = std::fs::read_to_string(format!("/proc/{}/cgroup", pid))
+            .ok()
+            .and_then(|content| cgroup::parse_cgroup_path(&content).map(str::to_string))
+            .with_context(|| format!("Could not find the cgroup v2 of PID {}", pid))?;
+
+        let conn =
+            zbus::blocking::Connection::system().context("Failed to connect to system bus")?;
+        let mut isolation = Self {
+            conn,
+            cpus: cpus.to_string(),
+            originals: Vec::new(),
+        };
+
+        for unit in BACKGROUND_UNITS {
+            if holds_cgroup(unit, &game_cgroup) {
+                warn!(
+                    "PID {} runs in {}, leaving its CPUs alone for background_cpus",
+                    pid, unit
+                );
+                continue;
+            }
+            match isolation.confine(unit, &mask) {
+                Ok(original) => isolation.originals.push((unit.to_string(), original)),
+                Err(e) => {
+                    if let Err(e) = isolation.release() {
+                        error!("Failed to roll back CPU isolation: {}", e);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        info!(
+            "Confined {} background units to CPUs {}",
+            isolation.originals.len(),
+            isolation.cpus
+        );
+        Ok(isolation)
+    }
+
+    /// Set the CPUs of one unit, returning the mask it had
+    fn confine(&self, unit: &str, mask: &[u8]) -> Result<Vec<u8>> {
+        let original = self
+            .allowed_cpus(unit)
+            .with_context(|| format!("Failed to read AllowedCPUs of {}", unit))?;
+        debug!("Setting AllowedCPUs of {} to {}", unit, self.cpus);
+        self.set_allowed_cpus(unit, mask)?;
+        Ok(original)
+    }
+
+    fn allowed_cpus(&self, unit: &str) -> Result<Vec<u8>> {
+        let path = SystemdManagerProxyBlocking::new(&self.conn)?.get_unit(unit)?;
+        let props = zbus::blocking::fdo::PropertiesProxy::builder(&self.conn)
+            .destination("org.freedesktop.systemd1")?
+            .path(path)?
+            .build()?;
+        let value = props.get(unit_interface(unit).try_into()?, "AllowedCPUs")?;
+        Ok(Vec::<u8>::try_from(value)?)
+    }
+
+    fn set_allowed_cpus(&self, unit: &str, mask: &[u8]) -> Result<()> {
+        SystemdManagerProxyBlocking::new(&self.conn)?
+            .set_unit_properties(unit, true, &[("AllowedCPUs", Value::from(mask))])
+            .with_context(|| format!("Failed to set AllowedCPUs of {}", unit))
+    }
+
+    pub fn cpus(&self) -> &str {
+        &self.cpus
+    }
+
+    /// Give every confined unit its original CPUs back
+    pub fn release(self) -> Result<()> {
+        let mut failed = 0;
+        for (unit, original) in &self.originals {
+            if let Err(e) = self.set_allowed_cpus(unit, original) {
+                error!("{:#}", e);
+                failed += 1;
+            }
+        }
+
+        anyhow::ensure!(
+            failed == 0,
+            "Failed to restore the CPUs of {} units",
+            failed
+        );
+        info!("Released background units from CPUs {}", self.cpus);
+        Ok(())
+    }
+}
+
+/// systemd interface carrying a unit's cgroup properties, by unit type
+fn unit_interface(unit: &str) -> &'static str {
+    match unit.rsplit_once('.').map(|(_, kind)| kind) {
+        Some("scope") => "org.freedesktop.systemd1.Scope",
+        Some("service") => "org.freedesktop.systemd1.Service",
+        _ => "org.freedesktop.systemd1.Slice",
+    }
+}
+
+/// Whether the cgroup path from `/proc/<pid>/cgroup` is inside `unit`
+fn holds_cgroup(unit: &str, cgroup_path: &str) -> bool {
+    cgroup_path.split('/').any(|part| part == unit)
+}
+
+/// A CPU list like `0-3,8` as the bitmask systemd takes for `AllowedCPUs`,
+/// bit `n % 8` of byte `n / 8` standing for CPU `n`
+pub fn parse_cpu_list(list: &str) -> Result<Vec<u8>> {
+    let mut mask = Vec::new();
+    for part in list.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (first, last) = part.split_once('-').unwrap_or((part, part));
+        let parse = |cpu: &str| {
+            cpu.trim()
+                .parse::<usize>()
+                .with_context(|| format!("Invalid CPU '{}' in '{}'", cpu, list))
+        };
+        let (first, last) = (parse(first)?, parse(last)?);
+        anyhow::ensure!(first <= last, "Invalid CPU range '{}'", part);
+
+        for cpu in first..=last {
+            if mask.len() <= cpu / 8 {
+                mask.resize(cpu / 8 + 1, 0);
+            }
+            mask[cpu / 8] |= 1 << (cpu % 8);
+        }
+    }
+    anyhow::ensure!(!mask.is_empty(), "CPU list '{}' is empty", list);
+    Ok(mask)
+}
+
+/// The background CPUs must exist and leave at least one CPU to the game
+fn check_leaves_cpus(mask: &[u8], online: &[u8]) -> Result<()> {
+    let bit =
+        |mask: &[u8], cpu: usize| mask.get(cpu / 8).is_some_and(|b| b & (1 << (cpu % 8)) != 0);
+    let cpus = mask.len().max(online.len()) * 8;
+
+    if let Some(cpu) = (0..cpus).find(|cpu| bit(mask, *cpu) && !bit(online, *cpu)) {
+        anyhow::bail!("background_cpus includes CPU {}, which is not online", cpu);
+    }
+    anyhow::ensure!(
+        (0..cpus).any(|cpu| bit(online, cpu) && !bit(mask, cpu)),
+        "background_cpus covers every online CPU, leaving none to the game"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0-1").unwrap(), [0b11]);
+        assert_eq!(parse_cpu_list("0,2, 9").unwrap(), [0b101, 0b10]);
+        assert_eq!(parse_cpu_list("8-15").unwrap(), [0, 0xff]);
+
+        assert!(parse_cpu_list("").is_err());
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("a").is_err());
+    }
+
+    #[test]
+    fn test_check_leaves_cpus() {
+        let online = parse_cpu_list("0-7").unwrap();
+        assert!(check_leaves_cpus(&parse_cpu_list("0-1").unwrap(), &online).is_ok());
+        assert!(check_leaves_cpus(&parse_cpu_list("0-7").unwrap(), &online).is_err());
+        assert!(check_leaves_cpus(&parse_cpu_list("6-9").unwrap(), &online).is_err());
+    }
+
+    #[test]
+    fn test_holds_cgroup() {
+        let game = "/user.slice/user-1000.slice/user@1000.service/app.slice/app-steam.scope";
+        assert!(holds_cgroup("user.slice", game));
+        assert!(!holds_cgroup("system.slice", game));
+        assert_eq!(
+            unit_interface("init.scope"),
+            "org.freedesktop.systemd1.Scope"
+        );
+        assert_eq!(
+            unit_interface("user.slice"),
+            "org.freedesktop.systemd1.Slice"
+        );
+    }
+}
@@ -1,28 +1,212 @@
 use crate::common::{
-    config::{CpuTune, GpuTune, SysTune},
-    nvgpu::NvGpu,
+    cgroup,
+    config::{CpuTune, GpuTune, IoprioClass, ScheduleEntry, SysTune},
+    ipc::{AppliedTuning, Capabilities},
+    nvgpu::{NvGpu, NvmlRetry},
+    proc,
+    telemetry::{TelemetryRing, TelemetrySample},
 };
+use crate::service::conditions::{ConditionMonitor, GpuCondition};
+use crate::service::cpufreq::CpuFreqCap;
+use crate::service::cpuisolate::CpuIsolation;
+use crate::service::dynepp::DynamicEppController;
+use crate::service::handoff::Handoff;
+use crate::service::inhibit::SleepInhibitor;
+use crate::service::memlow::MemoryProtection;
+use crate::service::mount::MountManager;
+use crate::service::netshape::NetShaper;
+use crate::service::oomadj;
+use crate::service::oomavoid::{EarlyoomAvoid, OomdAvoid};
+use crate::service::pmqos::PmQosGuard;
+use crate::service::policy::DaemonPolicy;
+use crate::service::powercap::PackageLimit;
+use crate::service::powerd::{self, CpuOwner, ProfileHold};
 use crate::service::ryzen::RyzenEPPManager;
+use crate::service::sched::{self, PriorityOwner};
+use crate::service::smt::SmtControl;
+use crate::service::swap::SwapTuning;
 use anyhow::{Context, Result};
-use log::{debug, error, info};
-use std::collections::HashSet;
-use std::path::Path;
+use chrono::NaiveTime;
+use log::{debug, error, info, warn};
+use std::collections::{BTreeMap, BTreeSet, HashMap, hash_map::Entry};
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Named session `nvprime boost` holds its tuning under
+pub const BOOST_PROFILE: &str = "boost";
+
+/// Named sessions `ApplyGpuTuning` and `ApplyCpuTuning` hold their tuning
+/// under, until released like any profile
+pub const MANUAL_GPU_PROFILE: &str = "manual-gpu";
+pub const MANUAL_CPU_PROFILE: &str = "manual-cpu";
+
+/// Present when the CPU frequency driver takes EPP hints, amd-pstate in
+/// active mode
+const EPP_PATH: &str = "/sys/devices/system/cpu/cpu0/cpufreq/energy_performance_preference";
+
+/// A game session, identified by the user that requested it and the PID of
+/// its client. Two users gaming at once never share a key, so tearing down
+/// one session leaves the other's state alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SessionKey {
+    pub uid: u32,
+    pub pid: u32,
+}
+
+impl SessionKey {
+    pub fn new(uid: u32, pid: u32) -> Self {
+        Self { uid, pid }
+    }
+}
+
+/// Why the daemon moved the GPU power limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerLimitReason {
+    /// A session's tuning, or the tuning left once another session ended
+    Tuning,
+    /// A scheduled profile
+    Schedule,
+    /// Back to the driver default
+    Restore,
+}
+
+impl fmt::Display for PowerLimitReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Tuning => "tuning",
+            Self::Schedule => "schedule",
+            Self::Restore => "restore",
+        })
+    }
+}
+
+/// Tuning requested by name for an application not launched through
+/// nvprime, held until every request for it was released
+#[derive(Debug, Clone)]
+pub struct NamedSession {
+    pub refs: u32,
+    pub gpu: GpuTune,
+}
+
+/// A power limit change, broadcast as the `PowerLimitChanged` signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerLimitChange {
+    pub old_mw: u32,
+    pub new_mw: u32,
+    pub reason: PowerLimitReason,
+}
+
+/// What happened to the sessions, for publishers outside D-Bus
+#[derive(Debug, Clone)]
+pub enum SessionEvent {
+    /// A game session took its tuning, with the game's name when the client
+    /// sent one
+    Started {
+        pid: u32,
+        game: Option<String>,
+    },
+    Ended {
+        pid: u32,
+    },
+    /// GPU sample taken while sessions are active
+    Telemetry(TelemetrySample),
+}
 
 pub struct DaemonState {
     pub gpu: Option<NvGpu>,
-    pub active_pids: HashSet<u32>,
+    pub active_sessions: HashMap<SessionKey, GpuTune>,
+    /// Start time of each session's process, telling it apart from a later
+    /// process that got the same PID
+    pub session_start_times: HashMap<SessionKey, u64>,
+    /// Profiles applied with `apply-profile`, by owner and name
+    pub named_sessions: BTreeMap<(u32, String), NamedSession>,
     pub baseline_power_limit: Option<u32>,
+    /// Memory clock floor locked for the sessions, in MHz
+    pub mem_clock_floor: Option<u32>,
     pub baseline_epp: Option<String>,
+    /// CPU tuning last applied for a session, kept for the next instance
+    pub cpu_tuning: Option<CpuTune>,
+    /// power-profiles-daemon profile held instead of writing EPP
+    pub ppd_hold: Option<ProfileHold>,
+    /// Set while a session asked for EPP to follow the game's bottleneck
+    pub dynamic_epp: Option<DynamicEppController>,
+    pub original_priorities: HashMap<SessionKey, i32>,
+    /// Sessions whose priorities a scheduler takes care of instead
+    pub delegated_priorities: HashMap<SessionKey, PriorityOwner>,
+    pub pmqos: Option<PmQosGuard>,
+    pub cpu_freq_cap: Option<CpuFreqCap>,
+    pub smt: Option<SmtControl>,
+    pub package_limit: Option<PackageLimit>,
+    pub telemetry: TelemetryRing,
+    pub session_mounts: HashMap<SessionKey, Vec<PathBuf>>,
+    pub inhibitors: HashMap<SessionKey, SleepInhibitor>,
+    pub net_shaper: Option<NetShaper>,
+    pub cpu_isolation: Option<CpuIsolation>,
+    pub swap_tuning: Option<SwapTuning>,
+    pub original_oom_scores: HashMap<SessionKey, Vec<(u32, i32)>>,
+    /// Cgroups systemd-oomd avoids, by the session that marked them
+    pub oomd_avoid: HashMap<SessionKey, OomdAvoid>,
+    /// Set while earlyoom avoids the games of the sessions
+    pub earlyoom: Option<EarlyoomAvoid>,
+    pub memory_protection: HashMap<SessionKey, MemoryProtection>,
+    pub scheduled: Option<ScheduleEntry>,
+    /// Profiles the scheduler picks from, replaced on config reload
+    pub schedule_entries: Vec<ScheduleEntry>,
+    pub policy: DaemonPolicy,
+    /// Receives every power limit change, to be sent out as D-Bus signals
+    pub power_changes: Option<UnboundedSender<PowerLimitChange>>,
+    /// GPU temperatures sessions want to hear about, for their hooks
+    pub temp_alerts: HashMap<SessionKey, u32>,
+    pub conditions: ConditionMonitor,
+    /// Receives every GPU condition that started, sent out as D-Bus signals
+    pub condition_events: Option<UnboundedSender<GpuCondition>>,
+    /// Receives sessions starting and ending along with telemetry, for the
+    /// MQTT publisher
+    pub session_events: Option<UnboundedSender<SessionEvent>>,
+    /// Where the sessions are written for a restarted daemon to adopt
+    pub handoff_path: Option<PathBuf>,
 }
 
 impl DaemonState {
     pub fn new() -> Self {
         Self {
             gpu: None,
-            active_pids: HashSet::new(),
+            active_sessions: HashMap::new(),
+            session_start_times: HashMap::new(),
+            named_sessions: BTreeMap::new(),
             baseline_power_limit: None,
+            mem_clock_floor: None,
             baseline_epp: None,
+            cpu_tuning: None,
+            ppd_hold: None,
+            dynamic_epp: None,
+            original_priorities: HashMap::new(),
+            delegated_priorities: HashMap::new(),
+            pmqos: None,
+            cpu_freq_cap: None,
+            smt: None,
+            package_limit: None,
+            telemetry: TelemetryRing::default(),
+            session_mounts: HashMap::new(),
+            inhibitors: HashMap::new(),
+            net_shaper: None,
+            cpu_isolation: None,
+            swap_tuning: None,
+            original_oom_scores: HashMap::new(),
+            oomd_avoid: HashMap::new(),
+            earlyoom: None,
+            memory_protection: HashMap::new(),
+            scheduled: None,
+            schedule_entries: Vec::new(),
+            policy: DaemonPolicy::default(),
+            power_changes: None,
+            temp_alerts: HashMap::new(),
+            conditions: ConditionMonitor::default(),
+            condition_events: None,
+            session_events: None,
+            handoff_path: None,
         }
     }
 }
@@ -34,9 +218,11 @@ impl Default for DaemonState {
 }
 
 impl DaemonState {
-    pub fn init_gpu(&mut self, gpu_uuid: Option<String>) -> Result<()> {
+    pub fn init_gpu(&mut self, gpu_uuid: Option<String>, retry: NvmlRetry) -> Result<()> {
         info!("Initializing GPU");
-        let mut gpu = NvGpu::init(gpu_uuid).context("Failed to initialize NVML")?;
+        let mut gpu = NvGpu::init(gpu_uuid)
+            .context("Failed to initialize NVML")?
+            .with_retry(retry);
 
         gpu.log_gpu_info().context("Failed to get GPU info")?;
 
@@ -47,112 +233,1488 @@ impl DaemonState {
                 .context("Failed to get default power limit")?,
         );
 
-        self.gpu = Some(gpu);
-        Ok(())
-    }
+        self.gpu = Some(gpu);
+        Ok(())
+    }
+
+    pub fn apply_cpu_tuning(&mut self, cpu_config: &CpuTune) -> Result<()> {
+        if !cpu_config.enabled {
+            debug!("CPU tuning disabled, skipping");
+            return Ok(());
+        }
+
+        let owner = powerd::resolve(cpu_config.cpu_backend)?;
+        if self.cpu_tuning.is_none() {
+            for conflict in powerd::conflicts(owner) {
+                warn!("{}", conflict);
+            }
+        }
+
+        match owner {
+            CpuOwner::Sysfs => {
+                // Save the baseline EPP if not already saved (from config)
+                if self.baseline_epp.is_none() {
+                    self.baseline_epp = Some(cpu_config.amd_epp_base.clone());
+                }
+                RyzenEPPManager::set_epp(&cpu_config.amd_epp_tune)?;
+            }
+            CpuOwner::Ppd => match powerd::ppd_profile(&cpu_config.amd_epp_tune) {
+                Some(profile) if self.ppd_hold.as_ref().map(|h| h.profile()) != Some(profile) => {
+                    if let Some(hold) = self.ppd_hold.take() {
+                        hold.release()?;
+                    }
+                    self.ppd_hold = Some(ProfileHold::acquire(profile)?);
+                }
+                Some(_) => {}
+                None => info!(
+                    "EPP {} has no power-profiles-daemon profile, staying balanced",
+                    cpu_config.amd_epp_tune
+                ),
+            },
+        }
+        info!("Applied CPU tuning: {}", cpu_config.amd_epp_tune);
+        self.cpu_tuning = Some(cpu_config.clone());
+
+        if cpu_config.dynamic.enabled && self.dynamic_epp.is_none() {
+            if owner == CpuOwner::Ppd {
+                warn!("Dynamic EPP writes sysfs, not used with power-profiles-daemon");
+            } else if self.gpu.is_none() {
+                warn!("Dynamic EPP needs GPU tuning for the GPU utilization, skipping");
+            } else {
+                info!("Switching EPP with the game's bottleneck");
+                self.dynamic_epp = Some(DynamicEppController::new(
+                    cpu_config.dynamic.clone(),
+                    &cpu_config.amd_epp_tune,
+                ));
+            }
+        }
+
+        // Keep an already held request so repeated calls stay idempotent
+        if let Some(latency_us) = cpu_config.cpu_dma_latency_us
+            && self.pmqos.as_ref().map(|g| g.latency_us()) != Some(latency_us)
+        {
+            self.pmqos = Some(PmQosGuard::acquire(latency_us)?);
+        }
+
+        if let Some(mhz) = cpu_config.cpu_max_freq_mhz {
+            match &mut self.cpu_freq_cap {
+                Some(cap) if cap.max_mhz() == mhz => {}
+                Some(cap) => cap.set(mhz)?,
+                None => self.cpu_freq_cap = Some(CpuFreqCap::apply(mhz)?),
+            }
+        }
+
+        if let Some(mode) = cpu_config.smt {
+            match &mut self.smt {
+                Some(smt) if smt.mode() == mode => {}
+                Some(smt) => smt.set(mode)?,
+                None => self.smt = Some(SmtControl::apply(mode)?),
+            }
+        }
+
+        // Laptops without a writable RAPL zone still get the GPU share
+        if let Some(limit_mw) = cpu_config.ppt_limit {
+            let applied = match &mut self.package_limit {
+                Some(limit) if limit.limit_mw() == limit_mw => Ok(()),
+                Some(limit) => limit.set(limit_mw),
+                None => PackageLimit::apply(limit_mw).map(|limit| self.package_limit = Some(limit)),
+            };
+            if let Err(e) = applied {
+                warn!("CPU power limit not applied: {:#}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn apply_gpu_tuning(&mut self, gpu_config: &GpuTune) -> Result<()> {
+        if !gpu_config.enabled {
+            debug!("GPU tuning disabled, skipping");
+            return Ok(());
+        }
+
+        let gpu = self.gpu.as_mut().context("GPU not initialized")?;
+        let old = gpu.power_limit().ok();
+
+        gpu.set_power_limit(gpu_config.pwr_limit_tune, Some(gpu_config.set_max_pwr))
+            .context("Failed to set power limit")?;
+
+        let new = gpu.power_limit().ok();
+
+        // The floor follows the sessions, released once none asks for it
+        match gpu_config.min_mem_clock_mhz {
+            Some(mhz) if self.mem_clock_floor != Some(mhz) => {
+                gpu.set_min_mem_clock(mhz)
+                    .context("Failed to lock memory clock")?;
+                self.mem_clock_floor = Some(mhz);
+            }
+            None if self.mem_clock_floor.take().is_some() => {
+                gpu.reset_mem_clock()
+                    .context("Failed to reset memory clock")?;
+            }
+            _ => {}
+        }
+
+        self.report_power_limit(old, new, PowerLimitReason::Tuning);
+
+        info!("Applied GPU tuning");
+        Ok(())
+    }
+
+    pub fn apply_process_priority(&mut self, key: SessionKey, sys_config: &SysTune) -> Result<()> {
+        if !sys_config.enabled {
+            debug!("System tuning disabled, skipping");
+            return Ok(());
+        }
+
+        // The launch phase priorities come first, the watcher started by
+        // start_priority_phases switches to the gameplay ones later
+        let launch_phase = sys_config.launch_phase_sec.is_some();
+        let renice = if launch_phase {
+            sys_config.launch_renice
+        } else {
+            sys_config.proc_renice
+        };
+
+        anyhow::ensure!(
+            self.is_session_alive(key),
+            "Process {} is gone, its PID may belong to another process by now",
+            key.pid
+        );
+
+        match sched::resolve(sys_config.priority_backend)? {
+            PriorityOwner::Direct => {}
+            PriorityOwner::System76 => {
+                sched::set_foreground(key.pid)?;
+                info!(
+                    "Handed process {} to system76-scheduler as the foreground process",
+                    key.pid
+                );
+                self.delegated_priorities
+                    .insert(key, PriorityOwner::System76);
+                return Ok(());
+            }
+            PriorityOwner::SchedExt(name) => {
+                info!(
+                    "sched_ext scheduler {} is running, leaving the priorities of process {} to it",
+                    name, key.pid
+                );
+                self.delegated_priorities
+                    .insert(key, PriorityOwner::SchedExt(name));
+                return Ok(());
+            }
+        }
+
+        if renice != 0 || (launch_phase && sys_config.proc_renice != 0) {
+            // Only remember the first value we see, so applying the same
+            // tuning twice does not overwrite the real original priority
+            if let Entry::Vacant(entry) = self.original_priorities.entry(key) {
+                entry.insert(get_priority(key.pid)?);
+            }
+        }
+
+        if renice != 0 {
+            set_priority(key.pid, renice)?;
+            info!("Set process {} priority to {}", key.pid, renice);
+        }
+
+        // Threads that exist already do not pick up a new IO priority from
+        // the process, so every one of them is set
+        let (class, level, phase) = if launch_phase {
+            (
+                IoprioClass::Be,
+                sys_config.launch_ioprio,
+                "the launch phase",
+            )
+        } else {
+            (
+                sys_config.proc_ioprio_class,
+                sys_config.proc_ioprio,
+                "gameplay",
+            )
+        };
+        set_ioprio(key.pid, class, level)?;
+        let threads = set_tree_ioprio(key.pid, class, level);
+        info!(
+            "Set IO priority of {} threads of PID {} to {:?} {} for {}",
+            threads, key.pid, class, level, phase
+        );
+
+        Ok(())
+    }
+
+    /// Move every thread of the session's process tree to the gameplay
+    /// priorities. Threads only inherit priorities when they are created,
+    /// so the running game has to be walked thread by thread.
+    pub fn apply_gameplay_priority(&mut self, key: SessionKey, sys_config: &SysTune) {
+        // Without a gameplay niceness the launch one is undone
+        let nice = match sys_config.proc_renice {
+            0 => self.original_priorities.get(&key).copied(),
+            renice => Some(renice),
+        };
+
+        if !self.is_session_alive(key) {
+            debug!("Process {} is gone, skipping gameplay priorities", key.pid);
+            return;
+        }
+
+        if let Some(owner) = self.delegated_priorities.get(&key) {
+            debug!("Priorities of process {} are left to {:?}", key.pid, owner);
+            return;
+        }
+
+        let threads = proc::tree_threads(key.pid);
+        for tid in &threads {
+            // Threads may exit while we walk them
+            if let Some(nice) = nice
+                && let Err(e) = set_priority(*tid, nice)
+            {
+                debug!("Failed to set priority of thread {}: {}", tid, e);
+            }
+            if let Err(e) = set_ioprio(*tid, sys_config.proc_ioprio_class, sys_config.proc_ioprio) {
+                debug!("Failed to set IO priority of thread {}: {}", tid, e);
+            }
+        }
+
+        info!(
+            "Switched {} threads of PID {} to gameplay priorities",
+            threads.len(),
+            key.pid
+        );
+    }
+
+    pub fn restore_process_priority(&mut self, key: SessionKey) -> Result<()> {
+        let pid = key.pid;
+        if let Some(original) = self.original_priorities.remove(&key) {
+            if !self.is_session_alive(key) {
+                debug!(
+                    "Process {} no longer exists, skipping priority restore",
+                    pid
+                );
+                return Ok(());
+            }
+
+            set_priority(pid, original)?;
+            info!("Restored process {} priority to {}", pid, original);
+        }
+        Ok(())
+    }
+
+    pub fn apply_net_shaping(&mut self, sys_config: &SysTune) -> Result<()> {
+        let Some(limit_kbit) = sys_config.link_limit_kbit else {
+            return Ok(());
+        };
+
+        if !sys_config.enabled {
+            debug!("System tuning disabled, skipping link limit");
+            return Ok(());
+        }
+
+        if self.net_shaper.is_some() {
+            debug!("Link limit already active, skipping");
+            return Ok(());
+        }
+
+        self.net_shaper = Some(NetShaper::apply(
+            sys_config.link_iface.as_deref(),
+            limit_kbit,
+        )?);
+        Ok(())
+    }
+
+    /// Set the zswap and zram options of `[sys.memory]` for the sessions,
+    /// the first session's options staying until the last one ends
+    pub fn apply_swap_tuning(&mut self, sys_config: &SysTune) -> Result<()> {
+        if sys_config.memory.is_empty() {
+            return Ok(());
+        }
+
+        if !sys_config.enabled {
+            debug!("System tuning disabled, skipping swap tuning");
+            return Ok(());
+        }
+
+        if self.swap_tuning.is_some() {
+            debug!("Swap tuning already active, skipping");
+            return Ok(());
+        }
+
+        self.swap_tuning = Some(SwapTuning::apply(&sys_config.memory)?);
+        Ok(())
+    }
+
+    pub fn restore_swap_tuning(&mut self) -> Result<()> {
+        if let Some(tuning) = self.swap_tuning.take() {
+            tuning.restore()?;
+        }
+        Ok(())
+    }
+
+    pub fn restore_net_defaults(&mut self) -> Result<()> {
+        if let Some(shaper) = self.net_shaper.take() {
+            shaper.release().context("Failed to remove link limit")?;
+        }
+        Ok(())
+    }
+
+    /// Confine the background units to `background_cpus` for the sessions,
+    /// the first session's game deciding which unit is left alone
+    pub fn apply_cpu_isolation(&mut self, pid: u32, sys_config: &SysTune) -> Result<()> {
+        let Some(cpus) = sys_config.background_cpus.as_deref() else {
+            return Ok(());
+        };
+
+        if !sys_config.enabled {
+            debug!("System tuning disabled, skipping CPU isolation");
+            return Ok(());
+        }
+
+        if let Some(isolation) = &self.cpu_isolation {
+            debug!("Background units already on CPUs {}", isolation.cpus());
+            return Ok(());
+        }
+
+        self.cpu_isolation = Some(CpuIsolation::apply(cpus, pid)?);
+        Ok(())
+    }
+
+    pub fn restore_cpu_isolation(&mut self) -> Result<()> {
+        if let Some(isolation) = self.cpu_isolation.take() {
+            isolation
+                .release()
+                .context("Failed to release background CPUs")?;
+        }
+        Ok(())
+    }
+
+    pub fn restore_gpu_defaults(&mut self) -> Result<()> {
+        let Some(gpu) = self.gpu.as_mut() else {
+            return Ok(());
+        };
+        let old = gpu.power_limit().ok();
+        let mut result = Ok(());
+
+        // The memory clock is reset even when the power limit was not
+        if let Err(e) = gpu.restore_defaults() {
+            error!("Failed to restore GPU defaults: {}", e);
+            result = Err(e).context("Failed to restore GPU defaults");
+        }
+
+        if self.mem_clock_floor.take().is_some()
+            && let Err(e) = gpu.reset_mem_clock()
+        {
+            error!("Failed to reset memory clock: {}", e);
+            result = Err(e).context("Failed to reset memory clock");
+        }
+
+        let new = gpu.power_limit().ok();
+        self.report_power_limit(old, new, PowerLimitReason::Restore);
+        if result.is_ok() {
+            info!("Restored GPU to default settings");
+        }
+        result
+    }
+
+    /// Unlock GPU clocks, e.g. after another tuning tool crashed with them
+    /// pinned. Fails when GPU tuning is disabled, unlike the restores.
+    pub fn reset_gpu_clocks(&mut self) -> Result<()> {
+        self.gpu
+            .as_mut()
+            .context("GPU tuning is not enabled in the daemon")?
+            .reset_clocks()
+            .context("Failed to reset GPU clocks")?;
+        Ok(())
+    }
+
+    /// Log a power limit change with its reason and pass it on for the
+    /// `PowerLimitChanged` signal, nothing happens when the limit stayed
+    pub fn report_power_limit(&self, old: Option<u32>, new: Option<u32>, reason: PowerLimitReason) {
+        let (Some(old_mw), Some(new_mw)) = (old, new) else {
+            return;
+        };
+        if old_mw == new_mw {
+            return;
+        }
+
+        info!(
+            "Power limit changed from {}mW to {}mW ({})",
+            old_mw, new_mw, reason
+        );
+        if let Some(tx) = &self.power_changes {
+            // The receiver only goes away when the daemon shuts down
+            let _ = tx.send(PowerLimitChange {
+                old_mw,
+                new_mw,
+                reason,
+            });
+        }
+    }
+
+    pub fn restore_cpu_defaults(&mut self) -> Result<()> {
+        self.dynamic_epp = None;
+        self.cpu_tuning = None;
+
+        if self.pmqos.take().is_some() {
+            info!("Released CPU DMA latency request");
+        }
+
+        // Every step runs even when an earlier one failed
+        let mut result = Ok(());
+
+        if let Some(hold) = self.ppd_hold.take()
+            && let Err(e) = hold.release()
+        {
+            error!("Failed to release power-profiles-daemon hold: {}", e);
+            result = Err(e);
+        }
+
+        if let Some(base_epp) = &self.baseline_epp {
+            match RyzenEPPManager::set_epp(base_epp) {
+                Ok(()) => info!("Restored CPU EPP to default: {}", base_epp),
+                Err(e) => {
+                    error!("Failed to restore CPU EPP: {}", e);
+                    result = Err(e);
+                }
+            }
+        }
+
+        if let Some(cap) = self.cpu_freq_cap.take()
+            && let Err(e) = cap.restore()
+        {
+            error!("Failed to restore CPU frequency cap: {}", e);
+            result = Err(e);
+        }
+
+        if let Some(smt) = self.smt.take()
+            && let Err(e) = smt.restore()
+        {
+            error!("Failed to restore SMT: {}", e);
+            result = Err(e);
+        }
+
+        if let Some(limit) = self.package_limit.take()
+            && let Err(e) = limit.restore()
+        {
+            error!("Failed to restore package power limit: {}", e);
+            result = Err(e);
+        }
+        result
+    }
+
+    /// Mount the session's game drives, named in the policy's `[mounts]`,
+    /// rolling back on the first failure
+    pub fn apply_mounts(&mut self, key: SessionKey, names: &[String]) -> Result<()> {
+        let mounts = names
+            .iter()
+            .map(|name| {
+                self.policy
+                    .mounts
+                    .get(name)
+                    .cloned()
+                    .with_context(|| format!("No mount named '{}' in the daemon policy", name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for mount in &mounts {
+            let mountpoint = PathBuf::from(&mount.mountpoint);
+
+            // Mounted for another session, shared until the last one ends
+            if self.is_mount_held(&mountpoint) {
+                debug!("{} already mounted for another session", mount.mountpoint);
+                self.session_mounts.entry(key).or_default().push(mountpoint);
+                continue;
+            }
+
+            match MountManager::mount(mount) {
+                Ok(true) => self.session_mounts.entry(key).or_default().push(mountpoint),
+                Ok(false) => {}
+                Err(e) => {
+                    if let Err(e) = self.release_mounts(key) {
+                        error!("Failed to roll back mounts: {}", e);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the daemon mounted this for a session that still holds it
+    fn is_mount_held(&self, mountpoint: &Path) -> bool {
+        self.session_mounts
+            .values()
+            .any(|held| held.iter().any(|m| m == mountpoint))
+    }
+
+    /// Unmount what the session held and no other session still does, in
+    /// reverse order
+    pub fn release_mounts(&mut self, key: SessionKey) -> Result<()> {
+        let Some(mountpoints) = self.session_mounts.remove(&key) else {
+            return Ok(());
+        };
+
+        let mut result = Ok(());
+        for mountpoint in mountpoints.iter().rev() {
+            if self.is_mount_held(mountpoint) {
+                debug!("{} still held by another session", mountpoint.display());
+                continue;
+            }
+            if let Err(e) = MountManager::unmount(mountpoint) {
+                error!("Failed to unmount {}: {}", mountpoint.display(), e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Unmount the drives of every tracked session
+    pub fn release_all_mounts(&mut self) -> Result<()> {
+        let keys: Vec<SessionKey> = self.session_mounts.keys().copied().collect();
+        let mut result = Ok(());
+
+        for key in keys {
+            if let Err(e) = self.release_mounts(key) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Make the session's process tree a last resort for the OOM killer and
+    /// the named background helpers of the same user a first choice
+    pub fn apply_oom_protection(&mut self, key: SessionKey, sacrifice: &[String]) -> Result<()> {
+        let helpers = oomadj::find_processes(sacrifice, key.uid);
+
+        let targets = std::iter::once((key.pid, oomadj::PROTECT_SCORE))
+            .chain(helpers.into_iter().map(|p| (p, oomadj::SACRIFICE_SCORE)));
+
+        for (target, score) in targets {
+            let original = oomadj::get_oom_score_adj(target)?;
+            oomadj::set_oom_score_adj(target, score)?;
+            self.original_oom_scores
+                .entry(key)
+                .or_default()
+                .push((target, original));
+        }
+
+        info!("Protected PID {} from the OOM killer", key.pid);
+        Ok(())
+    }
+
+    /// Put back the OOM scores changed for a session, skipping exited processes
+    pub fn restore_oom_scores(&mut self, key: SessionKey) -> Result<()> {
+        let Some(scores) = self.original_oom_scores.remove(&key) else {
+            return Ok(());
+        };
+
+        let mut result = Ok(());
+        for (target, original) in scores {
+            if !Self::is_pid_alive(target) {
+                continue;
+            }
+            if let Err(e) = oomadj::set_oom_score_adj(target, original) {
+                error!("Failed to restore OOM score of PID {}: {}", target, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Restore the OOM scores of every tracked session
+    pub fn restore_all_oom_scores(&mut self) -> Result<()> {
+        let keys: Vec<SessionKey> = self.original_oom_scores.keys().copied().collect();
+        let mut result = Ok(());
+
+        for key in keys {
+            if let Err(e) = self.restore_oom_scores(key) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Keep systemd-oomd away from the session's cgroup and earlyoom away
+    /// from the game's executable, for whichever of them runs
+    pub fn apply_oom_avoidance(&mut self, key: SessionKey, exe: Option<&str>) -> Result<()> {
+        let mut result = Ok(());
+
+        if let Entry::Vacant(entry) = self.oomd_avoid.entry(key) {
+            match OomdAvoid::apply(key.pid) {
+                Ok(Some(avoid)) => {
+                    entry.insert(avoid);
+                }
+                Ok(None) => {}
+                Err(e) => result = Err(e),
+            }
+        }
+
+        if let Some(exe) = exe.filter(|exe| !exe.is_empty()) {
+            if self.earlyoom.is_none() {
+                self.earlyoom = EarlyoomAvoid::start();
+            }
+            if let Some(earlyoom) = &mut self.earlyoom
+                && let Err(e) = earlyoom.add(key.pid, exe)
+            {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Let the userspace OOM killers at the session's game again. A cgroup
+    /// other protected sessions still run in stays avoided, handed to one of
+    /// them.
+    pub fn release_oom_avoidance(&mut self, key: SessionKey) -> Result<()> {
+        let mut result = Ok(());
+
+        if let Some(avoid) = self.oomd_avoid.remove(&key) {
+            let heir = self.original_oom_scores.keys().copied().find(|other| {
+                *other != key
+                    && !self.oomd_avoid.contains_key(other)
+                    && cgroup::cgroup_of(other.pid).as_deref() == Some(avoid.cgroup())
+            });
+            match heir {
+                Some(heir) => {
+                    self.oomd_avoid.insert(heir, avoid);
+                }
+                None => {
+                    if let Err(e) = avoid.release() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+
+        if let Some(earlyoom) = &mut self.earlyoom {
+            if let Err(e) = earlyoom.remove(key.pid) {
+                result = Err(e);
+            }
+            if earlyoom.is_empty()
+                && let Some(earlyoom) = self.earlyoom.take()
+                && let Err(e) = earlyoom.release()
+            {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Undo every exclusion from the userspace OOM killers
+    pub fn release_all_oom_avoidance(&mut self) -> Result<()> {
+        let mut result = Ok(());
+
+        for (_, avoid) in self.oomd_avoid.drain() {
+            if let Err(e) = avoid.release() {
+                result = Err(e);
+            }
+        }
+        if let Some(earlyoom) = self.earlyoom.take()
+            && let Err(e) = earlyoom.release()
+        {
+            result = Err(e);
+        }
+        result
+    }
+
+    /// Protect `gb` gigabytes of the session's cgroup from reclaim
+    pub fn apply_memory_protection(&mut self, key: SessionKey, gb: u32) -> Result<()> {
+        if self.memory_protection.contains_key(&key) {
+            debug!("Memory protection already active for PID {}", key.pid);
+            return Ok(());
+        }
+
+        let protection = MemoryProtection::apply(key.pid, gb)?;
+        self.memory_protection.insert(key, protection);
+        Ok(())
+    }
+
+    /// Release a session's memory protection, then raise the cgroups of the
+    /// remaining sessions again in case they shared an ancestor
+    pub fn release_memory_protection(&mut self, key: SessionKey) -> Result<()> {
+        let Some(protection) = self.memory_protection.remove(&key) else {
+            return Ok(());
+        };
+
+        let result = protection.release();
+        for (other, protection) in &self.memory_protection {
+            if let Err(e) = protection.refresh() {
+                error!(
+                    "Failed to refresh memory protection of PID {}: {}",
+                    other.pid, e
+                );
+            }
+        }
+        result
+    }
+
+    /// Release the memory protection of every session
+    pub fn release_all_memory_protection(&mut self) -> Result<()> {
+        let mut result = Ok(());
+
+        for (_, protection) in self.memory_protection.drain() {
+            if let Err(e) = protection.release() {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    pub fn add_inhibitor(&mut self, key: SessionKey, inhibitor: SleepInhibitor) {
+        self.inhibitors.insert(key, inhibitor);
+    }
+
+    pub fn release_inhibitor(&mut self, key: SessionKey) {
+        if self.inhibitors.remove(&key).is_some() {
+            info!("Released idle and sleep inhibitor for PID {}", key.pid);
+        }
+    }
+
+    /// Track a session along with the GPU tuning it asked for
+    pub fn add_session(&mut self, key: SessionKey, gpu_config: GpuTune) {
+        self.active_sessions.insert(key, gpu_config);
+        if let Some(start_time) = proc::start_time(key.pid) {
+            self.session_start_times.entry(key).or_insert(start_time);
+        }
+    }
+
+    pub fn remove_session(&mut self, key: SessionKey) {
+        if self.active_sessions.remove(&key).is_some() {
+            self.send_event(SessionEvent::Ended { pid: key.pid });
+        }
+        self.temp_alerts.remove(&key);
+    }
+
+    /// Pass an event on to whoever listens, nobody does without MQTT
+    pub fn send_event(&self, event: SessionEvent) {
+        if let Some(tx) = &self.session_events {
+            let _ = tx.send(event);
+        }
+    }
+
+    /// Sessions with any state held by the daemon, limited to one user if given
+    pub fn session_keys(&self, uid: Option<u32>) -> BTreeSet<SessionKey> {
+        self.active_sessions
+            .keys()
+            .chain(self.original_priorities.keys())
+            .chain(self.session_mounts.keys())
+            .chain(self.inhibitors.keys())
+            .chain(self.original_oom_scores.keys())
+            .chain(self.oomd_avoid.keys())
+            .chain(self.memory_protection.keys())
+            .filter(|key| uid.is_none_or(|uid| key.uid == uid))
+            .copied()
+            .collect()
+    }
+
+    /// What a session's tuning came to, read back from the GPU, the CPUs
+    /// and the process instead of taken from the request
+    pub fn applied_tuning(&self, key: SessionKey) -> AppliedTuning {
+        let mut applied = AppliedTuning::default();
+
+        if self.effective_gpu_tuning().is_some_and(|gpu| gpu.enabled) {
+            applied.power_limit_mw = self.gpu.as_ref().and_then(|gpu| gpu.power_limit().ok());
+        }
+
+        if self.cpu_tuning.is_some() {
+            applied.epp = match &self.ppd_hold {
+                Some(hold) => Some(format!("power-profiles-daemon {}", hold.profile())),
+                None => std::fs::read_to_string(EPP_PATH)
+                    .ok()
+                    .map(|epp| epp.trim().to_string()),
+            };
+        }
+
+        match self.delegated_priorities.get(&key) {
+            Some(PriorityOwner::SchedExt(name)) => {
+                applied.delegate("renice", format!("sched_ext scheduler {}", name))
+            }
+            Some(_) => applied.delegate("renice", "system76-scheduler"),
+            None if self.original_priorities.contains_key(&key) => match get_priority(key.pid) {
+                Ok(nice) => {
+                    applied.renice.insert(key.pid, nice);
+                }
+                Err(e) => warn!("{}", e),
+            },
+            None => {}
+        }
+        applied
+    }
+
+    /// What this machine lets the daemon tune, for clients to leave out
+    /// the rest
+    pub fn capabilities(&self) -> Capabilities {
+        let mut caps = Capabilities::empty();
+        if self.gpu.is_some() {
+            caps.insert(Capabilities::GPU_TUNING);
+        }
+        if Path::new(cgroup::CGROUP_ROOT)
+            .join("cgroup.controllers")
+            .exists()
+        {
+            caps.insert(Capabilities::CGROUPS);
+        }
+        if Path::new(EPP_PATH).exists() {
+            caps.insert(Capabilities::AMD_EPP);
+        }
+        caps
+    }
+
+    /// Whether any game or named session holds tuning
+    pub fn has_sessions(&self) -> bool {
+        !self.active_sessions.is_empty() || !self.named_sessions.is_empty()
+    }
+
+    /// GPU tuning satisfying every active session, the most demanding wins
+    pub fn effective_gpu_tuning(&self) -> Option<GpuTune> {
+        merge_gpu_tuning(
+            self.active_sessions
+                .values()
+                .chain(self.named_sessions.values().map(|s| &s.gpu)),
+        )
+    }
+
+    /// Apply the GPU tuning the active sessions need together
+    pub fn apply_effective_gpu_tuning(&mut self) -> Result<()> {
+        match self.effective_gpu_tuning() {
+            Some(gpu_config) => self.apply_gpu_tuning(&gpu_config),
+            None => Ok(()),
+        }
+    }
+
+    /// Release everything held for one session, then bring the shared
+    /// settings in line with the sessions that are left
+    pub fn end_session(&mut self, key: SessionKey) -> Result<()> {
+        let mut result = Ok(());
+
+        self.remove_session(key);
+        self.release_inhibitor(key);
+
+        if let Err(e) = self.restore_process_priority(key) {
+            error!("Failed to restore process priority: {}", e);
+            result = Err(e);
+        }
+
+        if let Err(e) = self.release_mounts(key) {
+            error!("Failed to release mounts: {}", e);
+            result = Err(e);
+        }
+
+        // Before the OOM scores, which tell the sessions that share a cgroup
+        if let Err(e) = self.release_oom_avoidance(key) {
+            error!("Failed to release OOM killer exclusions: {}", e);
+            result = Err(e);
+        }
+
+        if let Err(e) = self.restore_oom_scores(key) {
+            error!("Failed to restore OOM scores: {}", e);
+            result = Err(e);
+        }
+
+        if let Err(e) = self.release_memory_protection(key) {
+            error!("Failed to release memory protection: {}", e);
+            result = Err(e);
+        }
+
+        if let Err(e) = self.reconcile_shared_tuning() {
+            result = Err(e);
+        }
+
+        self.delegated_priorities.remove(&key);
+        self.session_start_times.remove(&key);
+        result
+    }
+
+    /// Take a reference on a named profile, applying its tuning on the first
+    /// one. Returns how many references are held now.
+    pub fn acquire_named_session(
+        &mut self,
+        uid: u32,
+        name: &str,
+        cpu_config: &CpuTune,
+        gpu_config: GpuTune,
+    ) -> Result<u32> {
+        let key = (uid, name.to_string());
+        if let Some(session) = self.named_sessions.get_mut(&key) {
+            session.refs += 1;
+            debug!(
+                "Profile '{}' of UID {} now held {} times",
+                name, uid, session.refs
+            );
+            return Ok(session.refs);
+        }
+
+        info!("Applying profile '{}' for UID {}", name, uid);
+        self.named_sessions.insert(
+            key,
+            NamedSession {
+                refs: 1,
+                gpu: gpu_config,
+            },
+        );
+        self.persist();
+
+        if let Err(e) = self.apply_cpu_tuning(cpu_config) {
+            error!("Failed to apply CPU tuning: {}", e);
+        }
+        self.apply_effective_gpu_tuning()?;
+        Ok(1)
+    }
+
+    /// Hold tuning set outside a game or profile as a named session, a later
+    /// call under the same name replaces it instead of taking a reference
+    pub fn set_named_session(&mut self, uid: u32, name: &str, gpu_config: GpuTune) {
+        debug!("Holding '{}' tuning of UID {}", name, uid);
+        self.named_sessions.insert(
+            (uid, name.to_string()),
+            NamedSession {
+                refs: 1,
+                gpu: gpu_config,
+            },
+        );
+        self.persist();
+    }
+
+    /// Drop a reference on a named profile, its tuning is released with the
+    /// last one. Returns the references left, `None` when it was not held.
+    pub fn release_named_session(&mut self, uid: u32, name: &str) -> Result<Option<u32>> {
+        let key = (uid, name.to_string());
+        let Some(session) = self.named_sessions.get_mut(&key) else {
+            return Ok(None);
+        };
+
+        session.refs -= 1;
+        let refs = session.refs;
+        if refs == 0 {
+            info!("Releasing profile '{}' of UID {}", name, uid);
+            self.named_sessions.remove(&key);
+            self.reconcile_shared_tuning()?;
+        }
+        Ok(Some(refs))
+    }
+
+    /// Forget the named sessions of one user, or of everyone, without
+    /// reconciling the shared tuning yet. Returns their names.
+    pub fn discard_named_sessions(&mut self, uid: Option<u32>) -> Vec<String> {
+        let keys: Vec<(u32, String)> = self
+            .named_sessions
+            .keys()
+            .filter(|(owner, _)| uid.is_none_or(|uid| *owner == uid))
+            .cloned()
+            .collect();
+
+        for key in &keys {
+            warn!("Discarding profile '{}' of UID {}", key.1, key.0);
+            self.named_sessions.remove(key);
+        }
+        keys.into_iter().map(|(_, name)| name).collect()
+    }
+
+    /// Drop every session, of every user and whether or not its process is
+    /// still around, then put the shared settings back to their defaults.
+    /// For when the watchdog lost track, e.g. after a daemon restart.
+    pub fn discard_all_sessions(&mut self) -> Result<Vec<SessionKey>> {
+        let keys: Vec<SessionKey> = self.session_keys(None).into_iter().collect();
+        let mut result = Ok(());
+
+        self.discard_named_sessions(None);
+
+        for key in &keys {
+            let state = if self.is_session_alive(*key) {
+                "still running"
+            } else {
+                "gone"
+            };
+            warn!(
+                "Discarding session of UID {} PID {} ({})",
+                key.uid, key.pid, state
+            );
+
+            // Every release step forgets the session before undoing it, so
+            // nothing is left behind even when undoing fails
+            if let Err(e) = self.end_session(*key) {
+                result = Err(e);
+            }
+        }
+
+        if let Err(e) = self.reconcile_shared_tuning() {
+            result = Err(e);
+        }
+
+        result.map(|_| keys)
+    }
+
+    /// Restore system defaults once no session is left, otherwise re-apply
+    /// what the remaining sessions asked for
+    pub fn reconcile_shared_tuning(&mut self) -> Result<()> {
+        // Every session change ends up here
+        self.persist();
+
+        if self.has_sessions() {
+            return self
+                .apply_effective_gpu_tuning()
+                .inspect_err(|e| error!("Failed to re-apply GPU tuning: {}", e));
+        }
+
+        let mut result = Ok(());
+
+        if let Err(e) = self.restore_gpu_defaults() {
+            error!("Failed to restore GPU defaults: {}", e);
+            result = Err(e);
+        }
+        if let Err(e) = self.restore_cpu_defaults() {
+            error!("Failed to restore CPU defaults: {}", e);
+            result = Err(e);
+        }
+        if let Err(e) = self.restore_net_defaults() {
+            error!("Failed to restore network defaults: {}", e);
+            result = Err(e);
+        }
+        if let Err(e) = self.restore_cpu_isolation() {
+            error!("Failed to restore background CPUs: {}", e);
+            result = Err(e);
+        }
+        if let Err(e) = self.restore_swap_tuning() {
+            error!("Failed to restore swap settings: {}", e);
+            result = Err(e);
+        }
+        if let Err(e) = self.apply_scheduled_baseline() {
+            error!("Failed to apply scheduled profile: {}", e);
+            result = Err(e);
+        }
+
+        result
+    }
+
+    /// Replace the scheduled profiles, picked up on the scheduler's next tick
+    pub fn load_schedule(&mut self, entries: Vec<ScheduleEntry>, epp_base: &str) {
+        // Scheduled EPP hints need somewhere to return to once their window ends
+        if entries.iter().any(|e| e.amd_epp_tune.is_some()) {
+            self.baseline_epp
+                .get_or_insert_with(|| epp_base.to_string());
+        }
+        self.schedule_entries = entries;
+    }
+
+    /// Switch to another scheduled profile, applied right away unless a
+    /// session is active, in which case it takes over when the last one ends
+    pub fn set_schedule(&mut self, entry: Option<ScheduleEntry>) -> Result<()> {
+        if self.scheduled == entry {
+            return Ok(());
+        }
+
+        match &entry {
+            Some(entry) => info!(
+                "Entering scheduled profile {}-{}",
+                entry.start.format("%H:%M"),
+                entry.end.format("%H:%M")
+            ),
+            None => info!("Scheduled profile ended"),
+        }
+        self.scheduled = entry;
+
+        if self.has_sessions() {
+            debug!("Session active, deferring scheduled profile");
+            return Ok(());
+        }
+        self.reconcile_shared_tuning()
+    }
+
+    /// Apply the scheduled profile on top of the system defaults
+    fn apply_scheduled_baseline(&mut self) -> Result<()> {
+        let Some(entry) = self.scheduled.clone() else {
+            return Ok(());
+        };
+
+        if let Some(limit) = entry.pwr_limit_tune {
+            match self.gpu.as_mut() {
+                Some(gpu) => {
+                    let old = gpu.power_limit().ok();
+                    gpu.set_power_limit(Some(limit), Some(false))
+                        .context("Failed to set scheduled power limit")?;
+
+                    let new = gpu.power_limit().ok();
+                    self.report_power_limit(old, new, PowerLimitReason::Schedule);
+                }
+                None => debug!("GPU not initialized, skipping scheduled power limit"),
+            }
+        }
+
+        if let Some(epp) = &entry.amd_epp_tune {
+            RyzenEPPManager::set_epp(epp)?;
+        }
+        Ok(())
+    }
+
+    /// Take a utilization sample and switch EPP when the game's bottleneck
+    /// moved between GPU and CPU
+    pub fn update_dynamic_epp(&mut self) -> Result<()> {
+        let (Some(controller), Some(gpu)) = (self.dynamic_epp.as_mut(), self.gpu.as_ref()) else {
+            return Ok(());
+        };
+
+        let gpu_util = gpu
+            .utilization()
+            .context("Failed to read GPU utilization")?;
+        let Some(cpu_util) = controller.sample_cpu()? else {
+            return Ok(());
+        };
+
+        if let Some(epp) = controller.update(gpu_util, cpu_util).map(str::to_string) {
+            info!(
+                "Game is {}-bound (GPU {}%, busiest core {}%), switching EPP to {}",
+                if controller.is_gpu_bound() {
+                    "GPU"
+                } else {
+                    "CPU"
+                },
+                gpu_util,
+                cpu_util,
+                epp
+            );
+            RyzenEPPManager::set_epp(&epp)?;
+        }
+        Ok(())
+    }
+
+    /// Write the sessions for a restarted daemon, see `Handoff`
+    pub fn persist(&self) {
+        if let Some(path) = &self.handoff_path
+            && let Err(e) = Handoff::capture(self).save(path)
+        {
+            warn!("{:#}, a restarted daemon cannot adopt the sessions", e);
+        }
+    }
+
+    pub fn is_pid_alive(pid: u32) -> bool {
+        Path::new(&format!("/proc/{}", pid)).exists()
+    }
+
+    /// Whether the session's own process still runs, and not just another
+    /// one that got its PID
+    pub fn is_session_alive(&self, key: SessionKey) -> bool {
+        match self.session_start_times.get(&key) {
+            Some(start_time) => proc::start_time(key.pid) == Some(*start_time),
+            None => Self::is_pid_alive(key.pid),
+        }
+    }
+
+    /// Record a telemetry sample while at least one session is active
+    pub fn sample_telemetry(&mut self) -> Result<()> {
+        if !self.has_sessions() {
+            return Ok(());
+        }
+
+        if let Some(gpu) = self.gpu.as_ref() {
+            let mut sample = gpu.sample().context("Failed to sample GPU telemetry")?;
+            match gpu.process_vram() {
+                Ok(processes) => sample.game_vram_mib = self.game_vram(&processes),
+                Err(e) => debug!("Failed to read per-process VRAM: {}", e),
+            }
+            self.report_conditions(&sample);
+            self.send_event(SessionEvent::Telemetry(sample.clone()));
+            self.telemetry.push(sample);
+        }
+        Ok(())
+    }
+
+    /// VRAM of the processes each session's game started, by session PID
+    fn game_vram(&self, processes: &BTreeMap<u32, u64>) -> BTreeMap<u32, u64> {
+        let mut vram = BTreeMap::new();
+        for key in self.active_sessions.keys() {
+            let held: Vec<u64> = processes
+                .iter()
+                .filter(|(pid, _)| proc::is_descendant(**pid, key.pid))
+                .map(|(_, mib)| *mib)
+                .collect();
+            if !held.is_empty() {
+                vram.insert(key.pid, held.iter().sum());
+            }
+        }
+        vram
+    }
+
+    /// Pass on the GPU conditions that started with this sample
+    fn report_conditions(&mut self, sample: &TelemetrySample) {
+        let thresholds: BTreeSet<u32> = self.temp_alerts.values().copied().collect();
+        for condition in self.conditions.update(sample, thresholds) {
+            info!(
+                "GPU condition {} at {}°C",
+                condition.kind, condition.temperature_c
+            );
+            if let Some(tx) = &self.condition_events {
+                let _ = tx.send(condition);
+            }
+        }
+    }
+}
+
+fn get_priority(pid: u32) -> Result<i32> {
+    // getpriority can legitimately return -1, so errno is the only
+    // reliable way to tell a failure apart from a valid niceness
+    nix::errno::Errno::clear();
+    let priority = unsafe { libc::getpriority(libc::PRIO_PROCESS, pid) };
+
+    if priority == -1 && nix::errno::Errno::last_raw() != 0 {
+        anyhow::bail!(
+            "getpriority failed for PID {}: {}",
+            pid,
+            nix::errno::Errno::last()
+        );
+    }
+
+    Ok(priority)
+}
+
+fn set_priority(pid: u32, priority: i32) -> Result<()> {
+    unsafe {
+        let result = libc::setpriority(libc::PRIO_PROCESS, pid, priority);
+
+        if result != 0 {
+            anyhow::bail!("setpriority failed with code {}", result);
+        }
+    }
+    Ok(())
+}
+
+const IOPRIO_CLASS_SHIFT: i32 = 13;
+/// Applies to a single thread when given a thread ID
+const IOPRIO_WHO_PROCESS: i32 = 1;
+
+/// Value `ioprio_set` takes for a class and level, the idle class has none
+fn ioprio_value(class: IoprioClass, level: i32) -> i32 {
+    let (class, level) = match class {
+        IoprioClass::Rt => (1, level.clamp(0, 7)),
+        IoprioClass::Be => (2, level.clamp(0, 7)),
+        IoprioClass::Idle => (3, 0),
+    };
+    (class << IOPRIO_CLASS_SHIFT) | level
+}
+
+/// Set the IO priority of a thread and read it back, the kernel keeps the
+/// old one when a thread exits or the class is not permitted
+fn set_ioprio(tid: u32, class: IoprioClass, level: i32) -> Result<()> {
+    let ioprio = ioprio_value(class, level);
+    let result = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, tid, ioprio) };
+
+    if result != 0 {
+        anyhow::bail!(
+            "ioprio_set failed for PID {}: {}",
+            tid,
+            nix::errno::Errno::last()
+        );
+    }
+
+    let current = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, tid) };
+    anyhow::ensure!(
+        current == i64::from(ioprio),
+        "IO priority of PID {} reads back as {:#x} instead of {:#x}",
+        tid,
+        current,
+        ioprio
+    );
+    Ok(())
+}
+
+/// Set the IO priority of every thread in the process tree, returning how
+/// many took it
+fn set_tree_ioprio(pid: u32, class: IoprioClass, level: i32) -> usize {
+    proc::tree_threads(pid)
+        .into_iter()
+        .filter(|tid| match set_ioprio(*tid, class, level) {
+            Ok(()) => true,
+            // Threads may exit while we walk them
+            Err(e) => {
+                debug!("Failed to set IO priority of thread {}: {}", tid, e);
+                false
+            }
+        })
+        .count()
+}
+
+/// How often the launch phase checks whether it is over
+const LAUNCH_PHASE_POLL_SEC: u64 = 1;
 
-    pub fn apply_cpu_tuning(&mut self, cpu_config: &CpuTune) -> Result<()> {
-        if !cpu_config.enabled {
-            debug!("CPU tuning disabled, skipping");
-            return Ok(());
-        }
+/// Whether the launch phase has run its course, either by time or because
+/// the GPU got busy rendering gameplay
+fn launch_phase_over(
+    elapsed_sec: u64,
+    sys_config: &SysTune,
+    gpu_util: impl FnOnce() -> Option<u32>,
+) -> bool {
+    if sys_config
+        .launch_phase_sec
+        .is_none_or(|phase_sec| elapsed_sec >= phase_sec)
+    {
+        return true;
+    }
 
-        // Save the baseline EPP if not already saved (from config)
-        if self.baseline_epp.is_none() {
-            self.baseline_epp = Some(cpu_config.amd_epp_base.clone());
-        }
+    sys_config
+        .launch_gpu_util
+        .is_some_and(|threshold| gpu_util().is_some_and(|util| util >= threshold))
+}
 
-        RyzenEPPManager::set_epp(&cpu_config.amd_epp_tune)?;
-        info!("Applied CPU tuning: {}", cpu_config.amd_epp_tune);
-        Ok(())
+/// Switch a session from its launch phase to its gameplay priorities once
+/// the launch phase is over. Does nothing without a launch phase.
+pub fn start_priority_phases(state: Arc<Mutex<DaemonState>>, key: SessionKey, sys_config: SysTune) {
+    if !sys_config.enabled || sys_config.launch_phase_sec.is_none() {
+        return;
     }
 
-    pub fn apply_gpu_tuning(&mut self, gpu_config: &GpuTune) -> Result<()> {
-        if !gpu_config.enabled {
-            debug!("GPU tuning disabled, skipping");
-            return Ok(());
-        }
+    tokio::spawn(async move {
+        let started = tokio::time::Instant::now();
+        let mut interval =
+            tokio::time::interval(tokio::time::Duration::from_secs(LAUNCH_PHASE_POLL_SEC));
 
-        let gpu = self.gpu.as_mut().context("GPU not initialized")?;
+        loop {
+            interval.tick().await;
 
-        gpu.set_power_limit(gpu_config.pwr_limit_tune, Some(gpu_config.set_max_pwr))
-            .context("Failed to set power limit")?;
+            let mut state = state.lock().unwrap();
+            if !state.active_sessions.contains_key(&key) {
+                debug!("Session of PID {} ended during its launch phase", key.pid);
+                break;
+            }
 
-        info!("Applied GPU tuning");
-        Ok(())
-    }
+            let elapsed_sec = started.elapsed().as_secs();
+            let gpu_util = || state.gpu.as_ref()?.utilization().ok();
+            if launch_phase_over(elapsed_sec, &sys_config, gpu_util) {
+                info!(
+                    "Launch phase of PID {} over after {}s",
+                    key.pid, elapsed_sec
+                );
+                state.apply_gameplay_priority(key, &sys_config);
+                break;
+            }
+        }
+    });
+}
 
-    pub fn apply_process_priority(&self, pid: u32, sys_config: &SysTune) -> Result<()> {
-        if !sys_config.enabled {
-            debug!("System tuning disabled, skipping");
-            return Ok(());
+/// Drop the reference a boost took on its named session once its window is
+/// over. Another boost of the same user holds a reference of its own.
+pub fn start_boost_window(state: Arc<Mutex<DaemonState>>, uid: u32, minutes: u32) {
+    tokio::spawn(async move {
+        tokio::time::sleep(tokio::time::Duration::from_secs(u64::from(minutes) * 60)).await;
+
+        let mut state = state.lock().unwrap();
+        match state.release_named_session(uid, BOOST_PROFILE) {
+            Ok(Some(0)) => info!("Boost of UID {} is over, released its tuning", uid),
+            Ok(Some(refs)) => info!("Boost of UID {} is over, {} more still held", uid, refs),
+            Ok(None) => debug!("Boost of UID {} was already released", uid),
+            Err(e) => error!("Failed to end the boost of UID {}: {}", uid, e),
         }
+    });
+}
 
-        if sys_config.proc_renice != 0 {
-            unsafe {
-                let result = libc::setpriority(libc::PRIO_PROCESS, pid, sys_config.proc_renice);
+/// Clean up a session once its process is gone. A session running in a
+/// cgroup of its own is watched through `cgroup.events`, which fires the
+/// moment its whole process tree exits; anything else is polled, as is a
+/// cgroup that cannot be watched.
+pub async fn start_pid_watchdog(
+    state: Arc<Mutex<DaemonState>>,
+    key: SessionKey,
+    interval_sec: u64,
+) {
+    let cgroup = cgroup::session_cgroup(key.pid);
 
-                if result != 0 {
-                    anyhow::bail!("setpriority failed with code {}", result);
+    tokio::spawn(async move {
+        if let Some(dir) = cgroup {
+            info!("Watching cgroup {} of PID {}", dir.display(), key.pid);
+
+            match tokio::task::spawn_blocking(move || cgroup::wait_until_empty(&dir)).await {
+                Ok(Ok(())) => {
+                    info!("Process tree of PID {} exited, cleaning up", key.pid);
+                    let mut state = state.lock().unwrap();
+                    if let Err(e) = state.end_session(key) {
+                        error!("Failed to clean up session of PID {}: {}", key.pid, e);
+                    }
+                    return;
                 }
+                Ok(Err(e)) => warn!("{:#}, polling PID {} instead", e, key.pid),
+                Err(e) => warn!(
+                    "Cgroup watch of PID {} failed: {}, polling instead",
+                    key.pid, e
+                ),
             }
-
-            info!("Set process {} priority to {}", pid, sys_config.proc_renice);
         }
 
-        Ok(())
-    }
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(interval_sec)).await;
 
-    pub fn restore_gpu_defaults(&mut self) -> Result<()> {
-        if let Some(gpu) = self.gpu.as_mut() {
-            gpu.restore_defaults()
-                .context("Failed to restore GPU defaults")?;
-            info!("Restored GPU to default settings");
-        }
-        Ok(())
-    }
+            let mut state = state.lock().unwrap();
+            if !state.is_session_alive(key) {
+                info!("Process {} terminated, cleaning up", key.pid);
 
-    pub fn restore_cpu_defaults(&mut self) -> Result<()> {
-        if let Some(base_epp) = &self.baseline_epp {
-            RyzenEPPManager::set_epp(base_epp)?;
-            info!("Restored CPU EPP to default: {}", base_epp);
+                if let Err(e) = state.end_session(key) {
+                    error!("Failed to clean up session of PID {}: {}", key.pid, e);
+                }
+                break;
+            }
         }
-        Ok(())
-    }
+    });
+}
 
-    pub fn add_active_pid(&mut self, pid: u32) {
-        self.active_pids.insert(pid);
-    }
+/// Combine the GPU tuning of several sessions: forcing the maximum power limit
+/// beats any explicit limit, and among explicit limits the highest one wins
+pub fn merge_gpu_tuning<'a>(configs: impl Iterator<Item = &'a GpuTune>) -> Option<GpuTune> {
+    configs
+        .filter(|c| c.enabled)
+        .cloned()
+        .reduce(|mut merged, next| {
+            merged.set_max_pwr |= next.set_max_pwr;
+            merged.pwr_limit_tune = merged.pwr_limit_tune.max(next.pwr_limit_tune);
+            merged.min_mem_clock_mhz = merged.min_mem_clock_mhz.max(next.min_mem_clock_mhz);
+            merged
+        })
+}
 
-    pub fn remove_active_pid(&mut self, pid: u32) {
-        self.active_pids.remove(&pid);
-    }
+/// The first schedule entry whose window contains `now`
+pub fn active_schedule(entries: &[ScheduleEntry], now: NaiveTime) -> Option<&ScheduleEntry> {
+    entries.iter().find(|entry| entry.is_active(now))
+}
 
-    pub fn is_pid_alive(pid: u32) -> bool {
-        Path::new(&format!("/proc/{}", pid)).exists()
-    }
+pub fn start_scheduler(state: Arc<Mutex<DaemonState>>, interval_sec: u64) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(interval_sec));
+
+        loop {
+            interval.tick().await;
+
+            let now = chrono::Local::now().time();
+            let mut state = state.lock().unwrap();
+            let entry = active_schedule(&state.schedule_entries, now).cloned();
+
+            if let Err(e) = state.set_schedule(entry) {
+                warn!("{:#}", e);
+            }
+        }
+    });
 }
 
-pub async fn start_pid_watchdog(state: Arc<Mutex<DaemonState>>, pid: u32, interval_sec: u64) {
+pub fn start_dynamic_epp(state: Arc<Mutex<DaemonState>>) {
     tokio::spawn(async move {
+        // The controller counts `hold_sec` in samples
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(1));
+
         loop {
-            tokio::time::sleep(tokio::time::Duration::from_secs(interval_sec)).await;
+            interval.tick().await;
+
+            let mut state = state.lock().unwrap();
+            if let Err(e) = state.update_dynamic_epp() {
+                warn!("{:#}", e);
+            }
+        }
+    });
+}
 
-            if !DaemonState::is_pid_alive(pid) {
-                info!("Process {} terminated, cleaning up", pid);
+pub fn start_telemetry_sampler(state: Arc<Mutex<DaemonState>>, interval_ms: u64) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(interval_ms));
 
-                let mut state = state.lock().unwrap();
-                state.remove_active_pid(pid);
+        loop {
+            interval.tick().await;
 
-                if state.active_pids.is_empty() {
-                    if let Err(e) = state.restore_gpu_defaults() {
-                        error!("Failed to restore GPU defaults: {}", e);
-                    }
-                    if let Err(e) = state.restore_cpu_defaults() {
-                        error!("Failed to restore CPU defaults: {}", e);
-                    }
-                }
-                break;
+            let mut state = state.lock().unwrap();
+            if let Err(e) = state.sample_telemetry() {
+                warn!("{:#}", e);
             }
         }
     });
@@ -161,39 +1723,389 @@ pub async fn start_pid_watchdog(state: Arc<Mutex<DaemonState>>, pid: u32, interv
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::config::{CpuBackend, MountConfig, PriorityBackend};
 
     #[test]
     fn test_daemon_state_new() {
         let state = DaemonState::new();
         assert!(state.gpu.is_none());
-        assert!(state.active_pids.is_empty());
+        assert!(state.active_sessions.is_empty());
         assert!(state.baseline_power_limit.is_none());
         assert!(state.baseline_epp.is_none());
+        assert!(state.original_priorities.is_empty());
+        assert!(state.pmqos.is_none());
+        assert!(state.telemetry.is_empty());
+        assert!(state.session_mounts.is_empty());
+        assert!(state.inhibitors.is_empty());
+        assert!(state.net_shaper.is_none());
+        assert!(state.original_oom_scores.is_empty());
+    }
+
+    #[test]
+    fn test_restore_oom_scores() {
+        let mut state = DaemonState::new();
+        let pid = std::process::id();
+        let original = oomadj::get_oom_score_adj(pid).unwrap();
+
+        // Raising our own score is allowed without privileges
+        state
+            .original_oom_scores
+            .insert(SessionKey::new(0, pid), vec![(pid, original)]);
+        oomadj::set_oom_score_adj(pid, original.max(0) + 1).unwrap();
+
+        assert!(state.restore_all_oom_scores().is_ok());
+        assert!(state.original_oom_scores.is_empty());
+        assert_eq!(oomadj::get_oom_score_adj(pid).unwrap(), original);
+    }
+
+    #[test]
+    fn test_apply_net_shaping_unset() {
+        let mut state = DaemonState::new();
+        let sys_config = SysTune {
+            enabled: true,
+            ..Default::default()
+        };
+
+        assert!(state.apply_net_shaping(&sys_config).is_ok());
+        assert!(state.net_shaper.is_none());
+        assert!(state.restore_net_defaults().is_ok());
+    }
+
+    #[test]
+    fn test_release_inhibitor_untracked() {
+        let mut state = DaemonState::new();
+        state.release_inhibitor(SessionKey::new(1000, 1234));
+        assert!(state.inhibitors.is_empty());
+    }
+
+    #[test]
+    fn test_release_mounts_untracked() {
+        let mut state = DaemonState::new();
+        assert!(state.release_mounts(SessionKey::new(1000, 1234)).is_ok());
+        assert!(state.release_all_mounts().is_ok());
+    }
+
+    #[test]
+    fn test_mounts_shared_between_sessions() {
+        let mut state = DaemonState::new();
+        let first = SessionKey::new(1000, 1);
+        let second = SessionKey::new(1001, 2);
+        let mountpoint = PathBuf::from("/nonexistent/nvprime-test");
+        state.policy.mounts.insert(
+            "games".to_string(),
+            MountConfig {
+                device: None,
+                uuid: None,
+                mountpoint: mountpoint.display().to_string(),
+                fstype: None,
+                options: None,
+            },
+        );
+        state.session_mounts.insert(first, vec![mountpoint.clone()]);
+
+        // Held for the first session, the second shares it without mounting
+        assert!(state.apply_mounts(second, &["games".to_string()]).is_ok());
+        assert_eq!(state.session_mounts[&second], vec![mountpoint.clone()]);
+
+        assert!(state.release_mounts(first).is_ok());
+        assert!(state.is_mount_held(&mountpoint));
+        assert!(state.release_mounts(second).is_ok());
+        assert!(!state.is_mount_held(&mountpoint));
+    }
+
+    #[test]
+    fn test_apply_mounts_invalid_rolls_back() {
+        let mut state = DaemonState::new();
+        state.policy.mounts.insert(
+            "games".to_string(),
+            MountConfig {
+                device: None,
+                uuid: None,
+                mountpoint: "/nonexistent/nvprime-test".to_string(),
+                fstype: None,
+                options: None,
+            },
+        );
+
+        for name in ["games", "unknown"] {
+            assert!(
+                state
+                    .apply_mounts(SessionKey::new(1000, 1234), &[name.to_string()])
+                    .is_err()
+            );
+            assert!(state.session_mounts.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_sample_telemetry_no_gpu() {
+        let mut state = DaemonState::new();
+        state.add_session(SessionKey::new(1000, 1234), GpuTune::default());
+
+        assert!(state.sample_telemetry().is_ok());
+        assert!(state.telemetry.is_empty());
+    }
+
+    #[test]
+    fn test_daemon_state_add_remove_session() {
+        let mut state = DaemonState::new();
+        let first = SessionKey::new(1000, 1234);
+
+        state.add_session(first, GpuTune::default());
+        assert!(state.active_sessions.contains_key(&first));
+        assert_eq!(state.active_sessions.len(), 1);
+
+        state.add_session(SessionKey::new(1000, 5678), GpuTune::default());
+        assert_eq!(state.active_sessions.len(), 2);
+
+        state.remove_session(first);
+        assert!(!state.active_sessions.contains_key(&first));
+        assert_eq!(state.active_sessions.len(), 1);
+    }
+
+    #[test]
+    fn test_daemon_state_duplicate_session() {
+        let mut state = DaemonState::new();
+
+        state.add_session(SessionKey::new(1000, 1234), GpuTune::default());
+        state.add_session(SessionKey::new(1000, 1234), GpuTune::default());
+        assert_eq!(state.active_sessions.len(), 1);
+
+        // The same PID number under another user is a different session
+        state.add_session(SessionKey::new(1001, 1234), GpuTune::default());
+        assert_eq!(state.active_sessions.len(), 2);
+    }
+
+    #[test]
+    fn test_session_keys_by_owner() {
+        let mut state = DaemonState::new();
+        state.add_session(SessionKey::new(1000, 1), GpuTune::default());
+        state.add_session(SessionKey::new(1001, 2), GpuTune::default());
+        state
+            .session_mounts
+            .insert(SessionKey::new(1000, 3), Vec::new());
+
+        let mine: Vec<SessionKey> = state.session_keys(Some(1000)).into_iter().collect();
+        assert_eq!(
+            mine,
+            vec![SessionKey::new(1000, 1), SessionKey::new(1000, 3)]
+        );
+        assert_eq!(state.session_keys(None).len(), 3);
+    }
+
+    #[test]
+    fn test_end_session_keeps_other_users() {
+        let mut state = DaemonState::new();
+        let mine = SessionKey::new(1000, 1);
+        let theirs = SessionKey::new(1001, 2);
+
+        state.add_session(mine, GpuTune::default());
+        state.add_session(theirs, GpuTune::default());
+        state.session_mounts.insert(theirs, Vec::new());
+
+        assert!(state.end_session(mine).is_ok());
+        assert!(!state.active_sessions.contains_key(&mine));
+        assert!(state.active_sessions.contains_key(&theirs));
+        assert!(state.session_mounts.contains_key(&theirs));
+    }
+
+    #[test]
+    fn test_end_session_keeps_own_other_sessions() {
+        let mut state = DaemonState::new();
+        let ended = SessionKey::new(1000, 1);
+        let running = SessionKey::new(1000, 2);
+        let tune = |pwr_limit_tune| GpuTune {
+            enabled: true,
+            pwr_limit_tune,
+            ..Default::default()
+        };
+
+        state.add_session(ended, tune(Some(300)));
+        state.add_session(running, tune(Some(250)));
+
+        // Without a GPU here re-applying what is left fails, the session
+        // still ends
+        assert!(state.end_session(ended).is_err());
+        assert_eq!(state.session_keys(Some(1000)).len(), 1);
+        assert_eq!(
+            state.effective_gpu_tuning().unwrap().pwr_limit_tune,
+            Some(250)
+        );
+    }
+
+    #[test]
+    fn test_end_session_keeps_named_sessions() {
+        let mut state = DaemonState::new();
+        let game = SessionKey::new(1000, 1);
+
+        state.add_session(game, GpuTune::default());
+        state
+            .acquire_named_session(1000, BOOST_PROFILE, &CpuTune::default(), GpuTune::default())
+            .unwrap();
+
+        assert!(state.end_session(game).is_ok());
+        assert!(state.session_keys(Some(1000)).is_empty());
+        assert!(state.has_sessions());
+        assert_eq!(
+            state.release_named_session(1000, BOOST_PROFILE).unwrap(),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_named_session_refcount() {
+        let mut state = DaemonState::new();
+        let cpu = CpuTune::default();
+
+        assert_eq!(
+            state
+                .acquire_named_session(1000, "blender", &cpu, GpuTune::default())
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            state
+                .acquire_named_session(1000, "blender", &cpu, GpuTune::default())
+                .unwrap(),
+            2
+        );
+        state
+            .acquire_named_session(1001, "blender", &cpu, GpuTune::default())
+            .unwrap();
+        assert!(state.has_sessions());
+
+        assert_eq!(
+            state.release_named_session(1000, "blender").unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            state.release_named_session(1000, "blender").unwrap(),
+            Some(0)
+        );
+        assert_eq!(state.release_named_session(1000, "blender").unwrap(), None);
+        assert!(state.has_sessions());
+
+        assert_eq!(state.discard_named_sessions(Some(1001)), ["blender"]);
+        assert!(!state.has_sessions());
+    }
+
+    #[test]
+    fn test_set_named_session_replaces() {
+        let mut state = DaemonState::new();
+        let tune = |pwr_limit_tune| GpuTune {
+            enabled: true,
+            pwr_limit_tune,
+            ..Default::default()
+        };
+
+        state.add_session(SessionKey::new(1000, 1), tune(Some(200)));
+        state.set_named_session(1000, MANUAL_GPU_PROFILE, tune(Some(300)));
+        state.set_named_session(1000, MANUAL_GPU_PROFILE, tune(Some(250)));
+
+        assert_eq!(
+            state.effective_gpu_tuning().unwrap().pwr_limit_tune,
+            Some(250)
+        );
+        // Without a GPU here re-applying what is left fails, the profile is
+        // still released
+        assert!(
+            state
+                .release_named_session(1000, MANUAL_GPU_PROFILE)
+                .is_err()
+        );
+        assert_eq!(
+            state.effective_gpu_tuning().unwrap().pwr_limit_tune,
+            Some(200)
+        );
     }
 
     #[test]
-    fn test_daemon_state_add_remove_pid() {
+    fn test_discard_all_sessions() {
         let mut state = DaemonState::new();
+        let mine = SessionKey::new(1000, 1);
+        let theirs = SessionKey::new(1001, u32::MAX);
+
+        state.add_session(mine, GpuTune::default());
+        state.add_session(theirs, GpuTune::default());
+        state.original_priorities.insert(theirs, 0);
+        state.session_mounts.insert(mine, Vec::new());
+
+        let discarded = state.discard_all_sessions().unwrap();
+        assert_eq!(discarded, vec![mine, theirs]);
+        assert!(state.session_keys(None).is_empty());
+    }
+
+    #[test]
+    fn test_merge_gpu_tuning() {
+        let tune = |enabled, set_max_pwr, pwr_limit_tune| GpuTune {
+            enabled,
+            set_max_pwr,
+            pwr_limit_tune,
+            ..Default::default()
+        };
+
+        assert!(merge_gpu_tuning([].iter()).is_none());
+        assert!(merge_gpu_tuning([tune(false, true, None)].iter()).is_none());
+
+        let merged = merge_gpu_tuning(
+            [
+                tune(true, false, Some(200_000)),
+                tune(true, false, Some(300_000)),
+                tune(false, true, None),
+            ]
+            .iter(),
+        )
+        .unwrap();
+        assert_eq!(merged.pwr_limit_tune, Some(300_000));
+        assert!(!merged.set_max_pwr);
+
+        let merged =
+            merge_gpu_tuning([tune(true, false, Some(200_000)), tune(true, true, None)].iter())
+                .unwrap();
+        assert!(merged.set_max_pwr);
 
-        state.add_active_pid(1234);
-        assert!(state.active_pids.contains(&1234));
-        assert_eq!(state.active_pids.len(), 1);
+        let floor = |mhz| GpuTune {
+            min_mem_clock_mhz: mhz,
+            ..tune(true, false, None)
+        };
+        let merged = merge_gpu_tuning([floor(Some(9501)), floor(None)].iter()).unwrap();
+        assert_eq!(merged.min_mem_clock_mhz, Some(9501));
+    }
+
+    fn schedule(start: &str, end: &str) -> ScheduleEntry {
+        let at = |hm| NaiveTime::parse_from_str(hm, "%H:%M").unwrap();
+        ScheduleEntry {
+            start: at(start),
+            end: at(end),
+            pwr_limit_tune: None,
+            amd_epp_tune: None,
+        }
+    }
 
-        state.add_active_pid(5678);
-        assert_eq!(state.active_pids.len(), 2);
+    #[test]
+    fn test_active_schedule() {
+        let entries = [schedule("22:00", "07:00"), schedule("06:00", "09:00")];
+        let at = |hm| NaiveTime::parse_from_str(hm, "%H:%M").unwrap();
 
-        state.remove_active_pid(1234);
-        assert!(!state.active_pids.contains(&1234));
-        assert_eq!(state.active_pids.len(), 1);
+        assert_eq!(active_schedule(&entries, at("23:00")), Some(&entries[0]));
+        assert_eq!(active_schedule(&entries, at("06:30")), Some(&entries[0]));
+        assert_eq!(active_schedule(&entries, at("08:00")), Some(&entries[1]));
+        assert_eq!(active_schedule(&entries, at("12:00")), None);
     }
 
     #[test]
-    fn test_daemon_state_duplicate_pid() {
+    fn test_set_schedule() {
         let mut state = DaemonState::new();
+        state.add_session(SessionKey::new(1000, 100), GpuTune::default());
+
+        state
+            .set_schedule(Some(schedule("22:00", "07:00")))
+            .unwrap();
+        assert_eq!(state.scheduled, Some(schedule("22:00", "07:00")));
 
-        state.add_active_pid(1234);
-        state.add_active_pid(1234);
-        assert_eq!(state.active_pids.len(), 1);
+        state.remove_session(SessionKey::new(1000, 100));
+        state.set_schedule(None).unwrap();
+        assert!(state.scheduled.is_none());
     }
 
     #[test]
@@ -207,6 +2119,22 @@ mod tests {
         assert!(!DaemonState::is_pid_alive(999999));
     }
 
+    #[test]
+    fn test_is_session_alive_after_pid_reuse() {
+        let mut state = DaemonState::new();
+        let key = SessionKey::new(1000, std::process::id());
+        state.add_session(key, GpuTune::default());
+        assert!(state.is_session_alive(key));
+
+        // Same PID, started at another time
+        *state.session_start_times.get_mut(&key).unwrap() += 1;
+        assert!(!state.is_session_alive(key));
+
+        state.end_session(key).unwrap();
+        assert!(state.session_start_times.is_empty());
+        assert!(state.is_session_alive(key));
+    }
+
     #[test]
     fn test_apply_gpu_tuning_disabled() {
         let mut state = DaemonState::new();
@@ -215,8 +2143,13 @@ mod tests {
             gpu_name: None,
             gpu_uuid: None,
             gpu_vlk_icd: String::new(),
+            gpu_vlk_icd32: None,
             set_max_pwr: false,
             pwr_limit_tune: None,
+            prefer_switcheroo: false,
+            min_mem_clock_mhz: None,
+            busy_util_percent: None,
+            busy_vram_percent: None,
         };
 
         let result = state.apply_gpu_tuning(&gpu_config);
@@ -231,8 +2164,13 @@ mod tests {
             gpu_name: None,
             gpu_uuid: None,
             gpu_vlk_icd: String::new(),
+            gpu_vlk_icd32: None,
             set_max_pwr: true,
             pwr_limit_tune: Some(300000),
+            prefer_switcheroo: false,
+            min_mem_clock_mhz: None,
+            busy_util_percent: None,
+            busy_vram_percent: None,
         };
 
         let result = state.apply_gpu_tuning(&gpu_config);
@@ -247,34 +2185,132 @@ mod tests {
 
     #[test]
     fn test_apply_process_priority_disabled() {
-        let state = DaemonState::new();
+        let mut state = DaemonState::new();
         let sys_config = SysTune {
             enabled: false,
             proc_ioprio: 4,
+            proc_ioprio_class: IoprioClass::Be,
             proc_renice: 0,
             splitlock_hack: false,
             watchdog_interval_sec: 10,
+            link_limit_kbit: None,
+            link_iface: None,
+            background_cpus: None,
+            memory: Default::default(),
+            strict: false,
+            telemetry_export: None,
+            launch_phase_sec: None,
+            launch_renice: 0,
+            launch_ioprio: 0,
+            launch_gpu_util: None,
+            priority_backend: PriorityBackend::Direct,
         };
 
-        let result = state.apply_process_priority(std::process::id(), &sys_config);
+        let result =
+            state.apply_process_priority(SessionKey::new(0, std::process::id()), &sys_config);
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_apply_process_priority_zero_renice() {
-        let state = DaemonState::new();
+        let mut state = DaemonState::new();
         let sys_config = SysTune {
             enabled: true,
             proc_ioprio: 4,
+            proc_ioprio_class: IoprioClass::Be,
             proc_renice: 0,
             splitlock_hack: false,
             watchdog_interval_sec: 10,
+            link_limit_kbit: None,
+            link_iface: None,
+            background_cpus: None,
+            memory: Default::default(),
+            strict: false,
+            telemetry_export: None,
+            launch_phase_sec: None,
+            launch_renice: 0,
+            launch_ioprio: 0,
+            launch_gpu_util: None,
+            priority_backend: PriorityBackend::Direct,
+        };
+
+        let result =
+            state.apply_process_priority(SessionKey::new(0, std::process::id()), &sys_config);
+        assert!(result.is_ok());
+        assert!(state.original_priorities.is_empty());
+    }
+
+    #[test]
+    fn test_restore_process_priority_untracked() {
+        let mut state = DaemonState::new();
+        let result = state.restore_process_priority(SessionKey::new(0, std::process::id()));
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_launch_phase_over() {
+        let mut sys_config = SysTune {
+            launch_phase_sec: Some(30),
+            ..Default::default()
         };
 
-        let result = state.apply_process_priority(std::process::id(), &sys_config);
+        assert!(!launch_phase_over(10, &sys_config, || Some(100)));
+        assert!(launch_phase_over(30, &sys_config, || None));
+
+        sys_config.launch_gpu_util = Some(80);
+        assert!(!launch_phase_over(10, &sys_config, || Some(50)));
+        assert!(!launch_phase_over(10, &sys_config, || None));
+        assert!(launch_phase_over(10, &sys_config, || Some(85)));
+
+        sys_config.launch_phase_sec = None;
+        assert!(launch_phase_over(0, &sys_config, || None));
+    }
+
+    #[test]
+    fn test_ioprio_value() {
+        assert_eq!(ioprio_value(IoprioClass::Be, 4), 0x4004);
+        assert_eq!(ioprio_value(IoprioClass::Rt, 9), 0x2007);
+        assert_eq!(ioprio_value(IoprioClass::Idle, 2), 0x6000);
+    }
+
+    #[test]
+    fn test_set_ioprio_current_process() {
+        assert!(set_ioprio(std::process::id(), IoprioClass::Be, 4).is_ok());
+    }
+
+    #[test]
+    fn test_get_priority_current_process() {
+        let result = get_priority(std::process::id());
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_report_power_limit() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut state = DaemonState::new();
+        state.power_changes = Some(tx);
+
+        state.report_power_limit(Some(250_000), Some(250_000), PowerLimitReason::Tuning);
+        state.report_power_limit(None, Some(250_000), PowerLimitReason::Tuning);
+        state.report_power_limit(Some(320_000), Some(250_000), PowerLimitReason::Schedule);
+
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            PowerLimitChange {
+                old_mw: 320_000,
+                new_mw: 250_000,
+                reason: PowerLimitReason::Schedule,
+            }
+        );
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_reset_gpu_clocks_no_gpu() {
+        let mut state = DaemonState::new();
+        assert!(state.reset_gpu_clocks().is_err());
+    }
+
     #[test]
     fn test_restore_gpu_defaults_no_gpu() {
         let mut state = DaemonState::new();
@@ -289,6 +2325,12 @@ mod tests {
             enabled: false,
             amd_epp_tune: "performance".to_string(),
             amd_epp_base: "balance_performance".to_string(),
+            cpu_dma_latency_us: None,
+            cpu_max_freq_mhz: None,
+            smt: None,
+            dynamic: Default::default(),
+            cpu_backend: CpuBackend::Sysfs,
+            ppt_limit: None,
         };
 
         let result = state.apply_cpu_tuning(&cpu_config);
@@ -303,6 +2345,12 @@ mod tests {
             enabled: true,
             amd_epp_tune: "performance".to_string(),
             amd_epp_base: "balance_performance".to_string(),
+            cpu_dma_latency_us: None,
+            cpu_max_freq_mhz: None,
+            smt: None,
+            dynamic: Default::default(),
+            cpu_backend: CpuBackend::Sysfs,
+            ppt_limit: None,
         };
 
         // Note: This calls the real RyzenEPPManager, but since we are mocking/ignoring
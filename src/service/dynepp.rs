@@ -0,0 +1,169 @@
+use crate::common::config::DynamicEpp;
+use anyhow::Context;
+
+/// Busy and total jiffies of one CPU core from `/proc/stat`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CoreTimes {
+    busy: u64,
+    total: u64,
+}
+
+/// Per core times, skipping the aggregate `cpu` line
+fn parse_proc_stat(stat: &str) -> Vec<CoreTimes> {
+    stat.lines()
+        .filter(|line| line.starts_with("cpu") && !line.starts_with("cpu "))
+        .filter_map(|line| {
+            let fields: Vec<u64> = line
+                .split_whitespace()
+                .skip(1)
+                .map(|f| f.parse().ok())
+                .collect::<Option<_>>()?;
+            let total: u64 = fields.iter().sum();
+            // idle and iowait
+            let idle = fields.get(3)? + fields.get(4).unwrap_or(&0);
+            Some(CoreTimes {
+                busy: total - idle,
+                total,
+            })
+        })
+        .collect()
+}
+
+/// Utilization in percent of the busiest core between two readings. A game
+/// held back by its main thread shows up here long before the average moves.
+fn busiest_core(prev: &[CoreTimes], cur: &[CoreTimes]) -> u32 {
+    prev.iter()
+        .zip(cur)
+        .filter_map(|(prev, cur)| {
+            let total = cur.total.checked_sub(prev.total).filter(|t| *t > 0)?;
+            let busy = cur.busy.saturating_sub(prev.busy);
+            Some((busy * 100 / total) as u32)
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Switches between the session's EPP and `gpu_bound_epp` as the game moves
+/// between GPU-bound and CPU-bound, fed one sample per second
+#[derive(Debug)]
+pub struct DynamicEppController {
+    config: DynamicEpp,
+    tuned_epp: String,
+    gpu_bound: bool,
+    /// Consecutive samples asking for the other state
+    streak: u32,
+    last_cpu: Option<Vec<CoreTimes>>,
+}
+
+impl DynamicEppController {
+    pub fn new(config: DynamicEpp, tuned_epp: &str) -> Self {
+        Self {
+            config,
+            tuned_epp: tuned_epp.to_string(),
+            gpu_bound: false,
+            streak: 0,
+            last_cpu: None,
+        }
+    }
+
+    /// Busiest core utilization since the previous call, `None` on the first
+    pub fn sample_cpu(&mut self) -> anyhow::Result<Option<u32>> {
+        let stat = std::fs::read_to_string("/proc/stat").context("Failed to read /proc/stat")?;
+        let cur = parse_proc_stat(&stat);
+        let util = self.last_cpu.as_ref().map(|prev| busiest_core(prev, &cur));
+        self.last_cpu = Some(cur);
+        Ok(util)
+    }
+
+    /// Feed utilization in percent, returns the EPP hint to switch to
+    pub fn update(&mut self, gpu_util: u32, cpu_util: u32) -> Option<&str> {
+        let c = &self.config;
+        let switch = if self.gpu_bound {
+            cpu_util >= c.cpu_util_percent
+                || gpu_util < c.gpu_util_percent.saturating_sub(c.hysteresis_percent)
+        } else {
+            gpu_util >= c.gpu_util_percent
+                && cpu_util < c.cpu_util_percent.saturating_sub(c.hysteresis_percent)
+        };
+
+        if !switch {
+            self.streak = 0;
+            return None;
+        }
+
+        self.streak += 1;
+        if self.streak < c.hold_sec.max(1) {
+            return None;
+        }
+
+        self.streak = 0;
+        self.gpu_bound = !self.gpu_bound;
+        Some(self.epp())
+    }
+
+    pub fn is_gpu_bound(&self) -> bool {
+        self.gpu_bound
+    }
+
+    fn epp(&self) -> &str {
+        if self.gpu_bound {
+            &self.config.gpu_bound_epp
+        } else {
+            &self.tuned_epp
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_busiest_core() {
+        let prev = parse_proc_stat(
+            "cpu  300 0 100 600 0 0 0 0 0 0\n\
+             cpu0 100 0 50 300 0 0 0 0 0 0\n\
+             cpu1 200 0 50 300 0 0 0 0 0 0\n\
+             intr 12345\n",
+        );
+        let cur = parse_proc_stat(
+            "cpu  450 0 100 750 0 0 0 0 0 0\n\
+             cpu0 110 0 50 390 0 0 0 0 0 0\n\
+             cpu1 290 0 50 310 0 0 0 0 0 0\n",
+        );
+
+        assert_eq!(prev.len(), 2);
+        assert_eq!(busiest_core(&prev, &cur), 90);
+        assert_eq!(busiest_core(&prev, &prev), 0);
+    }
+
+    #[test]
+    fn test_controller_switches_after_hold() {
+        let config = DynamicEpp {
+            enabled: true,
+            hold_sec: 2,
+            ..Default::default()
+        };
+        let mut controller = DynamicEppController::new(config, "performance");
+
+        // GPU-bound, once is not enough
+        assert_eq!(controller.update(99, 40), None);
+        assert_eq!(controller.update(70, 40), None);
+        assert_eq!(controller.update(99, 40), None);
+        assert_eq!(controller.update(98, 40), Some("balance_performance"));
+        assert!(controller.is_gpu_bound());
+
+        // Within the hysteresis band nothing changes
+        assert_eq!(controller.update(91, 82), None);
+        assert_eq!(controller.update(91, 82), None);
+
+        // CPU-bound
+        assert_eq!(controller.update(96, 90), None);
+        assert_eq!(controller.update(96, 90), Some("performance"));
+        assert!(!controller.is_gpu_bound());
+
+        // A busy CPU keeps it from relaxing again
+        assert_eq!(controller.update(99, 82), None);
+        assert_eq!(controller.update(99, 82), None);
+    }
+}
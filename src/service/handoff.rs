@@ -0,0 +1,223 @@
+use anyhow::Context;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::common::config::{CpuTune, GpuTune};
+use crate::common::proc;
+use crate::service::daemon::{BOOST_PROFILE, DaemonState, NamedSession, SessionKey};
+
+/// Where the daemon keeps its sessions for the next instance, gone on reboot
+pub const HANDOFF_FILE: &str = "/run/nvprime/sessions.json";
+
+/// A game session as the next instance needs it to take over
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct HandoffSession {
+    uid: u32,
+    pid: u32,
+    start_time: Option<u64>,
+    gpu: GpuTune,
+    original_priority: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct HandoffProfile {
+    uid: u32,
+    name: String,
+    refs: u32,
+    gpu: GpuTune,
+}
+
+/// The sessions of a daemon instance and what is needed to restore the
+/// system after them, written whenever they change so a restarted daemon
+/// can resume managing them with `--adopt`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Handoff {
+    sessions: Vec<HandoffSession>,
+    profiles: Vec<HandoffProfile>,
+    cpu: Option<CpuTune>,
+    baseline_epp: Option<String>,
+}
+
+impl Handoff {
+    pub fn capture(state: &DaemonState) -> Self {
+        let sessions = state
+            .active_sessions
+            .iter()
+            .map(|(key, gpu)| HandoffSession {
+                uid: key.uid,
+                pid: key.pid,
+                start_time: state.session_start_times.get(key).copied(),
+                gpu: gpu.clone(),
+                original_priority: state.original_priorities.get(key).copied(),
+            })
+            .collect();
+
+        // A boost's window ends with the instance that timed it
+        let profiles = state
+            .named_sessions
+            .iter()
+            .filter(|((_, name), _)| name != BOOST_PROFILE)
+            .map(|((uid, name), session)| HandoffProfile {
+                uid: *uid,
+                name: name.clone(),
+                refs: session.refs,
+                gpu: session.gpu.clone(),
+            })
+            .collect();
+
+        Self {
+            sessions,
+            profiles,
+            cpu: state.cpu_tuning.clone(),
+            baseline_epp: state.baseline_epp.clone(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        // Renamed into place so a crash never leaves half a file behind
+        let partial = path.with_extension("json.part");
+        std::fs::write(&partial, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        std::fs::rename(&partial, path)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// `None` when no earlier instance left sessions behind
+    pub fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+            .map(Some)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty() && self.profiles.is_empty()
+    }
+
+    /// Take over the sessions whose process still runs and re-apply their
+    /// tuning, or restore the defaults when none is left. Returns the game
+    /// sessions taken over, which need a watchdog again.
+    pub fn adopt(self, state: &mut DaemonState) -> Vec<SessionKey> {
+        let mut adopted = Vec::new();
+        state.baseline_epp = state.baseline_epp.take().or(self.baseline_epp);
+
+        for session in self.sessions {
+            let key = SessionKey::new(session.uid, session.pid);
+            let alive = match session.start_time {
+                Some(start_time) => proc::start_time(key.pid) == Some(start_time),
+                None => DaemonState::is_pid_alive(key.pid),
+            };
+            if !alive {
+                info!("Session of PID {} ended while the daemon was down", key.pid);
+                continue;
+            }
+
+            if let Some(start_time) = session.start_time {
+                state.session_start_times.insert(key, start_time);
+            }
+            state.add_session(key, session.gpu);
+            if let Some(priority) = session.original_priority {
+                state.original_priorities.insert(key, priority);
+            }
+            info!("Adopted session of UID {} PID {}", key.uid, key.pid);
+            adopted.push(key);
+        }
+
+        for profile in self.profiles {
+            info!("Adopted profile '{}' of UID {}", profile.name, profile.uid);
+            state.named_sessions.insert(
+                (profile.uid, profile.name),
+                NamedSession {
+                    refs: profile.refs,
+                    gpu: profile.gpu,
+                },
+            );
+        }
+
+        if !state.has_sessions() {
+            info!("No session left to adopt, restoring defaults");
+        } else if let Some(cpu) = &self.cpu
+            && let Err(e) = state.apply_cpu_tuning(cpu)
+        {
+            error!("Failed to re-apply CPU tuning: {}", e);
+        }
+
+        if let Err(e) = state.reconcile_shared_tuning() {
+            error!("Failed to resume shared tuning: {:#}", e);
+        }
+        adopted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handoff_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nvprime").join("sessions.json");
+        assert!(Handoff::load(&path).unwrap().is_none());
+
+        let live = SessionKey::new(1000, std::process::id());
+        let dead = SessionKey::new(1000, 999_999);
+
+        let mut old = DaemonState::new();
+        old.add_session(live, GpuTune::default());
+        old.add_session(dead, GpuTune::default());
+        old.original_priorities.insert(live, 0);
+        old.baseline_epp = Some("balance_performance".to_string());
+        old.named_sessions.insert(
+            (1000, "blender".to_string()),
+            NamedSession {
+                refs: 2,
+                gpu: GpuTune::default(),
+            },
+        );
+        old.named_sessions.insert(
+            (1000, BOOST_PROFILE.to_string()),
+            NamedSession {
+                refs: 1,
+                gpu: GpuTune::default(),
+            },
+        );
+        Handoff::capture(&old).save(&path).unwrap();
+
+        let handoff = Handoff::load(&path).unwrap().unwrap();
+        assert_eq!(
+            serde_json::to_value(&handoff).unwrap(),
+            serde_json::to_value(Handoff::capture(&old)).unwrap()
+        );
+        assert_eq!(handoff.profiles.len(), 1);
+
+        let mut new = DaemonState::new();
+        assert_eq!(handoff.adopt(&mut new), [live]);
+        assert!(new.is_session_alive(live));
+        assert_eq!(new.original_priorities.get(&live), Some(&0));
+        assert_eq!(new.named_sessions[&(1000, "blender".to_string())].refs, 2);
+        assert!(!new.active_sessions.contains_key(&dead));
+        assert_eq!(new.baseline_epp.as_deref(), Some("balance_performance"));
+    }
+
+    #[test]
+    fn test_handoff_pid_reused() {
+        let mut old = DaemonState::new();
+        let key = SessionKey::new(1000, std::process::id());
+        old.add_session(key, GpuTune::default());
+        *old.session_start_times.get_mut(&key).unwrap() += 1;
+
+        let mut new = DaemonState::new();
+        assert!(Handoff::capture(&old).adopt(&mut new).is_empty());
+        assert!(!new.has_sessions());
+    }
+}
@@ -0,0 +1,44 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use zbus::proxy;
+use zbus::zvariant::OwnedFd;
+
+#[proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Login1Manager {
+    fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::Result<OwnedFd>;
+}
+
+/// Holds a logind idle+sleep inhibitor lock for as long as it is alive.
+/// logind releases the lock as soon as the file descriptor is closed.
+pub struct SleepInhibitor {
+    _fd: OwnedFd,
+    pid: u32,
+}
+
+impl SleepInhibitor {
+    /// Take a blocking idle+sleep inhibitor lock on behalf of a game session
+    pub async fn acquire(conn: &zbus::Connection, pid: u32) -> Result<Self> {
+        let manager = Login1ManagerProxy::new(conn)
+            .await
+            .context("Failed to create logind proxy")?;
+
+        let why = format!("Game session (PID {}) is running", pid);
+        let fd = manager
+            .inhibit("idle:sleep", "nvprime", &why, "block")
+            .await
+            .context("Failed to take logind inhibitor lock")?;
+
+        info!("Inhibiting idle and sleep for PID {}", pid);
+        Ok(Self { _fd: fd, pid })
+    }
+}
+
+impl Drop for SleepInhibitor {
+    fn drop(&mut self) {
+        debug!("Releasing idle and sleep inhibitor for PID {}", self.pid);
+    }
+}
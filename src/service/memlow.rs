@@ -0,0 +1,213 @@
+use anyhow::{Context, Result};
+use log::{debug, error, info};
+use std::path::{Path, PathBuf};
+
+use crate::common::cgroup::{self, CGROUP_ROOT};
+
+const BYTES_PER_GB: u64 = 1024 * 1024 * 1024;
+
+/// `memory.low` protection on a game's cgroup, so the kernel reclaims from
+/// other applications first. A cgroup is only protected up to what its
+/// parent is, so every ancestor below the root is raised as well.
+pub struct MemoryProtection {
+    bytes: u64,
+    /// Raised cgroups with their original value, leaf first
+    originals: Vec<(PathBuf, String)>,
+}
+
+impl MemoryProtection {
+    /// Protect `gb` gigabytes of the cgroup of `pid`, need superuser access
+    pub fn apply(pid: u32, gb: u32) -> Result<Self> {
+        let bytes = u64::from(gb) * BYTES_PER_GB;
+        check_total_ram(bytes, total_ram()?)?;
+
+        let leaf = cgroup::cgroup_of(pid)
+            .with_context(|| format!("Could not find the cgroup v2 of PID {}", pid))?;
+        let protection = Self::apply_at(Path::new(CGROUP_ROOT), &leaf, bytes)?;
+
+        info!(
+            "Protected {} GB of memory for the cgroup of PID {}",
+            gb, pid
+        );
+        Ok(protection)
+    }
+
+    fn apply_at(root: &Path, leaf: &Path, bytes: u64) -> Result<Self> {
+        anyhow::ensure!(
+            leaf.join("memory.low").exists(),
+            "Memory controller is not enabled for {}",
+            leaf.display()
+        );
+
+        let mut protection = Self {
+            bytes,
+            originals: Vec::new(),
+        };
+
+        for dir in leaf.ancestors().take_while(|dir| *dir != root) {
+            match protection.raise(dir) {
+                Ok(Some(original)) => protection.originals.push((dir.to_path_buf(), original)),
+                Ok(None) => {}
+                Err(e) => {
+                    if let Err(e) = protection.release() {
+                        error!("Failed to roll back memory protection: {}", e);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(protection)
+    }
+
+    /// Raise `memory.low` of one cgroup, returning the value it had if changed
+    fn raise(&self, dir: &Path) -> Result<Option<String>> {
+        let path = dir.join("memory.low");
+        let original = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        if parse_memory_low(&original) >= self.bytes {
+            return Ok(None);
+        }
+
+        debug!("Setting {} to {}", path.display(), self.bytes);
+        std::fs::write(&path, self.bytes.to_string())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(Some(original.trim().to_string()))
+    }
+
+    /// Raise the cgroups again, after another session restored a shared ancestor
+    pub fn refresh(&self) -> Result<()> {
+        for (dir, _) in &self.originals {
+            self.raise(dir)?;
+        }
+        Ok(())
+    }
+
+    /// Put back the original `memory.low` values, need superuser access
+    pub fn release(self) -> Result<()> {
+        let mut result = Ok(());
+
+        for (dir, original) in &self.originals {
+            let path = dir.join("memory.low");
+            if !path.exists() {
+                continue;
+            }
+            if let Err(e) = std::fs::write(&path, original) {
+                error!("Failed to restore {}: {}", path.display(), e);
+                result = Err(e).with_context(|| format!("Failed to restore {}", path.display()));
+            }
+        }
+
+        if result.is_ok() && !self.originals.is_empty() {
+            info!("Released memory protection");
+        }
+        result
+    }
+}
+
+/// `memory.low` in bytes, where `max` protects everything
+fn parse_memory_low(value: &str) -> u64 {
+    match value.trim() {
+        "max" => u64::MAX,
+        value => value.parse().unwrap_or(0),
+    }
+}
+
+/// Protecting all of RAM would leave nothing for the rest of the system
+fn check_total_ram(bytes: u64, total: u64) -> Result<()> {
+    anyhow::ensure!(bytes > 0, "memory_protect_gb must be greater than zero");
+    anyhow::ensure!(
+        bytes < total,
+        "memory_protect_gb of {} GB is not below the {:.1} GB of total RAM",
+        bytes / BYTES_PER_GB,
+        total as f64 / BYTES_PER_GB as f64
+    );
+    Ok(())
+}
+
+/// Total RAM in bytes from `/proc/meminfo`
+fn total_ram() -> Result<u64> {
+    let meminfo =
+        std::fs::read_to_string("/proc/meminfo").context("Failed to read /proc/meminfo")?;
+    parse_mem_total(&meminfo).context("MemTotal missing from /proc/meminfo")
+}
+
+fn parse_mem_total(meminfo: &str) -> Option<u64> {
+    let kb: u64 = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cgroup_tree(root: &Path, values: &[(&str, &str)]) -> PathBuf {
+        let mut dir = root.to_path_buf();
+        for (name, value) in values {
+            dir = dir.join(name);
+            std::fs::create_dir(&dir).unwrap();
+            std::fs::write(dir.join("memory.low"), value).unwrap();
+        }
+        dir
+    }
+
+    fn memory_low(dir: &Path) -> String {
+        std::fs::read_to_string(dir.join("memory.low")).unwrap()
+    }
+
+    #[test]
+    fn test_parse_mem_total() {
+        let meminfo = "MemTotal:       32768000 kB\nMemFree:         1024 kB\n";
+        assert_eq!(parse_mem_total(meminfo), Some(32_768_000 * 1024));
+        assert_eq!(parse_mem_total("MemFree: 1 kB\n"), None);
+    }
+
+    #[test]
+    fn test_check_total_ram() {
+        assert!(check_total_ram(8 * BYTES_PER_GB, 32 * BYTES_PER_GB).is_ok());
+        assert!(check_total_ram(32 * BYTES_PER_GB, 32 * BYTES_PER_GB).is_err());
+        assert!(check_total_ram(0, 32 * BYTES_PER_GB).is_err());
+    }
+
+    #[test]
+    fn test_memory_protection_apply_release() {
+        let root = tempfile::tempdir().unwrap();
+        let leaf = cgroup_tree(
+            root.path(),
+            &[
+                ("user.slice", "0"),
+                ("app.slice", "max"),
+                ("game.scope", "1024"),
+            ],
+        );
+        let slice = root.path().join("user.slice");
+
+        let protection = MemoryProtection::apply_at(root.path(), &leaf, 4096).unwrap();
+        assert_eq!(memory_low(&leaf), "4096");
+        assert_eq!(memory_low(leaf.parent().unwrap()), "max");
+        assert_eq!(memory_low(&slice), "4096");
+
+        std::fs::write(slice.join("memory.low"), "0").unwrap();
+        protection.refresh().unwrap();
+        assert_eq!(memory_low(&slice), "4096");
+
+        protection.release().unwrap();
+        assert_eq!(memory_low(&leaf), "1024");
+        assert_eq!(memory_low(leaf.parent().unwrap()), "max");
+        assert_eq!(memory_low(&slice), "0");
+    }
+
+    #[test]
+    fn test_memory_protection_without_controller() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(MemoryProtection::apply_at(root.path(), &root.path().join("game"), 4096).is_err());
+    }
+}
@@ -1,4 +1,35 @@
+pub mod conditions;
+pub mod cpufreq;
+pub mod cpuisolate;
 pub mod daemon;
+pub mod dynepp;
+pub mod handoff;
+pub mod inhibit;
+pub mod memlow;
+pub mod mount;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod netshape;
+pub mod oomadj;
+pub mod oomavoid;
+pub mod pmqos;
+pub mod policy;
+pub mod powercap;
+pub mod powerd;
 pub mod ryzen;
+pub mod sched;
+pub mod smt;
+pub mod swap;
 
-pub use daemon::{DaemonState, start_pid_watchdog};
+pub use conditions::{ConditionKind, GpuCondition};
+pub use daemon::{
+    BOOST_PROFILE, DaemonState, MANUAL_CPU_PROFILE, MANUAL_GPU_PROFILE, PowerLimitChange,
+    PowerLimitReason, SessionEvent, SessionKey, start_boost_window, start_dynamic_epp,
+    start_pid_watchdog, start_priority_phases, start_scheduler, start_telemetry_sampler,
+};
+pub use handoff::{HANDOFF_FILE, Handoff};
+pub use inhibit::SleepInhibitor;
+pub use mount::MountManager;
+pub use netshape::NetShaper;
+pub use pmqos::PmQosGuard;
+pub use policy::DaemonPolicy;
@@ -0,0 +1,241 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::common::config::MountConfig;
+
+pub struct MountManager;
+
+impl MountManager {
+    /// Mount a game drive, need superuser access.
+    /// Returns `false` when the mountpoint was already mounted, in which case
+    /// it is left alone and must not be unmounted at the end of the session.
+    pub fn mount(mount: &MountConfig) -> Result<bool> {
+        let mountpoint = Path::new(&mount.mountpoint);
+
+        if Self::is_mounted(mountpoint) {
+            info!(
+                "{} is already mounted, leaving it as is",
+                mountpoint.display()
+            );
+            return Ok(false);
+        }
+
+        let args = mount_args(mount)?;
+
+        std::fs::create_dir_all(mountpoint)
+            .with_context(|| format!("Failed to create mountpoint {}", mountpoint.display()))?;
+
+        debug!("Running mount {:?}", args);
+
+        let status = Command::new("mount")
+            .args(&args)
+            .status()
+            .context("Failed to run mount")?;
+
+        anyhow::ensure!(
+            status.success(),
+            "mount of {} failed with {}",
+            mountpoint.display(),
+            status
+        );
+
+        info!("Mounted {}", mountpoint.display());
+        Ok(true)
+    }
+
+    /// Unmount a mountpoint previously mounted by `mount`, need superuser access
+    pub fn unmount(mountpoint: &Path) -> Result<()> {
+        if !Self::is_mounted(mountpoint) {
+            debug!("{} is not mounted, nothing to do", mountpoint.display());
+            return Ok(());
+        }
+
+        let status = Command::new("umount")
+            .arg(mountpoint)
+            .status()
+            .context("Failed to run umount")?;
+
+        anyhow::ensure!(
+            status.success(),
+            "umount of {} failed with {}",
+            mountpoint.display(),
+            status
+        );
+
+        info!("Unmounted {}", mountpoint.display());
+        Ok(())
+    }
+
+    /// Check `/proc/self/mounts` for the given mountpoint
+    pub fn is_mounted(mountpoint: &Path) -> bool {
+        let Ok(mounts) = std::fs::read_to_string("/proc/self/mounts") else {
+            return false;
+        };
+        let target = mountpoint
+            .canonicalize()
+            .unwrap_or_else(|_| mountpoint.to_path_buf());
+
+        parse_mountpoints(&mounts).any(|m| m == target)
+    }
+}
+
+/// Options always added, so a drive can not carry suid binaries or device
+/// nodes onto the machine
+const FORCED_OPTIONS: &str = "nosuid,nodev";
+
+/// Options that would undo `FORCED_OPTIONS` or mount an image file
+const REFUSED_OPTIONS: [&str; 3] = ["suid", "dev", "loop"];
+
+fn mount_args(mount: &MountConfig) -> Result<Vec<String>> {
+    let source = match (&mount.device, &mount.uuid) {
+        (Some(device), None) => {
+            anyhow::ensure!(
+                !Path::new(device).is_file(),
+                "Mount for {} can not use the image file {}",
+                mount.mountpoint,
+                device
+            );
+            device.clone()
+        }
+        (None, Some(uuid)) => format!("UUID={}", uuid),
+        _ => anyhow::bail!(
+            "Mount for {} needs exactly one of `device` or `uuid`",
+            mount.mountpoint
+        ),
+    };
+
+    let mut options = Vec::new();
+    for option in mount.options.iter().flat_map(|options| options.split(',')) {
+        let name = option.split_once('=').map_or(option, |(name, _)| name);
+        anyhow::ensure!(
+            !REFUSED_OPTIONS.contains(&name),
+            "Mount for {} can not use the option '{}'",
+            mount.mountpoint,
+            option
+        );
+        options.push(option);
+    }
+    options.push(FORCED_OPTIONS);
+
+    let mut args = Vec::new();
+    if let Some(fstype) = &mount.fstype {
+        args.extend(["-t".to_string(), fstype.clone()]);
+    }
+    args.extend(["-o".to_string(), options.join(",")]);
+    args.extend([source, mount.mountpoint.clone()]);
+    Ok(args)
+}
+
+/// Mountpoints from a mounts table, with the kernel's octal escapes decoded
+fn parse_mountpoints(mounts: &str) -> impl Iterator<Item = PathBuf> + '_ {
+    mounts
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(|field| {
+            PathBuf::from(
+                field
+                    .replace("\\040", " ")
+                    .replace("\\011", "\t")
+                    .replace("\\134", "\\"),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mount_config() -> MountConfig {
+        MountConfig {
+            device: None,
+            uuid: None,
+            mountpoint: "/mnt/games".to_string(),
+            fstype: None,
+            options: None,
+        }
+    }
+
+    #[test]
+    fn test_mount_args_device() {
+        let mount = MountConfig {
+            device: Some("/dev/sdb1".to_string()),
+            fstype: Some("ext4".to_string()),
+            options: Some("noatime".to_string()),
+            ..mount_config()
+        };
+
+        assert_eq!(
+            mount_args(&mount).unwrap(),
+            vec![
+                "-t",
+                "ext4",
+                "-o",
+                "noatime,nosuid,nodev",
+                "/dev/sdb1",
+                "/mnt/games"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mount_args_uuid() {
+        let mount = MountConfig {
+            uuid: Some("1234-ABCD".to_string()),
+            ..mount_config()
+        };
+
+        assert_eq!(
+            mount_args(&mount).unwrap(),
+            vec!["-o", "nosuid,nodev", "UUID=1234-ABCD", "/mnt/games"]
+        );
+    }
+
+    #[test]
+    fn test_mount_args_ambiguous_source() {
+        assert!(mount_args(&mount_config()).is_err());
+
+        let mount = MountConfig {
+            device: Some("/dev/sdb1".to_string()),
+            uuid: Some("1234-ABCD".to_string()),
+            ..mount_config()
+        };
+        assert!(mount_args(&mount).is_err());
+    }
+
+    #[test]
+    fn test_mount_args_refused() {
+        for options in ["suid", "ro,dev", "loop=/dev/loop0"] {
+            let mount = MountConfig {
+                device: Some("/dev/sdb1".to_string()),
+                options: Some(options.to_string()),
+                ..mount_config()
+            };
+            assert!(mount_args(&mount).is_err(), "{}", options);
+        }
+
+        let image = tempfile::NamedTempFile::new().unwrap();
+        let mount = MountConfig {
+            device: Some(image.path().to_string_lossy().into_owned()),
+            ..mount_config()
+        };
+        assert!(mount_args(&mount).is_err());
+    }
+
+    #[test]
+    fn test_parse_mountpoints() {
+        let mounts = "/dev/sda1 / ext4 rw 0 0\n//nas/games /mnt/my\\040games cifs rw 0 0\n";
+        let points: Vec<PathBuf> = parse_mountpoints(mounts).collect();
+        assert_eq!(
+            points,
+            vec![PathBuf::from("/"), PathBuf::from("/mnt/my games")]
+        );
+    }
+
+    #[test]
+    fn test_is_mounted() {
+        assert!(MountManager::is_mounted(Path::new("/proc")));
+        assert!(!MountManager::is_mounted(Path::new("/nonexistent/mount")));
+    }
+}
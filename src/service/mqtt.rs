@@ -0,0 +1,310 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::common::config::MqttConfig;
+use crate::service::daemon::SessionEvent;
+
+const DEFAULT_PORT: u16 = 1883;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A broker that went away is not tried again sooner than this, events in
+/// between are dropped
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// MQTT 3.1.1
+const PROTOCOL_LEVEL: u8 = 4;
+
+/// Publish what the daemon reports to the broker in `config`, from a thread
+/// of its own since the broker may be slow or gone. Messages go out at QoS 0:
+///
+/// - `<topic>/session`, `{"event": "start", "pid": .., "game": ..}` as each
+///   session starts and ends
+/// - `<topic>/game`, retained, the game of the latest session still running,
+///   empty when none is
+/// - `<topic>/gpu`, `{"temperature_c": .., "power_w": ..}` every
+///   `interval_sec` during sessions
+pub fn start_publisher(config: MqttConfig, events: UnboundedReceiver<SessionEvent>) {
+    let Some(broker) = config.broker.clone() else {
+        return;
+    };
+    info!("Publishing sessions to MQTT broker {}", broker);
+
+    std::thread::spawn(move || {
+        let mut publisher = Publisher::new(config, broker);
+        let mut events = events;
+        while let Some(event) = events.blocking_recv() {
+            publisher.handle(event);
+        }
+    });
+}
+
+struct Publisher {
+    config: MqttConfig,
+    broker: String,
+    stream: Option<TcpStream>,
+    last_attempt: Option<Instant>,
+    /// Games of the running sessions, by PID, in the order they started
+    games: Vec<(u32, String)>,
+    last_sample: Option<Instant>,
+}
+
+impl Publisher {
+    fn new(config: MqttConfig, broker: String) -> Self {
+        Self {
+            config,
+            broker,
+            stream: None,
+            last_attempt: None,
+            games: Vec::new(),
+            last_sample: None,
+        }
+    }
+
+    fn handle(&mut self, event: SessionEvent) {
+        match event {
+            SessionEvent::Started { pid, game } => {
+                let game = game.unwrap_or_default();
+                self.games.retain(|(session, _)| *session != pid);
+                self.games.push((pid, game.clone()));
+                self.publish_session("start", pid, &game);
+                self.publish_game();
+            }
+            SessionEvent::Ended { pid } => {
+                let game = self
+                    .games
+                    .iter()
+                    .position(|(session, _)| *session == pid)
+                    .map(|index| self.games.remove(index).1)
+                    .unwrap_or_default();
+                self.publish_session("end", pid, &game);
+                self.publish_game();
+            }
+            SessionEvent::Telemetry(sample) => {
+                let interval = Duration::from_secs(self.config.interval_sec.max(1));
+                if self
+                    .last_sample
+                    .is_some_and(|last| last.elapsed() < interval)
+                {
+                    return;
+                }
+                self.last_sample = Some(Instant::now());
+
+                let payload = serde_json::json!({
+                    "temperature_c": sample.temperature_c,
+                    "power_w": sample.power_mw as f64 / 1000.0,
+                });
+                self.publish("gpu", &payload.to_string(), false);
+            }
+        }
+    }
+
+    fn publish_session(&mut self, event: &str, pid: u32, game: &str) {
+        let payload = serde_json::json!({
+            "event": event,
+            "pid": pid,
+            "game": game,
+            "sessions": self.games.len(),
+        });
+        self.publish("session", &payload.to_string(), false);
+    }
+
+    fn publish_game(&mut self) {
+        let game = self
+            .games
+            .last()
+            .map(|(_, game)| game.clone())
+            .unwrap_or_default();
+        self.publish("game", &game, true);
+    }
+
+    fn publish(&mut self, subtopic: &str, payload: &str, retain: bool) {
+        let topic = format!("{}/{}", self.config.topic.trim_end_matches('/'), subtopic);
+        let Some(stream) = self.connection() else {
+            debug!("Dropping MQTT message to {}, not connected", topic);
+            return;
+        };
+
+        if let Err(e) = stream.write_all(&publish_packet(&topic, payload.as_bytes(), retain)) {
+            warn!("Lost MQTT broker {}: {}", self.broker, e);
+            self.stream = None;
+        }
+    }
+
+    /// The connection to the broker, made again once the last attempt is
+    /// long enough ago
+    fn connection(&mut self) -> Option<&mut TcpStream> {
+        if self.stream.is_none()
+            && self
+                .last_attempt
+                .is_none_or(|last| last.elapsed() >= RECONNECT_DELAY)
+        {
+            self.last_attempt = Some(Instant::now());
+            match self.connect() {
+                Ok(stream) => {
+                    info!("Connected to MQTT broker {}", self.broker);
+                    self.stream = Some(stream);
+                    // Whatever a previous daemon left retained is out of date
+                    self.publish_game();
+                }
+                Err(e) => warn!("{:#}", e),
+            }
+        }
+        self.stream.as_mut()
+    }
+
+    fn connect(&self) -> Result<TcpStream> {
+        let address = broker_address(&self.broker)
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve MQTT broker {}", self.broker))?
+            .next()
+            .with_context(|| format!("MQTT broker {} has no address", self.broker))?;
+        let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+            .with_context(|| format!("Failed to connect to MQTT broker {}", self.broker))?;
+        stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+
+        stream.write_all(&connect_packet(
+            &self.config.client_id,
+            self.config.username.as_deref(),
+            self.config.password.as_deref(),
+        ))?;
+        let mut connack = [0; 4];
+        stream
+            .read_exact(&mut connack)
+            .with_context(|| format!("MQTT broker {} did not answer", self.broker))?;
+        check_connack(&connack)?;
+        Ok(stream)
+    }
+}
+
+/// `host:port` with the default port added when missing
+fn broker_address(broker: &str) -> String {
+    let has_port = broker
+        .rsplit_once(':')
+        .is_some_and(|(host, port)| !host.ends_with(':') && port.parse::<u16>().is_ok());
+    if has_port {
+        broker.to_string()
+    } else {
+        format!("{}:{}", broker, DEFAULT_PORT)
+    }
+}
+
+/// CONNECT with a clean session and no keep alive, the daemon only writes
+fn connect_packet(client_id: &str, username: Option<&str>, password: Option<&str>) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_string(&mut body, b"MQTT");
+    body.push(PROTOCOL_LEVEL);
+
+    // MQTT 3.1.1 has no password without a user name
+    let password = password.filter(|_| username.is_some());
+    let mut flags = 0x02;
+    if username.is_some() {
+        flags |= 0x80;
+    }
+    if password.is_some() {
+        flags |= 0x40;
+    }
+    body.push(flags);
+    body.extend_from_slice(&0u16.to_be_bytes());
+
+    put_string(&mut body, client_id.as_bytes());
+    if let Some(username) = username {
+        put_string(&mut body, username.as_bytes());
+    }
+    if let Some(password) = password {
+        put_string(&mut body, password.as_bytes());
+    }
+    packet(0x10, &body)
+}
+
+fn publish_packet(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_string(&mut body, topic.as_bytes());
+    body.extend_from_slice(payload);
+    packet(if retain { 0x31 } else { 0x30 }, &body)
+}
+
+fn check_connack(connack: &[u8; 4]) -> Result<()> {
+    anyhow::ensure!(
+        connack[0] == 0x20 && connack[1] == 2,
+        "MQTT broker sent {:02x?} instead of CONNACK",
+        connack
+    );
+    let reason = match connack[3] {
+        0 => return Ok(()),
+        1 => "unsupported protocol version",
+        2 => "client ID rejected",
+        3 => "server unavailable",
+        4 => "bad user name or password",
+        5 => "not authorized",
+        _ => "unknown error",
+    };
+    anyhow::bail!("MQTT broker refused the connection: {}", reason)
+}
+
+/// Fixed header and body, the remaining length encoded seven bits at a time
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn put_string(body: &mut Vec<u8>, value: &[u8]) {
+    body.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    body.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packets() {
+        assert_eq!(
+            connect_packet("nv", None, Some("ignored")),
+            [
+                0x10, 14, 0, 4, b'M', b'Q', b'T', b'T', 4, 0x02, 0, 0, 0, 2, b'n', b'v'
+            ]
+        );
+        let with_login = connect_packet("nv", Some("u"), Some("p"));
+        assert_eq!(with_login[9], 0xc2);
+        assert!(with_login.ends_with(&[0, 1, b'u', 0, 1, b'p']));
+
+        assert_eq!(
+            publish_packet("a/b", b"on", true),
+            [0x31, 7, 0, 3, b'a', b'/', b'b', b'o', b'n']
+        );
+        let long = publish_packet("t", &[0; 200], false);
+        assert_eq!(&long[..3], [0x30, 0xcb, 0x01]);
+    }
+
+    #[test]
+    fn test_check_connack() {
+        assert!(check_connack(&[0x20, 2, 0, 0]).is_ok());
+        assert!(check_connack(&[0x20, 2, 0, 5]).is_err());
+        assert!(check_connack(&[0x30, 2, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_broker_address() {
+        assert_eq!(broker_address("nas.local"), "nas.local:1883");
+        assert_eq!(broker_address("10.0.0.2:8883"), "10.0.0.2:8883");
+    }
+}
@@ -0,0 +1,213 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Smallest burst allowed for the policer, in bytes
+const MIN_BURST_BYTES: u64 = 16_000;
+
+/// Where the interfaces of the machine are listed
+const NET_CLASS_DIR: &str = "/sys/class/net";
+
+/// Where a marker is kept for each ingress qdisc the daemon installed, so
+/// one left by a crashed instance can be told from the admin's own
+const MARKER_DIR: &str = "/run/nvprime";
+
+/// Ingress bandwidth limit on a network interface, implemented with a `tc`
+/// ingress qdisc and policer. This caps all inbound traffic on the link:
+/// ingress packets reach the qdisc before any socket, so they cannot be
+/// classified by the cgroup of the process receiving them, and the game's
+/// own traffic is dropped above the limit like everything else.
+pub struct NetShaper {
+    iface: String,
+    limit_kbit: u32,
+}
+
+impl NetShaper {
+    /// Install the ingress policer, need superuser access. Refused when the
+    /// interface has an ingress qdisc the daemon did not install.
+    pub fn apply(iface: Option<&str>, limit_kbit: u32) -> Result<Self> {
+        anyhow::ensure!(limit_kbit > 0, "Link limit must be greater than zero");
+
+        let iface = match iface {
+            Some(iface) => iface.to_string(),
+            None => {
+                default_route_iface().context("Could not detect the default network interface")?
+            }
+        };
+        check_iface(&iface, Path::new(NET_CLASS_DIR))?;
+
+        let marker = marker_path(&iface);
+        if has_ingress_qdisc(&tc(&["qdisc", "show", "dev", &iface, "ingress"])?) {
+            anyhow::ensure!(
+                marker.exists(),
+                "{} already has an ingress qdisc not set by nvprime, leaving it alone",
+                iface
+            );
+            warn!(
+                "Removing the link limit a previous instance left on {}",
+                iface
+            );
+            tc(&del_args(&iface))?;
+        }
+
+        tc(&["qdisc", "add", "dev", &iface, "handle", "ffff:", "ingress"])?;
+        let installed = tc(&filter_args(&iface, limit_kbit)).and_then(|_| {
+            std::fs::create_dir_all(MARKER_DIR)
+                .and_then(|()| std::fs::write(&marker, limit_kbit.to_string()))
+                .with_context(|| format!("Failed to write {}", marker.display()))
+        });
+        if let Err(e) = installed {
+            let _ = tc(&del_args(&iface));
+            return Err(e);
+        }
+
+        info!(
+            "Limited all inbound traffic on {} to {} kbit/s, the game's included",
+            iface, limit_kbit
+        );
+        Ok(Self { iface, limit_kbit })
+    }
+
+    /// Remove the ingress policer, need superuser access
+    pub fn release(self) -> Result<()> {
+        tc(&del_args(&self.iface))?;
+        let marker = marker_path(&self.iface);
+        if let Err(e) = std::fs::remove_file(&marker) {
+            debug!("Failed to remove {}: {}", marker.display(), e);
+        }
+        info!(
+            "Removed {} kbit/s link limit from {}",
+            self.limit_kbit, self.iface
+        );
+        Ok(())
+    }
+}
+
+/// Run `tc`, returning what it printed
+fn tc<S: AsRef<str>>(args: &[S]) -> Result<String> {
+    let args: Vec<&str> = args.iter().map(|a| a.as_ref()).collect();
+    debug!("Running tc {:?}", args);
+
+    let output = Command::new("tc")
+        .args(&args)
+        .output()
+        .context("Failed to run tc")?;
+
+    anyhow::ensure!(
+        output.status.success(),
+        "tc {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn del_args(iface: &str) -> Vec<&str> {
+    vec!["qdisc", "del", "dev", iface, "handle", "ffff:", "ingress"]
+}
+
+fn filter_args(iface: &str, limit_kbit: u32) -> Vec<String> {
+    // Allow roughly 100ms worth of traffic to pass in a single burst
+    let burst = (limit_kbit as u64 * 125 / 10).max(MIN_BURST_BYTES);
+
+    let rate = format!("{}kbit", limit_kbit);
+    let burst = burst.to_string();
+
+    [
+        "filter", "add", "dev", iface, "parent", "ffff:", "protocol", "all", "prio", "50", "u32",
+        "match", "u32", "0", "0", "police", "rate", &rate, "burst", &burst, "drop", "flowid", ":1",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+/// Refuse names that are not an interface of the machine
+fn check_iface(iface: &str, net_dir: &Path) -> Result<()> {
+    let plain = !iface.is_empty()
+        && iface != "."
+        && iface != ".."
+        && !iface.contains(|c: char| c == '/' || c.is_whitespace());
+    anyhow::ensure!(
+        plain && net_dir.join(iface).exists(),
+        "No network interface named '{}'",
+        iface.escape_debug()
+    );
+    Ok(())
+}
+
+/// Whether `tc qdisc show dev <iface> ingress` listed a qdisc
+fn has_ingress_qdisc(output: &str) -> bool {
+    output.lines().any(|line| line.starts_with("qdisc "))
+}
+
+fn marker_path(iface: &str) -> PathBuf {
+    Path::new(MARKER_DIR).join(format!("ingress-{}", iface))
+}
+
+/// Interface carrying the IPv4 default route, from `/proc/net/route`
+fn default_route_iface() -> Option<String> {
+    let routes = std::fs::read_to_string("/proc/net/route").ok()?;
+    parse_default_route(&routes)
+}
+
+fn parse_default_route(routes: &str) -> Option<String> {
+    routes.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        (fields.get(1) == Some(&"00000000")).then(|| fields[0].to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_default_route() {
+        let routes = "Iface\tDestination\tGateway\tFlags\n\
+                      wlan0\t0000A8C0\t00000000\t0001\n\
+                      eth0\t00000000\t0100A8C0\t0003\n";
+        assert_eq!(parse_default_route(routes), Some("eth0".to_string()));
+        assert_eq!(parse_default_route("Iface\tDestination\n"), None);
+    }
+
+    #[test]
+    fn test_filter_args() {
+        let args = filter_args("eth0", 8000);
+        assert!(args.contains(&"8000kbit".to_string()));
+        assert!(args.contains(&"100000".to_string()));
+
+        let args = filter_args("eth0", 100);
+        assert!(args.contains(&MIN_BURST_BYTES.to_string()));
+
+        // The interface stays a single argument
+        let args = filter_args("eth0 parent 1:", 100);
+        assert_eq!(args[3], "eth0 parent 1:");
+        assert_eq!(args.len(), filter_args("eth0", 100).len());
+    }
+
+    #[test]
+    fn test_check_iface() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("eth0")).unwrap();
+
+        assert!(check_iface("eth0", dir.path()).is_ok());
+        for iface in ["wlan0", "", ".", "..", "eth0 root", "../eth0"] {
+            assert!(check_iface(iface, dir.path()).is_err(), "{:?}", iface);
+        }
+    }
+
+    #[test]
+    fn test_has_ingress_qdisc() {
+        assert!(has_ingress_qdisc(
+            "qdisc ingress ffff: parent ffff:fff1 ----------------\n"
+        ));
+        assert!(has_ingress_qdisc("qdisc clsact ffff: parent ffff:fff1\n"));
+        assert!(!has_ingress_qdisc(""));
+    }
+
+    #[test]
+    fn test_apply_zero_limit() {
+        assert!(NetShaper::apply(Some("eth0"), 0).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use anyhow::{Context, Result};
+use log::debug;
+use std::path::Path;
+
+/// Score given to the game tree, low enough to outlast browsers and the
+/// desktop but still above -1000, which would exempt it entirely
+pub const PROTECT_SCORE: i32 = -500;
+
+/// Score given to background helpers so they are picked before anything else
+pub const SACRIFICE_SCORE: i32 = 500;
+
+/// Read `/proc/<pid>/oom_score_adj`
+pub fn get_oom_score_adj(pid: u32) -> Result<i32> {
+    let path = format!("/proc/{}/oom_score_adj", pid);
+    std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path))?
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid value in {}", path))
+}
+
+/// Write `/proc/<pid>/oom_score_adj`, lowering it needs CAP_SYS_RESOURCE
+pub fn set_oom_score_adj(pid: u32, score: i32) -> Result<()> {
+    let path = format!("/proc/{}/oom_score_adj", pid);
+    std::fs::write(&path, score.to_string())
+        .with_context(|| format!("Failed to write {}", path))?;
+    debug!("Set oom_score_adj of PID {} to {}", pid, score);
+    Ok(())
+}
+
+/// Real UID owning a process
+pub fn process_uid(pid: u32) -> Option<u32> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    parse_uid(&status)
+}
+
+/// PIDs of the given user's processes whose name is in `names`
+pub fn find_processes(names: &[String], uid: u32) -> Vec<u32> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| {
+            process_name(pid).is_some_and(|name| names.contains(&name))
+                && process_uid(*pid) == Some(uid)
+        })
+        .collect()
+}
+
+fn process_name(pid: &u32) -> Option<String> {
+    let comm =
+        std::fs::read_to_string(Path::new("/proc").join(pid.to_string()).join("comm")).ok()?;
+    Some(comm.trim().to_string())
+}
+
+fn parse_uid(status: &str) -> Option<u32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uid() {
+        let status = "Name:\tsteam\nState:\tS (sleeping)\nUid:\t1000\t1000\t1000\t1000\n";
+        assert_eq!(parse_uid(status), Some(1000));
+        assert_eq!(parse_uid("Name:\tsteam\n"), None);
+    }
+
+    #[test]
+    fn test_own_process() {
+        let pid = std::process::id();
+        let score = get_oom_score_adj(pid).unwrap();
+        assert!((-1000..=1000).contains(&score));
+        assert_eq!(process_uid(pid), Some(nix::unistd::getuid().as_raw()));
+
+        let name = process_name(&pid).unwrap();
+        assert!(find_processes(&[name], process_uid(pid).unwrap()).contains(&pid));
+    }
+}
@@ -0,0 +1,381 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::common::cgroup;
+
+/// Extended attribute systemd-oomd reads for `ManagedOOMPreference=avoid`
+const OOMD_AVOID_XATTR: &str = "user.oomd_avoid";
+
+const OOMD_UNIT: &str = "systemd-oomd.service";
+
+/// Where distributions keep the arguments of earlyoom's unit
+const EARLYOOM_DEFAULTS: &str = "/etc/default/earlyoom";
+
+const EARLYOOM_UNIT: &str = "earlyoom.service";
+
+/// Longest process name the kernel keeps, what earlyoom matches against
+const COMM_LEN: usize = 15;
+
+/// systemd-oomd's avoid preference on the cgroup a game runs in, what
+/// `ManagedOOMPreference=avoid` sets on a unit
+pub struct OomdAvoid {
+    cgroup: PathBuf,
+}
+
+impl OomdAvoid {
+    /// Mark the cgroup of `pid`, `None` when systemd-oomd does not run or
+    /// the cgroup is marked already
+    pub fn apply(pid: u32) -> Result<Option<Self>> {
+        if !unit_active(OOMD_UNIT) {
+            debug!("{} is not running, not marking PID {}", OOMD_UNIT, pid);
+            return Ok(None);
+        }
+
+        let cgroup = cgroup::cgroup_of(pid)
+            .with_context(|| format!("Could not find the cgroup v2 of PID {}", pid))?;
+        if get_xattr(&cgroup, OOMD_AVOID_XATTR).is_some() {
+            debug!("{} is already avoided by systemd-oomd", cgroup.display());
+            return Ok(None);
+        }
+
+        set_xattr(&cgroup, OOMD_AVOID_XATTR, b"1")?;
+        info!("systemd-oomd now avoids {}", cgroup.display());
+        Ok(Some(Self { cgroup }))
+    }
+
+    pub fn cgroup(&self) -> &Path {
+        &self.cgroup
+    }
+
+    pub fn release(self) -> Result<()> {
+        // Gone along with the cgroup when the game had one of its own
+        if !self.cgroup.exists() {
+            return Ok(());
+        }
+        remove_xattr(&self.cgroup, OOMD_AVOID_XATTR)?;
+        info!("systemd-oomd no longer avoids {}", self.cgroup.display());
+        Ok(())
+    }
+}
+
+/// Games added to earlyoom's `--avoid` pattern, by the PID of their session.
+/// earlyoom only reads its arguments on start, so it is restarted whenever
+/// the pattern changes.
+pub struct EarlyoomAvoid {
+    path: PathBuf,
+    /// The file as it was before, `None` when it did not exist
+    original: Option<String>,
+    names: BTreeMap<u32, String>,
+    /// What was last written, to skip restarts that change nothing
+    written: Option<String>,
+}
+
+impl EarlyoomAvoid {
+    /// `None` when earlyoom does not run
+    pub fn start() -> Option<Self> {
+        if !unit_active(EARLYOOM_UNIT) {
+            debug!("{} is not running, leaving it alone", EARLYOOM_UNIT);
+            return None;
+        }
+        let path = PathBuf::from(EARLYOOM_DEFAULTS);
+        let original = std::fs::read_to_string(&path).ok();
+        Some(Self {
+            path,
+            original,
+            names: BTreeMap::new(),
+            written: None,
+        })
+    }
+
+    pub fn add(&mut self, pid: u32, name: &str) -> Result<()> {
+        anyhow::ensure!(
+            is_plain_name(name),
+            "Refusing to add '{}' to the earlyoom arguments",
+            name.escape_debug()
+        );
+        self.names.insert(pid, comm_name(name));
+        self.write()
+    }
+
+    pub fn remove(&mut self, pid: u32) -> Result<()> {
+        if self.names.remove(&pid).is_none() {
+            return Ok(());
+        }
+        self.write()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Put the arguments back as they were
+    pub fn release(self) -> Result<()> {
+        match &self.original {
+            Some(original) => std::fs::write(&self.path, original)
+                .with_context(|| format!("Failed to restore {}", self.path.display()))?,
+            None => std::fs::remove_file(&self.path)
+                .with_context(|| format!("Failed to remove {}", self.path.display()))?,
+        }
+        restart_earlyoom()?;
+        info!("Restored the earlyoom arguments");
+        Ok(())
+    }
+
+    fn write(&mut self) -> Result<()> {
+        let names: BTreeSet<&str> = self.names.values().map(String::as_str).collect();
+        let names: Vec<&str> = names.into_iter().collect();
+        let defaults = with_avoid(self.original.as_deref().unwrap_or_default(), &names);
+        if self.written.as_ref() == Some(&defaults) {
+            debug!("earlyoom already avoids {}", names.join(", "));
+            return Ok(());
+        }
+
+        std::fs::write(&self.path, &defaults)
+            .with_context(|| format!("Failed to write {}", self.path.display()))?;
+        self.written = Some(defaults);
+        restart_earlyoom()?;
+        info!("earlyoom now avoids {}", names.join(", "));
+        Ok(())
+    }
+}
+
+/// `EARLYOOM_ARGS` of `defaults` with the processes in `names` added to its
+/// `--avoid` pattern, or the pattern added when there is none
+fn with_avoid(defaults: &str, names: &[&str]) -> String {
+    let games = format!(
+        "^({})$",
+        names
+            .iter()
+            .map(|name| escape_regex(name))
+            .collect::<Vec<_>>()
+            .join("|")
+    );
+
+    let mut found = false;
+    let mut lines: Vec<String> = defaults
+        .lines()
+        .map(|line| {
+            let Some(args) = line.trim().strip_prefix("EARLYOOM_ARGS=") else {
+                return line.to_string();
+            };
+            found = true;
+            let args = args.trim_matches('"');
+            format!("EARLYOOM_ARGS=\"{}\"", add_avoid(args, &games))
+        })
+        .collect();
+
+    if !found {
+        lines.push(format!("EARLYOOM_ARGS=\"{}\"", add_avoid("", &games)));
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Combine an existing `--avoid '<regex>'` with `pattern`
+fn add_avoid(args: &str, pattern: &str) -> String {
+    let Some(start) = args.find("--avoid") else {
+        return format!("{} --avoid '{}'", args, pattern).trim().to_string();
+    };
+
+    let rest = args[start + "--avoid".len()..].trim_start_matches([' ', '=']);
+    let (existing, after) = match rest.strip_prefix('\'') {
+        Some(quoted) => quoted.split_once('\'').unwrap_or((quoted, "")),
+        None => rest.split_once(' ').unwrap_or((rest, "")),
+    };
+
+    format!(
+        "{}--avoid '({})|{}'{}{}",
+        &args[..start],
+        existing,
+        pattern,
+        if after.is_empty() { "" } else { " " },
+        after.trim_start()
+    )
+}
+
+/// Whether a name can go into the arguments as is, letters, digits and
+/// `._+-` only, so it can not end the pattern, the quotes or the line
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._+-".contains(c))
+}
+
+/// Escape the characters of a plain name that mean something in a regex
+fn escape_regex(name: &str) -> String {
+    name.chars()
+        .flat_map(|c| {
+            let special = c == '.' || c == '+';
+            special.then_some('\\').into_iter().chain([c])
+        })
+        .collect()
+}
+
+/// The name the kernel shows for an executable, cut to its first 15 bytes
+fn comm_name(name: &str) -> String {
+    let mut end = name.len().min(COMM_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    name[..end].to_string()
+}
+
+fn unit_active(unit: &str) -> bool {
+    Command::new("systemctl")
+        .args(["is-active", "--quiet", unit])
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn restart_earlyoom() -> Result<()> {
+    let output = Command::new("systemctl")
+        .args(["try-restart", EARLYOOM_UNIT])
+        .output()
+        .context("Failed to run systemctl")?;
+    anyhow::ensure!(
+        output.status.success(),
+        "Failed to restart {}: {}",
+        EARLYOOM_UNIT,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(())
+}
+
+fn c_path(path: &Path) -> Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .with_context(|| format!("Invalid path {}", path.display()))
+}
+
+fn get_xattr(path: &Path, name: &str) -> Option<Vec<u8>> {
+    let path = c_path(path).ok()?;
+    let name = CString::new(name).ok()?;
+    let mut value = [0u8; 16];
+    let len = unsafe {
+        libc::getxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_mut_ptr().cast(),
+            value.len(),
+        )
+    };
+    (len >= 0).then(|| value[..len as usize].to_vec())
+}
+
+fn set_xattr(path: &Path, name: &str, value: &[u8]) -> Result<()> {
+    let c_name = CString::new(name)?;
+    let ret = unsafe {
+        libc::setxattr(
+            c_path(path)?.as_ptr(),
+            c_name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    };
+    anyhow::ensure!(
+        ret == 0,
+        "Failed to set {} on {}: {}",
+        name,
+        path.display(),
+        std::io::Error::last_os_error()
+    );
+    Ok(())
+}
+
+fn remove_xattr(path: &Path, name: &str) -> Result<()> {
+    let c_name = CString::new(name)?;
+    let ret = unsafe { libc::removexattr(c_path(path)?.as_ptr(), c_name.as_ptr()) };
+    anyhow::ensure!(
+        ret == 0,
+        "Failed to remove {} from {}: {}",
+        name,
+        path.display(),
+        std::io::Error::last_os_error()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_avoid() {
+        let defaults = "# Options for earlyoom\nEARLYOOM_ARGS=\"-r 3600 -n --avoid '(^|/)(init|Xorg|ssh)$'\"\n";
+        assert_eq!(
+            with_avoid(defaults, &["eldenring.exe"]),
+            "# Options for earlyoom\nEARLYOOM_ARGS=\"-r 3600 -n --avoid '((^|/)(init|Xorg|ssh)$)|^(eldenring\\.exe)$'\"\n"
+        );
+
+        assert_eq!(
+            with_avoid("EARLYOOM_ARGS=\"-r 60\"\n", &["doom", "game+1"]),
+            "EARLYOOM_ARGS=\"-r 60 --avoid '^(doom|game\\+1)$'\"\n"
+        );
+        assert_eq!(
+            with_avoid("", &["doom"]),
+            "EARLYOOM_ARGS=\"--avoid '^(doom)$'\"\n"
+        );
+        assert_eq!(
+            add_avoid("--avoid=init -r 60", "^(doom)$"),
+            "--avoid '(init)|^(doom)$' -r 60"
+        );
+    }
+
+    #[test]
+    fn test_is_plain_name() {
+        assert!(is_plain_name("eldenring.exe"));
+        assert!(is_plain_name("Game_x64-Shipping"));
+        for name in [
+            "",
+            "a'b",
+            "a\"b",
+            "x\nEARLYOOM_ARGS=-N /tmp/x",
+            "a b",
+            "(a)",
+            "a/b",
+        ] {
+            assert!(!is_plain_name(name), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_earlyoom_add_refuses_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut avoid = EarlyoomAvoid {
+            path: dir.path().join("earlyoom"),
+            original: None,
+            names: BTreeMap::new(),
+            written: None,
+        };
+        assert!(avoid.add(1234, "doom\nEARLYOOM_ARGS=\"-p\"").is_err());
+        assert!(avoid.is_empty());
+        assert!(!avoid.path.exists());
+    }
+
+    #[test]
+    fn test_earlyoom_unchanged_skips_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut avoid = EarlyoomAvoid {
+            path: dir.path().join("earlyoom"),
+            original: None,
+            names: BTreeMap::from([(1234, "doom".to_string())]),
+            written: Some(with_avoid("", &["doom"])),
+        };
+
+        // A second session of the same game leaves the pattern as it is
+        avoid.add(5678, "doom").unwrap();
+        avoid.remove(1234).unwrap();
+        assert!(!avoid.path.exists());
+    }
+
+    #[test]
+    fn test_comm_name() {
+        assert_eq!(comm_name("Cyberpunk2077.exe"), "Cyberpunk2077.e");
+        assert_eq!(comm_name("doom"), "doom");
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+const CPU_DMA_LATENCY: &str = "/dev/cpu_dma_latency";
+
+/// Holds a PM QoS CPU latency request for as long as it is alive.
+/// The kernel only honors the request while the file descriptor stays
+/// open, and drops it automatically once the file is closed.
+pub struct PmQosGuard {
+    _file: File,
+    latency_us: i32,
+}
+
+impl PmQosGuard {
+    /// Request a maximum CPU wakeup latency in microseconds, need superuser access
+    pub fn acquire(latency_us: i32) -> Result<Self> {
+        Self::acquire_at(Path::new(CPU_DMA_LATENCY), latency_us)
+    }
+
+    fn acquire_at(path: &Path, latency_us: i32) -> Result<Self> {
+        anyhow::ensure!(
+            latency_us >= 0,
+            "CPU DMA latency must not be negative, got {}",
+            latency_us
+        );
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        // The interface expects a raw native-endian s32, not text
+        file.write_all(&latency_us.to_ne_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        info!("Holding CPU DMA latency request at {}us", latency_us);
+        Ok(Self {
+            _file: file,
+            latency_us,
+        })
+    }
+
+    pub fn latency_us(&self) -> i32 {
+        self.latency_us
+    }
+}
+
+impl Drop for PmQosGuard {
+    fn drop(&mut self) {
+        debug!("Releasing CPU DMA latency request of {}us", self.latency_us);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_acquire_writes_raw_value() {
+        let file = NamedTempFile::new().unwrap();
+        let guard = PmQosGuard::acquire_at(file.path(), 50).unwrap();
+
+        assert_eq!(guard.latency_us(), 50);
+        assert_eq!(std::fs::read(file.path()).unwrap(), 50i32.to_ne_bytes());
+    }
+
+    #[test]
+    fn test_acquire_negative_latency() {
+        let file = NamedTempFile::new().unwrap();
+        assert!(PmQosGuard::acquire_at(file.path(), -1).is_err());
+    }
+
+    #[test]
+    fn test_acquire_missing_device() {
+        assert!(PmQosGuard::acquire_at(Path::new("/nonexistent/cpu_dma_latency"), 0).is_err());
+    }
+}
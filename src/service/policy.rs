@@ -0,0 +1,866 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::common::config::{CpuTune, GpuTune, IoprioClass, MountConfig, SysTune, power_limit_mw};
+use crate::service::oomadj;
+use crate::service::ryzen::EppProfile;
+
+/// System wide limits, only trusted when owned by root
+pub const POLICY_PATH: &str = "/etc/nvprime/daemon.toml";
+
+/// Where logind keeps a file for each login session
+const SESSIONS_DIR: &str = "/run/systemd/sessions";
+
+/// Root-side features reaching beyond the game, off unless the policy
+/// enables them in `allowed_features`
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyFeature {
+    /// `link_limit_kbit`, capping the whole link's inbound traffic
+    LinkLimit,
+    /// `[sys.memory]`, zswap and swap priorities
+    Swap,
+    /// `background_cpus`, confining the system and user slices
+    BackgroundCpus,
+    /// `memory_protect_gb`, reserving memory against everything else
+    MemoryProtect,
+    /// `oom_sacrifice`, pushing other processes towards the OOM killer
+    OomSacrifice,
+    /// `smt`, taking sibling threads offline
+    Smt,
+    /// `ppt_limit`, the CPU package power limit
+    PptLimit,
+    /// `cpu_max_freq_mhz`, capping every core
+    CpuMaxFreq,
+    /// `cpu_dma_latency_us`, keeping every core out of deep C-states
+    CpuDmaLatency,
+    /// Resetting GPU and CPU tuning outside of a session's own teardown
+    Reset,
+    /// `mount`, the drives named in `[mounts]`
+    Mount,
+    /// `oom_protect`, lowering the game's OOM score
+    OomProtect,
+    /// Excluding games with `oom_protect` from systemd-oomd and earlyoom,
+    /// which rewrites earlyoom's arguments and restarts it
+    OomAvoid,
+}
+
+impl fmt::Display for PolicyFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::LinkLimit => "link_limit",
+            Self::Swap => "swap",
+            Self::BackgroundCpus => "background_cpus",
+            Self::MemoryProtect => "memory_protect",
+            Self::OomSacrifice => "oom_sacrifice",
+            Self::Smt => "smt",
+            Self::PptLimit => "ppt_limit",
+            Self::CpuMaxFreq => "cpu_max_freq",
+            Self::CpuDmaLatency => "cpu_dma_latency",
+            Self::Reset => "reset",
+            Self::Mount => "mount",
+            Self::OomProtect => "oom_protect",
+            Self::OomAvoid => "oom_avoid",
+        })
+    }
+}
+
+/// Hard limits on what clients may ask the daemon for. Requests outside of
+/// them are clamped, features the policy does not enable are dropped, and
+/// callers outside `allowed_uids` are refused.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonPolicy {
+    /// Highest GPU power limit, same forms as `pwr_limit_tune`
+    #[serde(deserialize_with = "power_limit_mw")]
+    pub max_power_limit: Option<u32>,
+
+    /// Lowest GPU power limit, same forms as `pwr_limit_tune`
+    #[serde(deserialize_with = "power_limit_mw")]
+    pub min_power_limit: Option<u32>,
+
+    /// Watts below which a GPU power limit is refused instead of clamped,
+    /// so a typo does not leave the game running at the GPU's minimum
+    pub min_allowed_power_w: Option<u32>,
+
+    /// Lowest niceness a client may give its game
+    pub min_renice: i32,
+
+    /// Highest niceness a client may give its game
+    pub max_renice: i32,
+
+    /// Let clients put their game in the realtime IO class, which can
+    /// starve everything else of disk access. Refused requests get the
+    /// best-effort class at the same level.
+    pub allow_realtime_ioprio: bool,
+
+    /// Highest realtime IO priority a client may ask for (0-7, lower is
+    /// higher)
+    pub min_realtime_ioprio: i32,
+
+    /// EPP hints clients may set, any when empty
+    pub allowed_epp: Vec<String>,
+
+    /// Users allowed to request tuning. When unset, those with an active
+    /// local login session. Root always is.
+    pub allowed_uids: Option<Vec<u32>>,
+
+    /// Root-side features clients may use, none when empty
+    pub allowed_features: BTreeSet<PolicyFeature>,
+
+    /// Lowest link limit in kbit/s
+    /// Default: 1000
+    pub min_link_limit_kbit: u32,
+
+    /// Highest share of RAM zswap may be given, in percent
+    /// Default: 25
+    pub max_zswap_pool_percent: u32,
+
+    /// Most memory a session may protect, in GB
+    /// Default: 8
+    pub max_memory_protect_gb: u32,
+
+    /// Lowest CPU frequency cap in MHz
+    /// Default: 1000
+    pub min_cpu_max_freq_mhz: u32,
+
+    /// Drives clients may have mounted for a session, by name
+    pub mounts: BTreeMap<String, MountConfig>,
+}
+
+impl Default for DaemonPolicy {
+    fn default() -> Self {
+        Self {
+            max_power_limit: None,
+            min_power_limit: None,
+            min_allowed_power_w: None,
+            min_renice: -20,
+            max_renice: 19,
+            allow_realtime_ioprio: false,
+            min_realtime_ioprio: 4,
+            allowed_epp: Vec::new(),
+            allowed_uids: None,
+            allowed_features: BTreeSet::new(),
+            min_link_limit_kbit: 1000,
+            max_zswap_pool_percent: 25,
+            max_memory_protect_gb: 8,
+            min_cpu_max_freq_mhz: 1000,
+            mounts: BTreeMap::new(),
+        }
+    }
+}
+
+impl DaemonPolicy {
+    /// Load the system policy, the defaults when there is none
+    pub fn load() -> Result<Self> {
+        Self::load_file(Path::new(POLICY_PATH))
+    }
+
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!(
+                    "No daemon policy at {}, using the default limits",
+                    path.display()
+                );
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+
+        // Anyone able to edit the policy could lift the limits it sets
+        anyhow::ensure!(
+            metadata.uid() == 0 && metadata.mode() & 0o022 == 0,
+            "{} must be owned by root and not writable by others",
+            path.display()
+        );
+
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let policy: Self = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        anyhow::ensure!(
+            policy.min_renice <= policy.max_renice,
+            "min_renice is above max_renice"
+        );
+        anyhow::ensure!(
+            (0..=7).contains(&policy.min_realtime_ioprio),
+            "min_realtime_ioprio must be between 0 and 7"
+        );
+        if let (Some(min), Some(max)) = (policy.min_power_limit, policy.max_power_limit) {
+            anyhow::ensure!(min <= max, "min_power_limit is above max_power_limit");
+        }
+        if let (Some(floor), Some(max)) = (policy.min_allowed_power_w, policy.max_power_limit) {
+            anyhow::ensure!(
+                u64::from(floor) * 1000 <= u64::from(max),
+                "min_allowed_power_w is above max_power_limit"
+            );
+        }
+
+        info!("Loaded daemon policy from {}", path.display());
+        Ok(policy)
+    }
+
+    /// Whether a user may request tuning at all
+    pub fn check_uid(&self, uid: u32) -> Result<()> {
+        self.check_uid_in(uid, Path::new(SESSIONS_DIR))
+    }
+
+    fn check_uid_in(&self, uid: u32, sessions_dir: &Path) -> Result<()> {
+        let allowed = match &self.allowed_uids {
+            _ if uid == 0 => true,
+            Some(uids) => uids.contains(&uid),
+            None => local_session_uids(sessions_dir).contains(&uid),
+        };
+        anyhow::ensure!(allowed, "UID {} is not allowed to request tuning", uid);
+        Ok(())
+    }
+
+    /// Whether the policy enables a root-side feature
+    pub fn allows(&self, feature: PolicyFeature) -> bool {
+        self.allowed_features.contains(&feature)
+    }
+
+    /// Refuse a feature the policy does not enable
+    pub fn check_feature(&self, feature: PolicyFeature) -> Result<()> {
+        anyhow::ensure!(
+            self.allows(feature),
+            "{} is not enabled in the daemon policy",
+            feature
+        );
+        Ok(())
+    }
+
+    /// Drop a request for a feature the policy does not enable, returning
+    /// whether it is still there
+    fn gate<T: Default + PartialEq>(
+        &self,
+        feature: PolicyFeature,
+        value: &mut T,
+        changes: &mut Vec<String>,
+    ) -> bool {
+        if *value == T::default() {
+            return false;
+        }
+        if !self.allows(feature) {
+            *value = T::default();
+            changes.push(format!("{} is not enabled in the daemon policy", feature));
+            return false;
+        }
+        true
+    }
+
+    /// Clamp the power limit, returning what was changed. Limits below
+    /// `min_allowed_power_w` are refused.
+    pub fn constrain_gpu(&self, gpu: &mut GpuTune) -> Result<Vec<String>> {
+        let mut changes = Vec::new();
+
+        if let (Some(requested), Some(floor_w)) = (gpu.pwr_limit_tune, self.min_allowed_power_w)
+            && !gpu.set_max_pwr
+            && u64::from(requested) < u64::from(floor_w) * 1000
+        {
+            anyhow::bail!(
+                "Power limit {}mW is below the allowed {}W",
+                requested,
+                floor_w
+            );
+        }
+
+        if gpu.set_max_pwr
+            && let Some(max) = self.max_power_limit
+        {
+            gpu.set_max_pwr = false;
+            gpu.pwr_limit_tune = Some(max);
+            changes.push(format!("maximum power limit capped to {}mW", max));
+        }
+
+        if let Some(requested) = gpu.pwr_limit_tune {
+            let min = self.min_power_limit.unwrap_or(0);
+            let max = self.max_power_limit.unwrap_or(u32::MAX);
+            let clamped = requested.clamp(min, max);
+
+            if clamped != requested {
+                gpu.pwr_limit_tune = Some(clamped);
+                changes.push(format!(
+                    "power limit {}mW clamped to {}mW",
+                    requested, clamped
+                ));
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Replace EPP hints that are not allowed with the closest allowed one,
+    /// and drop or clamp the root-side CPU settings
+    pub fn constrain_cpu(&self, cpu: &mut CpuTune) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        if self.gate(
+            PolicyFeature::CpuMaxFreq,
+            &mut cpu.cpu_max_freq_mhz,
+            &mut changes,
+        ) {
+            clamp_min(
+                "CPU frequency cap",
+                "MHz",
+                &mut cpu.cpu_max_freq_mhz,
+                self.min_cpu_max_freq_mhz,
+                &mut changes,
+            );
+        }
+        self.gate(PolicyFeature::Smt, &mut cpu.smt, &mut changes);
+        self.gate(PolicyFeature::PptLimit, &mut cpu.ppt_limit, &mut changes);
+        self.gate(
+            PolicyFeature::CpuDmaLatency,
+            &mut cpu.cpu_dma_latency_us,
+            &mut changes,
+        );
+
+        for epp in [
+            &mut cpu.amd_epp_tune,
+            &mut cpu.amd_epp_base,
+            &mut cpu.dynamic.gpu_bound_epp,
+        ] {
+            changes.extend(self.constrain_epp(epp));
+        }
+
+        changes
+    }
+
+    /// Replace an EPP hint the policy does not allow with the closest one
+    pub fn constrain_epp(&self, epp: &mut String) -> Option<String> {
+        let allowed = self.closest_epp(epp).filter(|allowed| allowed != epp)?;
+        let change = format!("EPP '{}' replaced with '{}'", epp, allowed);
+        *epp = allowed;
+        Some(change)
+    }
+
+    /// Clamp the niceness into the allowed range, and drop or clamp the
+    /// root-side system settings, returning what was changed
+    pub fn constrain_sys(&self, sys: &mut SysTune) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        if self.gate(
+            PolicyFeature::LinkLimit,
+            &mut sys.link_limit_kbit,
+            &mut changes,
+        ) {
+            clamp_min(
+                "link limit",
+                "kbit/s",
+                &mut sys.link_limit_kbit,
+                self.min_link_limit_kbit,
+                &mut changes,
+            );
+        }
+        self.gate(
+            PolicyFeature::BackgroundCpus,
+            &mut sys.background_cpus,
+            &mut changes,
+        );
+        if self.gate(PolicyFeature::Swap, &mut sys.memory, &mut changes)
+            && let Some(percent) = sys.memory.zswap_max_pool_percent
+            && percent > self.max_zswap_pool_percent
+        {
+            sys.memory.zswap_max_pool_percent = Some(self.max_zswap_pool_percent);
+            changes.push(format!(
+                "zswap pool {}% clamped to {}%",
+                percent, self.max_zswap_pool_percent
+            ));
+        }
+
+        for (name, renice) in [
+            ("renice", &mut sys.proc_renice),
+            ("launch renice", &mut sys.launch_renice),
+        ] {
+            // Zero leaves the priority alone, so it never needs clamping
+            let requested = *renice;
+            let clamped = requested.clamp(self.min_renice, self.max_renice);
+            if requested != 0 && clamped != requested {
+                *renice = clamped;
+                changes.push(format!("{} {} clamped to {}", name, requested, clamped));
+            }
+        }
+
+        if sys.proc_ioprio_class == IoprioClass::Rt {
+            if !self.allow_realtime_ioprio {
+                sys.proc_ioprio_class = IoprioClass::Be;
+                changes.push("realtime IO class replaced with best-effort".to_string());
+            } else if sys.proc_ioprio < self.min_realtime_ioprio {
+                changes.push(format!(
+                    "realtime IO priority {} clamped to {}",
+                    sys.proc_ioprio, self.min_realtime_ioprio
+                ));
+                sys.proc_ioprio = self.min_realtime_ioprio;
+            }
+        }
+
+        changes
+    }
+
+    /// Drop or clamp what a session protects itself with at the expense of
+    /// the rest of the system
+    pub fn constrain_protection(
+        &self,
+        oom_protect: &mut bool,
+        memory_protect_gb: &mut Option<u32>,
+        oom_sacrifice: &mut Vec<String>,
+    ) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        self.gate(PolicyFeature::OomProtect, oom_protect, &mut changes);
+
+        if self.gate(
+            PolicyFeature::MemoryProtect,
+            memory_protect_gb,
+            &mut changes,
+        ) && let Some(gb) = *memory_protect_gb
+            && gb > self.max_memory_protect_gb
+        {
+            *memory_protect_gb = Some(self.max_memory_protect_gb);
+            changes.push(format!(
+                "memory protection {}GB clamped to {}GB",
+                gb, self.max_memory_protect_gb
+            ));
+        }
+        self.gate(PolicyFeature::OomSacrifice, oom_sacrifice, &mut changes);
+
+        changes
+    }
+
+    /// Drop the exclusion from userspace OOM killers that comes with
+    /// `oom_protect` when the policy does not enable it
+    pub fn constrain_oom_avoid(&self, oom_avoid: &mut bool) -> Vec<String> {
+        let mut changes = Vec::new();
+        self.gate(PolicyFeature::OomAvoid, oom_avoid, &mut changes);
+        changes
+    }
+
+    /// Drop mounts when the policy does not enable them, and names it does
+    /// not define
+    pub fn constrain_mounts(&self, mounts: &mut Vec<String>) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        if self.gate(PolicyFeature::Mount, mounts, &mut changes) {
+            mounts.retain(|name| {
+                let known = self.mounts.contains_key(name);
+                if !known {
+                    changes.push(format!("mount '{}' is not in the daemon policy", name));
+                }
+                known
+            });
+        }
+
+        changes
+    }
+
+    /// `None` when every hint is allowed or no allowed hint is valid
+    fn closest_epp(&self, requested: &str) -> Option<String> {
+        let allowed: Vec<usize> = EppProfile::ALL
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| self.allowed_epp.iter().any(|a| a == p.as_str()))
+            .map(|(i, _)| i)
+            .collect();
+        if allowed.is_empty() {
+            return None;
+        }
+
+        let rank = EppProfile::from_str(requested)
+            .ok()
+            .and_then(|p| EppProfile::ALL.iter().position(|a| *a == p))
+            .unwrap_or(0);
+        let closest = allowed.into_iter().min_by_key(|i| i.abs_diff(rank))?;
+        Some(EppProfile::ALL[closest].as_str().to_string())
+    }
+}
+
+/// Refuse acting on another user's process, unless the caller is root. A
+/// process that is gone is left to the caller to fail on.
+pub fn check_process_owner(uid: u32, pid: u32) -> Result<()> {
+    check_owner(uid, pid, oomadj::process_uid(pid))
+}
+
+fn check_owner(uid: u32, pid: u32, owner: Option<u32>) -> Result<()> {
+    if let Some(owner) = owner
+        && uid != 0
+    {
+        anyhow::ensure!(
+            owner == uid,
+            "PID {} belongs to UID {}, not UID {}",
+            pid,
+            owner,
+            uid
+        );
+    }
+    Ok(())
+}
+
+/// Raise a value below `min` to it
+fn clamp_min(what: &str, unit: &str, value: &mut Option<u32>, min: u32, changes: &mut Vec<String>) {
+    if let Some(requested) = *value
+        && requested < min
+    {
+        *value = Some(min);
+        changes.push(format!(
+            "{} {}{} clamped to {}{}",
+            what, requested, unit, min, unit
+        ));
+    }
+}
+
+/// Users with an active login session on a local seat, as logind records
+/// them in `sessions_dir`
+fn local_session_uids(sessions_dir: &Path) -> BTreeSet<u32> {
+    let Ok(entries) = std::fs::read_dir(sessions_dir) else {
+        debug!("No login sessions in {}", sessions_dir.display());
+        return BTreeSet::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| std::fs::read_to_string(entry.path()).ok())
+        .filter_map(|content| {
+            let mut uid = None;
+            let (mut active, mut remote) = (false, false);
+            for line in content.lines() {
+                match line.split_once('=') {
+                    Some(("UID", value)) => uid = value.parse().ok(),
+                    Some(("ACTIVE", value)) => active = value == "1",
+                    Some(("REMOTE", value)) => remote = value == "1",
+                    _ => {}
+                }
+            }
+            uid.filter(|_| active && !remote)
+        })
+        .collect()
+}
+
+/// Log what the policy changed in a request
+pub fn report(uid: u32, changes: &[String]) {
+    for change in changes {
+        warn!("Request of UID {} limited by policy: {}", uid, change);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::{MemoryTune, SmtMode};
+
+    fn policy(toml: &str) -> DaemonPolicy {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_policy_defaults() {
+        let policy = DaemonPolicy::default();
+        let mut gpu = GpuTune {
+            set_max_pwr: true,
+            pwr_limit_tune: Some(1_000),
+            ..Default::default()
+        };
+        let mut cpu = CpuTune::default();
+        let mut sys = SysTune {
+            proc_renice: -20,
+            ..Default::default()
+        };
+
+        assert!(policy.constrain_gpu(&mut gpu).unwrap().is_empty());
+        assert!(policy.constrain_cpu(&mut cpu).is_empty());
+        assert!(policy.constrain_sys(&mut sys).is_empty());
+
+        // Root-side features are off
+        cpu.smt = Some(SmtMode::Off);
+        sys.link_limit_kbit = Some(5000);
+        let mut oom_protect = true;
+        let mut memory_protect_gb = Some(4);
+        let mut oom_sacrifice = vec!["steamwebhelper".to_string()];
+        assert_eq!(
+            policy.constrain_cpu(&mut cpu),
+            vec!["smt is not enabled in the daemon policy"]
+        );
+        assert_eq!(cpu.smt, None);
+        cpu.cpu_dma_latency_us = Some(0);
+        assert_eq!(
+            policy.constrain_cpu(&mut cpu),
+            vec!["cpu_dma_latency is not enabled in the daemon policy"]
+        );
+        assert_eq!(cpu.cpu_dma_latency_us, None);
+        assert_eq!(policy.constrain_sys(&mut sys).len(), 1);
+        assert_eq!(sys.link_limit_kbit, None);
+        assert_eq!(
+            policy
+                .constrain_protection(&mut oom_protect, &mut memory_protect_gb, &mut oom_sacrifice)
+                .len(),
+            3
+        );
+        assert!(!oom_protect);
+        let mut oom_avoid = true;
+        assert_eq!(policy.constrain_oom_avoid(&mut oom_avoid).len(), 1);
+        assert!(!oom_avoid);
+        assert_eq!(memory_protect_gb, None);
+        assert!(oom_sacrifice.is_empty());
+        assert!(policy.check_feature(PolicyFeature::Reset).is_err());
+    }
+
+    #[test]
+    fn test_policy_allowed_features() {
+        let policy = policy(
+            r#"
+            allowed_features = ["cpu_max_freq", "link_limit", "swap", "memory_protect"]
+            max_memory_protect_gb = 4
+            "#,
+        );
+        let mut cpu = CpuTune {
+            cpu_max_freq_mhz: Some(400),
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.constrain_cpu(&mut cpu),
+            vec!["CPU frequency cap 400MHz clamped to 1000MHz"]
+        );
+
+        let mut sys = SysTune {
+            link_limit_kbit: Some(20_000),
+            memory: MemoryTune {
+                zswap_max_pool_percent: Some(50),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.constrain_sys(&mut sys),
+            vec!["zswap pool 50% clamped to 25%"]
+        );
+        assert_eq!(sys.link_limit_kbit, Some(20_000));
+
+        let mut memory_protect_gb = Some(16);
+        policy.constrain_protection(&mut false, &mut memory_protect_gb, &mut Vec::new());
+        assert_eq!(memory_protect_gb, Some(4));
+        assert!(toml::from_str::<DaemonPolicy>(r#"allowed_features = ["umount"]"#).is_err());
+    }
+
+    #[test]
+    fn test_policy_mounts() {
+        let mut mounts = vec!["games".to_string()];
+        assert_eq!(
+            DaemonPolicy::default().constrain_mounts(&mut mounts),
+            vec!["mount is not enabled in the daemon policy"]
+        );
+        assert!(mounts.is_empty());
+
+        let policy = policy(
+            r#"
+            allowed_features = ["mount"]
+
+            [mounts.games]
+            uuid = "1234-ABCD"
+            mountpoint = "/mnt/games"
+            "#,
+        );
+        let mut mounts = vec!["games".to_string(), "etc".to_string()];
+        assert_eq!(
+            policy.constrain_mounts(&mut mounts),
+            vec!["mount 'etc' is not in the daemon policy"]
+        );
+        assert_eq!(mounts, vec!["games"]);
+        assert_eq!(policy.mounts["games"].mountpoint, "/mnt/games");
+        assert!(
+            toml::from_str::<DaemonPolicy>("[mounts.x]\nmountpoint = \"/x\"\nsuid = true").is_err()
+        );
+    }
+
+    #[test]
+    fn test_policy_clamps_power_limit() {
+        let policy = policy(
+            r#"
+            max_power_limit = "250W"
+            min_power_limit = "100W"
+            "#,
+        );
+
+        let mut gpu = GpuTune {
+            pwr_limit_tune: Some(300_000),
+            ..Default::default()
+        };
+        assert_eq!(policy.constrain_gpu(&mut gpu).unwrap().len(), 1);
+        assert_eq!(gpu.pwr_limit_tune, Some(250_000));
+
+        gpu.pwr_limit_tune = Some(50_000);
+        policy.constrain_gpu(&mut gpu).unwrap();
+        assert_eq!(gpu.pwr_limit_tune, Some(100_000));
+
+        gpu.set_max_pwr = true;
+        policy.constrain_gpu(&mut gpu).unwrap();
+        assert!(!gpu.set_max_pwr);
+        assert_eq!(gpu.pwr_limit_tune, Some(250_000));
+    }
+
+    #[test]
+    fn test_policy_refuses_power_below_floor() {
+        let policy = policy(
+            r#"
+            min_power_limit = "100W"
+            min_allowed_power_w = 60
+            "#,
+        );
+
+        let mut gpu = GpuTune {
+            pwr_limit_tune: Some(5_000),
+            ..Default::default()
+        };
+        assert!(policy.constrain_gpu(&mut gpu).is_err());
+        assert_eq!(gpu.pwr_limit_tune, Some(5_000));
+
+        // Above the floor still gets clamped to the minimum
+        gpu.pwr_limit_tune = Some(80_000);
+        assert_eq!(policy.constrain_gpu(&mut gpu).unwrap().len(), 1);
+        assert_eq!(gpu.pwr_limit_tune, Some(100_000));
+
+        let mut gpu = GpuTune {
+            set_max_pwr: true,
+            ..Default::default()
+        };
+        assert!(policy.constrain_gpu(&mut gpu).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_policy_clamps_renice() {
+        let policy = policy("min_renice = -5\nmax_renice = 5");
+        let mut sys = SysTune {
+            proc_renice: -15,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            policy.constrain_sys(&mut sys),
+            vec!["renice -15 clamped to -5"]
+        );
+        assert_eq!(sys.proc_renice, -5);
+
+        sys.proc_renice = 0;
+        assert!(policy.constrain_sys(&mut sys).is_empty());
+
+        sys.launch_renice = 10;
+        assert_eq!(
+            policy.constrain_sys(&mut sys),
+            vec!["launch renice 10 clamped to 5"]
+        );
+    }
+
+    #[test]
+    fn test_policy_realtime_ioprio() {
+        let mut sys = SysTune {
+            proc_ioprio: 0,
+            proc_ioprio_class: IoprioClass::Rt,
+            ..Default::default()
+        };
+        assert_eq!(
+            DaemonPolicy::default().constrain_sys(&mut sys.clone()),
+            vec!["realtime IO class replaced with best-effort"]
+        );
+
+        let policy = policy("allow_realtime_ioprio = true\nmin_realtime_ioprio = 2");
+        assert_eq!(
+            policy.constrain_sys(&mut sys),
+            vec!["realtime IO priority 0 clamped to 2"]
+        );
+        assert_eq!(sys.proc_ioprio_class, IoprioClass::Rt);
+        assert_eq!(sys.proc_ioprio, 2);
+    }
+
+    #[test]
+    fn test_policy_closest_epp() {
+        let policy = policy(r#"allowed_epp = ["balance_performance", "power"]"#);
+        let mut cpu = CpuTune {
+            amd_epp_tune: "performance".to_string(),
+            amd_epp_base: "balance_power".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(policy.constrain_cpu(&mut cpu).len(), 2);
+        assert_eq!(cpu.amd_epp_tune, "balance_performance");
+        assert_eq!(cpu.amd_epp_base, "power");
+
+        let mut epp = "power".to_string();
+        assert_eq!(policy.constrain_epp(&mut epp), None);
+        epp = "balance_power".to_string();
+        assert!(policy.constrain_epp(&mut epp).is_some());
+        assert_eq!(epp, "power");
+    }
+
+    #[test]
+    fn test_policy_gpu_bound_epp() {
+        let policy = policy(r#"allowed_epp = ["balance_performance", "power"]"#);
+        let mut cpu = CpuTune {
+            amd_epp_tune: "power".to_string(),
+            amd_epp_base: "power".to_string(),
+            ..Default::default()
+        };
+        cpu.dynamic.gpu_bound_epp = "performance".to_string();
+
+        assert_eq!(policy.constrain_cpu(&mut cpu).len(), 1);
+        assert_eq!(cpu.dynamic.gpu_bound_epp, "balance_performance");
+    }
+
+    #[test]
+    fn test_policy_allowed_uids() {
+        let policy = policy("allowed_uids = [1000]");
+        assert!(policy.check_uid(1000).is_ok());
+        assert!(policy.check_uid(0).is_ok());
+        assert!(policy.check_uid(1001).is_err());
+    }
+
+    #[test]
+    fn test_policy_local_session_uids() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("1"), "UID=1000\nACTIVE=1\nREMOTE=0\n").unwrap();
+        std::fs::write(dir.path().join("2"), "UID=1001\nACTIVE=1\nREMOTE=1\n").unwrap();
+        std::fs::write(dir.path().join("3"), "UID=1002\nACTIVE=0\n").unwrap();
+        assert_eq!(local_session_uids(dir.path()), BTreeSet::from([1000]));
+
+        // Without allowed_uids, only users at the machine
+        let policy = DaemonPolicy::default();
+        assert!(policy.check_uid_in(1000, dir.path()).is_ok());
+        assert!(policy.check_uid_in(1001, dir.path()).is_err());
+        assert!(policy.check_uid_in(0, dir.path()).is_ok());
+        assert!(local_session_uids(Path::new("/nonexistent")).is_empty());
+    }
+
+    #[test]
+    fn test_policy_process_owner() {
+        assert!(check_owner(1000, 1234, Some(1000)).is_ok());
+        assert!(check_owner(1000, 1234, Some(0)).is_err());
+        assert!(check_owner(1000, 1234, Some(1001)).is_err());
+        assert!(check_owner(0, 1234, Some(1000)).is_ok());
+        assert!(check_owner(1000, 1234, None).is_ok());
+
+        let me = oomadj::process_uid(std::process::id()).unwrap();
+        assert!(check_process_owner(me, std::process::id()).is_ok());
+    }
+
+    #[test]
+    fn test_policy_load_file() {
+        assert_eq!(
+            DaemonPolicy::load_file(Path::new("/nonexistent/daemon.toml")).unwrap(),
+            DaemonPolicy::default()
+        );
+        assert!(toml::from_str::<DaemonPolicy>("max_power = 1").is_err());
+
+        // Files not owned by root are never trusted
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "min_renice = -5\n").unwrap();
+        let owned_by_root = std::fs::metadata(file.path()).unwrap().uid() == 0;
+        assert_eq!(DaemonPolicy::load_file(file.path()).is_ok(), owned_by_root);
+    }
+}
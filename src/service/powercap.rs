@@ -0,0 +1,182 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const POWERCAP_DIR: &str = "/sys/class/powercap";
+
+/// Long-term package power limit (PL1 on Intel, PPT on Zen) set through the
+/// RAPL zones of the powercap interface, remembering each zone's previous
+/// limit to put back afterwards
+#[derive(Debug)]
+pub struct PackageLimit {
+    limit_mw: u32,
+    /// `constraint_<n>_power_limit_uw` files with the value they had
+    original: Vec<(PathBuf, String)>,
+}
+
+impl PackageLimit {
+    /// Limit every CPU package to `limit_mw`, need superuser access
+    pub fn apply(limit_mw: u32) -> Result<Self> {
+        Self::apply_in(Path::new(POWERCAP_DIR), limit_mw)
+    }
+
+    fn apply_in(powercap_dir: &Path, limit_mw: u32) -> Result<Self> {
+        anyhow::ensure!(limit_mw > 0, "CPU power limit must not be zero");
+
+        let mut limit = Self {
+            limit_mw,
+            original: Vec::new(),
+        };
+        for path in package_limits(powercap_dir)? {
+            let original = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            limit.original.push((path, original.trim().to_string()));
+        }
+        anyhow::ensure!(
+            !limit.original.is_empty(),
+            "No RAPL package zone found, is intel_rapl_msr loaded?"
+        );
+
+        if let Err(e) = limit.set(limit_mw) {
+            let _ = limit.restore();
+            return Err(e);
+        }
+        Ok(limit)
+    }
+
+    /// Move the limit of an applied session, keeping the original limits
+    pub fn set(&mut self, limit_mw: u32) -> Result<()> {
+        for (path, _) in &self.original {
+            let uw = clamp_uw(path, u64::from(limit_mw) * 1000);
+            fs::write(path, uw.to_string())
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+
+        self.limit_mw = limit_mw;
+        info!(
+            "Limited CPU package power to {}W on {} packages",
+            limit_mw / 1000,
+            self.original.len()
+        );
+        Ok(())
+    }
+
+    pub fn limit_mw(&self) -> u32 {
+        self.limit_mw
+    }
+
+    /// Put every package's previous limit back
+    pub fn restore(self) -> Result<()> {
+        let mut failed = 0;
+        for (path, original) in &self.original {
+            if let Err(e) = fs::write(path, original) {
+                debug!("Failed to restore {}: {}", path.display(), e);
+                failed += 1;
+            }
+        }
+
+        anyhow::ensure!(
+            failed == 0,
+            "Failed to restore the power limit of {} packages",
+            failed
+        );
+        info!("Restored CPU package power limits");
+        Ok(())
+    }
+}
+
+/// Long-term limit files of the package zones, `intel-rapl:0` and so on.
+/// The kernel names them after Intel on Zen as well.
+fn package_limits(powercap_dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(powercap_dir)
+        .with_context(|| format!("Failed to read {}", powercap_dir.display()))?;
+
+    let mut limits: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|zone| {
+            fs::read_to_string(zone.join("name")).is_ok_and(|name| name.starts_with("package"))
+        })
+        .filter_map(|zone| long_term_constraint(&zone))
+        .collect();
+    limits.sort();
+    Ok(limits)
+}
+
+fn long_term_constraint(zone: &Path) -> Option<PathBuf> {
+    (0..4).find_map(|n| {
+        let name = fs::read_to_string(zone.join(format!("constraint_{}_name", n))).ok()?;
+        (name.trim() == "long_term").then(|| zone.join(format!("constraint_{}_power_limit_uw", n)))
+    })
+}
+
+/// Keep the requested limit at or below the most the zone allows
+fn clamp_uw(limit_path: &Path, uw: u64) -> u64 {
+    let max_path = limit_path
+        .to_str()
+        .map(|p| PathBuf::from(p.replace("_power_limit_uw", "_max_power_uw")));
+    let max = max_path
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|max| *max > 0);
+
+    match max {
+        Some(max) if uw > max => {
+            warn!(
+                "CPU power limit {}W is above the package maximum, using {}W",
+                uw / 1_000_000,
+                max / 1_000_000
+            );
+            max
+        }
+        _ => uw,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_zone(dir: &Path, zone: &str, name: &str, limit_uw: u64) -> PathBuf {
+        let zone = dir.join(zone);
+        fs::create_dir_all(&zone).unwrap();
+        fs::write(zone.join("name"), format!("{}\n", name)).unwrap();
+        fs::write(zone.join("constraint_0_name"), "long_term\n").unwrap();
+        fs::write(zone.join("constraint_1_name"), "short_term\n").unwrap();
+        fs::write(zone.join("constraint_0_max_power_uw"), "120000000\n").unwrap();
+        fs::write(
+            zone.join("constraint_0_power_limit_uw"),
+            format!("{}\n", limit_uw),
+        )
+        .unwrap();
+        zone.join("constraint_0_power_limit_uw")
+    }
+
+    #[test]
+    fn test_limit_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let package = fake_zone(dir.path(), "intel-rapl:0", "package-0", 65_000_000);
+        let core = fake_zone(dir.path(), "intel-rapl:0:0", "core", 0);
+
+        let mut limit = PackageLimit::apply_in(dir.path(), 25_000).unwrap();
+        assert_eq!(limit.limit_mw(), 25_000);
+        assert_eq!(fs::read_to_string(&package).unwrap(), "25000000");
+        assert_eq!(fs::read_to_string(&core).unwrap(), "0\n");
+
+        // Above the package maximum
+        limit.set(200_000).unwrap();
+        assert_eq!(fs::read_to_string(&package).unwrap(), "120000000");
+
+        limit.restore().unwrap();
+        assert_eq!(fs::read_to_string(&package).unwrap(), "65000000");
+    }
+
+    #[test]
+    fn test_limit_without_rapl() {
+        let dir = tempfile::tempdir().unwrap();
+        fake_zone(dir.path(), "intel-rapl:0:0", "core", 0);
+        assert!(PackageLimit::apply_in(dir.path(), 25_000).is_err());
+        assert!(PackageLimit::apply_in(dir.path(), 0).is_err());
+    }
+}
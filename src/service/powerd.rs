@@ -0,0 +1,148 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use std::path::Path;
+use std::str::FromStr;
+use zbus::proxy;
+
+use crate::common::config::CpuBackend;
+use crate::common::proc;
+use crate::service::ryzen::EppProfile;
+
+const PPD_SERVICE: &str = "org.freedesktop.UPower.PowerProfiles";
+/// Name power-profiles-daemon used before 0.20, still owned next to the new one
+const PPD_LEGACY_SERVICE: &str = "net.hadess.PowerProfiles";
+
+/// State directory TLP creates once it applied its settings
+const TLP_RUN_DIR: &str = "/run/tlp";
+
+#[proxy(
+    interface = "org.freedesktop.UPower.PowerProfiles",
+    default_service = "org.freedesktop.UPower.PowerProfiles",
+    default_path = "/org/freedesktop/UPower/PowerProfiles"
+)]
+trait PowerProfiles {
+    fn hold_profile(&self, profile: &str, reason: &str, application_id: &str) -> zbus::Result<u32>;
+    fn release_profile(&self, cookie: u32) -> zbus::Result<()>;
+}
+
+/// What sets the CPU power profile for the sessions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CpuOwner {
+    Sysfs,
+    Ppd,
+}
+
+/// Pick what sets the power profile, `auto` going through
+/// power-profiles-daemon when it runs so it does not overwrite the tuning
+pub fn resolve(backend: CpuBackend) -> Result<CpuOwner> {
+    match backend {
+        CpuBackend::Sysfs => Ok(CpuOwner::Sysfs),
+        CpuBackend::Ppd => {
+            anyhow::ensure!(
+                ppd_running(),
+                "cpu_backend is ppd but power-profiles-daemon is not running"
+            );
+            Ok(CpuOwner::Ppd)
+        }
+        CpuBackend::Auto if ppd_running() => Ok(CpuOwner::Ppd),
+        CpuBackend::Auto => Ok(CpuOwner::Sysfs),
+    }
+}
+
+/// Other tools changing what the CPU tuning sets, one message each
+pub fn conflicts(owner: CpuOwner) -> Vec<&'static str> {
+    let mut conflicts = Vec::new();
+    if owner == CpuOwner::Sysfs && ppd_running() {
+        conflicts.push(
+            "power-profiles-daemon is running and may overwrite EPP, \
+             cpu_backend = \"ppd\" goes through it instead",
+        );
+    }
+    if Path::new(TLP_RUN_DIR).exists() {
+        conflicts.push(
+            "TLP is active and applies its own EPP when the power source changes, \
+             undoing the tuning until the next launch",
+        );
+    }
+    if proc::is_running("thermald") {
+        conflicts.push("thermald is running and may lower CPU frequencies under load");
+    }
+    conflicts
+}
+
+/// The power-profiles-daemon profile closest to an EPP hint. It only lets
+/// applications hold `performance` and `power-saver`, `None` stays balanced.
+pub fn ppd_profile(epp: &str) -> Option<&'static str> {
+    match EppProfile::from_str(epp).ok()? {
+        EppProfile::Performance | EppProfile::BalancePerformance => Some("performance"),
+        EppProfile::BalancePower | EppProfile::Power => Some("power-saver"),
+        EppProfile::Default => None,
+    }
+}
+
+/// A power-profiles-daemon profile held for the sessions. The daemon also
+/// drops the hold on its own when the connection closes.
+pub struct ProfileHold {
+    conn: zbus::blocking::Connection,
+    cookie: u32,
+    profile: &'static str,
+}
+
+impl ProfileHold {
+    pub fn acquire(profile: &'static str) -> Result<Self> {
+        let conn =
+            zbus::blocking::Connection::system().context("Failed to connect to system bus")?;
+        let cookie = PowerProfilesProxyBlocking::new(&conn)
+            .context("Failed to create power-profiles-daemon proxy")?
+            .hold_profile(profile, "Game session", "nvprime")
+            .context("Failed to hold power-profiles-daemon profile")?;
+
+        info!("Holding power-profiles-daemon profile {}", profile);
+        Ok(Self {
+            conn,
+            cookie,
+            profile,
+        })
+    }
+
+    pub fn profile(&self) -> &'static str {
+        self.profile
+    }
+
+    pub fn release(self) -> Result<()> {
+        PowerProfilesProxyBlocking::new(&self.conn)?
+            .release_profile(self.cookie)
+            .context("Failed to release power-profiles-daemon profile")?;
+        info!("Released power-profiles-daemon profile {}", self.profile);
+        Ok(())
+    }
+}
+
+fn ppd_running() -> bool {
+    let owned = zbus::blocking::Connection::system().and_then(|conn| {
+        let dbus = zbus::blocking::fdo::DBusProxy::new(&conn)?;
+        for name in [PPD_SERVICE, PPD_LEGACY_SERVICE] {
+            if dbus.name_has_owner(name.try_into()?)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    });
+    owned
+        .inspect_err(|e| debug!("Could not look for power-profiles-daemon: {}", e))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ppd_profile() {
+        assert_eq!(ppd_profile("performance"), Some("performance"));
+        assert_eq!(ppd_profile("balance_performance"), Some("performance"));
+        assert_eq!(ppd_profile("power"), Some("power-saver"));
+        assert_eq!(ppd_profile("default"), None);
+        assert_eq!(ppd_profile("turbo"), None);
+    }
+}
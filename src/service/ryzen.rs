@@ -30,6 +30,15 @@ impl FromStr for EppProfile {
 }
 
 impl EppProfile {
+    /// Every profile, from the most performance to the most power saving
+    pub const ALL: [EppProfile; 5] = [
+        EppProfile::Performance,
+        EppProfile::BalancePerformance,
+        EppProfile::Default,
+        EppProfile::BalancePower,
+        EppProfile::Power,
+    ];
+
     pub fn as_str(&self) -> &'static str {
         match self {
             EppProfile::Performance => "performance",
@@ -0,0 +1,112 @@
+use anyhow::{Context, Result};
+use log::debug;
+use std::fs;
+use std::path::Path;
+use zbus::proxy;
+
+use crate::common::config::PriorityBackend;
+
+const SYSTEM76_SERVICE: &str = "com.system76.Scheduler";
+
+/// Where the kernel reports the running sched_ext scheduler
+const SCHED_EXT_DIR: &str = "/sys/kernel/sched_ext";
+
+#[proxy(
+    interface = "com.system76.Scheduler",
+    default_service = "com.system76.Scheduler",
+    default_path = "/com/system76/Scheduler"
+)]
+trait System76Scheduler {
+    fn set_foreground_process(&self, pid: u32) -> zbus::Result<()>;
+}
+
+/// Who takes care of the game's priorities for a session
+#[derive(Debug, Clone, PartialEq)]
+pub enum PriorityOwner {
+    /// nvprime renices and ionices the game itself
+    Direct,
+    /// system76-scheduler boosts the game as the foreground process
+    System76,
+    /// A sched_ext scheduler, by name, prioritizes interactive tasks on its
+    /// own and is left alone
+    SchedExt(String),
+}
+
+/// Pick who prioritizes the game. `auto` prefers a scheduler that already
+/// does it, so two tools do not fight over the same priorities.
+pub fn resolve(backend: PriorityBackend) -> Result<PriorityOwner> {
+    match backend {
+        PriorityBackend::Direct => Ok(PriorityOwner::Direct),
+        PriorityBackend::System76 => {
+            anyhow::ensure!(
+                system76_running(),
+                "priority_backend is system76 but system76-scheduler is not running"
+            );
+            Ok(PriorityOwner::System76)
+        }
+        PriorityBackend::Auto => {
+            if system76_running() {
+                return Ok(PriorityOwner::System76);
+            }
+            Ok(sched_ext_in(Path::new(SCHED_EXT_DIR))
+                .map_or(PriorityOwner::Direct, PriorityOwner::SchedExt))
+        }
+    }
+}
+
+/// Make the game system76-scheduler's foreground process
+pub fn set_foreground(pid: u32) -> Result<()> {
+    let conn = zbus::blocking::Connection::system().context("Failed to connect to system bus")?;
+    System76SchedulerProxyBlocking::new(&conn)
+        .context("Failed to create system76-scheduler proxy")?
+        .set_foreground_process(pid)
+        .context("Failed to hand the game to system76-scheduler")
+}
+
+fn system76_running() -> bool {
+    let owned = zbus::blocking::Connection::system().and_then(|conn| {
+        let dbus = zbus::blocking::fdo::DBusProxy::new(&conn)?;
+        Ok(dbus.name_has_owner(SYSTEM76_SERVICE.try_into()?)?)
+    });
+    owned
+        .inspect_err(|e| debug!("Could not look for system76-scheduler: {}", e))
+        .unwrap_or(false)
+}
+
+/// Name of the sched_ext scheduler loaded in the kernel, if any
+fn sched_ext_in(dir: &Path) -> Option<String> {
+    let state = fs::read_to_string(dir.join("state")).ok()?;
+    if state.trim() != "enabled" {
+        return None;
+    }
+
+    let name = fs::read_to_string(dir.join("root").join("ops"))
+        .map(|ops| ops.trim().to_string())
+        .unwrap_or_default();
+    Some(if name.is_empty() {
+        "unknown".to_string()
+    } else {
+        name
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sched_ext_detection() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(sched_ext_in(dir.path()), None);
+
+        fs::write(dir.path().join("state"), "disabled\n").unwrap();
+        assert_eq!(sched_ext_in(dir.path()), None);
+
+        fs::write(dir.path().join("state"), "enabled\n").unwrap();
+        assert_eq!(sched_ext_in(dir.path()), Some("unknown".to_string()));
+
+        fs::create_dir_all(dir.path().join("root")).unwrap();
+        fs::write(dir.path().join("root").join("ops"), "lavd_1.0.6\n").unwrap();
+        assert_eq!(sched_ext_in(dir.path()), Some("lavd_1.0.6".to_string()));
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::common::config::SmtMode;
+
+const CPU_DIR: &str = "/sys/devices/system/cpu";
+
+/// Fewest physical cores SMT may be turned off with, below that the game
+/// and the desktop fight over what is left
+const MIN_CORES: usize = 4;
+
+/// SMT switched through `smt/control` for the sessions, put back as it was
+/// once the last one ends
+#[derive(Debug)]
+pub struct SmtControl {
+    path: PathBuf,
+    mode: SmtMode,
+    /// What `smt/control` read before
+    original: String,
+}
+
+impl SmtControl {
+    pub fn apply(mode: SmtMode) -> Result<Self> {
+        Self::apply_in(Path::new(CPU_DIR), mode)
+    }
+
+    fn apply_in(cpu_dir: &Path, mode: SmtMode) -> Result<Self> {
+        let path = cpu_dir.join("smt/control");
+        let original = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}, SMT cannot be switched", path.display()))?
+            .trim()
+            .to_string();
+        anyhow::ensure!(
+            original == "on" || original == "off",
+            "SMT cannot be switched, the kernel reports it as {}",
+            original
+        );
+
+        let mut control = Self {
+            path,
+            mode,
+            original,
+        };
+        control.set_in(cpu_dir, mode)?;
+        Ok(control)
+    }
+
+    /// Switch SMT for an applied session, keeping the original state
+    pub fn set(&mut self, mode: SmtMode) -> Result<()> {
+        self.set_in(Path::new(CPU_DIR), mode)
+    }
+
+    fn set_in(&mut self, cpu_dir: &Path, mode: SmtMode) -> Result<()> {
+        let value = mode.control_value();
+        let current = fs::read_to_string(&self.path).unwrap_or_default();
+        if current.trim() == value {
+            debug!("SMT already {}", value);
+            self.mode = mode;
+            return Ok(());
+        }
+
+        if mode == SmtMode::Off {
+            let cores = physical_cores(cpu_dir)?;
+            anyhow::ensure!(
+                cores >= MIN_CORES,
+                "Turning SMT off leaves {} cores, fewer than {}",
+                cores,
+                MIN_CORES
+            );
+        }
+
+        fs::write(&self.path, value).with_context(|| format!("Failed to turn SMT {}", value))?;
+        self.mode = mode;
+        info!("Turned SMT {}", value);
+        Ok(())
+    }
+
+    pub fn mode(&self) -> SmtMode {
+        self.mode
+    }
+
+    pub fn restore(self) -> Result<()> {
+        let current = fs::read_to_string(&self.path).unwrap_or_default();
+        if current.trim() == self.original {
+            return Ok(());
+        }
+        fs::write(&self.path, &self.original)
+            .with_context(|| format!("Failed to turn SMT back {}", self.original))?;
+        info!("Turned SMT back {}", self.original);
+        Ok(())
+    }
+}
+
+/// Online physical cores, each set of thread siblings counted once. Cores
+/// without SMT, like the efficiency cores of hybrid CPUs, count as well.
+fn physical_cores(cpu_dir: &Path) -> Result<usize> {
+    let entries =
+        fs::read_dir(cpu_dir).with_context(|| format!("Failed to read {}", cpu_dir.display()))?;
+
+    let cores: BTreeSet<String> = entries
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_prefix("cpu"))
+                .is_some_and(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+        })
+        // Offline CPUs have no topology
+        .filter_map(|entry| {
+            fs::read_to_string(entry.path().join("topology/thread_siblings_list")).ok()
+        })
+        .map(|siblings| siblings.trim().to_string())
+        .collect();
+
+    anyhow::ensure!(
+        !cores.is_empty(),
+        "No CPU topology found in {}",
+        cpu_dir.display()
+    );
+    Ok(cores.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two CPUs per core for the first `smt_cores`, one for the rest
+    fn fake_topology(cpu_dir: &Path, smt_cores: usize, single_cores: usize) {
+        let mut cpu = 0;
+        let mut add = |siblings: &str| {
+            let dir = cpu_dir.join(format!("cpu{}/topology", cpu));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("thread_siblings_list"), format!("{}\n", siblings)).unwrap();
+            cpu += 1;
+        };
+        for thread in 0..smt_cores * 2 {
+            let core = thread % smt_cores;
+            add(&format!("{},{}", core, core + smt_cores));
+        }
+        for core in 0..single_cores {
+            add(&(smt_cores * 2 + core).to_string());
+        }
+
+        fs::create_dir_all(cpu_dir.join("smt")).unwrap();
+        fs::write(cpu_dir.join("smt/control"), "on\n").unwrap();
+    }
+
+    #[test]
+    fn test_smt_off_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let control = dir.path().join("smt/control");
+        fake_topology(dir.path(), 2, 4);
+        assert_eq!(physical_cores(dir.path()).unwrap(), 6);
+
+        let smt = SmtControl::apply_in(dir.path(), SmtMode::Off).unwrap();
+        assert_eq!(fs::read_to_string(&control).unwrap(), "off");
+        smt.restore().unwrap();
+        assert_eq!(fs::read_to_string(&control).unwrap(), "on");
+
+        fs::write(&control, "notsupported\n").unwrap();
+        assert!(SmtControl::apply_in(dir.path(), SmtMode::Off).is_err());
+    }
+
+    #[test]
+    fn test_smt_off_leaves_enough_cores() {
+        let dir = tempfile::tempdir().unwrap();
+        fake_topology(dir.path(), 2, 0);
+
+        let err = SmtControl::apply_in(dir.path(), SmtMode::Off).unwrap_err();
+        assert!(err.to_string().contains("leaves 2 cores"), "{}", err);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("smt/control")).unwrap(),
+            "on\n"
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::{Context, Result};
+use log::{debug, error, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::common::config::MemoryTune;
+
+const ZSWAP_DIR: &str = "/sys/module/zswap/parameters";
+
+const PROC_SWAPS: &str = "/proc/swaps";
+
+/// zswap parameters and zram swap priorities for the sessions, put back as
+/// they were once the last session ends
+#[derive(Debug, Default)]
+pub struct SwapTuning {
+    /// zswap parameter files with the value they had
+    params: Vec<(PathBuf, String)>,
+    /// zram swap devices with the priority they had
+    zram: Vec<(String, i32)>,
+}
+
+impl SwapTuning {
+    pub fn apply(config: &MemoryTune) -> Result<Self> {
+        Self::apply_in(Path::new(ZSWAP_DIR), config)
+    }
+
+    fn apply_in(zswap_dir: &Path, config: &MemoryTune) -> Result<Self> {
+        let mut tuning = Self::default();
+        let applied = tuning.set_zswap(zswap_dir, config).and_then(|()| {
+            config
+                .zram_priority
+                .map_or(Ok(()), |priority| tuning.set_zram_priority(priority))
+        });
+
+        if let Err(e) = applied {
+            if let Err(e) = tuning.restore() {
+                error!("Failed to roll back swap tuning: {}", e);
+            }
+            return Err(e);
+        }
+        Ok(tuning)
+    }
+
+    fn set_zswap(&mut self, dir: &Path, config: &MemoryTune) -> Result<()> {
+        if let Some(percent) = config.zswap_max_pool_percent {
+            anyhow::ensure!(
+                (1..=100).contains(&percent),
+                "zswap_max_pool_percent {} is not between 1 and 100",
+                percent
+            );
+        }
+
+        let params = [
+            (
+                "max_pool_percent",
+                config.zswap_max_pool_percent.map(|p| p.to_string()),
+            ),
+            ("compressor", config.zswap_compressor.clone()),
+        ];
+        for (name, value) in params {
+            let Some(value) = value else {
+                continue;
+            };
+            anyhow::ensure!(
+                dir.exists(),
+                "zswap is not available, {} does not exist",
+                dir.display()
+            );
+
+            let path = dir.join(name);
+            let original = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?
+                .trim()
+                .to_string();
+            if original == value {
+                debug!("zswap {} already {}", name, value);
+                continue;
+            }
+
+            // The kernel refuses a compressor it has no crypto module for
+            fs::write(&path, &value)
+                .with_context(|| format!("Failed to set zswap {} to {}", name, value))?;
+            info!("Set zswap {} to {}, was {}", name, value, original);
+            self.params.push((path, original));
+        }
+
+        let enabled = fs::read_to_string(dir.join("enabled")).unwrap_or_default();
+        if !self.params.is_empty() && enabled.trim() == "N" {
+            warn!("zswap is disabled, its settings take effect once it is enabled");
+        }
+        Ok(())
+    }
+
+    fn set_zram_priority(&mut self, priority: i32) -> Result<()> {
+        let swaps = fs::read_to_string(PROC_SWAPS)
+            .with_context(|| format!("Failed to read {}", PROC_SWAPS))?;
+        let devices = zram_devices(&swaps);
+        anyhow::ensure!(!devices.is_empty(), "No zram device is in use as swap");
+
+        for (device, original) in devices {
+            if original == priority {
+                continue;
+            }
+            set_swap_priority(&device, priority)?;
+            info!(
+                "Set swap priority of {} to {}, was {}",
+                device, priority, original
+            );
+            self.zram.push((device, original));
+        }
+        Ok(())
+    }
+
+    /// Put back every zswap parameter and zram priority changed
+    pub fn restore(self) -> Result<()> {
+        let mut failed = 0;
+        for (path, original) in self.params.iter().rev() {
+            if let Err(e) = fs::write(path, original) {
+                error!("Failed to restore {}: {}", path.display(), e);
+                failed += 1;
+            }
+        }
+        for (device, original) in &self.zram {
+            if let Err(e) = set_swap_priority(device, *original) {
+                error!("{:#}", e);
+                failed += 1;
+            }
+        }
+
+        anyhow::ensure!(failed == 0, "Failed to restore {} swap settings", failed);
+        info!("Restored swap settings");
+        Ok(())
+    }
+}
+
+/// zram swap devices in `/proc/swaps` with their priorities
+fn zram_devices(swaps: &str) -> Vec<(String, i32)> {
+    swaps
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let device = *fields.first()?;
+            let priority = fields.get(4)?.parse().ok()?;
+            device
+                .starts_with("/dev/zram")
+                .then(|| (device.to_string(), priority))
+        })
+        .collect()
+}
+
+/// A swap device keeps its priority while in use, so it is taken off and
+/// put back. Negative priorities are the kernel's own, which it hands out
+/// again when none is given.
+fn set_swap_priority(device: &str, priority: i32) -> Result<()> {
+    run("swapoff", &[device])?;
+
+    let priority = priority.to_string();
+    let args = if priority.starts_with('-') {
+        vec![device]
+    } else {
+        vec!["--priority", &priority, device]
+    };
+    if let Err(e) = run("swapon", &args) {
+        // Better back at any priority than left off
+        let _ = run("swapon", &[device]);
+        return Err(e);
+    }
+    Ok(())
+}
+
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    debug!("Running {} {:?}", program, args);
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run {}", program))?;
+
+    anyhow::ensure!(
+        output.status.success(),
+        "{} {} failed: {}",
+        program,
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zswap_apply_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("max_pool_percent"), "20\n").unwrap();
+        fs::write(dir.path().join("compressor"), "lzo\n").unwrap();
+        fs::write(dir.path().join("enabled"), "Y\n").unwrap();
+
+        let config = MemoryTune {
+            zswap_max_pool_percent: Some(40),
+            zswap_compressor: Some("zstd".to_string()),
+            zram_priority: None,
+        };
+        let tuning = SwapTuning::apply_in(dir.path(), &config).unwrap();
+        let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("max_pool_percent"), "40");
+        assert_eq!(read("compressor"), "zstd");
+
+        tuning.restore().unwrap();
+        assert_eq!(read("max_pool_percent"), "20");
+        assert_eq!(read("compressor"), "lzo");
+
+        let config = MemoryTune {
+            zswap_max_pool_percent: Some(0),
+            ..Default::default()
+        };
+        assert!(SwapTuning::apply_in(dir.path(), &config).is_err());
+    }
+
+    #[test]
+    fn test_zram_devices() {
+        let swaps = "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n\
+                     /dev/zram0                              partition\t8388604\t\t0\t\t100\n\
+                     /swapfile                               file\t\t4194300\t\t0\t\t-2\n";
+        assert_eq!(zram_devices(swaps), [("/dev/zram0".to_string(), 100)]);
+    }
+}